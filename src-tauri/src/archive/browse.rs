@@ -0,0 +1,366 @@
+// Presents archive contents as a read-only directory tree
+//
+// Paths address a folder inside an archive as `/x/build.zip!/src/`. Most
+// archives do not store every parent directory, so folders are derived from
+// the entry names rather than from explicit directory entries.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use super::{list, ArchiveEntryInfo, ArchiveListing};
+use crate::hidden;
+use crate::{DirectoryContents, EntryKind, FileEntry, OhMyFSError};
+
+pub const ARCHIVE_SEPARATOR: char = '!';
+
+// Listing a compressed tarball means decompressing all of it, so the last
+// listing is kept around while the user clicks through its folders
+struct CachedListing {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    size: u64,
+    listing: Arc<ArchiveListing>,
+}
+
+static LAST_LISTING: Mutex<Option<CachedListing>> = Mutex::new(None);
+
+fn cached_list(archive: &Path) -> Result<Arc<ArchiveListing>, OhMyFSError> {
+    let metadata = fs::metadata(archive).map_err(|err| OhMyFSError::ArchiveReadFailed {
+        path: archive.to_string_lossy().to_string(),
+        details: err.to_string(),
+    })?;
+    let modified = metadata.modified().ok();
+
+    let mut cache = LAST_LISTING
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(cached) = cache.as_ref() {
+        if cached.path == archive && cached.modified == modified && cached.size == metadata.len() {
+            return Ok(cached.listing.clone());
+        }
+    }
+
+    let listing = Arc::new(list(archive)?);
+    *cache = Some(CachedListing {
+        path: archive.to_path_buf(),
+        modified,
+        size: metadata.len(),
+        listing: listing.clone(),
+    });
+    Ok(listing)
+}
+
+// Splits `/x/build.zip!/src/` into the archive file and the folder inside it.
+// The first `!` that follows an existing file wins, so directories that merely
+// contain a `!` in their name keep working.
+pub(crate) fn split_archive_path(path: &str) -> Option<(PathBuf, String)> {
+    for (index, _) in path.match_indices(ARCHIVE_SEPARATOR) {
+        let inner = &path[index + 1..];
+        if !(inner.is_empty() || inner.starts_with('/') || inner.starts_with('\\')) {
+            continue;
+        }
+
+        let archive = Path::new(&path[..index]);
+        if archive.is_file() {
+            return Some((archive.to_path_buf(), normalize_entry_name(inner)));
+        }
+    }
+    None
+}
+
+// Archive entry names use `/`, may start with `./` and mark folders with a
+// trailing slash; reduce them to `a/b/c`
+pub(crate) fn normalize_entry_name(name: &str) -> String {
+    name.replace('\\', "/")
+        .split('/')
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// `/x/build.zip!/src/main.rs` for the entry `src/main.rs` of `/x/build.zip`
+pub(crate) fn virtual_path(archive: &Path, relative: &str) -> String {
+    format!("{}{}/{}", archive.display(), ARCHIVE_SEPARATOR, relative)
+}
+
+fn virtual_entry(
+    archive: &Path,
+    relative: &str,
+    name: &str,
+    is_directory: bool,
+    info: Option<&ArchiveEntryInfo>,
+) -> FileEntry {
+    let extension = if is_directory {
+        None
+    } else {
+        Path::new(name)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_string())
+    };
+
+    FileEntry {
+        name: name.to_string(),
+        path: virtual_path(archive, relative),
+        kind: if is_directory {
+            EntryKind::Directory
+        } else if info.is_some_and(|info| info.is_symlink) {
+            EntryKind::Symlink
+        } else {
+            EntryKind::File
+        },
+        is_directory,
+        is_file: !is_directory && !info.is_some_and(|info| info.is_symlink),
+        is_symlink: info.is_some_and(|info| info.is_symlink),
+        // Links inside an archive are not resolved
+        symlink_target: None,
+        target_kind: None,
+        is_broken: false,
+        size: Some(if is_directory {
+            0
+        } else {
+            info.map_or(0, |info| info.size)
+        }),
+        compressed_size: info.and_then(|info| info.compressed_size),
+        modified_at: info.and_then(|info| info.modified_at.clone()),
+        created_at: None,
+        permissions: None,
+        extension,
+        is_hidden: hidden::is_dotfile(name),
+        is_ignored: false,
+    }
+}
+
+pub(crate) fn read_archive_directory(
+    archive: &Path,
+    inner: &str,
+    show_hidden: bool,
+) -> Result<DirectoryContents, OhMyFSError> {
+    let listing = cached_list(archive)?;
+    let virtual_path = virtual_path(archive, inner);
+    let prefix = if inner.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", inner)
+    };
+
+    // Keyed by name so that implicit folders and repeated tar entries collapse
+    let mut directories: BTreeMap<String, FileEntry> = BTreeMap::new();
+    let mut files: BTreeMap<String, FileEntry> = BTreeMap::new();
+    let mut found = inner.is_empty();
+
+    for info in &listing.entries {
+        let name = normalize_entry_name(&info.name);
+        if name == inner {
+            if !info.is_directory {
+                return Err(OhMyFSError::PathNotDirectory { path: virtual_path });
+            }
+            found = true;
+            continue;
+        }
+
+        let Some(rest) = name.strip_prefix(&prefix) else {
+            continue;
+        };
+        found = true;
+
+        let (child, nested) = match rest.split_once('/') {
+            Some((child, _)) => (child, true),
+            None => (rest, false),
+        };
+        if !show_hidden && hidden::is_dotfile(child) {
+            continue;
+        }
+
+        let relative = format!("{}{}", prefix, child);
+        if nested || info.is_directory {
+            // Prefer the explicit directory entry for its timestamp
+            let explicit = (!nested).then_some(info);
+            match directories.get_mut(child) {
+                Some(existing) if explicit.is_some() => {
+                    *existing = virtual_entry(archive, &relative, child, true, explicit);
+                }
+                Some(_) => {}
+                None => {
+                    directories.insert(
+                        child.to_string(),
+                        virtual_entry(archive, &relative, child, true, explicit),
+                    );
+                }
+            }
+        } else {
+            files.insert(
+                child.to_string(),
+                virtual_entry(archive, &relative, child, false, Some(info)),
+            );
+        }
+    }
+
+    if !found {
+        return Err(OhMyFSError::DirectoryNotFound { path: virtual_path });
+    }
+
+    Ok(DirectoryContents {
+        directories: directories.into_values().collect(),
+        files: files.into_values().collect(),
+        groups: None,
+    })
+}
+
+// Every entry of an archive as it shows up while browsing it, including the
+// folders that only exist implicitly in entry names
+pub(crate) fn virtual_entries(archive: &Path, entries: &[ArchiveEntryInfo]) -> Vec<FileEntry> {
+    let mut directories: BTreeMap<String, Option<&ArchiveEntryInfo>> = BTreeMap::new();
+    let mut files: BTreeMap<String, &ArchiveEntryInfo> = BTreeMap::new();
+
+    for info in entries {
+        let name = normalize_entry_name(&info.name);
+        if name.is_empty() {
+            continue;
+        }
+        for (index, _) in name.match_indices('/') {
+            directories.entry(name[..index].to_string()).or_insert(None);
+        }
+        if info.is_directory {
+            directories.insert(name, Some(info));
+        } else {
+            files.insert(name, info);
+        }
+    }
+
+    let base_name = |relative: &str| relative.rsplit('/').next().unwrap_or(relative).to_string();
+    directories
+        .into_iter()
+        .map(|(relative, info)| {
+            virtual_entry(archive, &relative, &base_name(&relative), true, info)
+        })
+        .chain(files.into_iter().map(|(relative, info)| {
+            virtual_entry(archive, &relative, &base_name(&relative), false, Some(info))
+        }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    fn write_archive(folder: &Path) -> PathBuf {
+        let archive = folder.join("docs.zip");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+        writer
+            .add_directory("empty/", SimpleFileOptions::default())
+            .unwrap();
+        for name in [
+            "README.md",
+            "docs/notes.txt",
+            "docs/.draft.md",
+            "docs/guide/intro.md",
+        ] {
+            writer
+                .start_file(name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(name.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+        archive
+    }
+
+    fn names(entries: &[FileEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[test]
+    fn lists_the_archive_root() {
+        let temp = tempfile::tempdir().unwrap();
+        let archive = write_archive(temp.path());
+
+        let contents = read_archive_directory(&archive, "", false).unwrap();
+        assert_eq!(names(&contents.directories), ["docs", "empty"]);
+        assert_eq!(names(&contents.files), ["README.md"]);
+        assert_eq!(
+            contents.directories[0].path,
+            format!("{}!/docs", archive.display())
+        );
+        assert!(contents.directories[0].is_directory);
+        assert_eq!(contents.files[0].size, Some(9));
+    }
+
+    #[test]
+    fn lists_nested_folders() {
+        let temp = tempfile::tempdir().unwrap();
+        let archive = write_archive(temp.path());
+
+        let docs = read_archive_directory(&archive, "docs", false).unwrap();
+        assert_eq!(names(&docs.directories), ["guide"]);
+        assert_eq!(names(&docs.files), ["notes.txt"]);
+        assert_eq!(
+            docs.files[0].path,
+            format!("{}!/docs/notes.txt", archive.display())
+        );
+
+        let docs = read_archive_directory(&archive, "docs", true).unwrap();
+        assert_eq!(names(&docs.files), [".draft.md", "notes.txt"]);
+        assert!(docs.files[0].is_hidden);
+
+        let guide = read_archive_directory(&archive, "docs/guide", false).unwrap();
+        assert!(guide.directories.is_empty());
+        assert_eq!(names(&guide.files), ["intro.md"]);
+
+        let empty = read_archive_directory(&archive, "empty", false).unwrap();
+        assert!(empty.directories.is_empty() && empty.files.is_empty());
+    }
+
+    #[test]
+    fn missing_inner_paths_are_errors() {
+        let temp = tempfile::tempdir().unwrap();
+        let archive = write_archive(temp.path());
+
+        assert!(matches!(
+            read_archive_directory(&archive, "nowhere", false),
+            Err(OhMyFSError::DirectoryNotFound { .. })
+        ));
+        assert!(matches!(
+            read_archive_directory(&archive, "doc", false),
+            Err(OhMyFSError::DirectoryNotFound { .. })
+        ));
+        assert!(matches!(
+            read_archive_directory(&archive, "README.md", false),
+            Err(OhMyFSError::PathNotDirectory { .. })
+        ));
+    }
+
+    #[test]
+    fn splits_virtual_paths() {
+        let temp = tempfile::tempdir().unwrap();
+        let archive = write_archive(temp.path());
+        let path = archive.display();
+
+        assert_eq!(
+            split_archive_path(&format!("{}!/docs/guide/", path)),
+            Some((archive.clone(), "docs/guide".to_string()))
+        );
+        assert_eq!(
+            split_archive_path(&format!("{}!", path)),
+            Some((archive.clone(), String::new()))
+        );
+        assert_eq!(split_archive_path(&format!("{}!docs", path)), None);
+        assert_eq!(split_archive_path(&archive.to_string_lossy()), None);
+        assert_eq!(
+            split_archive_path(&format!("{}/missing.zip!/docs", temp.path().display())),
+            None
+        );
+
+        // Folders with a `!` in their name are only split at the archive
+        let folder = temp.path().join("wow!");
+        fs::create_dir(&folder).unwrap();
+        let nested = write_archive(&folder);
+        assert_eq!(
+            split_archive_path(&format!("{}!/docs", nested.display())),
+            Some((nested, "docs".to_string()))
+        );
+    }
+}
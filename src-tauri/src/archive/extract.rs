@@ -0,0 +1,712 @@
+// Format independent extraction into a destination directory
+//
+// Backends feed entries one by one; this module decides where each entry may
+// land on disk. Nothing is ever written outside the destination root.
+
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use super::{
+    ArchiveFormat, ArchiveOperation, ArchiveProgress, ConflictPolicy, ExtractionSummary,
+    COPY_BUFFER_SIZE,
+};
+use crate::operation::CancellationToken;
+use crate::{log_error, unique_destination, OhMyFSError};
+
+pub(crate) enum EntryData<'r> {
+    Directory,
+    File(&'r mut dyn Read),
+    Symlink(String),
+    // Name of an earlier entry in the same archive
+    HardLink(String),
+}
+
+enum EntryError {
+    Unsafe(String),
+    Io(String),
+}
+
+impl From<io::Error> for EntryError {
+    fn from(err: io::Error) -> Self {
+        EntryError::Io(err.to_string())
+    }
+}
+
+// Turns an archive entry name into a path relative to the destination,
+// rejecting absolute paths, drive prefixes and `..` components.
+pub(crate) fn sanitize_entry_name(name: &str) -> Result<PathBuf, String> {
+    if name.contains('\0') {
+        return Err("entry name contains a NUL byte".to_string());
+    }
+
+    let normalized = name.replace('\\', "/");
+    if normalized.starts_with('/') {
+        return Err("entry has an absolute path".to_string());
+    }
+
+    let mut relative = PathBuf::new();
+    for part in normalized.split('/') {
+        match part {
+            "" | "." => continue,
+            ".." => return Err("entry path escapes the destination".to_string()),
+            _ => {}
+        }
+        // Catch `C:` style prefixes and anything else the platform would not treat as a plain name
+        let mut components = Path::new(part).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) => relative.push(part),
+            _ => {
+                return Err(format!(
+                    "entry path contains an invalid component `{}`",
+                    part
+                ))
+            }
+        }
+    }
+
+    if relative.as_os_str().is_empty() {
+        return Err("entry has an empty path".to_string());
+    }
+
+    Ok(relative)
+}
+
+// Lexically resolves a symlink target relative to the link location and
+// makes sure it stays inside the destination. `..` may only lead the target:
+// after a name it would climb out of whatever that name resolves to, which
+// for another link can be anywhere.
+fn symlink_stays_inside(link: &Path, target: &str) -> bool {
+    let target = Path::new(target);
+    if target.has_root() {
+        return false;
+    }
+
+    let mut depth: usize = link
+        .parent()
+        .map_or(0, |parent| parent.components().count());
+    let mut descended = false;
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => {
+                depth += 1;
+                descended = true;
+            }
+            Component::CurDir => {}
+            Component::ParentDir if descended => return false,
+            Component::ParentDir => match depth.checked_sub(1) {
+                Some(parent) => depth = parent,
+                None => return false,
+            },
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
+fn create_symlink(target: &str, link: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(target, link)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (target, link);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "symbolic links are not supported on this platform",
+        ))
+    }
+}
+
+pub(crate) struct Extractor<'a> {
+    root: PathBuf,
+    policy: ConflictPolicy,
+    buffer: Vec<u8>,
+    progress: ArchiveProgress,
+    summary: ExtractionSummary,
+    // When set, progress follows archive bytes consumed instead of bytes written
+    input_counter: Option<Rc<Cell<u64>>>,
+    token: &'a CancellationToken,
+    on_progress: &'a mut dyn FnMut(&ArchiveProgress, bool),
+    // Where each entry ended up, which differs from its name after a rename
+    claimed: HashMap<PathBuf, PathBuf>,
+    // Links this extraction created, relative to the root
+    links: HashSet<PathBuf>,
+}
+
+impl<'a> Extractor<'a> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        format: ArchiveFormat,
+        archive_path: &Path,
+        destination: &Path,
+        policy: ConflictPolicy,
+        entries_total: u64,
+        bytes_total: u64,
+        token: &'a CancellationToken,
+        on_progress: &'a mut dyn FnMut(&ArchiveProgress, bool),
+    ) -> Result<Self, OhMyFSError> {
+        let write_error = |err: io::Error| OhMyFSError::FileWriteFailed {
+            path: destination.to_string_lossy().to_string(),
+            details: err.to_string(),
+        };
+        fs::create_dir_all(destination).map_err(write_error)?;
+        let root = fs::canonicalize(destination).map_err(write_error)?;
+
+        let progress = ArchiveProgress {
+            operation: ArchiveOperation::Extract,
+            archive_path: archive_path.to_string_lossy().to_string(),
+            current_file: None,
+            entries_done: 0,
+            entries_total,
+            bytes_done: 0,
+            bytes_total,
+        };
+        on_progress(&progress, true);
+
+        Ok(Self {
+            summary: ExtractionSummary {
+                format,
+                destination: root.to_string_lossy().to_string(),
+                written: Vec::new(),
+                skipped: Vec::new(),
+                bytes_written: 0,
+                cancelled: false,
+                failed: Vec::new(),
+            },
+            root,
+            policy,
+            buffer: vec![0u8; COPY_BUFFER_SIZE],
+            progress,
+            input_counter: None,
+            token,
+            on_progress,
+            claimed: HashMap::new(),
+            links: HashSet::new(),
+        })
+    }
+
+    pub fn track_input(&mut self, counter: Rc<Cell<u64>>) {
+        self.input_counter = Some(counter);
+    }
+
+    // Checked by the format loops between entries; blocks while the job is paused
+    pub fn should_stop(&mut self) -> bool {
+        if self.token.checkpoint() {
+            self.summary.cancelled = true;
+        }
+        self.summary.cancelled
+    }
+
+    pub fn extract_entry(
+        &mut self,
+        name: &str,
+        data: EntryData,
+        mode: Option<u32>,
+        modified: Option<SystemTime>,
+    ) {
+        self.progress.current_file = Some(name.to_string());
+        (self.on_progress)(&self.progress, false);
+
+        match self.write_entry(name, data, mode, modified) {
+            Ok(()) => {}
+            Err(EntryError::Unsafe(details)) => {
+                log_error!("Rejected archive entry {}: {}", name, details);
+                self.summary.failed.push(OhMyFSError::UnsafeArchiveEntry {
+                    path: name.to_string(),
+                    details,
+                });
+            }
+            // The partial file is already gone, there is nothing to report
+            Err(EntryError::Io(_)) if self.token.is_cancelled() => {
+                self.summary.cancelled = true;
+            }
+            Err(EntryError::Io(details)) => {
+                log_error!("Failed to extract {}: {}", name, details);
+                self.summary.failed.push(OhMyFSError::ArchiveEntryFailed {
+                    path: name.to_string(),
+                    details,
+                });
+            }
+        }
+
+        self.progress.entries_done += 1;
+        if let Some(counter) = &self.input_counter {
+            self.progress.bytes_done = counter.get();
+        }
+    }
+
+    // Records an entry the backend could not even read
+    pub fn skip_entry(&mut self, name: &str, details: String) {
+        log_error!("Failed to read archive entry {}: {}", name, details);
+        self.summary.failed.push(OhMyFSError::ArchiveEntryFailed {
+            path: name.to_string(),
+            details,
+        });
+        self.progress.entries_done += 1;
+    }
+
+    pub fn finish(mut self) -> ExtractionSummary {
+        self.progress.current_file = None;
+        (self.on_progress)(&self.progress, true);
+        self.summary
+    }
+
+    // Refuses to write through a symlink that an earlier entry (or anything
+    // already on disk) planted inside the destination
+    fn check_ancestors(&self, relative: &Path) -> Result<(), EntryError> {
+        let mut current = self.root.clone();
+        if let Some(parent) = relative.parent() {
+            for component in parent.components() {
+                current.push(component);
+                match fs::symlink_metadata(&current) {
+                    Ok(metadata) if metadata.file_type().is_symlink() => {
+                        return Err(EntryError::Unsafe(format!(
+                            "parent {} is a symbolic link",
+                            current.display()
+                        )));
+                    }
+                    Ok(metadata) if !metadata.is_dir() => {
+                        return Err(EntryError::Io(format!(
+                            "parent {} is not a directory",
+                            current.display()
+                        )));
+                    }
+                    Ok(_) => {}
+                    Err(_) => break,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // A link target may only pass through folders, or through links this
+    // extraction made and checked; a link that was already on disk could
+    // point anywhere
+    fn check_link_path(&self, link: &Path, target: &str) -> Result<(), EntryError> {
+        let mut relative = link.parent().map(Path::to_path_buf).unwrap_or_default();
+        let components = Path::new(target).components().collect::<Vec<_>>();
+        let Some((_, passed)) = components.split_last() else {
+            return Ok(());
+        };
+        for component in passed {
+            match component {
+                Component::ParentDir => {
+                    relative.pop();
+                }
+                Component::Normal(name) => {
+                    relative.push(name);
+                    let is_symlink = fs::symlink_metadata(self.root.join(&relative))
+                        .is_ok_and(|metadata| metadata.file_type().is_symlink());
+                    if is_symlink && !self.links.contains(&relative) {
+                        return Err(EntryError::Unsafe(format!(
+                            "symbolic link target {} passes through the existing link {}",
+                            target,
+                            relative.display()
+                        )));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Where an entry goes relative to the root: below its nearest extracted
+    // folder, which is somewhere else when the rename policy moved it
+    fn placement(&self, relative: &Path) -> PathBuf {
+        for ancestor in relative.ancestors().skip(1) {
+            let Some(claimed) = self.claimed.get(ancestor) else {
+                continue;
+            };
+            if let (Ok(rest), Ok(claimed)) = (
+                relative.strip_prefix(ancestor),
+                claimed.strip_prefix(&self.root),
+            ) {
+                return claimed.join(rest);
+            }
+        }
+        relative.to_path_buf()
+    }
+
+    // Applies the conflict policy. `None` means the entry should be skipped.
+    fn claim(
+        &mut self,
+        target: PathBuf,
+        is_directory: bool,
+    ) -> Result<Option<PathBuf>, EntryError> {
+        let existing = match fs::symlink_metadata(&target) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Some(target)),
+            Err(err) => return Err(err.into()),
+        };
+
+        // Directories merge into existing directories without asking
+        if is_directory && existing.is_dir() {
+            return Ok(Some(target));
+        }
+
+        match self.policy {
+            ConflictPolicy::Skip => {
+                self.summary
+                    .skipped
+                    .push(target.to_string_lossy().to_string());
+                Ok(None)
+            }
+            ConflictPolicy::Rename => Ok(Some(unique_destination(&target))),
+            ConflictPolicy::Overwrite => {
+                if existing.is_dir() {
+                    return Err(EntryError::Io(format!(
+                        "{} is an existing directory and will not be replaced",
+                        target.display()
+                    )));
+                }
+                // Removing first also unlinks symlinks instead of writing through them
+                fs::remove_file(&target)?;
+                Ok(Some(target))
+            }
+        }
+    }
+
+    fn write_entry(
+        &mut self,
+        name: &str,
+        data: EntryData,
+        mode: Option<u32>,
+        modified: Option<SystemTime>,
+    ) -> Result<(), EntryError> {
+        let relative = sanitize_entry_name(name).map_err(EntryError::Unsafe)?;
+        let placed = self.placement(&relative);
+        self.check_ancestors(&placed)?;
+
+        if let EntryData::Symlink(link_target) = &data {
+            if !symlink_stays_inside(&placed, link_target) {
+                return Err(EntryError::Unsafe(format!(
+                    "symbolic link target {} points outside the destination",
+                    link_target
+                )));
+            }
+            self.check_link_path(&placed, link_target)?;
+        }
+        // Resolved before claiming so a missing original leaves nothing behind
+        let original = match &data {
+            EntryData::HardLink(original) => {
+                let original = sanitize_entry_name(original).map_err(EntryError::Unsafe)?;
+                let claimed = self.claimed.get(&original).cloned().ok_or_else(|| {
+                    EntryError::Io(format!(
+                        "hard link target {} was not extracted",
+                        original.display()
+                    ))
+                })?;
+                Some(claimed)
+            }
+            _ => None,
+        };
+
+        let is_directory = matches!(data, EntryData::Directory);
+        let Some(target) = self.claim(self.root.join(&placed), is_directory)? else {
+            return Ok(());
+        };
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        match data {
+            EntryData::Directory => {
+                if target.is_dir() {
+                    // Merged into a directory that already existed
+                    return Ok(());
+                }
+                fs::create_dir_all(&target)?;
+            }
+            EntryData::Symlink(link_target) => {
+                create_symlink(&link_target, &target)?;
+                if let Ok(created) = target.strip_prefix(&self.root) {
+                    self.links.insert(created.to_path_buf());
+                }
+            }
+            EntryData::HardLink(_) => {
+                if let Some(original) = original {
+                    fs::hard_link(original, &target)?;
+                }
+            }
+            EntryData::File(reader) => {
+                // `create_new` guarantees we never follow a link that appeared after `claim`
+                let mut file = fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&target)?;
+
+                if let Err(err) = self.copy_contents(reader, &mut file) {
+                    drop(file);
+                    let _ = fs::remove_file(&target);
+                    return Err(err.into());
+                }
+
+                #[cfg(unix)]
+                if let Some(mode) = mode {
+                    use std::os::unix::fs::PermissionsExt;
+                    file.set_permissions(fs::Permissions::from_mode(mode & 0o777))?;
+                }
+                #[cfg(not(unix))]
+                let _ = mode;
+                if let Some(modified) = modified {
+                    file.set_modified(modified)?;
+                }
+            }
+        }
+
+        self.summary
+            .written
+            .push(target.to_string_lossy().to_string());
+        self.claimed.insert(relative, target);
+        Ok(())
+    }
+
+    fn copy_contents(&mut self, reader: &mut dyn Read, file: &mut fs::File) -> io::Result<()> {
+        loop {
+            if self.token.checkpoint() {
+                return Err(io::Error::other("extraction cancelled"));
+            }
+            let read = match reader.read(&mut self.buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            file.write_all(&self.buffer[..read])?;
+            self.summary.bytes_written += read as u64;
+            self.progress.bytes_done = match &self.input_counter {
+                Some(counter) => counter.get(),
+                None => self.progress.bytes_done + read as u64,
+            };
+            (self.on_progress)(&self.progress, false);
+        }
+        file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extractor<'a>(
+        destination: &Path,
+        policy: ConflictPolicy,
+        token: &'a CancellationToken,
+        on_progress: &'a mut dyn FnMut(&ArchiveProgress, bool),
+    ) -> Extractor<'a> {
+        Extractor::new(
+            ArchiveFormat::Tar,
+            Path::new("test.tar"),
+            destination,
+            policy,
+            0,
+            0,
+            token,
+            on_progress,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn sanitize_entry_name_keeps_plain_paths() {
+        assert_eq!(
+            sanitize_entry_name("a/./b//c.txt").unwrap(),
+            PathBuf::from("a/b/c.txt")
+        );
+        assert_eq!(
+            sanitize_entry_name("dir\\file").unwrap(),
+            PathBuf::from("dir/file")
+        );
+        assert_eq!(
+            sanitize_entry_name("folder/").unwrap(),
+            PathBuf::from("folder")
+        );
+    }
+
+    #[test]
+    fn sanitize_entry_name_rejects_escapes() {
+        for name in [
+            "/etc/passwd",
+            "\\windows",
+            "../outside",
+            "a/../../b",
+            "a/..",
+            "",
+            "./",
+            "a\0b",
+        ] {
+            assert!(sanitize_entry_name(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn sanitize_entry_name_rejects_drive_prefixes() {
+        assert!(sanitize_entry_name("C:/Windows").is_err());
+        assert!(sanitize_entry_name("a/C:b").is_err());
+    }
+
+    #[test]
+    fn symlink_stays_inside_allows_targets_within_the_destination() {
+        assert!(symlink_stays_inside(Path::new("link"), "file"));
+        assert!(symlink_stays_inside(Path::new("a/b/link"), "../c"));
+        assert!(symlink_stays_inside(Path::new("a/b/link"), "../../c/./d"));
+        assert!(symlink_stays_inside(Path::new("link"), "."));
+    }
+
+    #[test]
+    fn symlink_stays_inside_rejects_escapes() {
+        assert!(!symlink_stays_inside(Path::new("link"), "/etc"));
+        assert!(!symlink_stays_inside(Path::new("link"), ".."));
+        assert!(!symlink_stays_inside(Path::new("a/link"), "../../b"));
+        // Climbing out of a name that may itself be a link
+        assert!(!symlink_stays_inside(Path::new("esc"), "b/../outside"));
+        assert!(!symlink_stays_inside(Path::new("a/link"), "../x/../y"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn chained_links_cannot_escape() {
+        let temp = tempfile::tempdir().unwrap();
+        let destination = temp.path().join("out");
+        let token = CancellationToken::default();
+        let mut on_progress = |_: &ArchiveProgress, _: bool| {};
+        let mut extractor = extractor(&destination, ConflictPolicy::Skip, &token, &mut on_progress);
+        extractor.extract_entry("b", EntryData::Symlink(".".to_string()), None, None);
+        extractor.extract_entry(
+            "esc",
+            EntryData::Symlink("b/../outside".to_string()),
+            None,
+            None,
+        );
+        extractor.extract_entry("c", EntryData::Symlink("b/file".to_string()), None, None);
+        let summary = extractor.finish();
+
+        assert!(fs::symlink_metadata(destination.join("b")).is_ok());
+        assert!(fs::symlink_metadata(destination.join("esc")).is_err());
+        assert!(fs::symlink_metadata(destination.join("c")).is_ok());
+        assert_eq!(summary.failed.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_through_existing_links_are_rejected() {
+        let temp = tempfile::tempdir().unwrap();
+        let destination = temp.path().join("out");
+        fs::create_dir(&destination).unwrap();
+        std::os::unix::fs::symlink(temp.path(), destination.join("up")).unwrap();
+        let token = CancellationToken::default();
+        let mut on_progress = |_: &ArchiveProgress, _: bool| {};
+        let mut extractor = extractor(&destination, ConflictPolicy::Skip, &token, &mut on_progress);
+        extractor.extract_entry("x", EntryData::Symlink("up/secret".to_string()), None, None);
+        let summary = extractor.finish();
+
+        assert!(fs::symlink_metadata(destination.join("x")).is_err());
+        assert_eq!(summary.failed.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hard_links_follow_renamed_entries() {
+        let temp = tempfile::tempdir().unwrap();
+        let destination = temp.path().join("out");
+        fs::create_dir(&destination).unwrap();
+        fs::write(destination.join("data.txt"), "old").unwrap();
+        let token = CancellationToken::default();
+        let mut on_progress = |_: &ArchiveProgress, _: bool| {};
+        let mut extractor = extractor(
+            &destination,
+            ConflictPolicy::Rename,
+            &token,
+            &mut on_progress,
+        );
+        let mut content: &[u8] = b"new";
+        extractor.extract_entry("data.txt", EntryData::File(&mut content), None, None);
+        extractor.extract_entry(
+            "link.txt",
+            EntryData::HardLink("data.txt".to_string()),
+            None,
+            None,
+        );
+        extractor.extract_entry(
+            "missing.txt",
+            EntryData::HardLink("nowhere.txt".to_string()),
+            None,
+            None,
+        );
+        let summary = extractor.finish();
+
+        assert_eq!(
+            fs::read_to_string(destination.join("data.txt")).unwrap(),
+            "old"
+        );
+        assert_eq!(
+            fs::read_to_string(destination.join("data (1).txt")).unwrap(),
+            "new"
+        );
+        assert_eq!(
+            fs::read_to_string(destination.join("link.txt")).unwrap(),
+            "new"
+        );
+        assert!(fs::symlink_metadata(destination.join("missing.txt")).is_err());
+        assert_eq!(summary.failed.len(), 1);
+    }
+
+    #[test]
+    fn renamed_folders_keep_their_entries() {
+        let temp = tempfile::tempdir().unwrap();
+        let destination = temp.path().join("out");
+        fs::create_dir(&destination).unwrap();
+        fs::write(destination.join("docs"), "old").unwrap();
+        let token = CancellationToken::default();
+        let mut on_progress = |_: &ArchiveProgress, _: bool| {};
+        let mut extractor = extractor(
+            &destination,
+            ConflictPolicy::Rename,
+            &token,
+            &mut on_progress,
+        );
+        extractor.extract_entry("docs/", EntryData::Directory, None, None);
+        extractor.extract_entry("docs/guide/", EntryData::Directory, None, None);
+        let mut intro: &[u8] = b"intro";
+        extractor.extract_entry(
+            "docs/guide/intro.md",
+            EntryData::File(&mut intro),
+            None,
+            None,
+        );
+        let mut notes: &[u8] = b"notes";
+        extractor.extract_entry("docs/notes.txt", EntryData::File(&mut notes), None, None);
+        extractor.extract_entry(
+            "notes.txt",
+            EntryData::HardLink("docs/notes.txt".to_string()),
+            None,
+            None,
+        );
+        let summary = extractor.finish();
+
+        assert!(summary.failed.is_empty(), "{:?}", summary.failed);
+        assert_eq!(fs::read_to_string(destination.join("docs")).unwrap(), "old");
+        let renamed = destination.join("docs (1)");
+        assert_eq!(
+            fs::read_to_string(renamed.join("guide").join("intro.md")).unwrap(),
+            "intro"
+        );
+        assert_eq!(
+            fs::read_to_string(renamed.join("notes.txt")).unwrap(),
+            "notes"
+        );
+        assert_eq!(
+            fs::read_to_string(destination.join("notes.txt")).unwrap(),
+            "notes"
+        );
+        assert_eq!(summary.written.len(), 5);
+    }
+}
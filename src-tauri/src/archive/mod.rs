@@ -0,0 +1,894 @@
+// Native archive commands
+//
+// Everything in here streams between disk and the archive so that large
+// folders never have to pass through the webview the way JSZip did.
+
+mod browse;
+mod extract;
+mod sevenz_format;
+mod tar_format;
+mod zip_format;
+
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use tauri::{State, Window};
+use walkdir::WalkDir;
+
+use crate::job::{self, Job, JobKind, JobProgress, Jobs};
+use crate::operation::{CancellationToken, ProgressEmitter};
+use crate::{log_error, log_info, CommandError, OhMyFSError};
+
+pub(crate) use browse::{
+    normalize_entry_name, read_archive_directory, split_archive_path, virtual_entries, virtual_path,
+};
+
+pub const PROGRESS_EVENT: &str = "archive://progress";
+
+pub(crate) const COPY_BUFFER_SIZE: usize = 256 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+    SevenZ,
+}
+
+impl ArchiveFormat {
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        let format = if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            ArchiveFormat::TarGz
+        } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+            ArchiveFormat::TarXz
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            ArchiveFormat::TarZst
+        } else if name.ends_with(".tar") {
+            ArchiveFormat::Tar
+        } else if [".zip", ".jar", ".war", ".ear"]
+            .iter()
+            .any(|ext| name.ends_with(ext))
+        {
+            ArchiveFormat::Zip
+        } else if name.ends_with(".7z") {
+            ArchiveFormat::SevenZ
+        } else {
+            return None;
+        };
+        Some(format)
+    }
+
+    // Compressed streams are assumed to wrap a tarball, which is the only
+    // thing we know how to unpack from them
+    pub fn from_magic(path: &Path) -> io::Result<Option<Self>> {
+        let mut header = Vec::with_capacity(512);
+        fs::File::open(path)?.take(512).read_to_end(&mut header)?;
+
+        let format = if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+            ArchiveFormat::Zip
+        } else if header.starts_with(&[0x37, 0x7A, 0xBC, 0xAF, 0x27, 0x1C]) {
+            ArchiveFormat::SevenZ
+        } else if header.starts_with(&[0x1F, 0x8B]) {
+            ArchiveFormat::TarGz
+        } else if header.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
+            ArchiveFormat::TarXz
+        } else if header.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+            ArchiveFormat::TarZst
+        } else if header.get(257..262) == Some(b"ustar".as_slice()) {
+            ArchiveFormat::Tar
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(format))
+    }
+
+    // Existing archives are identified by content first so that misnamed
+    // downloads still open; the extension is only a fallback
+    pub fn detect(path: &Path) -> Option<Self> {
+        Self::from_magic(path)
+            .ok()
+            .flatten()
+            .or_else(|| Self::from_extension(path))
+    }
+
+    pub fn can_create(self) -> bool {
+        self != ArchiveFormat::SevenZ
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveOperation {
+    Compress,
+    Extract,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArchiveProgress {
+    pub operation: ArchiveOperation,
+    pub archive_path: String,
+    pub current_file: Option<String>,
+    pub entries_done: u64,
+    // Zero when the format cannot tell up front (tar streams)
+    pub entries_total: u64,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+}
+
+impl JobProgress for ArchiveProgress {
+    fn apply(&self, job: &mut Job) {
+        job.current_file = self.current_file.clone();
+        job.entries_done = self.entries_done;
+        job.entries_total = self.entries_total;
+        job.bytes_done = self.bytes_done;
+        job.bytes_total = self.bytes_total;
+    }
+}
+
+// Encryption scheme for password protected ZIP archives
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ZipEncryption {
+    // WinZip AE-2
+    #[default]
+    Aes256,
+    // Legacy PKWARE scheme; weak, but opens in every unzip tool
+    ZipCrypto,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CompressionOptions {
+    // Defaults to the output extension, then ZIP
+    pub format: Option<ArchiveFormat>,
+    // 0-9, 0 stores entries without compression
+    pub level: Option<u32>,
+    pub comment: Option<String>,
+    // Only ZIP archives can be encrypted
+    pub password: Option<String>,
+    pub encryption: Option<ZipEncryption>,
+    // Id for the job running this compression, generated when missing
+    pub job_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CompressionSummary {
+    pub archive_path: String,
+    pub format: ArchiveFormat,
+    pub entries_written: u64,
+    pub bytes_read: u64,
+    pub archive_size: u64,
+    // A cancelled compression removes the partial archive
+    pub cancelled: bool,
+    pub failed: Vec<OhMyFSError>,
+}
+
+// What to do when an extracted entry already exists at the destination
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    Overwrite,
+    #[default]
+    Skip,
+    // Keep both, writing the new entry as "name (1).ext"
+    Rename,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ExtractionOptions {
+    pub conflict: Option<ConflictPolicy>,
+    pub password: Option<String>,
+    // Id for the job running this extraction, generated when missing
+    pub job_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExtractionSummary {
+    pub format: ArchiveFormat,
+    pub destination: String,
+    // Paths created on disk, including renamed ones
+    pub written: Vec<String>,
+    // Existing paths left untouched by the `skip` policy
+    pub skipped: Vec<String>,
+    pub bytes_written: u64,
+    pub cancelled: bool,
+    pub failed: Vec<OhMyFSError>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArchiveEntryInfo {
+    pub name: String,
+    pub size: u64,
+    // Only known for formats that compress entries individually
+    pub compressed_size: Option<u64>,
+    pub is_directory: bool,
+    pub is_symlink: bool,
+    // Extracting this entry needs a password
+    pub is_encrypted: bool,
+    pub modified_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ArchiveListing {
+    pub path: String,
+    pub format: ArchiveFormat,
+    pub entries: Vec<ArchiveEntryInfo>,
+    pub total_size: u64,
+    pub compressed_size: u64,
+    pub file_count: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SourceKind {
+    Directory,
+    File,
+    Symlink,
+}
+
+// A filesystem entry scheduled to be written into an archive
+#[derive(Debug)]
+pub(crate) struct SourceEntry {
+    pub path: PathBuf,
+    // Archive-relative name, always using `/` as separator
+    pub name: String,
+    pub kind: SourceKind,
+    pub size: u64,
+}
+
+// Walks every requested path recursively. Entries that cannot be read are
+// recorded in `failed` instead of aborting the whole walk.
+pub(crate) fn collect_sources(paths: &[String], failed: &mut Vec<OhMyFSError>) -> Vec<SourceEntry> {
+    let mut sources = Vec::new();
+
+    for root in paths {
+        let root_path = Path::new(root);
+        let base = root_path.parent().unwrap_or_else(|| Path::new(""));
+
+        for entry in WalkDir::new(root_path).follow_links(false) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    let path = err
+                        .path()
+                        .map(|p| p.to_string_lossy().to_string())
+                        .unwrap_or_else(|| root.clone());
+                    log_error!("Failed to walk {}: {}", path, err);
+                    failed.push(OhMyFSError::ArchiveEntryFailed {
+                        path,
+                        details: err.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            let file_type = entry.file_type();
+            let kind = if file_type.is_dir() {
+                SourceKind::Directory
+            } else if file_type.is_symlink() {
+                SourceKind::Symlink
+            } else if file_type.is_file() {
+                SourceKind::File
+            } else {
+                // Sockets, FIFOs and device nodes have no meaningful archive representation
+                continue;
+            };
+
+            let size = match kind {
+                SourceKind::File => entry.metadata().map(|m| m.len()).unwrap_or(0),
+                _ => 0,
+            };
+
+            let relative = entry.path().strip_prefix(base).unwrap_or(entry.path());
+            let name = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            sources.push(SourceEntry {
+                path: entry.into_path(),
+                name,
+                kind,
+                size,
+            });
+        }
+    }
+
+    sources
+}
+
+// Distinguishes problems with a single source entry, which are reported and
+// skipped, from problems with the archive itself, which abort the operation
+pub(crate) enum WriteError {
+    Source(String),
+    // Reading a source file failed part way through its entry
+    Read(String),
+    Archive(String),
+}
+
+// Implemented by every format that can be created
+pub(crate) trait ArchiveWriter {
+    fn add_entry(
+        &mut self,
+        source: &SourceEntry,
+        token: &CancellationToken,
+        progress: &mut ArchiveProgress,
+        on_progress: &mut dyn FnMut(&ArchiveProgress, bool),
+    ) -> Result<(), WriteError>;
+
+    fn finish(self: Box<Self>) -> Result<(), String>;
+}
+
+// Counts the bytes pulled out of an archive file, which is the only honest
+// progress measure for compressed streams of unknown uncompressed size
+pub(crate) struct CountingReader<R> {
+    inner: R,
+    count: Rc<Cell<u64>>,
+}
+
+impl<R> CountingReader<R> {
+    pub fn new(inner: R) -> (Self, Rc<Cell<u64>>) {
+        let count = Rc::new(Cell::new(0));
+        (
+            Self {
+                inner,
+                count: count.clone(),
+            },
+            count,
+        )
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.set(self.count.get() + read as u64);
+        Ok(read)
+    }
+}
+
+// Copies `reader` into the archive chunk by chunk, reporting bytes as they go
+pub(crate) fn copy_with_progress(
+    reader: &mut dyn Read,
+    writer: &mut dyn io::Write,
+    buffer: &mut [u8],
+    progress: &mut ArchiveProgress,
+    on_progress: &mut dyn FnMut(&ArchiveProgress, bool),
+) -> io::Result<u64> {
+    let mut copied = 0;
+    loop {
+        let read = match reader.read(buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        writer.write_all(&buffer[..read])?;
+        copied += read as u64;
+        progress.bytes_done += read as u64;
+        on_progress(progress, false);
+    }
+    Ok(copied)
+}
+
+fn unsupported(path: &Path) -> OhMyFSError {
+    OhMyFSError::UnsupportedArchiveFormat {
+        path: path.to_string_lossy().to_string(),
+    }
+}
+
+pub(crate) fn compress(
+    paths: &[String],
+    output: &Path,
+    options: &CompressionOptions,
+    token: &CancellationToken,
+    on_progress: &mut dyn FnMut(&ArchiveProgress, bool),
+) -> Result<CompressionSummary, OhMyFSError> {
+    let format = options
+        .format
+        .or_else(|| ArchiveFormat::from_extension(output))
+        .unwrap_or(ArchiveFormat::Zip);
+    if !format.can_create() {
+        return Err(unsupported(output));
+    }
+
+    let archive_path = output.to_string_lossy().to_string();
+    let archive_error = |details: String| OhMyFSError::ArchiveWriteFailed {
+        path: archive_path.clone(),
+        details,
+    };
+
+    if options
+        .password
+        .as_ref()
+        .is_some_and(|password| !password.is_empty())
+        && format != ArchiveFormat::Zip
+    {
+        return Err(archive_error(
+            "password protection is only supported for ZIP archives".to_string(),
+        ));
+    }
+
+    let file = fs::File::create(output).map_err(|err| archive_error(err.to_string()))?;
+    let canonical_output = fs::canonicalize(output).ok();
+
+    let mut failed = Vec::new();
+    let sources: Vec<SourceEntry> = collect_sources(paths, &mut failed)
+        .into_iter()
+        // Never try to pack the archive we are currently writing
+        .filter(|source| {
+            source.kind != SourceKind::File
+                || canonical_output.as_deref() != fs::canonicalize(&source.path).ok().as_deref()
+        })
+        .collect();
+
+    let mut progress = ArchiveProgress {
+        operation: ArchiveOperation::Compress,
+        archive_path: archive_path.clone(),
+        current_file: None,
+        entries_done: 0,
+        entries_total: sources.len() as u64,
+        bytes_done: 0,
+        bytes_total: sources.iter().map(|source| source.size).sum(),
+    };
+    on_progress(&progress, true);
+
+    let level = options.level.unwrap_or(6).min(9);
+    let writer = match format {
+        ArchiveFormat::Zip => zip_format::writer(file, level, options),
+        ArchiveFormat::SevenZ => unreachable!("7z creation is rejected above"),
+        tar => tar_format::writer(file, tar, level),
+    };
+    let mut writer = match writer {
+        Ok(writer) => writer,
+        Err(details) => {
+            let _ = fs::remove_file(output);
+            return Err(archive_error(details));
+        }
+    };
+
+    let mut entries_written = 0;
+    let mut cancelled = false;
+    for source in &sources {
+        if token.checkpoint() {
+            cancelled = true;
+            break;
+        }
+        progress.current_file = Some(source.name.clone());
+        on_progress(&progress, false);
+
+        match writer.add_entry(source, token, &mut progress, on_progress) {
+            Ok(()) => entries_written += 1,
+            // Writers stop reading half way through an entry once cancelled
+            Err(_) if token.is_cancelled() => {
+                cancelled = true;
+                break;
+            }
+            Err(WriteError::Source(details)) => {
+                log_error!(
+                    "Failed to add {} to archive: {}",
+                    source.path.display(),
+                    details
+                );
+                failed.push(OhMyFSError::ArchiveEntryFailed {
+                    path: source.path.to_string_lossy().to_string(),
+                    details,
+                });
+            }
+            Err(WriteError::Read(details)) => {
+                log_error!("Failed to read {}: {}", source.path.display(), details);
+                failed.push(OhMyFSError::FileReadFailed {
+                    path: source.path.to_string_lossy().to_string(),
+                    details,
+                });
+            }
+            Err(WriteError::Archive(details)) => {
+                drop(writer);
+                let _ = fs::remove_file(output);
+                return Err(archive_error(details));
+            }
+        }
+
+        progress.entries_done += 1;
+    }
+
+    if cancelled {
+        // A truncated archive is of no use to anyone
+        drop(writer);
+        let _ = fs::remove_file(output);
+    } else if let Err(details) = writer.finish() {
+        let _ = fs::remove_file(output);
+        return Err(archive_error(details));
+    }
+
+    progress.current_file = None;
+    on_progress(&progress, true);
+
+    Ok(CompressionSummary {
+        archive_path,
+        format,
+        entries_written,
+        bytes_read: progress.bytes_done,
+        archive_size: fs::metadata(output).map(|m| m.len()).unwrap_or(0),
+        cancelled,
+        failed,
+    })
+}
+
+pub(crate) fn extract(
+    archive: &Path,
+    destination: &Path,
+    options: &ExtractionOptions,
+    token: &CancellationToken,
+    on_progress: &mut dyn FnMut(&ArchiveProgress, bool),
+) -> Result<ExtractionSummary, OhMyFSError> {
+    match ArchiveFormat::detect(archive).ok_or_else(|| unsupported(archive))? {
+        ArchiveFormat::Zip => {
+            zip_format::extract(archive, destination, options, token, on_progress)
+        }
+        ArchiveFormat::SevenZ => {
+            sevenz_format::extract(archive, destination, options, token, on_progress)
+        }
+        tar => tar_format::extract(archive, tar, destination, options, token, on_progress),
+    }
+}
+
+pub(crate) fn list(archive: &Path) -> Result<ArchiveListing, OhMyFSError> {
+    let format = ArchiveFormat::detect(archive).ok_or_else(|| unsupported(archive))?;
+    let read_error = |details: String| OhMyFSError::ArchiveReadFailed {
+        path: archive.to_string_lossy().to_string(),
+        details,
+    };
+
+    let entries = match format {
+        ArchiveFormat::Zip => zip_format::list(archive),
+        ArchiveFormat::SevenZ => sevenz_format::list(archive),
+        tar => tar_format::list(archive, tar),
+    }
+    .map_err(read_error)?;
+
+    let archive_size = fs::metadata(archive).map(|m| m.len()).unwrap_or(0);
+    Ok(ArchiveListing {
+        path: archive.to_string_lossy().to_string(),
+        format,
+        total_size: entries.iter().map(|entry| entry.size).sum(),
+        compressed_size: archive_size,
+        file_count: entries.iter().filter(|entry| !entry.is_directory).count() as u64,
+        entries,
+    })
+}
+
+// Streams the regular files inside an archive without extracting it.
+// Encrypted entries are left out since there is no password to ask for.
+pub(crate) fn read_files(
+    archive: &Path,
+    token: &CancellationToken,
+    visit: &mut dyn FnMut(&ArchiveEntryInfo, &mut dyn Read),
+) -> Result<(), OhMyFSError> {
+    match ArchiveFormat::detect(archive).ok_or_else(|| unsupported(archive))? {
+        ArchiveFormat::Zip => zip_format::read_files(archive, token, visit),
+        ArchiveFormat::SevenZ => sevenz_format::read_files(archive, token, visit),
+        tar => tar_format::read_files(archive, tar, token, visit),
+    }
+    .map_err(|details| OhMyFSError::ArchiveReadFailed {
+        path: archive.to_string_lossy().to_string(),
+        details,
+    })
+}
+
+#[tauri::command]
+pub async fn compress_paths(
+    window: Window,
+    jobs: State<'_, Jobs>,
+    paths: Vec<String>,
+    output_path: String,
+    options: Option<CompressionOptions>,
+) -> Result<CompressionSummary, String> {
+    let mut options = options.unwrap_or_default();
+    let job = jobs.create(
+        &window,
+        options.job_id.take(),
+        JobKind::Compress,
+        paths.clone(),
+        Some(output_path.clone()),
+    );
+    log_info!("Compressing {} path(s) into {}", paths.len(), output_path);
+
+    job::run(job, move |job| {
+        let token = job.token().clone();
+        let mut emitter = ProgressEmitter::new(&window, PROGRESS_EVENT);
+        compress(
+            &paths,
+            Path::new(&output_path),
+            &options,
+            &token,
+            &mut |progress, force| {
+                emitter.emit(progress, force);
+                job.report(progress, force);
+            },
+        )
+        .map_err(|err| {
+            log_error!("Compression into {} failed: {}", output_path, err);
+            err.to_string()
+        })
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn extract_archive(
+    window: Window,
+    jobs: State<'_, Jobs>,
+    archive_path: String,
+    output_dir: String,
+    options: Option<ExtractionOptions>,
+) -> Result<ExtractionSummary, CommandError> {
+    let mut options = options.unwrap_or_default();
+    let job = jobs.create(
+        &window,
+        options.job_id.take(),
+        JobKind::Extract,
+        vec![archive_path.clone()],
+        Some(output_dir.clone()),
+    );
+    log_info!("Extracting {} into {}", archive_path, output_dir);
+
+    job::run(job, move |job| {
+        let token = job.token().clone();
+        let mut emitter = ProgressEmitter::new(&window, PROGRESS_EVENT);
+        extract(
+            Path::new(&archive_path),
+            Path::new(&output_dir),
+            &options,
+            &token,
+            &mut |progress, force| {
+                emitter.emit(progress, force);
+                job.report(progress, force);
+            },
+        )
+        .map_err(|err| {
+            log_error!("Extraction of {} failed: {}", archive_path, err);
+            CommandError::from(err)
+        })
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn list_archive(archive_path: String) -> Result<ArchiveListing, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        list(Path::new(&archive_path)).map_err(|err| {
+            log_error!("Failed to list {}: {}", archive_path, err);
+            err.to_string()
+        })
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `project/README.md`, `project/src/main.rs` and the empty `project/empty`
+    fn write_project(folder: &Path) -> PathBuf {
+        let project = folder.join("project");
+        fs::create_dir_all(project.join("src")).unwrap();
+        fs::create_dir(project.join("empty")).unwrap();
+        fs::write(project.join("README.md"), "# Project\n").unwrap();
+        fs::write(project.join("src").join("main.rs"), "fn main() {}\n").unwrap();
+        project
+    }
+
+    fn listed_names(archive: &Path) -> Vec<String> {
+        let mut names = list(archive)
+            .unwrap()
+            .entries
+            .iter()
+            .map(|entry| normalize_entry_name(&entry.name))
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    fn extract_into(archive: &Path, destination: &Path) -> ExtractionSummary {
+        extract(
+            archive,
+            destination,
+            &ExtractionOptions::default(),
+            &CancellationToken::default(),
+            &mut |_: &ArchiveProgress, _: bool| {},
+        )
+        .unwrap()
+    }
+
+    fn round_trip(format: ArchiveFormat, file_name: &str) {
+        let temp = tempfile::tempdir().unwrap();
+        let project = write_project(temp.path());
+        let archive = temp.path().join(file_name);
+
+        let summary = compress(
+            &[project.to_string_lossy().to_string()],
+            &archive,
+            &CompressionOptions::default(),
+            &CancellationToken::default(),
+            &mut |_: &ArchiveProgress, _: bool| {},
+        )
+        .unwrap();
+        assert_eq!(summary.format, format);
+        assert_eq!(summary.entries_written, 5);
+        assert!(summary.failed.is_empty());
+        assert!(!summary.cancelled);
+
+        assert_eq!(
+            listed_names(&archive),
+            [
+                "project",
+                "project/README.md",
+                "project/empty",
+                "project/src",
+                "project/src/main.rs",
+            ]
+        );
+        let listing = list(&archive).unwrap();
+        assert_eq!(listing.format, format);
+        assert_eq!(listing.file_count, 2);
+        assert_eq!(listing.total_size, 23);
+
+        let destination = temp.path().join("out");
+        let extracted = extract_into(&archive, &destination);
+        assert!(extracted.failed.is_empty());
+        assert_eq!(
+            fs::read_to_string(destination.join("project").join("README.md")).unwrap(),
+            "# Project\n"
+        );
+        assert_eq!(
+            fs::read_to_string(destination.join("project").join("src").join("main.rs")).unwrap(),
+            "fn main() {}\n"
+        );
+        assert!(destination.join("project").join("empty").is_dir());
+
+        // Content alone identifies the format once the name gives nothing away
+        let renamed = temp.path().join("download.bin");
+        fs::rename(&archive, &renamed).unwrap();
+        assert_eq!(ArchiveFormat::from_magic(&renamed).unwrap(), Some(format));
+        assert_eq!(ArchiveFormat::detect(&renamed), Some(format));
+    }
+
+    #[test]
+    fn zip_round_trip() {
+        round_trip(ArchiveFormat::Zip, "project.zip");
+    }
+
+    #[test]
+    fn tar_round_trip() {
+        round_trip(ArchiveFormat::Tar, "project.tar");
+    }
+
+    #[test]
+    fn tar_gz_round_trip() {
+        round_trip(ArchiveFormat::TarGz, "project.tgz");
+    }
+
+    #[test]
+    fn tar_xz_round_trip() {
+        round_trip(ArchiveFormat::TarXz, "project.tar.xz");
+    }
+
+    #[test]
+    fn tar_zst_round_trip() {
+        round_trip(ArchiveFormat::TarZst, "project.tar.zst");
+    }
+
+    #[test]
+    fn seven_z_archives_are_listed_and_extracted() {
+        let temp = tempfile::tempdir().unwrap();
+        let project = write_project(temp.path());
+        let archive = temp.path().join("project.7z");
+        let mut writer = sevenz_rust::SevenZWriter::create(&archive).unwrap();
+        for (name, path) in [
+            ("project", project.clone()),
+            ("project/README.md", project.join("README.md")),
+            ("project/empty", project.join("empty")),
+            ("project/src", project.join("src")),
+            ("project/src/main.rs", project.join("src").join("main.rs")),
+        ] {
+            let entry = sevenz_rust::SevenZArchiveEntry::from_path(&path, name.to_string());
+            let contents = path.is_file().then(|| fs::File::open(&path).unwrap());
+            writer.push_archive_entry(entry, contents).unwrap();
+        }
+        writer.finish().unwrap();
+
+        assert_eq!(
+            ArchiveFormat::from_magic(&archive).unwrap(),
+            Some(ArchiveFormat::SevenZ)
+        );
+        assert!(!ArchiveFormat::SevenZ.can_create());
+        assert_eq!(
+            listed_names(&archive),
+            [
+                "project",
+                "project/README.md",
+                "project/empty",
+                "project/src",
+                "project/src/main.rs",
+            ]
+        );
+        assert_eq!(list(&archive).unwrap().file_count, 2);
+
+        let destination = temp.path().join("out");
+        assert!(extract_into(&archive, &destination).failed.is_empty());
+        assert_eq!(
+            fs::read_to_string(destination.join("project").join("src").join("main.rs")).unwrap(),
+            "fn main() {}\n"
+        );
+        assert!(destination.join("project").join("empty").is_dir());
+    }
+
+    #[test]
+    fn other_files_are_not_archives() {
+        let temp = tempfile::tempdir().unwrap();
+        let text = temp.path().join("notes.zip");
+        fs::write(&text, "not an archive").unwrap();
+        assert_eq!(ArchiveFormat::from_magic(&text).unwrap(), None);
+        // The extension is the fallback
+        assert_eq!(ArchiveFormat::detect(&text), Some(ArchiveFormat::Zip));
+        assert!(list(&text).is_err());
+
+        let empty = temp.path().join("empty");
+        fs::write(&empty, "").unwrap();
+        assert_eq!(ArchiveFormat::from_magic(&empty).unwrap(), None);
+        assert!(matches!(
+            list(&empty),
+            Err(OhMyFSError::UnsupportedArchiveFormat { .. })
+        ));
+        assert!(ArchiveFormat::from_magic(&temp.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn formats_from_extensions() {
+        for (name, format) in [
+            ("a.ZIP", Some(ArchiveFormat::Zip)),
+            ("a.jar", Some(ArchiveFormat::Zip)),
+            ("a.tar", Some(ArchiveFormat::Tar)),
+            ("a.tar.gz", Some(ArchiveFormat::TarGz)),
+            ("a.txz", Some(ArchiveFormat::TarXz)),
+            ("a.tzst", Some(ArchiveFormat::TarZst)),
+            ("a.7z", Some(ArchiveFormat::SevenZ)),
+            ("a.gz", None),
+            ("a.txt", None),
+        ] {
+            assert_eq!(
+                ArchiveFormat::from_extension(Path::new(name)),
+                format,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn seven_z_archives_cannot_be_created() {
+        let temp = tempfile::tempdir().unwrap();
+        let project = write_project(temp.path());
+        let archive = temp.path().join("project.7z");
+        let created = compress(
+            &[project.to_string_lossy().to_string()],
+            &archive,
+            &CompressionOptions::default(),
+            &CancellationToken::default(),
+            &mut |_: &ArchiveProgress, _: bool| {},
+        );
+        assert!(matches!(
+            created,
+            Err(OhMyFSError::UnsupportedArchiveFormat { .. })
+        ));
+        assert!(!archive.exists());
+    }
+}
@@ -0,0 +1,149 @@
+// 7z backend for the archive commands. Creation is not supported.
+
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::SystemTime;
+
+use sevenz_rust::{Password, SevenZArchiveEntry, SevenZReader};
+
+use super::extract::{EntryData, Extractor};
+use super::{
+    ArchiveEntryInfo, ArchiveFormat, ArchiveProgress, ExtractionOptions, ExtractionSummary,
+};
+use crate::operation::CancellationToken;
+use crate::{to_epoch_millis, OhMyFSError};
+
+// p7zip stores the unix mode in the upper 16 bits of the attributes when this bit is set
+const UNIX_EXTENSION: u32 = 0x8000;
+
+fn unix_mode(entry: &SevenZArchiveEntry) -> Option<u32> {
+    let attributes = entry.windows_attributes();
+    (entry.has_windows_attributes && attributes & UNIX_EXTENSION != 0).then_some(attributes >> 16)
+}
+
+fn is_symlink(entry: &SevenZArchiveEntry) -> bool {
+    unix_mode(entry).is_some_and(|mode| mode & 0o170000 == 0o120000)
+}
+
+fn modified(entry: &SevenZArchiveEntry) -> Option<SystemTime> {
+    entry
+        .has_last_modified_date
+        .then(|| SystemTime::from(entry.last_modified_date()))
+}
+
+pub(crate) fn extract(
+    archive: &Path,
+    destination: &Path,
+    options: &ExtractionOptions,
+    token: &CancellationToken,
+    on_progress: &mut dyn FnMut(&ArchiveProgress, bool),
+) -> Result<ExtractionSummary, OhMyFSError> {
+    let read_error = |details: String| OhMyFSError::ArchiveReadFailed {
+        path: archive.to_string_lossy().to_string(),
+        details,
+    };
+
+    let mut reader = SevenZReader::open(archive, Password::empty())
+        .map_err(|err| read_error(err.to_string()))?;
+    let files = &reader.archive().files;
+    let entries_total = files.len() as u64;
+    let bytes_total = files.iter().map(|entry| entry.size()).sum();
+
+    let mut extractor = Extractor::new(
+        ArchiveFormat::SevenZ,
+        archive,
+        destination,
+        options.conflict.unwrap_or_default(),
+        entries_total,
+        bytes_total,
+        token,
+        on_progress,
+    )?;
+
+    reader
+        .for_each_entries(|entry, data| {
+            if extractor.should_stop() {
+                return Ok(false);
+            }
+            // Anti-items mark deletions in update archives, there is nothing to write
+            if entry.is_anti_item() {
+                return Ok(true);
+            }
+
+            let name = entry.name();
+            let mode = unix_mode(entry);
+            if entry.is_directory() {
+                extractor.extract_entry(name, EntryData::Directory, mode, modified(entry));
+            } else if is_symlink(entry) {
+                let mut target = String::new();
+                match data.read_to_string(&mut target) {
+                    Ok(_) => extractor.extract_entry(
+                        name,
+                        EntryData::Symlink(target),
+                        mode,
+                        modified(entry),
+                    ),
+                    Err(err) => extractor.skip_entry(name, err.to_string()),
+                }
+            } else {
+                extractor.extract_entry(name, EntryData::File(data), mode, modified(entry));
+            }
+
+            if extractor.should_stop() {
+                return Ok(false);
+            }
+            // Solid blocks decode sequentially, so whatever a skipped entry left
+            // unread has to be consumed before the next one starts
+            io::copy(data, &mut io::sink())?;
+            Ok(true)
+        })
+        .map_err(|err| read_error(err.to_string()))?;
+
+    Ok(extractor.finish())
+}
+
+fn entry_info(entry: &SevenZArchiveEntry) -> ArchiveEntryInfo {
+    ArchiveEntryInfo {
+        name: entry.name().to_string(),
+        size: entry.size(),
+        // Solid compression makes per-entry sizes meaningless
+        compressed_size: None,
+        is_directory: entry.is_directory(),
+        is_symlink: is_symlink(entry),
+        is_encrypted: false,
+        modified_at: modified(entry).map(to_epoch_millis),
+    }
+}
+
+pub(crate) fn list(archive: &Path) -> Result<Vec<ArchiveEntryInfo>, String> {
+    let archive = sevenz_rust::Archive::open(archive).map_err(|err| err.to_string())?;
+
+    Ok(archive
+        .files
+        .iter()
+        .filter(|entry| !entry.is_anti_item())
+        .map(entry_info)
+        .collect())
+}
+
+pub(crate) fn read_files(
+    archive: &Path,
+    token: &CancellationToken,
+    visit: &mut dyn FnMut(&ArchiveEntryInfo, &mut dyn Read),
+) -> Result<(), String> {
+    let mut reader =
+        SevenZReader::open(archive, Password::empty()).map_err(|err| err.to_string())?;
+    reader
+        .for_each_entries(|entry, data| {
+            if token.is_cancelled() {
+                return Ok(false);
+            }
+            if !(entry.is_anti_item() || entry.is_directory() || is_symlink(entry)) {
+                visit(&entry_info(entry), data);
+            }
+            // Solid blocks decode sequentially, see `extract`
+            io::copy(data, &mut io::sink())?;
+            Ok(true)
+        })
+        .map_err(|err| err.to_string())
+}
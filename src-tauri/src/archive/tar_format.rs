@@ -0,0 +1,365 @@
+// Tarball backend (plain, gzip, xz and zstd) for the archive commands
+
+use std::cell::Cell;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use tar::{Builder, EntryType, Header};
+use xz2::read::XzDecoder;
+use xz2::write::XzEncoder;
+
+use super::extract::{EntryData, Extractor};
+use super::{
+    ArchiveEntryInfo, ArchiveFormat, ArchiveProgress, ArchiveWriter, CountingReader,
+    ExtractionOptions, ExtractionSummary, SourceEntry, SourceKind, WriteError,
+};
+use crate::operation::CancellationToken;
+use crate::{to_epoch_millis, OhMyFSError};
+
+// Maps the 0-9 scale shared with ZIP onto zstd's 1-19
+fn zstd_level(level: u32) -> i32 {
+    1 + level as i32 * 2
+}
+
+enum TarSink {
+    Plain(BufWriter<fs::File>),
+    Gz(GzEncoder<BufWriter<fs::File>>),
+    Xz(XzEncoder<BufWriter<fs::File>>),
+    Zst(zstd::Encoder<'static, BufWriter<fs::File>>),
+}
+
+impl TarSink {
+    fn finish(self) -> io::Result<()> {
+        match self {
+            TarSink::Plain(mut writer) => writer.flush(),
+            TarSink::Gz(encoder) => encoder.finish()?.flush(),
+            TarSink::Xz(encoder) => encoder.finish()?.flush(),
+            TarSink::Zst(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for TarSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            TarSink::Plain(writer) => writer.write(buf),
+            TarSink::Gz(encoder) => encoder.write(buf),
+            TarSink::Xz(encoder) => encoder.write(buf),
+            TarSink::Zst(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            TarSink::Plain(writer) => writer.flush(),
+            TarSink::Gz(encoder) => encoder.flush(),
+            TarSink::Xz(encoder) => encoder.flush(),
+            TarSink::Zst(encoder) => encoder.flush(),
+        }
+    }
+}
+
+// Feeds a source file into the tar builder while reporting progress. Tar has
+// no way to drop a half-written entry and the header already promised `size`
+// bytes, so when the file fails or shrinks part way the rest is filled with
+// zeros. The archive stays readable and the caller reports the entry as
+// failed.
+struct ProgressReader<'a> {
+    inner: io::Take<fs::File>,
+    size: u64,
+    token: &'a CancellationToken,
+    progress: &'a mut ArchiveProgress,
+    on_progress: &'a mut dyn FnMut(&ArchiveProgress, bool),
+    copied: u64,
+    failed: Option<String>,
+}
+
+impl Read for ProgressReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.token.checkpoint() {
+            return Err(io::Error::other("compression cancelled"));
+        }
+        let read = if self.failed.is_some() {
+            0
+        } else {
+            match self.inner.read(buf) {
+                Ok(0) if self.copied < self.size => {
+                    self.failed = Some("the file shrank while being archived".to_string());
+                    0
+                }
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => return Err(err),
+                Err(err) => {
+                    self.failed = Some(err.to_string());
+                    0
+                }
+            }
+        };
+        let read = if self.failed.is_some() {
+            let padding = (self.size - self.copied).min(buf.len() as u64) as usize;
+            buf[..padding].fill(0);
+            padding
+        } else {
+            read
+        };
+        self.copied += read as u64;
+        self.progress.bytes_done += read as u64;
+        (self.on_progress)(self.progress, false);
+        Ok(read)
+    }
+}
+
+struct TarArchiveWriter {
+    builder: Builder<TarSink>,
+}
+
+pub(crate) fn writer(
+    file: fs::File,
+    format: ArchiveFormat,
+    level: u32,
+) -> Result<Box<dyn ArchiveWriter>, String> {
+    let output = BufWriter::new(file);
+    let sink = match format {
+        ArchiveFormat::Tar => TarSink::Plain(output),
+        ArchiveFormat::TarGz => {
+            TarSink::Gz(GzEncoder::new(output, flate2::Compression::new(level)))
+        }
+        ArchiveFormat::TarXz => TarSink::Xz(XzEncoder::new(output, level)),
+        ArchiveFormat::TarZst => TarSink::Zst(
+            zstd::Encoder::new(output, zstd_level(level)).map_err(|err| err.to_string())?,
+        ),
+        other => return Err(format!("{:?} is not a tar format", other)),
+    };
+
+    let mut builder = Builder::new(sink);
+    builder.follow_symlinks(false);
+    Ok(Box::new(TarArchiveWriter { builder }))
+}
+
+impl ArchiveWriter for TarArchiveWriter {
+    fn add_entry(
+        &mut self,
+        source: &SourceEntry,
+        token: &CancellationToken,
+        progress: &mut ArchiveProgress,
+        on_progress: &mut dyn FnMut(&ArchiveProgress, bool),
+    ) -> Result<(), WriteError> {
+        let metadata = fs::symlink_metadata(&source.path)
+            .map_err(|err| WriteError::Source(err.to_string()))?;
+        let mut header = Header::new_gnu();
+        header.set_metadata(&metadata);
+
+        match source.kind {
+            SourceKind::Directory => {
+                header.set_size(0);
+                self.builder
+                    .append_data(&mut header, format!("{}/", source.name), io::empty())
+                    .map_err(|err| WriteError::Archive(err.to_string()))
+            }
+            SourceKind::Symlink => {
+                let target = fs::read_link(&source.path)
+                    .map_err(|err| WriteError::Source(err.to_string()))?;
+                self.builder
+                    .append_link(&mut header, &source.name, target)
+                    .map_err(|err| WriteError::Archive(err.to_string()))
+            }
+            SourceKind::File => {
+                let file = fs::File::open(&source.path)
+                    .map_err(|err| WriteError::Source(err.to_string()))?;
+                let size = metadata.len();
+                let mut reader = ProgressReader {
+                    inner: file.take(size),
+                    size,
+                    token,
+                    progress,
+                    on_progress,
+                    copied: 0,
+                    failed: None,
+                };
+
+                let appended = self
+                    .builder
+                    .append_data(&mut header, &source.name, &mut reader);
+                appended.map_err(|err| WriteError::Archive(err.to_string()))?;
+                match reader.failed {
+                    Some(details) => Err(WriteError::Read(details)),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), String> {
+        self.builder
+            .into_inner()
+            .and_then(TarSink::finish)
+            .map_err(|err| err.to_string())
+    }
+}
+
+type TarStream = tar::Archive<Box<dyn Read>>;
+
+// Opens the archive and layers the right decompressor on top of it
+fn open_stream(archive: &Path, format: ArchiveFormat) -> io::Result<(TarStream, Rc<Cell<u64>>)> {
+    let file = fs::File::open(archive)?;
+    let (reader, counter) = CountingReader::new(BufReader::new(file));
+
+    let stream: Box<dyn Read> = match format {
+        ArchiveFormat::Tar => Box::new(reader),
+        ArchiveFormat::TarGz => Box::new(MultiGzDecoder::new(reader)),
+        ArchiveFormat::TarXz => Box::new(XzDecoder::new_multi_decoder(reader)),
+        ArchiveFormat::TarZst => Box::new(zstd::Decoder::new(reader)?),
+        other => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} is not a tar format", other),
+            ))
+        }
+    };
+
+    Ok((tar::Archive::new(stream), counter))
+}
+
+fn entry_mtime(header: &Header) -> Option<SystemTime> {
+    header
+        .mtime()
+        .ok()
+        .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+pub(crate) fn extract(
+    archive: &Path,
+    format: ArchiveFormat,
+    destination: &Path,
+    options: &ExtractionOptions,
+    token: &CancellationToken,
+    on_progress: &mut dyn FnMut(&ArchiveProgress, bool),
+) -> Result<ExtractionSummary, OhMyFSError> {
+    let read_error = |details: String| OhMyFSError::ArchiveReadFailed {
+        path: archive.to_string_lossy().to_string(),
+        details,
+    };
+
+    let archive_size = fs::metadata(archive)
+        .map(|m| m.len())
+        .map_err(|err| read_error(err.to_string()))?;
+    let (mut tar, counter) =
+        open_stream(archive, format).map_err(|err| read_error(err.to_string()))?;
+    let entries = tar.entries().map_err(|err| read_error(err.to_string()))?;
+
+    // Tar streams have no index, so progress follows the compressed bytes consumed
+    let mut extractor = Extractor::new(
+        format,
+        archive,
+        destination,
+        options.conflict.unwrap_or_default(),
+        0,
+        archive_size,
+        token,
+        on_progress,
+    )?;
+    extractor.track_input(counter);
+
+    for entry in entries {
+        if extractor.should_stop() {
+            break;
+        }
+        let mut entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                // A corrupt stream cannot be resynchronised; keep what was extracted so far
+                extractor.skip_entry(&archive.to_string_lossy(), err.to_string());
+                break;
+            }
+        };
+
+        let name = String::from_utf8_lossy(&entry.path_bytes()).to_string();
+        let entry_type = entry.header().entry_type();
+        let mode = entry.header().mode().ok();
+        let modified = entry_mtime(entry.header());
+        let link_name = entry
+            .link_name_bytes()
+            .map(|link| String::from_utf8_lossy(&link).to_string());
+
+        match (entry_type, link_name) {
+            (EntryType::Directory, _) => {
+                extractor.extract_entry(&name, EntryData::Directory, mode, modified)
+            }
+            (EntryType::Regular | EntryType::Continuous, _) => {
+                extractor.extract_entry(&name, EntryData::File(&mut entry), mode, modified)
+            }
+            (EntryType::Symlink, Some(target)) => {
+                extractor.extract_entry(&name, EntryData::Symlink(target), mode, modified)
+            }
+            (EntryType::Link, Some(original)) => {
+                extractor.extract_entry(&name, EntryData::HardLink(original), mode, modified)
+            }
+            // Metadata records are folded into the entries they describe
+            (EntryType::XGlobalHeader | EntryType::XHeader, _) => {}
+            (other, _) => {
+                extractor.skip_entry(&name, format!("unsupported tar entry type {:?}", other))
+            }
+        }
+    }
+
+    Ok(extractor.finish())
+}
+
+fn entry_info<R: Read>(entry: &tar::Entry<'_, R>) -> ArchiveEntryInfo {
+    let header = entry.header();
+    ArchiveEntryInfo {
+        name: String::from_utf8_lossy(&entry.path_bytes()).to_string(),
+        size: entry.size(),
+        compressed_size: None,
+        is_directory: header.entry_type().is_dir(),
+        is_symlink: header.entry_type().is_symlink(),
+        is_encrypted: false,
+        modified_at: entry_mtime(header).map(to_epoch_millis),
+    }
+}
+
+pub(crate) fn list(archive: &Path, format: ArchiveFormat) -> Result<Vec<ArchiveEntryInfo>, String> {
+    let (mut tar, _) = open_stream(archive, format).map_err(|err| err.to_string())?;
+
+    let mut entries = Vec::new();
+    for entry in tar.entries().map_err(|err| err.to_string())? {
+        let entry = entry.map_err(|err| err.to_string())?;
+        if matches!(
+            entry.header().entry_type(),
+            EntryType::XGlobalHeader | EntryType::XHeader
+        ) {
+            continue;
+        }
+        entries.push(entry_info(&entry));
+    }
+    Ok(entries)
+}
+
+pub(crate) fn read_files(
+    archive: &Path,
+    format: ArchiveFormat,
+    token: &CancellationToken,
+    visit: &mut dyn FnMut(&ArchiveEntryInfo, &mut dyn Read),
+) -> Result<(), String> {
+    let (mut tar, _) = open_stream(archive, format).map_err(|err| err.to_string())?;
+
+    for entry in tar.entries().map_err(|err| err.to_string())? {
+        if token.is_cancelled() {
+            break;
+        }
+        let mut entry = entry.map_err(|err| err.to_string())?;
+        if matches!(
+            entry.header().entry_type(),
+            EntryType::Regular | EntryType::Continuous
+        ) {
+            let info = entry_info(&entry);
+            visit(&info, &mut entry);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,445 @@
+// ZIP backend for the archive commands
+
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+use zip::result::ZipError;
+use zip::unstable::write::FileOptionsExt;
+use zip::write::SimpleFileOptions;
+use zip::{AesMode, CompressionMethod, ZipArchive, ZipWriter};
+
+use super::extract::{EntryData, Extractor};
+use super::{
+    copy_with_progress, ArchiveEntryInfo, ArchiveFormat, ArchiveProgress, ArchiveWriter,
+    CompressionOptions, ExtractionOptions, ExtractionSummary, SourceEntry, SourceKind, WriteError,
+    ZipEncryption, COPY_BUFFER_SIZE,
+};
+use crate::operation::CancellationToken;
+use crate::{to_epoch_millis, OhMyFSError};
+
+// Entries at or above this size need ZIP64 headers
+const ZIP64_THRESHOLD: u64 = u32::MAX as u64;
+
+fn to_zip_time(time: SystemTime) -> zip::DateTime {
+    let local = chrono::DateTime::<chrono::Local>::from(time).naive_local();
+    zip::DateTime::try_from(local).unwrap_or_default()
+}
+
+fn from_zip_time(time: zip::DateTime) -> Option<SystemTime> {
+    let naive = chrono::NaiveDateTime::try_from(time).ok()?;
+    naive
+        .and_local_timezone(chrono::Local)
+        .earliest()
+        .map(SystemTime::from)
+}
+
+fn entry_options(base: SimpleFileOptions, metadata: &fs::Metadata) -> SimpleFileOptions {
+    let mut options = base.large_file(metadata.len() >= ZIP64_THRESHOLD);
+    if let Ok(modified) = metadata.modified() {
+        options = options.last_modified_time(to_zip_time(modified));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        options = options.unix_permissions(metadata.permissions().mode());
+    }
+    options
+}
+
+struct ZipArchiveWriter {
+    writer: ZipWriter<BufWriter<fs::File>>,
+    base: SimpleFileOptions,
+    comment: Option<String>,
+    password: Option<(ZipEncryption, String)>,
+    buffer: Vec<u8>,
+}
+
+pub(crate) fn writer(
+    file: fs::File,
+    level: u32,
+    options: &CompressionOptions,
+) -> Result<Box<dyn ArchiveWriter>, String> {
+    let base = if level == 0 {
+        SimpleFileOptions::default().compression_method(CompressionMethod::Stored)
+    } else {
+        SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .compression_level(Some(i64::from(level)))
+    };
+
+    Ok(Box::new(ZipArchiveWriter {
+        writer: ZipWriter::new(BufWriter::new(file)),
+        base,
+        comment: options.comment.clone(),
+        password: options
+            .password
+            .clone()
+            .filter(|password| !password.is_empty())
+            .map(|password| (options.encryption.unwrap_or_default(), password)),
+        buffer: vec![0u8; COPY_BUFFER_SIZE],
+    }))
+}
+
+impl ArchiveWriter for ZipArchiveWriter {
+    fn add_entry(
+        &mut self,
+        source: &SourceEntry,
+        token: &CancellationToken,
+        progress: &mut ArchiveProgress,
+        on_progress: &mut dyn FnMut(&ArchiveProgress, bool),
+    ) -> Result<(), WriteError> {
+        let metadata = fs::symlink_metadata(&source.path)
+            .map_err(|err| WriteError::Source(err.to_string()))?;
+        let options = entry_options(self.base, &metadata);
+
+        match source.kind {
+            SourceKind::Directory => self
+                .writer
+                .add_directory(source.name.as_str(), options)
+                .map_err(|err| WriteError::Source(err.to_string())),
+            SourceKind::Symlink => {
+                let target = fs::read_link(&source.path)
+                    .map_err(|err| WriteError::Source(err.to_string()))?;
+                self.writer
+                    .add_symlink(
+                        source.name.as_str(),
+                        target.to_string_lossy().replace('\\', "/"),
+                        options,
+                    )
+                    .map_err(|err| WriteError::Archive(err.to_string()))
+            }
+            SourceKind::File => {
+                let mut file = fs::File::open(&source.path)
+                    .map_err(|err| WriteError::Source(err.to_string()))?;
+                // Only file contents are encrypted; names, folders and links stay readable
+                let options = match &self.password {
+                    Some((ZipEncryption::Aes256, password)) => {
+                        options.with_aes_encryption(AesMode::Aes256, password)
+                    }
+                    Some((ZipEncryption::ZipCrypto, password)) => {
+                        options.with_deprecated_encryption(password.as_bytes())
+                    }
+                    None => options,
+                };
+                self.writer
+                    .start_file(source.name.as_str(), options)
+                    .map_err(|err| WriteError::Source(err.to_string()))?;
+
+                let mut reader = SourceReader {
+                    inner: &mut file,
+                    token,
+                    failed: None,
+                };
+                let copied = copy_with_progress(
+                    &mut reader,
+                    &mut self.writer,
+                    &mut self.buffer,
+                    progress,
+                    on_progress,
+                );
+                match (copied, reader.failed) {
+                    (_, Some(details)) => {
+                        // Drop the half-written entry so the archive stays consistent
+                        self.writer
+                            .abort_file()
+                            .map_err(|err| WriteError::Archive(err.to_string()))?;
+                        Err(WriteError::Read(details))
+                    }
+                    (Err(err), None) => Err(WriteError::Archive(err.to_string())),
+                    (Ok(_), None) => Ok(()),
+                }
+            }
+        }
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        if let Some(comment) = self.comment.take() {
+            self.writer.set_comment(comment);
+        }
+        self.writer
+            .finish()
+            .map_err(|err| err.to_string())?
+            .flush()
+            .map_err(|err| err.to_string())
+    }
+}
+
+// Remembers read failures so they can be told apart from archive write
+// failures once the copy loop returns
+struct SourceReader<'a> {
+    inner: &'a mut fs::File,
+    token: &'a CancellationToken,
+    failed: Option<String>,
+}
+
+impl Read for SourceReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.token.checkpoint() {
+            return Err(io::Error::other("compression cancelled"));
+        }
+        self.inner.read(buf).inspect_err(|err| {
+            if err.kind() != io::ErrorKind::Interrupted {
+                self.failed = Some(err.to_string());
+            }
+        })
+    }
+}
+
+// Tries the password on the first encrypted file before anything is written,
+// so a wrong password fails the whole command and the UI can ask again.
+// The header check is not exhaustive; a rare false positive still surfaces as
+// a checksum failure on the affected entries.
+fn check_password<R: Read + io::Seek>(
+    zip: &mut ZipArchive<R>,
+    archive: &Path,
+    password: Option<&str>,
+) -> Result<(), OhMyFSError> {
+    let encrypted = (0..zip.len()).find(|&index| {
+        zip.by_index_raw(index)
+            .is_ok_and(|entry| entry.encrypted() && !entry.is_dir())
+    });
+    let Some(index) = encrypted else {
+        return Ok(());
+    };
+
+    let path = archive.to_string_lossy().to_string();
+    let Some(password) = password else {
+        return Err(OhMyFSError::ArchivePasswordRequired { path });
+    };
+    match zip.by_index_decrypt(index, password.as_bytes()) {
+        Err(ZipError::InvalidPassword) => Err(OhMyFSError::InvalidArchivePassword { path }),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn extract(
+    archive: &Path,
+    destination: &Path,
+    options: &ExtractionOptions,
+    token: &CancellationToken,
+    on_progress: &mut dyn FnMut(&ArchiveProgress, bool),
+) -> Result<ExtractionSummary, OhMyFSError> {
+    let read_error = |details: String| OhMyFSError::ArchiveReadFailed {
+        path: archive.to_string_lossy().to_string(),
+        details,
+    };
+
+    let file = fs::File::open(archive).map_err(|err| read_error(err.to_string()))?;
+    let mut zip =
+        ZipArchive::new(BufReader::new(file)).map_err(|err| read_error(err.to_string()))?;
+
+    let mut bytes_total = 0;
+    for index in 0..zip.len() {
+        if let Ok(entry) = zip.by_index_raw(index) {
+            bytes_total += entry.size();
+        }
+    }
+
+    let password = options
+        .password
+        .as_deref()
+        .filter(|password| !password.is_empty());
+    check_password(&mut zip, archive, password)?;
+
+    let mut extractor = Extractor::new(
+        ArchiveFormat::Zip,
+        archive,
+        destination,
+        options.conflict.unwrap_or_default(),
+        zip.len() as u64,
+        bytes_total,
+        token,
+        on_progress,
+    )?;
+
+    for index in 0..zip.len() {
+        if extractor.should_stop() {
+            break;
+        }
+        let name = zip.name_for_index(index).unwrap_or_default().to_string();
+        let entry = match password {
+            Some(password) => zip.by_index_decrypt(index, password.as_bytes()),
+            None => zip.by_index(index),
+        };
+        let mut entry = match entry {
+            Ok(entry) => entry,
+            Err(ZipError::InvalidPassword) => {
+                extractor.skip_entry(&name, "incorrect password".to_string());
+                continue;
+            }
+            Err(err) => {
+                extractor.skip_entry(&name, err.to_string());
+                continue;
+            }
+        };
+
+        let mode = entry.unix_mode();
+        let modified = entry.last_modified().and_then(from_zip_time);
+
+        if entry.is_dir() {
+            extractor.extract_entry(&name, EntryData::Directory, mode, modified);
+        } else if entry.is_symlink() {
+            let mut target = String::new();
+            match entry.read_to_string(&mut target) {
+                Ok(_) => extractor.extract_entry(&name, EntryData::Symlink(target), mode, modified),
+                Err(err) => extractor.skip_entry(&name, err.to_string()),
+            }
+        } else {
+            extractor.extract_entry(&name, EntryData::File(&mut entry), mode, modified);
+        }
+    }
+
+    Ok(extractor.finish())
+}
+
+fn entry_info(entry: &zip::read::ZipFile<'_>) -> ArchiveEntryInfo {
+    ArchiveEntryInfo {
+        name: entry.name().to_string(),
+        size: entry.size(),
+        compressed_size: Some(entry.compressed_size()),
+        is_directory: entry.is_dir(),
+        is_symlink: entry.is_symlink(),
+        is_encrypted: entry.encrypted(),
+        modified_at: entry
+            .last_modified()
+            .and_then(from_zip_time)
+            .map(to_epoch_millis),
+    }
+}
+
+pub(crate) fn list(archive: &Path) -> Result<Vec<ArchiveEntryInfo>, String> {
+    let file = fs::File::open(archive).map_err(|err| err.to_string())?;
+    let mut zip = ZipArchive::new(BufReader::new(file)).map_err(|err| err.to_string())?;
+
+    let mut entries = Vec::with_capacity(zip.len());
+    for index in 0..zip.len() {
+        // Raw access reads the central directory only, so encrypted entries list fine
+        let entry = zip.by_index_raw(index).map_err(|err| err.to_string())?;
+        entries.push(entry_info(&entry));
+    }
+    Ok(entries)
+}
+
+pub(crate) fn read_files(
+    archive: &Path,
+    token: &CancellationToken,
+    visit: &mut dyn FnMut(&ArchiveEntryInfo, &mut dyn Read),
+) -> Result<(), String> {
+    let file = fs::File::open(archive).map_err(|err| err.to_string())?;
+    let mut zip = ZipArchive::new(BufReader::new(file)).map_err(|err| err.to_string())?;
+
+    for index in 0..zip.len() {
+        if token.is_cancelled() {
+            break;
+        }
+        let info = entry_info(&zip.by_index_raw(index).map_err(|err| err.to_string())?);
+        if info.is_directory || info.is_symlink || info.is_encrypted {
+            continue;
+        }
+        // A single damaged entry should not hide the rest of the archive
+        if let Ok(mut entry) = zip.by_index(index) {
+            visit(&info, &mut entry);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::ArchiveOperation;
+
+    const CONTENT: &str = "secret contents\n";
+
+    fn write_archive(folder: &Path, encryption: ZipEncryption) -> std::path::PathBuf {
+        let source = folder.join("secret.txt");
+        fs::write(&source, CONTENT).unwrap();
+        let archive = folder.join("secret.zip");
+        let options = CompressionOptions {
+            password: Some("hunter2".to_string()),
+            encryption: Some(encryption),
+            ..Default::default()
+        };
+        let mut writer = writer(fs::File::create(&archive).unwrap(), 6, &options).unwrap();
+        let mut progress = ArchiveProgress {
+            operation: ArchiveOperation::Compress,
+            archive_path: archive.to_string_lossy().to_string(),
+            current_file: None,
+            entries_done: 0,
+            entries_total: 1,
+            bytes_done: 0,
+            bytes_total: CONTENT.len() as u64,
+        };
+        let entry = SourceEntry {
+            path: source,
+            name: "secret.txt".to_string(),
+            kind: SourceKind::File,
+            size: CONTENT.len() as u64,
+        };
+        let written = writer.add_entry(
+            &entry,
+            &CancellationToken::default(),
+            &mut progress,
+            &mut |_: &ArchiveProgress, _: bool| {},
+        );
+        assert!(written.is_ok());
+        writer.finish().unwrap();
+        archive
+    }
+
+    fn extract_with(
+        archive: &Path,
+        destination: &Path,
+        password: Option<&str>,
+    ) -> Result<ExtractionSummary, OhMyFSError> {
+        let options = ExtractionOptions {
+            password: password.map(str::to_string),
+            ..Default::default()
+        };
+        extract(
+            archive,
+            destination,
+            &options,
+            &CancellationToken::default(),
+            &mut |_: &ArchiveProgress, _: bool| {},
+        )
+    }
+
+    fn round_trip(encryption: ZipEncryption) {
+        let temp = tempfile::tempdir().unwrap();
+        let archive = write_archive(temp.path(), encryption);
+        let destination = temp.path().join("out");
+
+        let entries = list(&archive).unwrap();
+        assert!(entries[0].is_encrypted);
+
+        assert!(matches!(
+            extract_with(&archive, &destination, None),
+            Err(OhMyFSError::ArchivePasswordRequired { .. })
+        ));
+        assert!(matches!(
+            extract_with(&archive, &destination, Some("wrong")),
+            Err(OhMyFSError::InvalidArchivePassword { .. })
+        ));
+        assert!(!destination.join("secret.txt").exists());
+
+        let summary = extract_with(&archive, &destination, Some("hunter2")).unwrap();
+        assert!(summary.failed.is_empty());
+        assert_eq!(
+            fs::read_to_string(destination.join("secret.txt")).unwrap(),
+            CONTENT
+        );
+    }
+
+    #[test]
+    fn aes256_archives_round_trip() {
+        round_trip(ZipEncryption::Aes256);
+    }
+
+    #[test]
+    fn zipcrypto_archives_round_trip() {
+        round_trip(ZipEncryption::ZipCrypto);
+    }
+}
@@ -0,0 +1,752 @@
+// Applies a planned diff to the disk as one transaction
+//
+// Plans holding changes the safety rules block are refused, and so are
+// destructive removals unless `force` is set, before anything is touched.
+// Changes run one at a time in dependency order. Each step records how to undo
+// itself before touching anything: created entries are removed again,
+// overwritten files get their original bytes and mode back and removed
+// entries are renamed aside rather than deleted. When a step fails, every
+// completed step is undone in reverse order, unless `ignore_errors` asks to
+// carry on. Once the plan has finished, entries set aside and the original
+// bytes of overwritten files are kept as backups when `backup` is set and
+// deleted otherwise.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+use tauri::Window;
+
+use super::diff::{ChangeType, FileSystemChange, FileSystemDiff};
+use super::{safety, EngineConfig};
+use crate::operation::ProgressEmitter;
+use crate::{first_free, log_error, log_info, to_epoch_millis, OhMyFSError};
+
+pub const PROGRESS_EVENT: &str = "engine://progress";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionStatus {
+    Pending,
+    Running,
+    Completed,
+    Failed,
+    Skipped,
+    Cancelled,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanStatus {
+    Completed,
+    Failed,
+}
+
+// How to undo one completed change
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum RollbackData {
+    // Removed again, then `folder`, the topmost parent made for it
+    Created {
+        path: String,
+        folder: Option<String>,
+    },
+    // The file's bytes and mode before it was overwritten
+    Content {
+        path: String,
+        #[serde(skip)]
+        bytes: Vec<u8>,
+        mode: Option<u32>,
+    },
+    Permissions {
+        path: String,
+        mode: u32,
+    },
+    // Renamed to `backup` instead of being deleted
+    MovedAside {
+        path: String,
+        backup: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionResult {
+    pub change_id: String,
+    pub status: ExecutionStatus,
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    // Milliseconds
+    pub duration: Option<u64>,
+    pub error: Option<String>,
+    pub output: Option<String>,
+    pub rollback_data: Option<RollbackData>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionPlan {
+    pub id: String,
+    pub diff: FileSystemDiff,
+    // In the order they were applied
+    pub changes: Vec<FileSystemChange>,
+    pub results: Vec<ExecutionResult>,
+    pub status: PlanStatus,
+    pub start_time: String,
+    pub end_time: String,
+    pub is_dry_run: bool,
+    // Whether a failure undoes the completed changes
+    pub can_rollback: bool,
+    // Set when a failure undid the completed changes
+    pub rolled_back: bool,
+}
+
+// Orders changes so each comes after the changes it depends on, keeping the
+// planned order otherwise. Dependencies outside the plan, such as changes the
+// safety rules blocked, are treated as met.
+fn order_changes(changes: Vec<FileSystemChange>) -> Result<Vec<FileSystemChange>, OhMyFSError> {
+    let ids = changes
+        .iter()
+        .map(|change| change.id.clone())
+        .collect::<HashSet<_>>();
+    let mut done = HashSet::new();
+    let mut pending = changes;
+    let mut ordered = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|change| {
+            change
+                .dependencies
+                .iter()
+                .all(|id| done.contains(id) || !ids.contains(id))
+        });
+        let Some(ready) = ready else {
+            return Err(OhMyFSError::InvalidPlan {
+                details: format!("changes depend on each other in a cycle: {}", pending[0].id),
+            });
+        };
+        let change = pending.remove(ready);
+        done.insert(change.id.clone());
+        ordered.push(change);
+    }
+    Ok(ordered)
+}
+
+struct Executor<'a> {
+    plan_id: &'a str,
+}
+
+impl Executor<'_> {
+    fn execute(&self, change: &FileSystemChange) -> Result<Option<RollbackData>, String> {
+        let path = Path::new(&change.path);
+        match change.kind {
+            // A folder that already exists is left alone, now and on rollback
+            ChangeType::CreateDirectory => {
+                Ok(create_parents(path)?.map(|folder| RollbackData::Created {
+                    path: folder,
+                    folder: None,
+                }))
+            }
+            ChangeType::CreateFile => {
+                let bytes = wanted_bytes(change)?;
+                ensure_free(path)?;
+                let folder = create_parents(path.parent().unwrap_or(path))?;
+                let created = RollbackData::Created {
+                    path: change.path.clone(),
+                    folder,
+                };
+                let written = fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(path)
+                    .and_then(|mut file| std::io::Write::write_all(&mut file, &bytes));
+                if let Err(err) = written {
+                    revert(&created);
+                    return Err(err.to_string());
+                }
+                if let Err(err) = apply_mode(change) {
+                    revert(&created);
+                    return Err(err);
+                }
+                Ok(Some(created))
+            }
+            ChangeType::CreateSymlink => {
+                let target = change
+                    .node
+                    .as_ref()
+                    .and_then(|node| node.resolved_target.clone())
+                    .ok_or("The change has no symlink target")?;
+                ensure_free(path)?;
+                let folder = create_parents(path.parent().unwrap_or(path))?;
+                let created = RollbackData::Created {
+                    path: change.path.clone(),
+                    folder,
+                };
+                if let Err(err) = create_symlink(&target, path) {
+                    revert(&created);
+                    return Err(err.to_string());
+                }
+                Ok(Some(created))
+            }
+            ChangeType::RemoveDirectory | ChangeType::RemoveFile | ChangeType::RemoveSymlink => {
+                let backup = aside_path(path, self.plan_id);
+                fs::rename(path, &backup).map_err(|err| err.to_string())?;
+                Ok(Some(RollbackData::MovedAside {
+                    path: change.path.clone(),
+                    backup: backup.to_string_lossy().to_string(),
+                }))
+            }
+            ChangeType::UpdateFileContent => {
+                let wanted = wanted_bytes(change)?;
+                let bytes = fs::read(path).map_err(|err| err.to_string())?;
+                let original = RollbackData::Content {
+                    path: change.path.clone(),
+                    bytes,
+                    mode: current_mode(path),
+                };
+                if let Err(err) = fs::write(path, wanted) {
+                    revert(&original);
+                    return Err(err.to_string());
+                }
+                if let Err(err) = apply_mode(change) {
+                    revert(&original);
+                    return Err(err);
+                }
+                Ok(Some(original))
+            }
+            ChangeType::UpdatePermissions => {
+                let mode = current_mode(path).ok_or("Permissions are not supported here")?;
+                apply_mode(change)?;
+                Ok(Some(RollbackData::Permissions {
+                    path: change.path.clone(),
+                    mode,
+                }))
+            }
+            ChangeType::NoChange => Ok(None),
+        }
+    }
+}
+
+fn wanted_bytes(change: &FileSystemChange) -> Result<Vec<u8>, String> {
+    let Some(node) = &change.node else {
+        return Err("The change has no definition node".to_string());
+    };
+    let content = node.resolved_content.as_deref().unwrap_or_default();
+    match node.template() {
+        Some(template) => template.encoding.decode(content),
+        None => Ok(Vec::new()),
+    }
+}
+
+// Creations never replace what is there; rolling back would delete it
+fn ensure_free(path: &Path) -> Result<(), String> {
+    match fs::symlink_metadata(path) {
+        Ok(_) => Err(format!("{} already exists", path.to_string_lossy())),
+        Err(_) => Ok(()),
+    }
+}
+
+// Creates `folder` and any missing parents, returning the topmost one made
+fn create_parents(folder: &Path) -> Result<Option<String>, String> {
+    let topmost = folder
+        .ancestors()
+        .take_while(|ancestor| {
+            !ancestor.as_os_str().is_empty() && fs::symlink_metadata(ancestor).is_err()
+        })
+        .last()
+        .map(|ancestor| ancestor.to_string_lossy().to_string());
+    fs::create_dir_all(folder).map_err(|err| err.to_string())?;
+    Ok(topmost)
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &str, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_symlink(target: &str, link: &Path) -> std::io::Result<()> {
+    let resolved = link.parent().unwrap_or(link).join(target);
+    if resolved.is_dir() {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+#[cfg(unix)]
+fn current_mode(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path)
+        .ok()
+        .map(|metadata| metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn current_mode(_: &Path) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_: &Path, _: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+// Sets the template's permissions, or the execute bits for `executable`
+fn apply_mode(change: &FileSystemChange) -> Result<(), String> {
+    let Some(template) = change.node.as_ref().and_then(|node| node.template()) else {
+        return Ok(());
+    };
+    let path = Path::new(&change.path);
+    let mode = match template.mode()? {
+        Some(mode) => mode,
+        None if template.executable => match current_mode(path) {
+            Some(mode) => mode | (mode & 0o444) >> 2,
+            None => return Ok(()),
+        },
+        None => return Ok(()),
+    };
+    set_mode(path, mode).map_err(|err| err.to_string())
+}
+
+// A free hidden sibling, so the rename never crosses file systems
+fn aside_path(path: &Path, plan_id: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let parent = path.parent().unwrap_or(Path::new(""));
+    first_free(0, |n| {
+        let candidate = match n {
+            0 => parent.join(format!(".{}.{}.undo", name, plan_id)),
+            n => parent.join(format!(".{}.{}.{}.undo", name, plan_id, n)),
+        };
+        fs::symlink_metadata(&candidate)
+            .is_err()
+            .then_some(candidate)
+    })
+}
+
+fn remove_any(path: &Path) -> std::io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+fn undo(data: &RollbackData) -> Result<(), String> {
+    let result = match data {
+        RollbackData::Created { path, folder } => remove_any(Path::new(path)).and_then(|_| {
+            folder
+                .as_ref()
+                .map_or(Ok(()), |folder| remove_any(Path::new(folder)))
+        }),
+        RollbackData::Content { path, bytes, mode } => fs::write(path, bytes)
+            .and_then(|_| mode.map_or(Ok(()), |mode| set_mode(Path::new(path), mode))),
+        RollbackData::Permissions { path, mode } => set_mode(Path::new(path), *mode),
+        RollbackData::MovedAside { path, backup } => {
+            remove_any(Path::new(path)).and_then(|_| fs::rename(backup, path))
+        }
+    };
+    result.map_err(|err| err.to_string())
+}
+
+// Undoes a step that failed halfway
+fn revert(data: &RollbackData) {
+    if let Err(err) = undo(data) {
+        log_error!("Failed to undo a partial change: {}", err);
+    }
+}
+
+// Entries set aside are only needed until the plan has finished
+fn discard(data: &RollbackData) {
+    if let RollbackData::MovedAside { backup, .. } = data {
+        if let Err(err) = remove_any(Path::new(backup)) {
+            log_error!("Failed to delete {}: {}", backup, err);
+        }
+    }
+}
+
+// Leaves what a change removed or overwrote next to its original path,
+// returning where it went
+fn keep_backup(data: &RollbackData, plan_id: &str) -> Option<String> {
+    match data {
+        RollbackData::MovedAside { backup, .. } => Some(backup.clone()),
+        RollbackData::Content { path, bytes, .. } => {
+            let backup = aside_path(Path::new(path), plan_id);
+            match fs::write(&backup, bytes) {
+                Ok(()) => Some(backup.to_string_lossy().to_string()),
+                Err(err) => {
+                    log_error!("Failed to back up {}: {}", path, err);
+                    None
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+fn now() -> String {
+    to_epoch_millis(SystemTime::now())
+}
+
+pub(crate) fn apply(
+    diff: FileSystemDiff,
+    config: &EngineConfig,
+    mut report: impl FnMut(&ExecutionResult),
+) -> Result<ExecutionPlan, OhMyFSError> {
+    if !diff.can_execute && !config.force {
+        return Err(OhMyFSError::InvalidPlan {
+            details: if diff.errors.is_empty() {
+                "the diff has nothing to apply".to_string()
+            } else {
+                diff.errors.join(", ")
+            },
+        });
+    }
+    // The diff comes back from the frontend, so it is checked again rather
+    // than trusted. Blocked changes are refused even with `force`.
+    let validation = safety::validate(&diff.changes);
+    if !validation.blocked.is_empty() {
+        return Err(OhMyFSError::InvalidPlan {
+            details: validation.errors.join(", "),
+        });
+    }
+    // Refused before anything runs, rather than failing halfway through
+    if !config.force && !config.dry_run {
+        if let Some(change) = diff
+            .changes
+            .iter()
+            .find(|change| change.kind.is_remove() && change.is_destructive)
+        {
+            return Err(OhMyFSError::InvalidPlan {
+                details: format!("removing {} needs the force flag", change.path),
+            });
+        }
+    }
+
+    let start_time = now();
+    let id = format!("plan_{}", start_time);
+    let changes = order_changes(diff.changes.clone())?;
+    let mut results = changes
+        .iter()
+        .map(|change| ExecutionResult {
+            change_id: change.id.clone(),
+            status: ExecutionStatus::Pending,
+            start_time: None,
+            end_time: None,
+            duration: None,
+            error: None,
+            output: None,
+            rollback_data: None,
+        })
+        .collect::<Vec<_>>();
+
+    let executor = Executor { plan_id: &id };
+    let mut statuses = HashMap::new();
+    let mut failed = false;
+    for (change, result) in changes.iter().zip(results.iter_mut()) {
+        if failed && !config.ignore_errors {
+            result.status = ExecutionStatus::Skipped;
+            report(result);
+            continue;
+        }
+        // Nothing to build on when a change this one depends on did not happen
+        let blocked = change.dependencies.iter().find(|id| {
+            statuses
+                .get(*id)
+                .is_some_and(|status| *status != ExecutionStatus::Completed)
+        });
+        if let Some(blocked) = blocked {
+            result.status = ExecutionStatus::Skipped;
+            result.output = Some(format!("Skipped because {} did not complete", blocked));
+            statuses.insert(change.id.clone(), result.status);
+            report(result);
+            continue;
+        }
+
+        let started = Instant::now();
+        result.status = ExecutionStatus::Running;
+        result.start_time = Some(now());
+        report(result);
+
+        if config.verbose && config.dry_run {
+            log_info!("[dry run] Would execute: {}", change.description);
+        } else if config.verbose {
+            log_info!("Executing: {}", change.description);
+        }
+        let outcome = if config.dry_run {
+            result.output = Some(format!("[dry run] {}", change.description));
+            Ok(None)
+        } else {
+            executor.execute(change)
+        };
+        match outcome {
+            Ok(rollback_data) => {
+                result.status = ExecutionStatus::Completed;
+                result.rollback_data = rollback_data;
+            }
+            Err(err) => {
+                log_error!("Change {} failed: {}", change.id, err);
+                result.status = ExecutionStatus::Failed;
+                result.error = Some(err);
+                failed = true;
+            }
+        }
+        result.end_time = Some(now());
+        result.duration = Some(started.elapsed().as_millis() as u64);
+        statuses.insert(change.id.clone(), result.status);
+        report(result);
+    }
+
+    let rolled_back = failed && !config.ignore_errors;
+    if rolled_back {
+        log_info!("Rolling back plan {}", id);
+        for result in results.iter_mut().rev() {
+            if result.status != ExecutionStatus::Completed {
+                continue;
+            }
+            result.status = ExecutionStatus::Cancelled;
+            match result.rollback_data.as_ref().map(undo) {
+                Some(Err(err)) => {
+                    log_error!("Failed to roll back {}: {}", result.change_id, err);
+                    result.error = Some(format!("Rollback failed: {}", err));
+                }
+                _ => result.output = Some("Rolled back".to_string()),
+            }
+            report(result);
+        }
+    } else if config.backup {
+        for result in &mut results {
+            let backup = result
+                .rollback_data
+                .as_ref()
+                .and_then(|data| keep_backup(data, &id));
+            if let Some(backup) = backup {
+                result.output = Some(format!("Backed up to {}", backup));
+            }
+        }
+    } else {
+        results
+            .iter()
+            .filter_map(|result| result.rollback_data.as_ref())
+            .for_each(discard);
+    }
+
+    Ok(ExecutionPlan {
+        id,
+        diff,
+        changes,
+        results,
+        status: if failed {
+            PlanStatus::Failed
+        } else {
+            PlanStatus::Completed
+        },
+        start_time,
+        end_time: now(),
+        is_dry_run: config.dry_run,
+        can_rollback: !config.ignore_errors && !config.dry_run,
+        rolled_back,
+    })
+}
+
+#[tauri::command]
+pub async fn apply_plan(
+    window: Window,
+    diff: FileSystemDiff,
+    config: Option<EngineConfig>,
+) -> Result<ExecutionPlan, String> {
+    let config = config.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut emitter = ProgressEmitter::new(&window, PROGRESS_EVENT);
+        let name = diff.definition.name.clone();
+        let plan = apply(diff, &config, |result| emitter.emit(result, true)).map_err(|err| {
+            log_error!("Failed to apply {}: {}", name, err);
+            err.to_string()
+        })?;
+        log_info!("Applied {}: {:?}", name, plan.status);
+        Ok(plan)
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{plan, FileStructureDefinition, VariableValues};
+    use serde_json::json;
+
+    fn plan_for(base: &Path, structure: serde_json::Value) -> FileSystemDiff {
+        let definition: FileStructureDefinition = serde_json::from_value(json!({
+            "name": "test",
+            "basePath": base.to_string_lossy(),
+            "structure": structure,
+        }))
+        .unwrap();
+        plan(definition, &VariableValues::new(), &EngineConfig::default()).unwrap()
+    }
+
+    fn status_of(plan: &ExecutionPlan, path: &Path) -> ExecutionStatus {
+        let index = plan
+            .changes
+            .iter()
+            .position(|change| Path::new(&change.path) == path)
+            .unwrap();
+        plan.results[index].status
+    }
+
+    #[test]
+    fn order_puts_dependencies_first_and_rejects_cycles() {
+        let dir = tempfile::tempdir().unwrap();
+        let diff = plan_for(
+            dir.path(),
+            json!([{ "type": "directory", "name": "a", "children": [
+                { "type": "file", "name": "b.txt", "content": { "content": "b" } }
+            ]}]),
+        );
+        let mut changes = diff.changes;
+        changes.reverse();
+        let ordered = order_changes(changes.clone()).unwrap();
+        assert_eq!(ordered[0].kind, ChangeType::CreateDirectory);
+        assert_eq!(
+            ordered[1].dependencies,
+            std::slice::from_ref(&ordered[0].id)
+        );
+
+        changes[1].dependencies = vec![changes[0].id.clone()];
+        assert!(matches!(
+            order_changes(changes),
+            Err(OhMyFSError::InvalidPlan { .. })
+        ));
+    }
+
+    #[test]
+    fn a_failed_change_rolls_back_the_completed_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let diff = plan_for(
+            dir.path(),
+            json!([
+                { "type": "directory", "name": "src", "children": [
+                    { "type": "file", "name": "main.rs", "content": { "content": "fn main() {}" } }
+                ]},
+                { "type": "file", "name": "README.md", "content": { "content": "hi" } }
+            ]),
+        );
+        // Appears after planning, so creating it fails
+        fs::write(dir.path().join("README.md"), "mine").unwrap();
+
+        let plan = apply(diff, &EngineConfig::default(), |_| {}).unwrap();
+        assert_eq!(plan.status, PlanStatus::Failed);
+        assert!(plan.rolled_back);
+        assert_eq!(
+            status_of(&plan, &dir.path().join("src")),
+            ExecutionStatus::Cancelled
+        );
+        assert_eq!(
+            status_of(&plan, &dir.path().join("README.md")),
+            ExecutionStatus::Failed
+        );
+        assert_eq!(
+            status_of(&plan, &dir.path().join("src/main.rs")),
+            ExecutionStatus::Skipped
+        );
+        assert!(!dir.path().join("src").exists());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("README.md")).unwrap(),
+            "mine"
+        );
+    }
+
+    #[test]
+    fn blocked_changes_are_refused_even_with_force() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut diff = plan_for(
+            dir.path(),
+            json!([{ "type": "file", "name": "notes.txt", "content": { "content": "hi" } }]),
+        );
+        // An edited diff slipping in a system file
+        let mut edited = diff.changes[0].clone();
+        edited.path = "/etc/ohmyfs.conf".to_string();
+        edited.id = format!("create_file:{}", edited.path);
+        diff.changes.push(edited);
+
+        let config = EngineConfig {
+            force: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            apply(diff, &config, |_| {}),
+            Err(OhMyFSError::InvalidPlan { .. })
+        ));
+        assert!(!dir.path().join("notes.txt").exists());
+    }
+
+    #[test]
+    fn destructive_removals_need_force_up_front() {
+        for force in [false, true] {
+            let dir = tempfile::tempdir().unwrap();
+            fs::write(dir.path().join("extra.txt"), "mine").unwrap();
+            let diff = plan_for(
+                dir.path(),
+                json!([{ "type": "file", "name": "notes.txt", "content": { "content": "hi" } }]),
+            );
+            let config = EngineConfig {
+                force,
+                ..Default::default()
+            };
+
+            let result = apply(diff, &config, |_| {});
+            if force {
+                assert_eq!(result.unwrap().status, PlanStatus::Completed);
+                assert!(!dir.path().join("extra.txt").exists());
+            } else {
+                assert!(matches!(result, Err(OhMyFSError::InvalidPlan { .. })));
+                assert!(dir.path().join("extra.txt").exists());
+            }
+            assert_eq!(dir.path().join("notes.txt").exists(), force);
+        }
+    }
+
+    #[test]
+    fn overwritten_files_are_backed_up_only_when_asked() {
+        for backup in [true, false] {
+            let dir = tempfile::tempdir().unwrap();
+            let notes = dir.path().join("notes.txt");
+            fs::write(&notes, "old").unwrap();
+            let diff = plan_for(
+                dir.path(),
+                json!([{ "type": "file", "name": "notes.txt", "content": { "content": "new" } }]),
+            );
+            let config = EngineConfig {
+                backup,
+                ..Default::default()
+            };
+
+            let plan = apply(diff, &config, |_| {}).unwrap();
+            assert_eq!(plan.status, PlanStatus::Completed);
+            assert_eq!(fs::read_to_string(&notes).unwrap(), "new");
+            let entries = fs::read_dir(dir.path()).unwrap().count();
+            match plan.results[0].output.as_deref() {
+                Some(output) if backup => {
+                    let kept = output.trim_start_matches("Backed up to ");
+                    assert_eq!(fs::read_to_string(kept).unwrap(), "old");
+                    assert_eq!(entries, 2);
+                }
+                output => {
+                    assert!(!backup, "no backup reported: {:?}", output);
+                    assert_eq!(entries, 1);
+                }
+            }
+        }
+    }
+}
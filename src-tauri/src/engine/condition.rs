@@ -0,0 +1,455 @@
+// Evaluates node conditions
+//
+// Leaf conditions look at a variable or at a path relative to the folder the
+// node is created in: `exists` and `notExists` check whether it is there, the
+// comparisons match a variable's value or a file's content. Paths must stay
+// inside the base path, and only the start of regular files is read. `all`,
+// `any` and `not` combine nested conditions. Each outcome carries a reason stating what
+// decided it, like `"src/main.ts" does not exist`, so skipped nodes can be
+// explained.
+
+use serde_json::Value;
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use super::definition::{Condition, ConditionType, VariableValues};
+use super::template::{render, value_to_string};
+use crate::OhMyFSError;
+
+// Content conditions only look at the start of larger files
+const MAX_CONTENT_READ: u64 = 1024 * 1024;
+
+#[derive(Debug)]
+pub(crate) struct Outcome {
+    pub met: bool,
+    pub reason: String,
+}
+
+impl Outcome {
+    fn new(met: bool, reason: String) -> Self {
+        Self { met, reason }
+    }
+}
+
+pub(crate) fn evaluate(
+    condition: &Condition,
+    base_path: &Path,
+    parent: &Path,
+    variables: &VariableValues,
+    location: &str,
+) -> Result<Outcome, OhMyFSError> {
+    match condition.kind {
+        ConditionType::All | ConditionType::Any => {
+            if condition.conditions.is_empty() {
+                return Err(invalid(location, "needs at least one nested condition"));
+            }
+            let wanted = condition.kind == ConditionType::Any;
+            let mut reasons = Vec::new();
+            for (index, nested) in condition.conditions.iter().enumerate() {
+                let location = format!("{}.conditions[{}]", location, index);
+                let outcome = evaluate(nested, base_path, parent, variables, &location)?;
+                // The first condition that settles the result explains it
+                if outcome.met == wanted {
+                    return Ok(outcome);
+                }
+                reasons.push(outcome.reason);
+            }
+            Ok(Outcome::new(!wanted, reasons.join(" and ")))
+        }
+        ConditionType::Not => {
+            let [nested] = condition.conditions.as_slice() else {
+                return Err(invalid(location, "not takes exactly one nested condition"));
+            };
+            let outcome = evaluate(
+                nested,
+                base_path,
+                parent,
+                variables,
+                &format!("{}.conditions[0]", location),
+            )?;
+            Ok(Outcome::new(!outcome.met, outcome.reason))
+        }
+        ConditionType::Exists | ConditionType::NotExists => {
+            let wanted = condition.kind == ConditionType::Exists;
+            if let Some(variable) = &condition.variable {
+                let set = variables
+                    .get(variable)
+                    .is_some_and(|value| !value.is_null());
+                let reason = if set { "is set" } else { "is not set" };
+                return Ok(Outcome::new(
+                    set == wanted,
+                    format!("{} {}", variable, reason),
+                ));
+            }
+            let path = condition_path(condition, variables, location)?;
+            let target = inside_base(base_path, parent, &path, location)?;
+            let exists = fs::symlink_metadata(target).is_ok();
+            let reason = if exists { "exists" } else { "does not exist" };
+            Ok(Outcome::new(
+                exists == wanted,
+                format!("{:?} {}", path, reason),
+            ))
+        }
+        kind => {
+            let expected = condition
+                .value
+                .as_ref()
+                .ok_or_else(|| invalid(location, "needs a value to compare with"))?;
+            let equality = matches!(kind, ConditionType::Equals | ConditionType::NotEquals);
+            let negated = matches!(kind, ConditionType::NotEquals | ConditionType::NotContains);
+            let (matched, subject, shown) = if let Some(variable) = &condition.variable {
+                let actual = variables.get(variable);
+                let matched = if equality {
+                    actual.is_some_and(|actual| same_value(actual, expected))
+                } else {
+                    contains(actual, expected)
+                };
+                (matched, variable.clone(), expected.to_string())
+            } else {
+                let path = condition_path(condition, variables, location)?;
+                let target = inside_base(base_path, parent, &path, location)?;
+                let Some((content, complete)) = read_start(base_path, &target) else {
+                    return Ok(Outcome::new(
+                        negated,
+                        format!("{:?} is not a readable file", path),
+                    ));
+                };
+                let content = String::from_utf8_lossy(&content);
+                let expected = value_to_string(expected);
+                if equality {
+                    // Ignoring the line break editors add at the end
+                    let matched = complete
+                        && content.trim_end_matches(['\r', '\n'])
+                            == expected.trim_end_matches(['\r', '\n']);
+                    (
+                        matched,
+                        format!("the content of {:?}", path),
+                        format!("{:?}", expected),
+                    )
+                } else {
+                    let matched = content.contains(&expected);
+                    (matched, format!("{:?}", path), format!("{:?}", expected))
+                }
+            };
+            let verb = match (equality, matched) {
+                (true, true) => "is",
+                (true, false) => "is not",
+                (false, true) => "contains",
+                (false, false) => "does not contain",
+            };
+            Ok(Outcome::new(
+                matched != negated,
+                format!("{} {} {}", subject, verb, shown),
+            ))
+        }
+    }
+}
+
+fn condition_path(
+    condition: &Condition,
+    variables: &VariableValues,
+    location: &str,
+) -> Result<String, OhMyFSError> {
+    let path = condition
+        .path
+        .as_ref()
+        .ok_or_else(|| invalid(location, "needs a path or a variable"))?;
+    render(path, variables, &format!("{}.path", location))
+}
+
+// `path` joined to `parent`, with `.` and `..` applied, as long as it stays
+// inside the base path
+fn inside_base(
+    base_path: &Path,
+    parent: &Path,
+    path: &str,
+    location: &str,
+) -> Result<PathBuf, OhMyFSError> {
+    let target = normalize(&parent.join(path));
+    if !target.starts_with(normalize(base_path)) {
+        return Err(invalid(
+            &format!("{}.path", location),
+            &format!("{:?} points outside the base path", path),
+        ));
+    }
+    Ok(target)
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+// The first `MAX_CONTENT_READ` bytes of a regular file and whether that is
+// all of it. Links are followed only while they stay inside the base path.
+fn read_start(base_path: &Path, target: &Path) -> Option<(Vec<u8>, bool)> {
+    let resolved = fs::canonicalize(target).ok()?;
+    if !fs::canonicalize(base_path).is_ok_and(|base| resolved.starts_with(base)) {
+        return None;
+    }
+    // Checked before opening, which would block on a FIFO
+    let metadata = fs::metadata(&resolved).ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+    let file = fs::File::open(&resolved).ok()?;
+    let mut content = Vec::new();
+    file.take(MAX_CONTENT_READ).read_to_end(&mut content).ok()?;
+    Some((content, metadata.len() <= MAX_CONTENT_READ))
+}
+
+// Numbers compare by value, so a provided `1` equals a default of `1.0`
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        (a, b) => a == b,
+    }
+}
+
+// Lists hold the value, strings the text
+fn contains(actual: Option<&Value>, expected: &Value) -> bool {
+    match (actual, expected) {
+        (Some(Value::Array(items)), expected) => {
+            items.iter().any(|item| same_value(item, expected))
+        }
+        (Some(Value::String(text)), Value::String(expected)) => text.contains(expected.as_str()),
+        _ => false,
+    }
+}
+
+fn invalid(location: &str, details: &str) -> OhMyFSError {
+    OhMyFSError::InvalidDefinition {
+        location: location.to_string(),
+        details: details.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn condition(value: Value) -> Condition {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn check(value: Value, parent: &Path) -> Outcome {
+        let variables = match json!({
+            "framework": "react",
+            "port": 3000,
+            "features": ["auth", "i18n"],
+            "nothing": null
+        }) {
+            Value::Object(variables) => variables,
+            _ => unreachable!(),
+        };
+        evaluate(
+            &condition(value),
+            parent,
+            parent,
+            &variables,
+            "structure[0].condition",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn variables_compare_by_value() {
+        let here = Path::new(".");
+        let outcome = check(
+            json!({ "type": "equals", "variable": "framework", "value": "react" }),
+            here,
+        );
+        assert!(outcome.met);
+        assert_eq!(outcome.reason, "framework is \"react\"");
+        assert!(
+            check(
+                json!({ "type": "equals", "variable": "port", "value": 3000.0 }),
+                here
+            )
+            .met
+        );
+        assert!(
+            check(
+                json!({ "type": "contains", "variable": "features", "value": "auth" }),
+                here
+            )
+            .met
+        );
+        assert!(
+            check(
+                json!({ "type": "notContains", "variable": "framework", "value": "vue" }),
+                here
+            )
+            .met
+        );
+        assert!(!check(json!({ "type": "exists", "variable": "nothing" }), here).met);
+        assert!(
+            check(
+                json!({ "type": "notEquals", "variable": "missing", "value": 1 }),
+                here
+            )
+            .met
+        );
+    }
+
+    #[test]
+    fn paths_are_checked_below_the_parent_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("package.json"), "{\"type\": \"module\"}\n").unwrap();
+
+        let outcome = check(
+            json!({ "type": "notExists", "path": "{{framework}}.config.js" }),
+            dir.path(),
+        );
+        assert!(outcome.met);
+        assert_eq!(outcome.reason, "\"react.config.js\" does not exist");
+        assert!(
+            check(
+                json!({ "type": "contains", "path": "package.json", "value": "module" }),
+                dir.path()
+            )
+            .met
+        );
+        assert!(check(json!({ "type": "equals", "path": "package.json", "value": "{\"type\": \"module\"}" }), dir.path()).met);
+        // A file that cannot be read contains nothing
+        assert!(
+            check(
+                json!({ "type": "notContains", "path": "missing.txt", "value": "x" }),
+                dir.path()
+            )
+            .met
+        );
+    }
+
+    #[test]
+    fn content_is_only_read_from_regular_files_inside_the_base() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("project");
+        fs::create_dir_all(base.join("src")).unwrap();
+        fs::write(dir.path().join("secret.txt"), "token").unwrap();
+        let mut large = "x".repeat(MAX_CONTENT_READ as usize);
+        large.push_str("needle");
+        fs::write(base.join("large.txt"), &large).unwrap();
+
+        let variables = VariableValues::new();
+        let outcome = |value: Value| {
+            evaluate(
+                &condition(value),
+                &base,
+                &base.join("src"),
+                &variables,
+                "structure[0].condition",
+            )
+        };
+        for path in [
+            "../../secret.txt",
+            "/etc/hostname",
+            "../src/../../secret.txt",
+        ] {
+            assert!(
+                matches!(
+                    outcome(json!({ "type": "contains", "path": path, "value": "token" })),
+                    Err(OhMyFSError::InvalidDefinition { location, .. })
+                        if location == "structure[0].condition.path"
+                ),
+                "{}",
+                path
+            );
+        }
+
+        // Only the start of a large file is looked at
+        let contains = json!({ "type": "contains", "path": "../large.txt", "value": "needle" });
+        assert!(!outcome(contains).unwrap().met);
+        let equals = json!({ "type": "equals", "path": "../large.txt", "value": large });
+        assert!(!outcome(equals).unwrap().met);
+
+        // Folders are not files
+        let outcome = outcome(json!({ "type": "contains", "path": ".", "value": "x" })).unwrap();
+        assert!(!outcome.met);
+        assert_eq!(outcome.reason, "\".\" is not a readable file");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_out_of_the_base_are_not_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("project");
+        fs::create_dir(&base).unwrap();
+        fs::write(dir.path().join("secret.txt"), "token").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("secret.txt"), base.join("link.txt")).unwrap();
+        fs::write(base.join("notes.txt"), "token").unwrap();
+        std::os::unix::fs::symlink("notes.txt", base.join("inside.txt")).unwrap();
+
+        let contains = |path: &str| {
+            let value = json!({ "type": "contains", "path": path, "value": "token" });
+            evaluate(
+                &condition(value),
+                &base,
+                &base,
+                &VariableValues::new(),
+                "structure[0].condition",
+            )
+            .unwrap()
+            .met
+        };
+        assert!(!contains("link.txt"));
+        assert!(contains("inside.txt"));
+    }
+
+    #[test]
+    fn combinators_explain_with_the_deciding_condition() {
+        let here = Path::new(".");
+        let outcome = check(
+            json!({ "type": "all", "conditions": [
+                { "type": "exists", "variable": "framework" },
+                { "type": "equals", "variable": "port", "value": 8080 }
+            ]}),
+            here,
+        );
+        assert!(!outcome.met);
+        assert_eq!(outcome.reason, "port is not 8080");
+
+        let outcome = check(
+            json!({ "type": "not", "conditions": [
+                { "type": "any", "conditions": [
+                    { "type": "exists", "variable": "nothing" },
+                    { "type": "exists", "variable": "missing" }
+                ]}
+            ]}),
+            here,
+        );
+        assert!(outcome.met);
+        assert_eq!(outcome.reason, "nothing is not set and missing is not set");
+    }
+
+    #[test]
+    fn malformed_conditions_name_their_location() {
+        let variables = VariableValues::new();
+        let err = evaluate(
+            &condition(
+                json!({ "type": "any", "conditions": [{ "type": "equals", "variable": "x" }] }),
+            ),
+            Path::new("."),
+            Path::new("."),
+            &variables,
+            "structure[2].condition",
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            OhMyFSError::InvalidDefinition { location, .. }
+                if location == "structure[2].condition.conditions[0]"
+        ));
+    }
+}
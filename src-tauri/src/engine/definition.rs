@@ -0,0 +1,285 @@
+// The declarative structure format and what it resolves to
+//
+// Field names follow src/types/filesystem-engine.ts, so definitions written by
+// the frontend or saved as JSON deserialize unchanged.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub type VariableValues = serde_json::Map<String, Value>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VariableType {
+    String,
+    Number,
+    Boolean,
+    Path,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Variable {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: VariableType,
+    pub default_value: Option<Value>,
+    pub description: Option<String>,
+    // A zod schema on the frontend, kept so definitions round-trip
+    pub validation: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Base64,
+    // One byte per character, like Node's "binary" (latin1) strings
+    Binary,
+}
+
+impl Encoding {
+    // The bytes a rendered template stands for
+    pub fn decode(self, content: &str) -> Result<Vec<u8>, String> {
+        match self {
+            Encoding::Utf8 => Ok(content.as_bytes().to_vec()),
+            Encoding::Base64 => decode_base64(content),
+            Encoding::Binary => content
+                .chars()
+                .map(|c| u8::try_from(c).map_err(|_| format!("{:?} is not a byte", c)))
+                .collect(),
+        }
+    }
+}
+
+// Standard alphabet, padding optional, whitespace skipped
+fn decode_base64(content: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(content.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in content.chars().filter(|c| !c.is_whitespace()) {
+        let value = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' => 62,
+            '/' => 63,
+            '=' => break,
+            _ => return Err(format!("{:?} is not valid base64", c)),
+        };
+        buffer = buffer << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Ok(bytes)
+}
+
+// Text, or with `"template": "json"` any JSON value, written out formatted
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum TemplateContent {
+    Text(String),
+    Structured(Value),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileTemplate {
+    pub content: TemplateContent,
+    // What the file holds, like "json", "markdown" or "javascript"
+    pub template: Option<String>,
+    #[serde(default)]
+    pub encoding: Encoding,
+    #[serde(default)]
+    pub executable: bool,
+    // Octal, like "755"
+    pub permissions: Option<String>,
+}
+
+impl FileTemplate {
+    pub fn mode(&self) -> Result<Option<u32>, String> {
+        self.permissions
+            .as_deref()
+            .map(|permissions| {
+                u32::from_str_radix(permissions, 8)
+                    .ok()
+                    .filter(|mode| *mode <= 0o7777)
+                    .ok_or_else(|| format!("{:?} is not an octal mode", permissions))
+            })
+            .transpose()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ConditionType {
+    Exists,
+    NotExists,
+    Equals,
+    NotEquals,
+    Contains,
+    NotContains,
+    All,
+    Any,
+    Not,
+}
+
+// Leaf conditions read `variable` when it is set and `path` otherwise
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Condition {
+    #[serde(rename = "type")]
+    pub kind: ConditionType,
+    pub path: Option<String>,
+    pub value: Option<Value>,
+    pub variable: Option<String>,
+    // What `all`, `any` and `not` combine
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<Condition>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DirectoryDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub children: Vec<FileSystemDefinition>,
+    pub condition: Option<Condition>,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileDefinition {
+    pub name: String,
+    pub content: Option<FileTemplate>,
+    pub condition: Option<Condition>,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SymlinkDefinition {
+    pub name: String,
+    pub target: String,
+    pub condition: Option<Condition>,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum FileSystemDefinition {
+    Directory(DirectoryDefinition),
+    File(FileDefinition),
+    Symlink(SymlinkDefinition),
+}
+
+impl FileSystemDefinition {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Directory(directory) => &directory.name,
+            Self::File(file) => &file.name,
+            Self::Symlink(symlink) => &symlink.name,
+        }
+    }
+
+    pub fn condition(&self) -> Option<&Condition> {
+        match self {
+            Self::Directory(directory) => directory.condition.as_ref(),
+            Self::File(file) => file.condition.as_ref(),
+            Self::Symlink(symlink) => symlink.condition.as_ref(),
+        }
+    }
+
+    pub fn description(&self) -> Option<&String> {
+        match self {
+            Self::Directory(directory) => directory.description.as_ref(),
+            Self::File(file) => file.description.as_ref(),
+            Self::Symlink(symlink) => symlink.description.as_ref(),
+        }
+    }
+
+    pub fn node_type(&self) -> NodeType {
+        match self {
+            Self::Directory(_) => NodeType::Directory,
+            Self::File(_) => NodeType::File,
+            Self::Symlink(_) => NodeType::Symlink,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileStructureDefinition {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub version: String,
+    // Root the structure is created in
+    pub base_path: String,
+    #[serde(default)]
+    pub variables: Vec<Variable>,
+    #[serde(default)]
+    pub structure: Vec<FileSystemDefinition>,
+    // `.gitignore`-style, relative to `base_path`
+    pub ignore_patterns: Option<Vec<String>>,
+    pub metadata: Option<serde_json::Map<String, Value>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeType {
+    Directory,
+    File,
+    Symlink,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeMetadata {
+    pub description: Option<String>,
+    pub should_exist: bool,
+    pub reason: Option<String>,
+}
+
+// One definition entry with its variables substituted. Directories are
+// flattened: each child becomes a node of its own, so `original_definition`
+// is stored without children.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedFileSystemNode {
+    #[serde(rename = "type")]
+    pub kind: NodeType,
+    pub name: String,
+    pub path: String,
+    pub original_definition: FileSystemDefinition,
+    // Files only, still in the template's encoding
+    pub resolved_content: Option<String>,
+    // Symlinks only
+    pub resolved_target: Option<String>,
+    pub condition: Option<Condition>,
+    pub metadata: Option<NodeMetadata>,
+}
+
+impl ResolvedFileSystemNode {
+    pub fn template(&self) -> Option<&FileTemplate> {
+        match &self.original_definition {
+            FileSystemDefinition::File(file) => file.content.as_ref(),
+            _ => None,
+        }
+    }
+
+    // False for nodes a condition left out
+    pub fn should_exist(&self) -> bool {
+        self.metadata
+            .as_ref()
+            .is_none_or(|metadata| metadata.should_exist)
+    }
+
+    pub fn reason(&self) -> Option<&String> {
+        self.metadata
+            .as_ref()
+            .and_then(|metadata| metadata.reason.as_ref())
+    }
+}
@@ -0,0 +1,830 @@
+// Compares resolved nodes with the disk and lists the changes between them
+//
+// Missing entries are created, entries of the wrong type are removed and
+// recreated, and files whose content or mode differ are updated. Anything
+// else found in the folders the definition describes, unless ignored, is
+// listed for removal. Changes come removals first, then creations, parents
+// before children, and each names the changes it has to wait for.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::definition::{
+    FileStructureDefinition, NodeType, ResolvedFileSystemNode, VariableValues,
+};
+use super::resolve::ResolvedDefinition;
+use super::safety;
+use crate::ignore::IgnoreRules;
+use crate::{file_entry, to_epoch_millis, EntryKind, FileEntry};
+
+const REPLACE_WARNINGS: &[&str] = &[
+    "This change will modify or replace existing filesystem item",
+    "Consider backing up important data before proceeding",
+];
+const EXTRA_WARNINGS: &[&str] = &[
+    "This item is not part of the desired structure",
+    "Removing it may cause data loss",
+    "Consider adding it to ignore patterns if it should be preserved",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeType {
+    CreateDirectory,
+    CreateFile,
+    CreateSymlink,
+    RemoveDirectory,
+    RemoveFile,
+    RemoveSymlink,
+    UpdateFileContent,
+    UpdatePermissions,
+    NoChange,
+}
+
+impl ChangeType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChangeType::CreateDirectory => "create_directory",
+            ChangeType::CreateFile => "create_file",
+            ChangeType::CreateSymlink => "create_symlink",
+            ChangeType::RemoveDirectory => "remove_directory",
+            ChangeType::RemoveFile => "remove_file",
+            ChangeType::RemoveSymlink => "remove_symlink",
+            ChangeType::UpdateFileContent => "update_file_content",
+            ChangeType::UpdatePermissions => "update_permissions",
+            ChangeType::NoChange => "no_change",
+        }
+    }
+
+    pub fn is_create(self) -> bool {
+        matches!(
+            self,
+            ChangeType::CreateDirectory | ChangeType::CreateFile | ChangeType::CreateSymlink
+        )
+    }
+
+    pub fn is_remove(self) -> bool {
+        matches!(
+            self,
+            ChangeType::RemoveDirectory | ChangeType::RemoveFile | ChangeType::RemoveSymlink
+        )
+    }
+
+    pub fn is_update(self) -> bool {
+        matches!(
+            self,
+            ChangeType::UpdateFileContent | ChangeType::UpdatePermissions
+        )
+    }
+
+    fn create(node: NodeType) -> Self {
+        match node {
+            NodeType::Directory => ChangeType::CreateDirectory,
+            NodeType::File => ChangeType::CreateFile,
+            NodeType::Symlink => ChangeType::CreateSymlink,
+        }
+    }
+
+    fn remove(entry: &FileEntry) -> Self {
+        match entry.kind {
+            EntryKind::Directory => ChangeType::RemoveDirectory,
+            EntryKind::Symlink => ChangeType::RemoveSymlink,
+            _ => ChangeType::RemoveFile,
+        }
+    }
+
+    // Removals first, then creations, then updates
+    fn priority(self) -> u8 {
+        match self {
+            ChangeType::RemoveDirectory => 1,
+            ChangeType::RemoveFile => 2,
+            ChangeType::RemoveSymlink => 3,
+            ChangeType::CreateDirectory => 4,
+            ChangeType::CreateFile => 5,
+            ChangeType::CreateSymlink => 6,
+            ChangeType::UpdateFileContent => 7,
+            ChangeType::UpdatePermissions => 8,
+            ChangeType::NoChange => 9,
+        }
+    }
+}
+
+// A `FileEntry` with the camelCase field names the engine types use
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryState {
+    pub name: String,
+    pub path: String,
+    pub kind: EntryKind,
+    pub is_directory: bool,
+    pub is_file: bool,
+    pub is_symlink: bool,
+    pub symlink_target: Option<String>,
+    pub target_kind: Option<EntryKind>,
+    #[serde(default)]
+    pub is_broken: bool,
+    pub size: Option<u64>,
+    pub compressed_size: Option<u64>,
+    pub modified_at: Option<String>,
+    pub created_at: Option<String>,
+    pub permissions: Option<String>,
+    pub extension: Option<String>,
+    #[serde(default)]
+    pub is_hidden: bool,
+    #[serde(default)]
+    pub is_ignored: bool,
+}
+
+impl From<FileEntry> for EntryState {
+    fn from(entry: FileEntry) -> Self {
+        Self {
+            name: entry.name,
+            path: entry.path,
+            kind: entry.kind,
+            is_directory: entry.is_directory,
+            is_file: entry.is_file,
+            is_symlink: entry.is_symlink,
+            symlink_target: entry.symlink_target,
+            target_kind: entry.target_kind,
+            is_broken: entry.is_broken,
+            size: entry.size,
+            compressed_size: entry.compressed_size,
+            modified_at: entry.modified_at,
+            created_at: entry.created_at,
+            permissions: entry.permissions,
+            extension: entry.extension,
+            is_hidden: entry.is_hidden,
+            is_ignored: entry.is_ignored,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileSystemChange {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: ChangeType,
+    pub path: String,
+    pub old_state: Option<EntryState>,
+    pub new_state: Option<EntryState>,
+    // Removes or overwrites data
+    pub is_destructive: bool,
+    pub is_safe: bool,
+    pub description: String,
+    pub reason: String,
+    // Ids of the changes that must be applied first
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    #[serde(default)]
+    pub errors: Vec<String>,
+    // What create and update changes write
+    pub node: Option<ResolvedFileSystemNode>,
+}
+
+impl FileSystemChange {
+    fn new(kind: ChangeType, path: &str, description: String, reason: String) -> Self {
+        Self {
+            id: format!("{}:{}", kind.as_str(), path),
+            kind,
+            path: path.to_string(),
+            old_state: None,
+            new_state: None,
+            is_destructive: false,
+            is_safe: true,
+            description,
+            reason,
+            dependencies: Vec::new(),
+            warnings: Vec::new(),
+            errors: Vec::new(),
+            node: None,
+        }
+    }
+
+    fn with_warnings(mut self, warnings: &[&str]) -> Self {
+        self.warnings = warnings.iter().map(|warning| warning.to_string()).collect();
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangeSummary {
+    pub total: u64,
+    pub create: u64,
+    pub update: u64,
+    pub remove: u64,
+    pub destructive: u64,
+    pub safe: u64,
+    pub warnings: u64,
+    pub errors: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffMetadata {
+    pub safety_score: u32,
+    pub blocked_changes_count: u64,
+    // Nodes a condition left out, each with the reason
+    #[serde(default)]
+    pub skipped_nodes: Vec<ResolvedFileSystemNode>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileSystemDiff {
+    pub definition: FileStructureDefinition,
+    pub variables: VariableValues,
+    // Without the changes the safety rules blocked
+    pub changes: Vec<FileSystemChange>,
+    // Counts every planned change, blocked ones included
+    pub summary: ChangeSummary,
+    pub timestamp: String,
+    pub is_safe: bool,
+    pub can_execute: bool,
+    pub warnings: Vec<String>,
+    pub errors: Vec<String>,
+    pub metadata: DiffMetadata,
+}
+
+pub(crate) fn generate(
+    definition: FileStructureDefinition,
+    resolved: ResolvedDefinition,
+    force: bool,
+) -> FileSystemDiff {
+    let mut changes = Vec::new();
+    for node in resolved.nodes.iter().filter(|node| node.should_exist()) {
+        compare_node(node, force, &mut changes);
+    }
+    let ignore = IgnoreRules::new(definition.ignore_patterns.as_deref().unwrap_or_default());
+    find_extra_entries(&resolved, &ignore, &mut changes);
+    link_dependencies(&mut changes);
+    changes.sort_by(|a, b| {
+        a.kind
+            .priority()
+            .cmp(&b.kind.priority())
+            .then_with(|| a.path.len().cmp(&b.path.len()))
+    });
+
+    let summary = summarize(&changes);
+    let validation = safety::validate(&changes);
+    let safety_score = safety::safety_score(&changes);
+    let no_errors = changes.iter().all(|change| change.errors.is_empty());
+    let is_safe = validation.errors.is_empty()
+        && changes.iter().all(|change| {
+            !change.is_destructive && change.warnings.is_empty() && change.errors.is_empty()
+        });
+    let can_execute = validation.errors.is_empty() && no_errors && !changes.is_empty();
+
+    let mut warnings = collect_unique(changes.iter().map(|change| &change.warnings));
+    warnings.extend(validation.warnings);
+    let mut errors = collect_unique(changes.iter().map(|change| &change.errors));
+    errors.extend(validation.errors);
+
+    let blocked = validation.blocked.len() as u64;
+    let changes = changes
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| !validation.blocked.contains(index))
+        .map(|(_, change)| change)
+        .collect();
+
+    FileSystemDiff {
+        definition,
+        variables: resolved.variables,
+        changes,
+        summary,
+        timestamp: to_epoch_millis(SystemTime::now()),
+        is_safe,
+        can_execute,
+        warnings,
+        errors,
+        metadata: DiffMetadata {
+            safety_score,
+            blocked_changes_count: blocked,
+            skipped_nodes: resolved
+                .nodes
+                .into_iter()
+                .filter(|node| !node.should_exist())
+                .collect(),
+        },
+    }
+}
+
+fn compare_node(node: &ResolvedFileSystemNode, force: bool, changes: &mut Vec<FileSystemChange>) {
+    let path = Path::new(&node.path);
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        changes.push(create_change(node));
+        return;
+    };
+    let actual = file_entry(path, &metadata);
+    let file_type = metadata.file_type();
+    let matches = match node.kind {
+        NodeType::Directory => file_type.is_dir(),
+        NodeType::File => file_type.is_file(),
+        NodeType::Symlink => file_type.is_symlink(),
+    };
+    if !matches {
+        let expected = match node.kind {
+            NodeType::Directory => "Expected directory but found file/symlink",
+            NodeType::File => "Expected file but found directory/symlink",
+            NodeType::Symlink => "Expected symlink but found file/directory",
+        };
+        changes.push(replace_change(node, actual, expected.to_string()));
+        changes.push(create_change(node));
+        return;
+    }
+
+    match node.kind {
+        NodeType::Directory if force => {
+            let mut change = FileSystemChange::new(
+                ChangeType::NoChange,
+                &node.path,
+                format!("Directory already exists: {}", node.name),
+                "Force bootstrap mode - recreating existing structure".to_string(),
+            );
+            change.old_state = Some(actual.into());
+            change.new_state = Some(node_entry(node));
+            changes.push(change);
+        }
+        NodeType::Directory => {}
+        NodeType::File => compare_file(node, actual, &metadata, force, changes),
+        NodeType::Symlink => {
+            let target = fs::read_link(path)
+                .map(|target| target.to_string_lossy().to_string())
+                .unwrap_or_default();
+            if node.resolved_target.as_deref() != Some(target.as_str()) {
+                let reason = format!("Symlink points to {} instead", target);
+                changes.push(replace_change(node, actual, reason));
+                changes.push(create_change(node));
+            }
+        }
+    }
+}
+
+fn compare_file(
+    node: &ResolvedFileSystemNode,
+    actual: FileEntry,
+    metadata: &fs::Metadata,
+    force: bool,
+    changes: &mut Vec<FileSystemChange>,
+) {
+    let Some(template) = node.template() else {
+        // A file without a template only has to exist
+        return;
+    };
+
+    let mut update = FileSystemChange::new(
+        ChangeType::UpdateFileContent,
+        &node.path,
+        format!("Update file content: {}", node.name),
+        String::new(),
+    )
+    .with_warnings(REPLACE_WARNINGS);
+    let wanted = template
+        .encoding
+        .decode(node.resolved_content.as_deref().unwrap_or_default());
+    match wanted {
+        Ok(wanted) => {
+            let same = metadata.len() == wanted.len() as u64
+                && fs::read(&node.path).is_ok_and(|current| current == wanted);
+            if !same {
+                update.reason = "File content differs from the template".to_string();
+            } else if force {
+                update.reason = "Force bootstrap mode - recreating existing structure".to_string();
+            }
+        }
+        Err(details) => {
+            update.reason = "File content cannot be decoded".to_string();
+            update
+                .errors
+                .push(format!("Invalid content for {}: {}", node.path, details));
+        }
+    }
+    if !update.reason.is_empty() {
+        update.is_destructive = true;
+        update.is_safe = false;
+        update.old_state = Some(actual.clone().into());
+        update.new_state = Some(node_entry(node));
+        update.node = Some(node.clone());
+        changes.push(update);
+    }
+
+    if let Some(reason) = permissions_mismatch(template, metadata) {
+        let mut change = FileSystemChange::new(
+            ChangeType::UpdatePermissions,
+            &node.path,
+            format!("Update permissions: {}", node.name),
+            reason,
+        );
+        change.old_state = Some(actual.into());
+        change.new_state = Some(node_entry(node));
+        change.node = Some(node.clone());
+        changes.push(change);
+    }
+}
+
+#[cfg(unix)]
+fn permissions_mismatch(
+    template: &super::definition::FileTemplate,
+    metadata: &fs::Metadata,
+) -> Option<String> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = metadata.permissions().mode() & 0o7777;
+    match template.mode() {
+        Ok(Some(wanted)) if wanted != mode => Some(format!(
+            "Permissions are {:o} instead of {:o}",
+            mode, wanted
+        )),
+        Ok(None) if template.executable && mode & 0o111 == 0 => {
+            Some("File is not executable".to_string())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+fn permissions_mismatch(_: &super::definition::FileTemplate, _: &fs::Metadata) -> Option<String> {
+    None
+}
+
+fn create_change(node: &ResolvedFileSystemNode) -> FileSystemChange {
+    let kind = ChangeType::create(node.kind);
+    let noun = match node.kind {
+        NodeType::Directory => "directory",
+        NodeType::File => "file",
+        NodeType::Symlink => "symlink",
+    };
+    let mut change = FileSystemChange::new(
+        kind,
+        &node.path,
+        format!("Create {}: {}", noun, node.name),
+        node.reason()
+            .cloned()
+            .unwrap_or_else(|| "Required by definition".to_string()),
+    );
+    change.new_state = Some(node_entry(node));
+    change.node = Some(node.clone());
+    change
+}
+
+// Removes an entry of the wrong type so the node can be created in its place
+fn replace_change(
+    node: &ResolvedFileSystemNode,
+    actual: FileEntry,
+    reason: String,
+) -> FileSystemChange {
+    let mut change = FileSystemChange::new(
+        ChangeType::remove(&actual),
+        &node.path,
+        format!("Replace {}: {}", noun(&actual), node.name),
+        reason,
+    )
+    .with_warnings(REPLACE_WARNINGS);
+    change.is_destructive = true;
+    change.is_safe = false;
+    change.old_state = Some(actual.into());
+    change
+}
+
+fn noun(entry: &FileEntry) -> &'static str {
+    match entry.kind {
+        EntryKind::Directory => "directory",
+        EntryKind::Symlink => "symlink",
+        _ => "file",
+    }
+}
+
+// Lists what sits in the base folder and the folders the definition
+// describes without being part of it. Extra folders are not descended into,
+// removing them covers their contents. Skipped nodes are left alone: they
+// count as known but their folders are not searched.
+fn find_extra_entries(
+    resolved: &ResolvedDefinition,
+    ignore: &IgnoreRules,
+    changes: &mut Vec<FileSystemChange>,
+) {
+    let mut known = HashSet::new();
+    let mut folders = BTreeSet::from([resolved.base_path.clone()]);
+    for node in &resolved.nodes {
+        let path = PathBuf::from(&node.path);
+        let wanted = node.should_exist();
+        if wanted && node.kind == NodeType::Directory {
+            folders.insert(path.clone());
+        }
+        // Folders implied by names like `src/index.js`
+        for ancestor in path.ancestors().skip(1) {
+            if ancestor == resolved.base_path || !ancestor.starts_with(&resolved.base_path) {
+                break;
+            }
+            known.insert(ancestor.to_path_buf());
+            if wanted {
+                folders.insert(ancestor.to_path_buf());
+            }
+        }
+        known.insert(path);
+    }
+
+    for folder in folders {
+        if !fs::symlink_metadata(&folder).is_ok_and(|metadata| metadata.is_dir()) {
+            continue;
+        }
+        let Ok(entries) = fs::read_dir(&folder) else {
+            continue;
+        };
+        let mut entries = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| !known.contains(path))
+            .collect::<Vec<_>>();
+        entries.sort();
+        for path in entries {
+            let Ok(metadata) = fs::symlink_metadata(&path) else {
+                continue;
+            };
+            let relative = path.strip_prefix(&resolved.base_path).unwrap_or(&path);
+            if ignore.is_ignored(relative, metadata.is_dir()) {
+                continue;
+            }
+            let actual = file_entry(&path, &metadata);
+            let mut change = FileSystemChange::new(
+                ChangeType::remove(&actual),
+                &actual.path,
+                format!("Remove {}: {}", noun(&actual), actual.name),
+                "Item exists in filesystem but is not defined in structure".to_string(),
+            )
+            .with_warnings(EXTRA_WARNINGS);
+            change.is_destructive = true;
+            change.is_safe = false;
+            change.old_state = Some(actual.into());
+            changes.push(change);
+        }
+    }
+}
+
+// A creation waits for the removal of whatever was at its path and for the
+// creation of the closest folder above it
+fn link_dependencies(changes: &mut [FileSystemChange]) {
+    let mut removals = HashMap::new();
+    let mut folders = HashMap::new();
+    for change in changes.iter() {
+        if change.kind.is_remove() {
+            removals.insert(PathBuf::from(&change.path), change.id.clone());
+        } else if change.kind == ChangeType::CreateDirectory {
+            folders.insert(PathBuf::from(&change.path), change.id.clone());
+        }
+    }
+
+    for change in changes.iter_mut().filter(|change| change.kind.is_create()) {
+        let path = PathBuf::from(&change.path);
+        if let Some(id) = removals.get(&path) {
+            change.dependencies.push(id.clone());
+        }
+        if let Some(id) = path
+            .ancestors()
+            .skip(1)
+            .find_map(|folder| folders.get(folder))
+        {
+            change.dependencies.push(id.clone());
+        }
+    }
+}
+
+fn summarize(changes: &[FileSystemChange]) -> ChangeSummary {
+    let mut summary = ChangeSummary {
+        total: changes.len() as u64,
+        ..Default::default()
+    };
+    for change in changes {
+        if change.kind.is_create() {
+            summary.create += 1;
+        } else if change.kind.is_update() {
+            summary.update += 1;
+        } else if change.kind.is_remove() {
+            summary.remove += 1;
+        }
+        if change.is_destructive {
+            summary.destructive += 1;
+        }
+        if change.is_safe {
+            summary.safe += 1;
+        }
+        summary.warnings += change.warnings.len() as u64;
+        summary.errors += change.errors.len() as u64;
+    }
+    summary
+}
+
+fn collect_unique<'a>(lists: impl Iterator<Item = &'a Vec<String>>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    lists
+        .flatten()
+        .filter(|item| seen.insert(item.as_str()))
+        .cloned()
+        .collect()
+}
+
+// How a node will look once created, for `new_state`
+fn node_entry(node: &ResolvedFileSystemNode) -> EntryState {
+    let kind = match node.kind {
+        NodeType::Directory => EntryKind::Directory,
+        NodeType::File => EntryKind::File,
+        NodeType::Symlink => EntryKind::Symlink,
+    };
+    EntryState {
+        name: node.name.clone(),
+        path: node.path.clone(),
+        kind,
+        is_directory: kind == EntryKind::Directory,
+        is_file: kind == EntryKind::File,
+        is_symlink: kind == EntryKind::Symlink,
+        symlink_target: node.resolved_target.clone(),
+        target_kind: None,
+        is_broken: false,
+        size: None,
+        compressed_size: None,
+        modified_at: None,
+        created_at: None,
+        permissions: node
+            .template()
+            .and_then(|template| template.permissions.clone()),
+        extension: Path::new(&node.name)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_string()),
+        is_hidden: crate::hidden::is_dotfile(&node.name),
+        is_ignored: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{plan, EngineConfig};
+    use serde_json::json;
+
+    fn plan_for(base: &Path, definition: serde_json::Value) -> FileSystemDiff {
+        let mut definition = definition;
+        definition["name"] = json!("test");
+        definition["basePath"] = json!(base.to_string_lossy());
+        let definition = serde_json::from_value(definition).unwrap();
+        plan(definition, &VariableValues::new(), &EngineConfig::default()).unwrap()
+    }
+
+    fn change<'a>(diff: &'a FileSystemDiff, kind: ChangeType, path: &Path) -> &'a FileSystemChange {
+        diff.changes
+            .iter()
+            .find(|change| change.kind == kind && Path::new(&change.path) == path)
+            .unwrap_or_else(|| panic!("no {:?} for {}", kind, path.display()))
+    }
+
+    #[test]
+    fn extra_entries_are_removed_unless_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("keep.txt"), "").unwrap();
+        fs::write(dir.path().join("extra.txt"), "").unwrap();
+        fs::write(dir.path().join("debug.log"), "").unwrap();
+        fs::create_dir_all(dir.path().join("build/out")).unwrap();
+        fs::create_dir(dir.path().join("stale")).unwrap();
+        fs::write(dir.path().join("stale/old.txt"), "").unwrap();
+
+        let diff = plan_for(
+            dir.path(),
+            json!({
+                "ignorePatterns": ["*.log", "build/"],
+                "structure": [{ "type": "file", "name": "keep.txt" }],
+            }),
+        );
+
+        let mut removed = diff
+            .changes
+            .iter()
+            .map(|change| (change.kind, change.path.clone()))
+            .collect::<Vec<_>>();
+        removed.sort_by(|a, b| a.1.cmp(&b.1));
+        let path = |name: &str| dir.path().join(name).to_string_lossy().to_string();
+        // The contents of extra folders go with them
+        assert_eq!(
+            removed,
+            [
+                (ChangeType::RemoveFile, path("extra.txt")),
+                (ChangeType::RemoveDirectory, path("stale")),
+            ]
+        );
+        let extra = change(&diff, ChangeType::RemoveFile, &dir.path().join("extra.txt"));
+        assert!(extra.is_destructive && !extra.is_safe);
+        assert_eq!(extra.warnings, EXTRA_WARNINGS);
+        assert_eq!(extra.old_state.as_ref().unwrap().name, "extra.txt");
+    }
+
+    #[test]
+    fn entries_of_the_wrong_type_are_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::write(&src, "not a folder").unwrap();
+
+        let diff = plan_for(
+            dir.path(),
+            json!({ "structure": [{ "type": "directory", "name": "src" }] }),
+        );
+
+        assert_eq!(diff.changes.len(), 2);
+        assert_eq!(diff.changes[0].kind, ChangeType::RemoveFile);
+        let remove = change(&diff, ChangeType::RemoveFile, &src);
+        let create = change(&diff, ChangeType::CreateDirectory, &src);
+        assert!(remove.is_destructive);
+        assert_eq!(remove.reason, "Expected directory but found file/symlink");
+        assert_eq!(create.dependencies, std::slice::from_ref(&remove.id));
+    }
+
+    #[test]
+    fn summary_counts_every_kind_of_change() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("notes.txt"), "old").unwrap();
+        fs::write(dir.path().join("extra.txt"), "").unwrap();
+
+        let diff = plan_for(
+            dir.path(),
+            json!({ "structure": [
+                { "type": "directory", "name": "docs", "children": [
+                    { "type": "file", "name": "index.md" }
+                ]},
+                { "type": "file", "name": "notes.txt", "content": { "content": "new" } }
+            ]}),
+        );
+
+        assert_eq!(
+            diff.summary,
+            ChangeSummary {
+                total: 4,
+                create: 2,
+                update: 1,
+                remove: 1,
+                destructive: 2,
+                safe: 2,
+                warnings: (REPLACE_WARNINGS.len() + EXTRA_WARNINGS.len()) as u64,
+                errors: 0,
+            }
+        );
+        // Two destructive changes with warnings
+        assert_eq!(diff.metadata.safety_score, 100 - 2 * 20 - 2 * 5);
+        assert!(!diff.is_safe);
+        assert!(diff.can_execute);
+    }
+
+    #[test]
+    fn critical_paths_are_blocked() {
+        // Ignoring everything else keeps the rest of /etc out of the plan
+        let definition = json!({
+            "ignorePatterns": ["*"],
+            "structure": [{ "type": "file", "name": "ohmyfs-planner-test.conf" }],
+        });
+        let diff = plan_for(Path::new("/etc"), definition);
+
+        assert!(diff.changes.is_empty());
+        assert_eq!(diff.summary.create, 1);
+        assert_eq!(diff.metadata.blocked_changes_count, 1);
+        assert_eq!(diff.metadata.safety_score, 0);
+        assert!(!diff.can_execute);
+        assert!(diff
+            .errors
+            .iter()
+            .any(|error| error.starts_with("Critical system path modification blocked")));
+
+        let mut changes = vec![FileSystemChange::new(
+            ChangeType::RemoveFile,
+            "/home/user/project/notes.txt",
+            String::new(),
+            String::new(),
+        )];
+        assert_eq!(safety::safety_score(&changes), 100);
+        changes[0].is_destructive = true;
+        changes[0].warnings.push("careful".to_string());
+        assert_eq!(safety::safety_score(&changes), 75);
+        assert!(safety::validate(&changes).blocked.is_empty());
+    }
+
+    #[test]
+    fn changes_serialize_with_camel_case_states() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("notes.txt"), "old").unwrap();
+        let diff = plan_for(
+            dir.path(),
+            json!({ "structure": [
+                { "type": "file", "name": "notes.txt", "content": { "content": "new" } }
+            ]}),
+        );
+
+        let value = serde_json::to_value(&diff.changes[0]).unwrap();
+        assert_eq!(value["type"], "update_file_content");
+        assert_eq!(value["isDestructive"], true);
+        assert_eq!(value["oldState"]["isFile"], true);
+        assert_eq!(value["oldState"]["size"], 3);
+        assert_eq!(value["newState"]["name"], "notes.txt");
+        assert!(value.get("old_state").is_none());
+        assert!(value["oldState"].get("is_file").is_none());
+    }
+}
@@ -0,0 +1,90 @@
+// Declarative filesystem engine
+//
+// A `FileStructureDefinition` describes a tree of folders, files and symlinks
+// whose names and contents are templates over a set of variables. Planning
+// renders it with the variable values and compares the result with what is on
+// disk, producing a `FileSystemDiff` the frontend can review before anything
+// is touched.
+//
+// The types mirror src/types/filesystem-engine.ts, camelCase field names
+// included, so definitions and diffs pass between the two as they are.
+
+pub mod apply;
+mod condition;
+mod definition;
+mod diff;
+mod resolve;
+mod safety;
+pub mod snapshot;
+mod template;
+
+use serde::{Deserialize, Serialize};
+
+pub use definition::{FileStructureDefinition, VariableValues};
+pub use diff::FileSystemDiff;
+
+use crate::{log_error, log_info};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EngineConfig {
+    pub dry_run: bool,
+    // Override safety checks and rewrite entries that already exist
+    pub force: bool,
+    // Log every change as it runs
+    pub verbose: bool,
+    // Keep what destructive changes remove or overwrite
+    pub backup: bool,
+    // Keep going after a change fails
+    pub ignore_errors: bool,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            force: false,
+            verbose: false,
+            backup: true,
+            ignore_errors: false,
+        }
+    }
+}
+
+pub(crate) fn plan(
+    definition: FileStructureDefinition,
+    variables: &VariableValues,
+    config: &EngineConfig,
+) -> Result<FileSystemDiff, crate::OhMyFSError> {
+    let resolved = resolve::resolve(&definition, variables)?;
+    Ok(diff::generate(definition, resolved, config.force))
+}
+
+#[tauri::command]
+pub async fn plan_definition(
+    definition: FileStructureDefinition,
+    variables: Option<VariableValues>,
+    config: Option<EngineConfig>,
+) -> Result<FileSystemDiff, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let name = definition.name.clone();
+        let diff = plan(
+            definition,
+            &variables.unwrap_or_default(),
+            &config.unwrap_or_default(),
+        )
+        .map_err(|err| {
+            log_error!("Failed to plan {}: {}", name, err);
+            err.to_string()
+        })?;
+        log_info!(
+            "Planned {}: {} changes, safety score {}",
+            name,
+            diff.changes.len(),
+            diff.metadata.safety_score
+        );
+        Ok(diff)
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
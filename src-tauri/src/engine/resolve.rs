@@ -0,0 +1,265 @@
+// Resolves a definition into the nodes it describes
+//
+// Variables get their provided or default values, conditions decide which
+// entries take part and record why, and the base path, names, symlink targets
+// and file contents are rendered as templates. Every error names its place in the
+// definition, like `structure[1].children[0].name`.
+
+use std::path::{Component, Path, PathBuf};
+
+use super::condition::{self, Outcome};
+use super::definition::{
+    FileStructureDefinition, FileSystemDefinition, NodeMetadata, ResolvedFileSystemNode,
+    TemplateContent, VariableValues,
+};
+use super::template::{render, render_value};
+use crate::OhMyFSError;
+
+pub(crate) struct ResolvedDefinition {
+    pub variables: VariableValues,
+    pub base_path: PathBuf,
+    pub nodes: Vec<ResolvedFileSystemNode>,
+}
+
+pub(crate) fn resolve(
+    definition: &FileStructureDefinition,
+    provided: &VariableValues,
+) -> Result<ResolvedDefinition, OhMyFSError> {
+    if definition.name.trim().is_empty() {
+        return Err(invalid("name", "the definition needs a name"));
+    }
+    if definition.base_path.trim().is_empty() {
+        return Err(invalid("basePath", "the definition needs a base path"));
+    }
+
+    let variables = resolve_variables(definition, provided)?;
+    let base_path = PathBuf::from(render(&definition.base_path, &variables, "basePath")?);
+    // Would let the safety rules see a different folder than the one written to
+    if base_path
+        .components()
+        .any(|component| component == Component::ParentDir)
+    {
+        return Err(invalid(
+            "basePath",
+            &format!("{:?} must not contain \"..\"", base_path),
+        ));
+    }
+    let mut nodes = Vec::new();
+    for (index, node) in definition.structure.iter().enumerate() {
+        resolve_node(
+            node,
+            &base_path,
+            &base_path,
+            &variables,
+            &format!("structure[{}]", index),
+            None,
+            &mut nodes,
+        )?;
+    }
+    Ok(ResolvedDefinition {
+        variables,
+        base_path,
+        nodes,
+    })
+}
+
+// Only declared variables are kept; each takes the provided value, then its
+// default
+fn resolve_variables(
+    definition: &FileStructureDefinition,
+    provided: &VariableValues,
+) -> Result<VariableValues, OhMyFSError> {
+    let mut variables = VariableValues::new();
+    for variable in &definition.variables {
+        let value = provided
+            .get(&variable.name)
+            .or(variable.default_value.as_ref())
+            .ok_or_else(|| OhMyFSError::VariableNotProvided {
+                name: variable.name.clone(),
+            })?;
+        variables.insert(variable.name.clone(), value.clone());
+    }
+    Ok(variables)
+}
+
+// Nodes a condition leaves out are kept with `should_exist` false, along with
+// everything below them, and a reason saying why
+fn resolve_node(
+    node: &FileSystemDefinition,
+    base_path: &Path,
+    parent: &Path,
+    variables: &VariableValues,
+    location: &str,
+    skipped_folder: Option<&str>,
+    nodes: &mut Vec<ResolvedFileSystemNode>,
+) -> Result<(), OhMyFSError> {
+    let name_location = format!("{}.name", location);
+    let name = render(node.name(), variables, &name_location)?;
+    check_name(&name, &name_location)?;
+    let path = parent.join(&name);
+    let noun = match node {
+        FileSystemDefinition::Directory(_) => "directory",
+        FileSystemDefinition::File(_) => "file",
+        FileSystemDefinition::Symlink(_) => "symlink",
+    };
+
+    let outcome = match (skipped_folder, node.condition()) {
+        (Some(folder), _) => Some(Outcome {
+            met: false,
+            reason: format!("{:?} is skipped", folder),
+        }),
+        (None, Some(condition)) => Some(condition::evaluate(
+            condition,
+            base_path,
+            parent,
+            variables,
+            &format!("{}.condition", location),
+        )?),
+        (None, None) => None,
+    };
+    let should_exist = outcome.as_ref().is_none_or(|outcome| outcome.met);
+
+    let mut resolved_content = None;
+    let mut resolved_target = None;
+    let mut reason = format!("Required {}: {}", noun, name);
+    if !should_exist {
+        reason = format!("Skipped {}: {}", noun, name);
+    } else if let FileSystemDefinition::File(file) = node {
+        if let Some(template) = &file.content {
+            template.mode().map_err(|details| {
+                invalid(&format!("{}.content.permissions", location), &details)
+            })?;
+            let content_location = format!("{}.content.content", location);
+            resolved_content = Some(match &template.content {
+                TemplateContent::Text(text) => render(text, variables, &content_location)?,
+                TemplateContent::Structured(value) => {
+                    let value = render_value(value, variables, &content_location)?;
+                    serde_json::to_string_pretty(&value)
+                        .map_err(|err| invalid(&content_location, &err.to_string()))?
+                }
+            });
+        }
+    } else if let FileSystemDefinition::Symlink(symlink) = node {
+        let target = render(&symlink.target, variables, &format!("{}.target", location))?;
+        reason = format!("{} -> {}", reason, target);
+        resolved_target = Some(target);
+    }
+    if let Some(outcome) = &outcome {
+        reason = format!("{}, since {}", reason, outcome.reason);
+    }
+
+    let original_definition = match node {
+        FileSystemDefinition::Directory(directory) => {
+            let mut directory = directory.clone();
+            directory.children.clear();
+            FileSystemDefinition::Directory(directory)
+        }
+        node => node.clone(),
+    };
+    nodes.push(ResolvedFileSystemNode {
+        kind: node.node_type(),
+        name: name.clone(),
+        path: path.to_string_lossy().to_string(),
+        original_definition,
+        resolved_content,
+        resolved_target,
+        condition: node.condition().cloned(),
+        metadata: Some(NodeMetadata {
+            description: node.description().cloned(),
+            should_exist,
+            reason: Some(reason),
+        }),
+    });
+
+    if let FileSystemDefinition::Directory(directory) = node {
+        let skipped_folder = match skipped_folder {
+            Some(folder) => Some(folder),
+            None if !should_exist => Some(name.as_str()),
+            None => None,
+        };
+        for (index, child) in directory.children.iter().enumerate() {
+            resolve_node(
+                child,
+                base_path,
+                &path,
+                variables,
+                &format!("{}.children[{}]", location, index),
+                skipped_folder,
+                nodes,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// Names may contain folders (`src/index.js`) but must stay inside their parent
+fn check_name(name: &str, location: &str) -> Result<(), OhMyFSError> {
+    let path = Path::new(name);
+    if name.is_empty() {
+        return Err(invalid(location, "the name is empty"));
+    }
+    if path
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(invalid(
+            location,
+            &format!("{:?} points outside its parent folder", name),
+        ));
+    }
+    // "." and "./" would stand for the parent folder itself
+    if !path
+        .components()
+        .any(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(invalid(
+            location,
+            &format!("{:?} does not name an entry", name),
+        ));
+    }
+    Ok(())
+}
+
+fn invalid(location: &str, details: &str) -> OhMyFSError {
+    OhMyFSError::InvalidDefinition {
+        location: location.to_string(),
+        details: details.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_paths_cannot_climb_out() {
+        let definition: FileStructureDefinition = serde_json::from_value(serde_json::json!({
+            "name": "test",
+            "basePath": "/home/{{user}}/../../etc",
+            "variables": [{ "name": "user", "type": "string", "defaultValue": "me" }],
+            "structure": [],
+        }))
+        .unwrap();
+        assert!(matches!(
+            resolve(&definition, &VariableValues::new()),
+            Err(OhMyFSError::InvalidDefinition { location, .. }) if location == "basePath"
+        ));
+    }
+
+    #[test]
+    fn names_stay_inside_their_parent() {
+        for name in ["notes.txt", "./notes.txt", "src/main.rs"] {
+            assert!(check_name(name, "structure[0].name").is_ok(), "{}", name);
+        }
+        for name in ["", ".", "./", "..", "../notes.txt", "/etc"] {
+            assert!(
+                matches!(
+                    check_name(name, "structure[0].name"),
+                    Err(OhMyFSError::InvalidDefinition { .. })
+                ),
+                "{}",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,216 @@
+// Safety rules for planned changes
+//
+// Changes to system folders and creating or updating executables, libraries
+// and keys are blocked outright. Other risky changes only add warnings and
+// lower the safety score.
+
+use super::diff::{ChangeType, FileSystemChange};
+
+// Roots only match themselves, the rest also match everything below them
+const CRITICAL_ROOTS: &[&str] = &["/", "c:/"];
+const CRITICAL_PATHS: &[&str] = &[
+    "/bin",
+    "/sbin",
+    "/usr",
+    "/etc",
+    "/var",
+    "/sys",
+    "/proc",
+    "/dev",
+    "/boot",
+    "/lib",
+    "/lib64",
+    "/opt",
+    "/root",
+    "c:/windows",
+    "c:/program files",
+    "c:/program files (x86)",
+];
+// Home folders are fine even where they sit below a critical path
+const USER_PATHS: &[&str] = &["/home", "/users", "c:/users"];
+const DANGEROUS_EXTENSIONS: &[&str] = &[
+    "exe", "dll", "so", "dylib", "sys", "drv", "key", "pem", "crt",
+];
+
+pub(crate) struct SafetyValidation {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+    // Indices into the validated changes
+    pub blocked: Vec<usize>,
+}
+
+// Lowercase with forward slashes, and `.` and `..` applied without touching
+// the disk, so "/home/me/../../etc" is seen as "/etc"
+fn normalize(path: &str) -> String {
+    let path = path.to_lowercase().replace('\\', "/");
+    let (prefix, rest) = match path.split_once(':') {
+        Some((drive, rest)) if drive.len() == 1 => (format!("{}:/", drive), rest),
+        _ if path.starts_with('/') => ("/".to_string(), path.as_str()),
+        _ => (String::new(), path.as_str()),
+    };
+    let mut parts = Vec::new();
+    for part in rest.split('/') {
+        match part {
+            "" | "." => {}
+            // Nothing goes above the root, relative paths keep leading `..`
+            ".." if !parts.is_empty() && parts.last() != Some(&"..") => {
+                parts.pop();
+            }
+            ".." if !prefix.is_empty() => {}
+            part => parts.push(part),
+        }
+    }
+    format!("{}{}", prefix, parts.join("/"))
+}
+
+fn is_within(path: &str, folder: &str) -> bool {
+    path == folder
+        || path
+            .strip_prefix(folder)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+pub(crate) fn is_critical_path(path: &str) -> bool {
+    let path = normalize(path);
+    if USER_PATHS.iter().any(|user| is_within(&path, user)) {
+        return false;
+    }
+    CRITICAL_ROOTS.contains(&path.as_str())
+        || CRITICAL_PATHS
+            .iter()
+            .any(|critical| is_within(&path, critical))
+}
+
+fn is_dangerous_extension(path: &str) -> bool {
+    path.rsplit(['/', '\\'])
+        .next()
+        .and_then(|name| name.rsplit_once('.'))
+        .is_some_and(|(_, extension)| {
+            DANGEROUS_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+        })
+}
+
+// Writing such files is always blocked, removing them only in system folders
+pub(crate) fn is_dangerous_operation(path: &str, change_type: ChangeType) -> bool {
+    if !is_dangerous_extension(path) {
+        return false;
+    }
+    if change_type.is_create() || change_type.is_update() {
+        return true;
+    }
+    change_type.is_remove() && is_critical_path(path)
+}
+
+pub(crate) fn validate(changes: &[FileSystemChange]) -> SafetyValidation {
+    let mut validation = SafetyValidation {
+        errors: Vec::new(),
+        warnings: Vec::new(),
+        blocked: Vec::new(),
+    };
+    for (index, change) in changes.iter().enumerate() {
+        if is_critical_path(&change.path) {
+            validation.errors.push(format!(
+                "Critical system path modification blocked: {}",
+                change.path
+            ));
+            validation.blocked.push(index);
+            continue;
+        }
+        if is_dangerous_operation(&change.path, change.kind) {
+            validation
+                .errors
+                .push(format!("Dangerous file operation blocked: {}", change.path));
+            validation.blocked.push(index);
+            continue;
+        }
+
+        if change.is_destructive {
+            validation
+                .warnings
+                .push(format!("Destructive operation: {}", change.description));
+        }
+        if change.path.contains("/.") || change.path.contains("\\.") {
+            validation
+                .warnings
+                .push(format!("Hidden file/directory operation: {}", change.path));
+        }
+        if change.kind == ChangeType::RemoveDirectory {
+            validation.warnings.push(format!(
+                "Directory removal: {} (ensure backup if needed)",
+                change.path
+            ));
+        }
+    }
+    validation
+}
+
+// 0 to 100, higher is safer
+pub(crate) fn safety_score(changes: &[FileSystemChange]) -> u32 {
+    let mut score = 100i64;
+    for change in changes {
+        if change.is_destructive {
+            score -= 20;
+        }
+        if !change.warnings.is_empty() {
+            score -= 5;
+        }
+        if !change.errors.is_empty() {
+            score -= 50;
+        }
+        if is_critical_path(&change.path) {
+            score -= 100;
+        }
+        if is_dangerous_operation(&change.path, change.kind) {
+            score -= 100;
+        }
+    }
+    score.clamp(0, 100) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_normalized_before_matching() {
+        assert_eq!(normalize("/home/me/../../etc/"), "/etc");
+        assert_eq!(normalize("/../../usr/./bin"), "/usr/bin");
+        assert_eq!(normalize("C:\\Users\\me\\..\\..\\Windows"), "c:/windows");
+        assert_eq!(normalize("C:"), "c:/");
+        assert_eq!(normalize("../notes/./a.txt"), "../notes/a.txt");
+
+        assert!(is_critical_path("/home/me/../../etc/passwd"));
+        assert!(is_critical_path("/home/.."));
+        assert!(is_critical_path("C:\\Users\\..\\Windows\\system32"));
+        assert!(!is_critical_path("/home/me/project/../notes"));
+        assert!(!is_critical_path("/etc/../home/me"));
+    }
+
+    #[test]
+    fn climbing_paths_are_blocked() {
+        let change = |path: &str| FileSystemChange {
+            id: format!("create_file:{}", path),
+            kind: ChangeType::CreateFile,
+            path: path.to_string(),
+            old_state: None,
+            new_state: None,
+            is_destructive: false,
+            is_safe: true,
+            description: String::new(),
+            reason: String::new(),
+            dependencies: Vec::new(),
+            warnings: Vec::new(),
+            errors: Vec::new(),
+            node: None,
+        };
+        let changes = [
+            change("/home/me/notes.txt"),
+            change("/home/me/../../etc/cron.d/job"),
+            change("/home/me/../../root/.bashrc"),
+        ];
+
+        let validation = validate(&changes);
+        assert_eq!(validation.blocked, [1, 2]);
+        assert_eq!(safety_score(&changes), 0);
+    }
+}
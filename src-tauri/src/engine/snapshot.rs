@@ -0,0 +1,473 @@
+// Captures an existing folder as a definition
+//
+// Folders, files and symlinks become definition entries. Text files up to
+// `maxEmbedSize` are embedded as template content; larger, binary and
+// unreadable files get an entry without content, which creates them empty,
+// and are listed in `metadata.referencedFiles` so they can be copied over by
+// hand. Symlinks whose target cannot be read get no entry and are listed
+// there as well. Literal strings picked as variables are replaced with
+// `{{name}}` in names, contents and symlink targets, and any `{{` already in
+// the text is escaped to stay literal. Backslashes right before either are
+// doubled so they are not read as escapes.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::definition::{
+    DirectoryDefinition, Encoding, FileDefinition, FileStructureDefinition, FileSystemDefinition,
+    FileTemplate, SymlinkDefinition, TemplateContent, Variable, VariableType,
+};
+use crate::ignore::IgnoreRules;
+use crate::search::content::BINARY_SNIFF_LENGTH;
+use crate::{log_error, log_info, to_epoch_millis, OhMyFSError};
+
+const DEFAULT_MAX_EMBED_SIZE: u64 = 64 * 1024;
+
+// A literal to turn into a variable, like the project's name
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnapshotVariable {
+    pub name: String,
+    pub value: String,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SnapshotOptions {
+    // The folder's name unless set
+    pub name: Option<String>,
+    pub description: Option<String>,
+    // Where the definition creates the structure, the captured folder unless
+    // set
+    pub base_path: Option<String>,
+    // `.gitignore`-style, relative to the captured folder, and kept in the
+    // definition
+    pub ignore_patterns: Vec<String>,
+    // In bytes
+    pub max_embed_size: Option<u64>,
+    pub variables: Vec<SnapshotVariable>,
+}
+
+struct Snapshot<'a> {
+    root: &'a Path,
+    ignore: IgnoreRules,
+    max_embed_size: u64,
+    // Longest literal first, so the most specific one wins
+    replacements: Vec<(&'a str, String)>,
+    referenced: Vec<String>,
+}
+
+impl Snapshot<'_> {
+    fn capture_folder(&mut self, folder: &Path) -> Result<Vec<FileSystemDefinition>, OhMyFSError> {
+        let entries = fs::read_dir(folder).map_err(|err| OhMyFSError::DirectoryReadFailed {
+            path: folder.to_string_lossy().to_string(),
+            details: err.to_string(),
+        })?;
+        let mut entries = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        entries.sort();
+
+        let mut structure = Vec::new();
+        for path in entries {
+            let Ok(metadata) = fs::symlink_metadata(&path) else {
+                continue;
+            };
+            let relative = path.strip_prefix(self.root).unwrap_or(&path).to_path_buf();
+            if self.ignore.is_ignored(&relative, metadata.is_dir()) {
+                continue;
+            }
+            let name = self.substitute(&path.file_name().unwrap_or_default().to_string_lossy());
+            let file_type = metadata.file_type();
+            if file_type.is_symlink() {
+                let target = match fs::read_link(&path) {
+                    Ok(target) => target,
+                    Err(err) => {
+                        log_error!(
+                            "Snapshot references {}: its target cannot be read: {}",
+                            path.display(),
+                            err
+                        );
+                        self.reference(&relative);
+                        continue;
+                    }
+                };
+                structure.push(FileSystemDefinition::Symlink(SymlinkDefinition {
+                    name,
+                    target: self.substitute(&target.to_string_lossy()),
+                    condition: None,
+                    description: None,
+                }));
+            } else if file_type.is_dir() {
+                structure.push(FileSystemDefinition::Directory(DirectoryDefinition {
+                    name,
+                    children: self.capture_folder(&path)?,
+                    condition: None,
+                    description: None,
+                }));
+            } else if file_type.is_file() {
+                let (content, description) = self.capture_file(&path, &relative, &metadata);
+                structure.push(FileSystemDefinition::File(FileDefinition {
+                    name,
+                    content,
+                    condition: None,
+                    description,
+                }));
+            } else {
+                log_info!(
+                    "Snapshot skips {}: not a file, folder or symlink",
+                    path.display()
+                );
+            }
+        }
+        Ok(structure)
+    }
+
+    // The template for a text file, or a description saying why it is only
+    // referenced
+    fn capture_file(
+        &mut self,
+        path: &Path,
+        relative: &Path,
+        metadata: &fs::Metadata,
+    ) -> (Option<FileTemplate>, Option<String>) {
+        let reason = if metadata.len() > self.max_embed_size {
+            format!("larger than {} bytes", self.max_embed_size)
+        } else {
+            let bytes = match fs::read(path) {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    return self.unembedded(path, relative, &format!("unreadable: {}", err))
+                }
+            };
+            let binary = bytes[..bytes.len().min(BINARY_SNIFF_LENGTH)].contains(&0);
+            match String::from_utf8(bytes) {
+                Ok(text) if !binary => {
+                    let template = FileTemplate {
+                        content: TemplateContent::Text(self.substitute(&text)),
+                        template: None,
+                        encoding: Encoding::Utf8,
+                        executable: is_executable(metadata),
+                        permissions: None,
+                    };
+                    return (Some(template), None);
+                }
+                _ => "binary".to_string(),
+            }
+        };
+        self.unembedded(path, relative, &reason)
+    }
+
+    fn unembedded(
+        &mut self,
+        path: &Path,
+        relative: &Path,
+        reason: &str,
+    ) -> (Option<FileTemplate>, Option<String>) {
+        self.reference(relative);
+        let description = format!("Not embedded ({}), copy it from {}", reason, path.display());
+        (None, Some(description))
+    }
+
+    fn reference(&mut self, relative: &Path) {
+        self.referenced
+            .push(relative.to_string_lossy().replace('\\', "/"));
+    }
+
+    // One pass, so a placeholder is never matched by a later literal
+    fn substitute(&self, text: &str) -> String {
+        let mut output = String::with_capacity(text.len());
+        let mut rest = text;
+        'scan: while let Some(c) = rest.chars().next() {
+            for (literal, placeholder) in &self.replacements {
+                if let Some(after) = rest.strip_prefix(literal) {
+                    push_tag(&mut output, placeholder);
+                    rest = after;
+                    continue 'scan;
+                }
+            }
+            if let Some(after) = rest.strip_prefix("{{") {
+                push_tag(&mut output, "\\{{");
+                rest = after;
+                continue;
+            }
+            output.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+        output
+    }
+}
+
+// Doubles the backslashes just before `tag` so they are not read as an escape
+fn push_tag(output: &mut String, tag: &str) {
+    let slashes = output.len() - output.trim_end_matches('\\').len();
+    output.extend(std::iter::repeat_n('\\', slashes));
+    output.push_str(tag);
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_: &fs::Metadata) -> bool {
+    false
+}
+
+fn check_variables(root: &Path, variables: &[SnapshotVariable]) -> Result<(), OhMyFSError> {
+    let failed = |details: String| OhMyFSError::SnapshotFailed {
+        path: root.to_string_lossy().to_string(),
+        details,
+    };
+    for (index, variable) in variables.iter().enumerate() {
+        let valid_name = variable
+            .name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+            && variable
+                .name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(failed(format!(
+                "{:?} is not a valid variable name",
+                variable.name
+            )));
+        }
+        if variable.value.is_empty() {
+            return Err(failed(format!("variable {} has no value", variable.name)));
+        }
+        if variables[..index]
+            .iter()
+            .any(|other| other.name == variable.name)
+        {
+            return Err(failed(format!(
+                "variable {} is listed twice",
+                variable.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn snapshot(
+    root: &Path,
+    options: SnapshotOptions,
+) -> Result<FileStructureDefinition, OhMyFSError> {
+    let root_name = root.to_string_lossy().to_string();
+    if !root.exists() {
+        return Err(OhMyFSError::DirectoryNotFound { path: root_name });
+    }
+    if !root.is_dir() {
+        return Err(OhMyFSError::PathNotDirectory { path: root_name });
+    }
+    check_variables(root, &options.variables)?;
+
+    let mut replacements = options
+        .variables
+        .iter()
+        .map(|variable| {
+            (
+                variable.value.as_str(),
+                format!("{{{{{}}}}}", variable.name),
+            )
+        })
+        .collect::<Vec<_>>();
+    replacements.sort_by_key(|(literal, _)| std::cmp::Reverse(literal.len()));
+    let mut snapshot = Snapshot {
+        root,
+        ignore: IgnoreRules::new(&options.ignore_patterns),
+        max_embed_size: options.max_embed_size.unwrap_or(DEFAULT_MAX_EMBED_SIZE),
+        replacements,
+        referenced: Vec::new(),
+    };
+    let structure = snapshot.capture_folder(root)?;
+
+    let base_path = options
+        .base_path
+        .unwrap_or_else(|| snapshot.substitute(&root_name));
+    let mut metadata = serde_json::Map::new();
+    metadata.insert("source".to_string(), Value::String(root_name.clone()));
+    metadata.insert(
+        "capturedAt".to_string(),
+        Value::String(to_epoch_millis(SystemTime::now())),
+    );
+    metadata.insert(
+        "referencedFiles".to_string(),
+        Value::from(std::mem::take(&mut snapshot.referenced)),
+    );
+
+    Ok(FileStructureDefinition {
+        name: options.name.unwrap_or_else(|| {
+            root.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| root_name.clone())
+        }),
+        description: Some(
+            options
+                .description
+                .unwrap_or_else(|| format!("Captured from {}", root_name)),
+        ),
+        version: "1.0.0".to_string(),
+        base_path,
+        variables: options
+            .variables
+            .into_iter()
+            .map(|variable| Variable {
+                name: variable.name,
+                kind: VariableType::String,
+                default_value: Some(Value::String(variable.value)),
+                description: variable.description,
+                validation: None,
+            })
+            .collect(),
+        structure,
+        ignore_patterns: (!options.ignore_patterns.is_empty()).then_some(options.ignore_patterns),
+        metadata: Some(metadata),
+    })
+}
+
+#[tauri::command]
+pub async fn snapshot_to_definition(
+    path: String,
+    options: Option<SnapshotOptions>,
+) -> Result<FileStructureDefinition, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let root = PathBuf::from(&path);
+        let definition = snapshot(&root, options.unwrap_or_default()).map_err(|err| {
+            log_error!("Failed to capture {}: {}", path, err);
+            err.to_string()
+        })?;
+        log_info!("Captured {} as definition {}", path, definition.name);
+        Ok(definition)
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::definition::{ResolvedFileSystemNode, VariableValues};
+    use crate::engine::resolve::resolve;
+
+    fn variable(name: &str, value: &str) -> SnapshotVariable {
+        SnapshotVariable {
+            name: name.to_string(),
+            value: value.to_string(),
+            description: None,
+        }
+    }
+
+    fn node<'a>(nodes: &'a [ResolvedFileSystemNode], path: &Path) -> &'a ResolvedFileSystemNode {
+        nodes
+            .iter()
+            .find(|node| Path::new(&node.path) == path)
+            .unwrap()
+    }
+
+    #[test]
+    fn captured_text_renders_back_to_the_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("acme-app");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("node_modules/left-pad")).unwrap();
+        let readme = "# acme-app\nUse {{ braces }} or \\{{ escaped }} in acme-app\\acme-app\n";
+        fs::write(root.join("README.md"), readme).unwrap();
+        fs::write(root.join("src/acme-app.ts"), "export {};\n").unwrap();
+        fs::write(root.join("logo.png"), [0x89, b'P', b'N', b'G', 0, 1]).unwrap();
+        fs::write(root.join("big.txt"), "x".repeat(100)).unwrap();
+
+        let definition = snapshot(
+            &root,
+            SnapshotOptions {
+                ignore_patterns: vec!["node_modules/".to_string()],
+                max_embed_size: Some(80),
+                variables: vec![variable("project", "acme-app")],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let names = definition
+            .structure
+            .iter()
+            .map(|entry| entry.name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["README.md", "big.txt", "logo.png", "src"]);
+        let referenced = &definition.metadata.as_ref().unwrap()["referencedFiles"];
+        assert_eq!(referenced, &serde_json::json!(["big.txt", "logo.png"]));
+
+        let resolved = resolve(&definition, &VariableValues::new()).unwrap();
+        assert_eq!(resolved.base_path, root);
+        assert_eq!(
+            node(&resolved.nodes, &root.join("README.md"))
+                .resolved_content
+                .as_deref(),
+            Some(readme)
+        );
+        assert!(resolved
+            .nodes
+            .iter()
+            .any(|node| Path::new(&node.path) == root.join("src/acme-app.ts")));
+        assert_eq!(
+            node(&resolved.nodes, &root.join("logo.png")).resolved_content,
+            None
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_files_are_referenced_instead_of_failing() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let secret = dir.path().join("secret.txt");
+        fs::write(&secret, "hidden").unwrap();
+        fs::write(dir.path().join("notes.txt"), "notes").unwrap();
+        fs::set_permissions(&secret, fs::Permissions::from_mode(0o000)).unwrap();
+        // Permissions do not apply to root
+        if fs::read(&secret).is_ok() {
+            return;
+        }
+
+        let definition = snapshot(dir.path(), SnapshotOptions::default()).unwrap();
+        fs::set_permissions(&secret, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let referenced = &definition.metadata.as_ref().unwrap()["referencedFiles"];
+        assert_eq!(referenced, &serde_json::json!(["secret.txt"]));
+        let FileSystemDefinition::File(file) = &definition.structure[1] else {
+            panic!("secret.txt should stay a file entry");
+        };
+        assert!(file.content.is_none());
+        assert!(file
+            .description
+            .as_deref()
+            .is_some_and(|description| description.starts_with("Not embedded (unreadable")));
+    }
+
+    #[test]
+    fn variables_are_checked_before_capturing() {
+        let dir = tempfile::tempdir().unwrap();
+        for variables in [
+            vec![variable("1st", "x")],
+            vec![variable("name", "")],
+            vec![variable("name", "a"), variable("name", "b")],
+        ] {
+            let options = SnapshotOptions {
+                variables,
+                ..Default::default()
+            };
+            assert!(matches!(
+                snapshot(dir.path(), options),
+                Err(OhMyFSError::SnapshotFailed { .. })
+            ));
+        }
+    }
+}
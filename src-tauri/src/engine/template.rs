@@ -0,0 +1,805 @@
+// Template rendering for definition names, targets, paths and file contents
+//
+// The syntax is a small Handlebars-like language:
+//
+//   {{ name }}                     a variable, `{{ author.email }}` for fields
+//   {{ name | kebab }}             filters: kebab, snake, pascal, camel, upper,
+//                                  lower, default("value"), date("%Y-%m-%d")
+//   {{#if name}}..{{else}}..{{/if}}, {{#unless name}}..{{/unless}}
+//   {{#each items}}..{{/each}}     `this`, `this.field` or just `field`, plus
+//                                  `@index`, `@first` and `@last`
+//
+// `now` is the current time unless a variable of that name is defined. A tag
+// that is not a block or an expression, like `{{ margin: 0 }}` in JSX, is kept
+// as text. As in Handlebars, `\{{` writes a literal `{{` and `\\{{` writes a
+// backslash followed by the tag's value. Block tags alone on their line take
+// the line with them so they leave no blank lines behind.
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use serde_json::Value;
+use std::fmt::Write;
+
+use super::definition::VariableValues;
+use crate::OhMyFSError;
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Clone, Copy)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+struct Filter {
+    name: String,
+    argument: Option<Value>,
+}
+
+struct Expression {
+    path: String,
+    filters: Vec<Filter>,
+    position: Position,
+}
+
+enum Node {
+    Text(String),
+    Output(Expression),
+    If {
+        expression: Expression,
+        negate: bool,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    Each {
+        expression: Expression,
+        body: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+enum Token {
+    Text(String),
+    Output(Expression),
+    Open {
+        block: String,
+        expression: Expression,
+    },
+    Else(Position),
+    Close {
+        block: String,
+        position: Position,
+    },
+}
+
+// One `{{#each}}` level while rendering
+struct Scope {
+    item: Value,
+    index: usize,
+    last: bool,
+}
+
+pub(crate) fn render(
+    template: &str,
+    variables: &VariableValues,
+    location: &str,
+) -> Result<String, OhMyFSError> {
+    if !template.contains("{{") {
+        return Ok(template.to_string());
+    }
+    let nodes = parse(template, location)?;
+    let mut renderer = Renderer {
+        variables,
+        location,
+        scopes: Vec::new(),
+    };
+    let mut output = String::with_capacity(template.len());
+    renderer.render_nodes(&nodes, &mut output)?;
+    Ok(output)
+}
+
+// Renders every string in structured content, object keys included
+pub(crate) fn render_value(
+    value: &Value,
+    variables: &VariableValues,
+    location: &str,
+) -> Result<Value, OhMyFSError> {
+    Ok(match value {
+        Value::String(text) => Value::String(render(text, variables, location)?),
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| {
+                    render_value(item, variables, &format!("{}[{}]", location, index))
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(fields) => {
+            let mut rendered = serde_json::Map::new();
+            for (key, field) in fields {
+                let location = format!("{}.{}", location, key);
+                rendered.insert(
+                    render(key, variables, &location)?,
+                    render_value(field, variables, &location)?,
+                );
+            }
+            Value::Object(rendered)
+        }
+        value => value.clone(),
+    })
+}
+
+// The way JavaScript's String() prints values, which existing templates expect
+pub(crate) fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        Value::Array(items) => items
+            .iter()
+            .map(value_to_string)
+            .collect::<Vec<_>>()
+            .join(","),
+        // `2.0` prints as `2`, like it does in JavaScript
+        Value::Number(number) => match number.as_f64() {
+            Some(float) if number.is_f64() && float.fract() == 0.0 && float.abs() < 1e15 => {
+                (float as i64).to_string()
+            }
+            _ => number.to_string(),
+        },
+        value => value.to_string(),
+    }
+}
+
+fn position_of(template: &str, offset: usize) -> Position {
+    let before = &template[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    Position {
+        line: before.matches('\n').count() + 1,
+        column: before[line_start..].chars().count() + 1,
+    }
+}
+
+fn at(location: &str, position: Position) -> String {
+    format!(
+        "{}, line {} column {}",
+        location, position.line, position.column
+    )
+}
+
+fn invalid(location: &str, position: Position, details: &str) -> OhMyFSError {
+    OhMyFSError::InvalidTemplate {
+        location: at(location, position),
+        details: details.to_string(),
+    }
+}
+
+fn tokenize(template: &str, location: &str) -> Result<Vec<Token>, OhMyFSError> {
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut cursor = 0;
+    while let Some(found) = template[cursor..].find("{{") {
+        let start = cursor + found;
+        // Backslashes before a tag pair up into one each, and an odd one left
+        // over escapes the tag
+        let before_tag = &template[cursor..start];
+        let slashes = before_tag.len() - before_tag.trim_end_matches('\\').len();
+        if slashes > 0 {
+            text.push_str(&template[cursor..start - slashes]);
+            text.push_str(&"\\".repeat(slashes / 2));
+            if slashes % 2 == 1 {
+                text.push_str("{{");
+                cursor = start + 2;
+                continue;
+            }
+            cursor = start;
+        }
+        let Some(length) = template[start + 2..].find("}}") else {
+            break;
+        };
+        let end = start + 2 + length + 2;
+        let tag = template[start + 2..end - 2].trim();
+        let position = position_of(template, start);
+
+        let opened = tag
+            .strip_prefix('#')
+            .map(|rest| rest.split_once(char::is_whitespace).unwrap_or((rest, "")))
+            .filter(|(block, _)| is_block(block));
+        let closed = tag
+            .strip_prefix('/')
+            .map(str::trim)
+            .filter(|block| is_block(block));
+
+        let token = if let Some((block, rest)) = opened {
+            let expression = parse_expression(rest.trim(), position).ok_or_else(|| {
+                invalid(
+                    location,
+                    position,
+                    &format!("{{{{#{}}}}} needs a variable", block),
+                )
+            })?;
+            Token::Open {
+                block: block.to_string(),
+                expression,
+            }
+        } else if let Some(block) = closed {
+            Token::Close {
+                block: block.to_string(),
+                position,
+            }
+        } else if tag == "else" {
+            Token::Else(position)
+        } else if let Some(expression) = parse_expression(tag, position) {
+            Token::Output(expression)
+        } else {
+            text.push_str(&template[cursor..end]);
+            cursor = end;
+            continue;
+        };
+
+        let mut before = &template[cursor..start];
+        let mut after = end;
+        if !matches!(token, Token::Output(_)) {
+            let line_start = template[..start].rfind('\n').map_or(0, |index| index + 1);
+            let line_end = template[end..]
+                .find('\n')
+                .map_or(template.len(), |index| end + index + 1);
+            if line_start >= cursor
+                && template[line_start..start].trim().is_empty()
+                && template[end..line_end].trim().is_empty()
+            {
+                before = &template[cursor..line_start];
+                after = line_end;
+            }
+        }
+        text.push_str(before);
+        if !text.is_empty() {
+            tokens.push(Token::Text(std::mem::take(&mut text)));
+        }
+        tokens.push(token);
+        cursor = after;
+    }
+    text.push_str(&template[cursor..]);
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+    Ok(tokens)
+}
+
+fn is_block(name: &str) -> bool {
+    matches!(name, "if" | "unless" | "each")
+}
+
+// `path | filter | filter("argument")`, or None when the tag is something else
+fn parse_expression(tag: &str, position: Position) -> Option<Expression> {
+    let mut parts = split_filters(tag)?.into_iter();
+    let path = parts.next()?.trim();
+    let valid_path = path.split('.').all(|segment| {
+        let segment = segment.strip_prefix('@').unwrap_or(segment);
+        segment
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+            && segment
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    });
+    if !valid_path {
+        return None;
+    }
+
+    let mut filters = Vec::new();
+    for part in parts {
+        let part = part.trim();
+        let (name, argument) = match part.split_once('(') {
+            Some((name, rest)) => {
+                let argument = rest.strip_suffix(')')?.trim();
+                (name.trim(), Some(parse_argument(argument)?))
+            }
+            None => (part, None),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return None;
+        }
+        filters.push(Filter {
+            name: name.to_string(),
+            argument,
+        });
+    }
+    Some(Expression {
+        path: path.to_string(),
+        filters,
+        position,
+    })
+}
+
+// Splits on `|` outside quoted arguments
+fn split_filters(tag: &str) -> Option<Vec<&str>> {
+    let mut parts = Vec::new();
+    let mut quote = None;
+    let mut escaped = false;
+    let mut start = 0;
+    for (index, c) in tag.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '|' => {
+                parts.push(&tag[start..index]);
+                start = index + 1;
+            }
+            None => {}
+        }
+    }
+    if quote.is_some() {
+        return None;
+    }
+    parts.push(&tag[start..]);
+    Some(parts)
+}
+
+// A JSON literal, or a single-quoted string
+fn parse_argument(argument: &str) -> Option<Value> {
+    if let Some(text) = argument
+        .strip_prefix('\'')
+        .and_then(|rest| rest.strip_suffix('\''))
+    {
+        return Some(Value::String(text.replace("\\'", "'")));
+    }
+    serde_json::from_str(argument).ok()
+}
+
+fn parse(template: &str, location: &str) -> Result<Vec<Node>, OhMyFSError> {
+    let mut tokens = tokenize(template, location)?.into_iter();
+    let (nodes, end) = parse_nodes(&mut tokens, location)?;
+    match end {
+        Some(Token::Else(position)) => {
+            Err(invalid(location, position, "{{else}} outside of a block"))
+        }
+        Some(Token::Close { block, position }) => Err(invalid(
+            location,
+            position,
+            &format!("{{{{/{}}}}} closes a block that was never opened", block),
+        )),
+        _ => Ok(nodes),
+    }
+}
+
+// Collects nodes up to the `{{else}}` or closing tag that ends them
+fn parse_nodes(
+    tokens: &mut impl Iterator<Item = Token>,
+    location: &str,
+) -> Result<(Vec<Node>, Option<Token>), OhMyFSError> {
+    let mut nodes = Vec::new();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Text(text) => nodes.push(Node::Text(text)),
+            Token::Output(expression) => nodes.push(Node::Output(expression)),
+            Token::Open { block, expression } => {
+                let opened = expression.position;
+                let (body, mut end) = parse_nodes(tokens, location)?;
+                let mut otherwise = Vec::new();
+                if let Some(Token::Else(_)) = end {
+                    (otherwise, end) = parse_nodes(tokens, location)?;
+                }
+                match end {
+                    Some(Token::Close { block: closed, .. }) if closed == block => {}
+                    Some(Token::Close {
+                        block: closed,
+                        position,
+                    }) => {
+                        return Err(invalid(
+                            location,
+                            position,
+                            &format!(
+                                "{{{{/{}}}}} does not match {{{{#{}}}}} at line {} column {}",
+                                closed, block, opened.line, opened.column
+                            ),
+                        ))
+                    }
+                    Some(Token::Else(position)) => {
+                        return Err(invalid(
+                            location,
+                            position,
+                            &format!("{{{{#{}}}}} has a second {{{{else}}}}", block),
+                        ))
+                    }
+                    _ => {
+                        return Err(invalid(
+                            location,
+                            opened,
+                            &format!("{{{{#{}}}}} is never closed", block),
+                        ))
+                    }
+                }
+                nodes.push(if block == "each" {
+                    Node::Each {
+                        expression,
+                        body,
+                        otherwise,
+                    }
+                } else {
+                    Node::If {
+                        expression,
+                        negate: block == "unless",
+                        then: body,
+                        otherwise,
+                    }
+                });
+            }
+            end => return Ok((nodes, Some(end))),
+        }
+    }
+    Ok((nodes, None))
+}
+
+struct Renderer<'a> {
+    variables: &'a VariableValues,
+    location: &'a str,
+    scopes: Vec<Scope>,
+}
+
+impl Renderer<'_> {
+    fn render_nodes(&mut self, nodes: &[Node], output: &mut String) -> Result<(), OhMyFSError> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => output.push_str(text),
+                Node::Output(expression) => {
+                    let value = self.evaluate(expression)?.ok_or_else(|| {
+                        OhMyFSError::UndefinedVariable {
+                            name: expression.path.clone(),
+                            location: at(self.location, expression.position),
+                        }
+                    })?;
+                    output.push_str(&value_to_string(&value));
+                }
+                Node::If {
+                    expression,
+                    negate,
+                    then,
+                    otherwise,
+                } => {
+                    // An undefined variable is simply false here
+                    let truthy = self.evaluate(expression)?.as_ref().is_some_and(is_truthy);
+                    let branch = if truthy != *negate { then } else { otherwise };
+                    self.render_nodes(branch, output)?;
+                }
+                Node::Each {
+                    expression,
+                    body,
+                    otherwise,
+                } => {
+                    let items = match self.evaluate(expression)? {
+                        Some(Value::Array(items)) => items,
+                        Some(Value::Null) => Vec::new(),
+                        Some(_) => {
+                            return Err(invalid(
+                                self.location,
+                                expression.position,
+                                &format!("{} is not a list", expression.path),
+                            ))
+                        }
+                        None => {
+                            return Err(OhMyFSError::UndefinedVariable {
+                                name: expression.path.clone(),
+                                location: at(self.location, expression.position),
+                            })
+                        }
+                    };
+                    if items.is_empty() {
+                        self.render_nodes(otherwise, output)?;
+                    }
+                    let count = items.len();
+                    for (index, item) in items.into_iter().enumerate() {
+                        self.scopes.push(Scope {
+                            item,
+                            index,
+                            last: index + 1 == count,
+                        });
+                        let rendered = self.render_nodes(body, output);
+                        self.scopes.pop();
+                        rendered?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn evaluate(&self, expression: &Expression) -> Result<Option<Value>, OhMyFSError> {
+        let mut value = self.lookup(&expression.path);
+        for filter in &expression.filters {
+            value = self.apply(filter, value, expression)?;
+        }
+        Ok(value)
+    }
+
+    fn lookup(&self, path: &str) -> Option<Value> {
+        let scope = self.scopes.last();
+        match path {
+            "@index" => return scope.map(|scope| Value::from(scope.index)),
+            "@first" => return scope.map(|scope| Value::Bool(scope.index == 0)),
+            "@last" => return scope.map(|scope| Value::Bool(scope.last)),
+            _ => {}
+        }
+
+        let mut segments = path.split('.');
+        let first = segments.next()?;
+        let mut value = if first == "this" {
+            scope?.item.clone()
+        } else if let Some(found) = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.item.get(first))
+        {
+            found.clone()
+        } else if let Some(found) = self.variables.get(first) {
+            found.clone()
+        } else if first == "now" {
+            Value::String(Local::now().to_rfc3339())
+        } else {
+            return None;
+        };
+        for segment in segments {
+            value = match &value {
+                Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?.clone(),
+                value => value.get(segment)?.clone(),
+            };
+        }
+        Some(value)
+    }
+
+    fn apply(
+        &self,
+        filter: &Filter,
+        value: Option<Value>,
+        expression: &Expression,
+    ) -> Result<Option<Value>, OhMyFSError> {
+        let fail = |details: String| invalid(self.location, expression.position, &details);
+        if filter.name == "default" {
+            let fallback = filter
+                .argument
+                .clone()
+                .ok_or_else(|| fail("default needs a value, like default(\"x\")".to_string()))?;
+            return Ok(match value {
+                None | Some(Value::Null) => Some(fallback),
+                Some(Value::String(text)) if text.is_empty() => Some(fallback),
+                value => value,
+            });
+        }
+
+        if !matches!(
+            filter.name.as_str(),
+            "kebab" | "snake" | "pascal" | "camel" | "upper" | "lower" | "date"
+        ) {
+            return Err(fail(format!("unknown filter {}", filter.name)));
+        }
+        // Undefined stays undefined so the error names the variable
+        let Some(value) = value else {
+            return Ok(None);
+        };
+        let text = value_to_string(&value);
+        Ok(Some(Value::String(match filter.name.as_str() {
+            "kebab" => words(&text).join("-").to_lowercase(),
+            "snake" => words(&text).join("_").to_lowercase(),
+            "pascal" => words(&text).iter().map(|word| capitalize(word)).collect(),
+            "camel" => words(&text)
+                .iter()
+                .enumerate()
+                .map(|(index, word)| {
+                    if index == 0 {
+                        word.to_lowercase()
+                    } else {
+                        capitalize(word)
+                    }
+                })
+                .collect(),
+            "upper" => text.to_uppercase(),
+            "lower" => text.to_lowercase(),
+            _ => {
+                let format = match &filter.argument {
+                    None => DEFAULT_DATE_FORMAT.to_string(),
+                    Some(Value::String(format)) => format.clone(),
+                    Some(_) => return Err(fail("date takes a format string".to_string())),
+                };
+                let date = to_date(&value).ok_or_else(|| {
+                    fail(format!("{} is not a date: {:?}", expression.path, text))
+                })?;
+                let mut formatted = String::new();
+                write!(formatted, "{}", date.format(&format))
+                    .map_err(|_| fail(format!("{:?} is not a valid date format", format)))?;
+                formatted
+            }
+        })))
+    }
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(value) => *value,
+        Value::Number(number) => number.as_f64().is_some_and(|number| number != 0.0),
+        Value::String(text) => !text.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(_) => true,
+    }
+}
+
+// Milliseconds since the epoch, RFC 3339 or a plain `YYYY-MM-DD`
+fn to_date(value: &Value) -> Option<DateTime<Local>> {
+    let millis = match value {
+        Value::Number(number) => number.as_i64(),
+        Value::String(text) => text.trim().parse::<i64>().ok(),
+        _ => return None,
+    };
+    if let Some(millis) = millis {
+        return Local.timestamp_millis_opt(millis).single();
+    }
+    let text = value.as_str()?.trim();
+    DateTime::parse_from_rfc3339(text)
+        .map(|date| date.with_timezone(&Local))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)?
+                .and_local_timezone(Local)
+                .single()
+        })
+}
+
+// Splits `myHTTPServer_v2` into my, HTTP, Server, v2
+fn words(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut words = Vec::new();
+    let mut current = String::new();
+    for (index, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        if !current.is_empty() && c.is_uppercase() {
+            let previous = chars[index - 1];
+            let next_is_lower = chars.get(index + 1).is_some_and(|next| next.is_lowercase());
+            if previous.is_lowercase()
+                || previous.is_numeric()
+                || (previous.is_uppercase() && next_is_lower)
+            {
+                words.push(std::mem::take(&mut current));
+            }
+        }
+        current.push(c);
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn variables(values: Value) -> VariableValues {
+        match values {
+            Value::Object(values) => values,
+            _ => panic!("variables must be an object"),
+        }
+    }
+
+    fn render_with(template: &str, values: Value) -> String {
+        render(template, &variables(values), "test").unwrap()
+    }
+
+    #[test]
+    fn filters_convert_case_and_fill_defaults() {
+        let values = json!({ "name": "myHTTPServer_v2", "empty": "" });
+        assert_eq!(
+            render_with(
+                "{{name|kebab}} {{ name | snake }} {{name|pascal}} {{name|camel}}",
+                values.clone()
+            ),
+            "my-http-server-v2 my_http_server_v2 MyHttpServerV2 myHttpServerV2"
+        );
+        assert_eq!(
+            render_with(
+                "{{ missing | kebab | default(\"x\") }}-{{ empty | default('a|b') }}",
+                values
+            ),
+            "x-a|b"
+        );
+    }
+
+    #[test]
+    fn dates_and_numbers_print_like_the_frontend() {
+        let values = json!({ "day": "2024-03-05", "count": 2.0, "ratio": 0.5 });
+        assert_eq!(
+            render_with(
+                "{{ day | date(\"%d/%m/%Y\") }} {{ day | date }}",
+                values.clone()
+            ),
+            "05/03/2024 2024-03-05"
+        );
+        assert_eq!(render_with("{{count}} {{ratio}}", values), "2 0.5");
+    }
+
+    #[test]
+    fn blocks_branch_and_loop() {
+        let values = json!({
+            "private": false,
+            "owner": "me",
+            "deps": [{ "name": "serde" }, { "name": "tauri" }],
+            "none": []
+        });
+        assert_eq!(
+            render_with(
+                "{{#if private}}closed{{else}}open{{/if}} {{#unless private}}by {{owner}}{{/unless}}",
+                values.clone()
+            ),
+            "open by me"
+        );
+        assert_eq!(
+            render_with(
+                "{{#each deps}}{{@index}}:{{name}}/{{this.name}}@{{owner}}{{#unless @last}}, {{/unless}}{{/each}}",
+                values.clone()
+            ),
+            "0:serde/serde@me, 1:tauri/tauri@me"
+        );
+        assert_eq!(
+            render_with("{{#each none}}x{{else}}empty{{/each}}", values),
+            "empty"
+        );
+    }
+
+    #[test]
+    fn block_lines_leave_no_blank_lines_and_other_braces_stay_text() {
+        assert_eq!(
+            render_with("a\n  {{#if yes}}\nb\n{{/if}}\nc", json!({ "yes": true })),
+            "a\nb\nc"
+        );
+        assert_eq!(
+            render_with("style={{ margin: 0 }} \\{{name}}", json!({})),
+            "style={{ margin: 0 }} {{name}}"
+        );
+        assert_eq!(
+            render_with("a\\\\{{name}} b\\\\\\{{name}}", json!({ "name": "x" })),
+            "a\\x b\\{{name}}"
+        );
+    }
+
+    #[test]
+    fn errors_name_the_variable_and_where_it_is() {
+        let empty = VariableValues::new();
+        let err = render("a\n  {{ nope }}", &empty, "structure[0].name").unwrap_err();
+        assert!(matches!(
+            err,
+            OhMyFSError::UndefinedVariable { name, location }
+                if name == "nope" && location == "structure[0].name, line 2 column 3"
+        ));
+
+        let err = render("{{#if x}}\n{{#each y}}{{/if}}", &empty, "t").unwrap_err();
+        assert!(matches!(
+            err,
+            OhMyFSError::InvalidTemplate { location, .. } if location == "t, line 2 column 12"
+        ));
+        assert!(matches!(
+            render("{{ x | shout }}", &variables(json!({ "x": 1 })), "t"),
+            Err(OhMyFSError::InvalidTemplate { details, .. }) if details == "unknown filter shout"
+        ));
+    }
+}
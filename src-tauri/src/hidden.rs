@@ -0,0 +1,139 @@
+// Which entries count as hidden
+//
+// Dotfiles, names listed one per line in the folder's `.hidden` file (the
+// convention GNOME Files and Dolphin follow) and, on Windows, entries with the
+// hidden attribute.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+pub(crate) const HIDDEN_FILE: &str = ".hidden";
+
+pub(crate) fn is_dotfile(name: &str) -> bool {
+    name.starts_with('.')
+}
+
+// Hidden by the file itself, without looking at its folder's `.hidden` list
+pub(crate) fn is_hidden_entry(name: &str, metadata: &fs::Metadata) -> bool {
+    is_dotfile(name) || has_hidden_attribute(metadata)
+}
+
+#[cfg(windows)]
+fn has_hidden_attribute(metadata: &fs::Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0
+}
+
+#[cfg(not(windows))]
+fn has_hidden_attribute(_: &fs::Metadata) -> bool {
+    false
+}
+
+// The names a folder's `.hidden` file lists
+#[derive(Default)]
+pub(crate) struct HiddenNames {
+    names: HashSet<String>,
+}
+
+impl HiddenNames {
+    // A missing or unreadable `.hidden` file hides nothing
+    pub fn for_directory(directory: &Path) -> Self {
+        let names = fs::read_to_string(directory.join(HIDDEN_FILE))
+            .map(|contents| {
+                contents
+                    .lines()
+                    .map(|line| line.trim_end_matches('\r'))
+                    .filter(|line| !line.is_empty())
+                    .map(|line| line.trim_end_matches('/').to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { names }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains(name)
+    }
+
+    // Whether an entry of the folder these names were read from is hidden.
+    // Its metadata is only read where hidden attributes exist.
+    pub fn hides(&self, entry: &fs::DirEntry) -> bool {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        is_dotfile(&name)
+            || self.contains(&name)
+            || (cfg!(windows)
+                && entry
+                    .metadata()
+                    .is_ok_and(|metadata| has_hidden_attribute(&metadata)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(directory: &Path, name: &str) -> fs::DirEntry {
+        fs::read_dir(directory)
+            .unwrap()
+            .flatten()
+            .find(|entry| entry.file_name() == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn dotfiles() {
+        assert!(is_dotfile(".git"));
+        assert!(is_dotfile(".hidden"));
+        assert!(!is_dotfile("notes.txt"));
+        assert!(!is_dotfile("notes.txt."));
+        assert!(!is_dotfile(""));
+    }
+
+    #[test]
+    fn parses_hidden_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(HIDDEN_FILE),
+            "build\r\n\ndist/\nnotes .txt\n  padded\n",
+        )
+        .unwrap();
+        let names = HiddenNames::for_directory(dir.path());
+        assert!(names.contains("build"));
+        assert!(names.contains("dist"));
+        assert!(names.contains("notes .txt"));
+        // Only line endings and trailing slashes are stripped
+        assert!(names.contains("  padded"));
+        assert!(!names.contains("padded"));
+        assert!(!names.contains(""));
+        assert!(!names.contains("dist/"));
+
+        let empty = tempfile::tempdir().unwrap();
+        assert!(!HiddenNames::for_directory(empty.path()).contains("build"));
+    }
+
+    #[test]
+    fn hides_dotfiles_and_listed_names() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [".env", "build", "src"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        fs::write(dir.path().join(HIDDEN_FILE), "build\n").unwrap();
+        let names = HiddenNames::for_directory(dir.path());
+        assert!(names.hides(&entry(dir.path(), ".env")));
+        assert!(names.hides(&entry(dir.path(), HIDDEN_FILE)));
+        assert!(names.hides(&entry(dir.path(), "build")));
+        assert!(!names.hides(&entry(dir.path(), "src")));
+
+        // Nothing listed still hides dotfiles
+        let unlisted = HiddenNames::default();
+        assert!(unlisted.hides(&entry(dir.path(), ".env")));
+        assert!(!unlisted.hides(&entry(dir.path(), "build")));
+
+        let metadata = fs::metadata(dir.path().join(".env")).unwrap();
+        assert!(is_hidden_entry(".env", &metadata));
+        assert!(!is_hidden_entry("build", &metadata));
+    }
+}
@@ -0,0 +1,423 @@
+// `.gitignore`-style patterns, as modelled by `ignorePatterns` in the engine types
+//
+// Patterns follow gitignore rules: the last matching pattern wins, `!`
+// re-includes, a trailing `/` only matches directories and a pattern with a
+// slash in it is anchored to the directory the rules are relative to.
+//
+// `GitIgnores` gathers the ignore files that apply inside a folder: the global
+// git excludes, `.git/info/exclude` and every `.gitignore` and `.ignore` from
+// the repository root down.
+
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+struct IgnoreRule {
+    regex: Regex,
+    negated: bool,
+    directory_only: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct IgnoreRules {
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreRules {
+    // Blank lines, comments and patterns that cannot be compiled are skipped,
+    // the same way git treats them
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Self {
+        Self {
+            rules: patterns
+                .iter()
+                .filter_map(|pattern| compile(pattern.as_ref()))
+                .collect(),
+        }
+    }
+
+    // Some(true) when the last matching pattern ignores the path, Some(false)
+    // when it re-includes it and None when no pattern matches. `relative` is
+    // relative to the directory the patterns apply to.
+    pub fn matched(&self, relative: &Path, is_dir: bool) -> Option<bool> {
+        let path = relative.to_string_lossy().replace('\\', "/");
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.directory_only) && rule.regex.is_match(&path))
+            .map(|rule| !rule.negated)
+    }
+
+    // Only looks at the path itself; walkers do not descend into ignored
+    // directories, which takes care of everything below them
+    pub fn is_ignored(&self, relative: &Path, is_dir: bool) -> bool {
+        self.matched(relative, is_dir).unwrap_or(false)
+    }
+}
+
+// The rules of one ignore file and the folder they are relative to
+struct IgnoreFile {
+    base: PathBuf,
+    rules: IgnoreRules,
+}
+
+impl IgnoreFile {
+    fn read(file: &Path, base: &Path) -> Option<Arc<Self>> {
+        let contents = fs::read_to_string(file).ok()?;
+        let rules = IgnoreRules::new(&contents.lines().collect::<Vec<_>>());
+        (!rules.rules.is_empty()).then(|| {
+            Arc::new(IgnoreFile {
+                base: base.to_path_buf(),
+                rules,
+            })
+        })
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct GitIgnores {
+    // Lowest precedence first
+    files: Vec<Arc<IgnoreFile>>,
+    in_repository: bool,
+    // The folder itself is ignored, and with it everything inside
+    ignored: bool,
+}
+
+impl GitIgnores {
+    // Everything that applies to the entries of `directory`. Outside a git
+    // repository only `.ignore` files count.
+    pub fn for_directory(directory: &Path) -> Self {
+        let repository = directory
+            .ancestors()
+            .find(|ancestor| ancestor.join(".git").exists());
+        let mut ignores = GitIgnores {
+            in_repository: repository.is_some(),
+            ..Default::default()
+        };
+
+        if let Some(repository) = repository {
+            if let Some(file) = global_excludes_file() {
+                ignores.push(&file, repository);
+            }
+            ignores.push(&repository.join(".git/info/exclude"), repository);
+        }
+
+        let top = repository.unwrap_or_else(|| directory.ancestors().last().unwrap_or(directory));
+        let mut folders = directory
+            .ancestors()
+            .take_while(|ancestor| ancestor.starts_with(top))
+            .collect::<Vec<_>>();
+        folders.reverse();
+        for folder in folders {
+            if folder != top && ignores.is_ignored(folder, true) {
+                ignores.ignored = true;
+            }
+            ignores.load(folder);
+        }
+        ignores
+    }
+
+    // The rules for a subfolder, adding its own ignore files. A nested
+    // repository starts over with its own rules.
+    pub fn descend(&self, directory: &Path) -> Self {
+        if directory.join(".git").exists() {
+            return Self::for_directory(directory);
+        }
+        let mut ignores = self.clone();
+        ignores.ignored = self.is_ignored(directory, true);
+        ignores.load(directory);
+        ignores
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if self.ignored {
+            return true;
+        }
+        // Git never lists its own folder
+        if self.in_repository && path.file_name().is_some_and(|name| name == ".git") {
+            return true;
+        }
+        self.files
+            .iter()
+            .rev()
+            .filter_map(|file| {
+                let relative = path.strip_prefix(&file.base).ok()?;
+                if relative.as_os_str().is_empty() {
+                    return None;
+                }
+                file.rules.matched(relative, is_dir)
+            })
+            .next()
+            .unwrap_or(false)
+    }
+
+    // `.ignore` is read after `.gitignore` so it can override it, as ripgrep does
+    fn load(&mut self, folder: &Path) {
+        if self.in_repository {
+            self.push(&folder.join(".gitignore"), folder);
+        }
+        self.push(&folder.join(".ignore"), folder);
+    }
+
+    fn push(&mut self, file: &Path, base: &Path) {
+        if let Some(file) = IgnoreFile::read(file, base) {
+            self.files.push(file);
+        }
+    }
+}
+
+// `core.excludesFile` from the user's git config, or git's default of
+// `$XDG_CONFIG_HOME/git/ignore`
+fn global_excludes_file() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|home| home.join(".config")));
+
+    let configs = [
+        config_home.as_ref().map(|dir| dir.join("git/config")),
+        home.as_ref().map(|home| home.join(".gitconfig")),
+    ];
+    // Later files win, as in git
+    let configured = configs
+        .iter()
+        .rev()
+        .flatten()
+        .filter_map(|config| fs::read_to_string(config).ok())
+        .find_map(|config| configured_excludes_file(&config));
+
+    let file = configured.or_else(|| {
+        config_home
+            .as_ref()
+            .map(|dir| dir.join("git/ignore").to_string_lossy().to_string())
+    })?;
+    match (file.strip_prefix("~/"), &home) {
+        (Some(rest), Some(home)) => Some(home.join(rest)),
+        _ => Some(PathBuf::from(file)),
+    }
+}
+
+// Reads `excludesfile` from the `[core]` section of a git config file
+fn configured_excludes_file(config: &str) -> Option<String> {
+    let mut in_core = false;
+    let mut found = None;
+    for line in config.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_core = line
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .trim()
+                .eq_ignore_ascii_case("core");
+            continue;
+        }
+        if !in_core {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            if key.trim().eq_ignore_ascii_case("excludesfile") {
+                found = Some(value.trim().trim_matches('"').to_string());
+            }
+        }
+    }
+    found
+}
+
+fn compile(pattern: &str) -> Option<IgnoreRule> {
+    // Trailing spaces are ignored unless escaped
+    let mut pattern = pattern
+        .trim_start_matches('\u{feff}')
+        .trim_end_matches(['\r', '\n']);
+    while pattern.ends_with(' ') && !pattern.ends_with("\\ ") {
+        pattern = &pattern[..pattern.len() - 1];
+    }
+    if pattern.is_empty() || pattern.starts_with('#') {
+        return None;
+    }
+
+    let negated = pattern.starts_with('!');
+    if negated {
+        pattern = &pattern[1..];
+    }
+    let directory_only = pattern.ends_with('/');
+    let pattern = pattern.trim_end_matches('/');
+    if pattern.is_empty() {
+        return None;
+    }
+
+    // A slash anywhere but the end anchors the pattern; otherwise it matches
+    // a name at any depth
+    let anchored = pattern.contains('/');
+    let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+    let mut regex = String::from("^");
+    if !anchored {
+        regex.push_str("(?:.*/)?");
+    }
+    regex.push_str(&glob_to_regex(pattern));
+    regex.push('$');
+
+    Some(IgnoreRule {
+        regex: Regex::new(&regex).ok()?,
+        negated,
+        directory_only,
+    })
+}
+
+fn glob_to_regex(pattern: &str) -> String {
+    let chars = pattern.chars().collect::<Vec<_>>();
+    let mut regex = String::new();
+    let mut index = 0;
+    while index < chars.len() {
+        match chars[index] {
+            '*' if chars.get(index + 1) == Some(&'*') => {
+                let at_start = index == 0 || chars[index - 1] == '/';
+                let at_end = index + 2 == chars.len();
+                let before_slash = chars.get(index + 2) == Some(&'/');
+                if at_start && before_slash {
+                    // `**/` matches zero or more leading folders
+                    regex.push_str("(?:.*/)?");
+                    index += 3;
+                    continue;
+                }
+                if at_start && at_end {
+                    regex.push_str(".*");
+                } else {
+                    // Elsewhere `**` is just two `*`
+                    regex.push_str("[^/]*");
+                }
+                index += 2;
+                continue;
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => match class_end(&chars, index) {
+                Some(end) => {
+                    regex.push('[');
+                    let mut class = chars[index + 1..end].iter().peekable();
+                    if let Some('!' | '^') = class.peek() {
+                        class.next();
+                        regex.push('^');
+                    }
+                    for &c in class {
+                        if matches!(c, '\\' | '[' | ']' | '&' | '~') {
+                            regex.push('\\');
+                        }
+                        regex.push(c);
+                    }
+                    regex.push(']');
+                    index = end;
+                }
+                None => regex.push_str("\\["),
+            },
+            '\\' if index + 1 < chars.len() => {
+                index += 1;
+                regex.push_str(&regex::escape(&chars[index].to_string()));
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        index += 1;
+    }
+    regex
+}
+
+// Index of the `]` closing the class opened at `start`, if there is one
+fn class_end(chars: &[char], start: usize) -> Option<usize> {
+    let mut index = start + 1;
+    if matches!(chars.get(index), Some('!' | '^')) {
+        index += 1;
+    }
+    // A `]` right after the opening bracket is part of the class
+    if chars.get(index) == Some(&']') {
+        index += 1;
+    }
+    (index..chars.len()).find(|&i| chars[i] == ']')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignored(patterns: &[&str], path: &str, is_dir: bool) -> bool {
+        IgnoreRules::new(patterns).is_ignored(Path::new(path), is_dir)
+    }
+
+    #[test]
+    fn translates_globs() {
+        assert_eq!(glob_to_regex("*.log"), "[^/]*\\.log");
+        assert_eq!(glob_to_regex("file?.txt"), "file[^/]\\.txt");
+        assert_eq!(glob_to_regex("**/build"), "(?:.*/)?build");
+        assert_eq!(glob_to_regex("docs/**"), "docs/.*");
+        assert_eq!(glob_to_regex("a/**/b"), "a/(?:.*/)?b");
+        assert_eq!(glob_to_regex("a**b"), "a[^/]*b");
+        assert_eq!(glob_to_regex("[!a-c]x"), "[^a-c]x");
+        assert_eq!(glob_to_regex("[]]"), "[\\]]");
+        assert_eq!(glob_to_regex("[unclosed"), "\\[unclosed");
+        assert_eq!(glob_to_regex("\\*literal"), "\\*literal");
+    }
+
+    #[test]
+    fn unanchored_patterns_match_at_any_depth() {
+        assert!(ignored(&["*.log"], "debug.log", false));
+        assert!(ignored(&["*.log"], "logs/deep/debug.log", false));
+        assert!(!ignored(&["*.log"], "debug.log.txt", false));
+        assert!(ignored(&["target"], "crates/app/target", true));
+    }
+
+    #[test]
+    fn slashes_anchor_patterns() {
+        assert!(ignored(&["/build"], "build", true));
+        assert!(!ignored(&["/build"], "src/build", true));
+        assert!(ignored(&["docs/*.md"], "docs/intro.md", false));
+        assert!(!ignored(&["docs/*.md"], "docs/guide/intro.md", false));
+        assert!(!ignored(&["docs/*.md"], "site/docs/intro.md", false));
+        assert!(ignored(&["a/**/b"], "a/b", false));
+        assert!(ignored(&["a/**/b"], "a/x/y/b", false));
+        assert!(ignored(&["docs/**"], "docs/x/y.md", false));
+        assert!(!ignored(&["docs/**"], "docs", true));
+    }
+
+    #[test]
+    fn trailing_slash_only_matches_folders() {
+        assert!(ignored(&["cache/"], "cache", true));
+        assert!(!ignored(&["cache/"], "cache", false));
+        assert!(ignored(&["cache/"], "src/cache", true));
+    }
+
+    #[test]
+    fn last_matching_pattern_wins() {
+        let patterns = ["*.log", "!keep.log"];
+        assert!(ignored(&patterns, "debug.log", false));
+        assert!(!ignored(&patterns, "keep.log", false));
+        let rules = IgnoreRules::new(&patterns);
+        assert_eq!(rules.matched(Path::new("keep.log"), false), Some(false));
+        assert_eq!(rules.matched(Path::new("main.rs"), false), None);
+        assert!(ignored(&["!keep.log", "*.log"], "keep.log", false));
+    }
+
+    #[test]
+    fn skips_comments_blanks_and_handles_escapes() {
+        let rules = IgnoreRules::new(&["", "   ", "# comment", "!", "/"]);
+        assert!(rules.rules.is_empty());
+        assert!(ignored(&["\\#notes"], "#notes", false));
+        assert!(ignored(&["\\!important"], "!important", false));
+        assert!(ignored(&["trailing   "], "trailing", false));
+        assert!(ignored(&["space\\ "], "space ", false));
+        assert!(!ignored(&["space\\ "], "space", false));
+        assert!(ignored(&["\u{feff}*.tmp\r"], "a.tmp", false));
+    }
+
+    #[test]
+    fn windows_separators_are_normalized() {
+        assert!(ignored(&["docs/*.md"], "docs\\intro.md", false));
+    }
+
+    #[test]
+    fn reads_excludes_file_from_core() {
+        let config = "[user]\n\texcludesfile = wrong\n[core]\n\teditor = vim\n\tExcludesFile = \"~/.gitignore_global\"\n";
+        assert_eq!(
+            configured_excludes_file(config).as_deref(),
+            Some("~/.gitignore_global")
+        );
+        assert_eq!(configured_excludes_file("[core]\n"), None);
+    }
+}
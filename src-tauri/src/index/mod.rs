@@ -0,0 +1,1158 @@
+// Persistent file index for instant global search
+//
+// Configured roots are scanned in the background into an in-memory table of
+// paths, sizes and modification times. The table is saved to disk and kept
+// current from inotify, so `query_index` never has to touch the disk.
+
+mod store;
+mod table;
+mod watcher;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+use std::time::{Duration, Instant, SystemTime};
+use tauri::{AppHandle, Emitter, State};
+use walkdir::WalkDir;
+
+use crate::hidden;
+use crate::operation::CancellationToken;
+use crate::search::{
+    walk_parallel, Filters, MatchMode, NameMatcher, SearchFilters, SearchHit, WalkOptions,
+};
+use crate::{log_error, log_info, to_epoch_millis, EntryKind, FileEntry, OhMyFSError};
+use table::{HiddenLists, Kind, Record, Table};
+use watcher::{Change, Watcher};
+
+pub const STATUS_EVENT: &str = "index://status";
+
+const INDEX_FILE: &str = "index.db";
+// Changes are written out at most this often
+const SAVE_DELAY: Duration = Duration::from_secs(5);
+// How long the background thread waits for inotify events per round
+const POLL_TIMEOUT_MS: i32 = 200;
+const DEFAULT_MAX_RESULTS: usize = 1000;
+// Roots with more entries than this are queried on several threads
+const PARALLEL_QUERY_THRESHOLD: usize = 50_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RootState {
+    Scanning,
+    Ready,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexRoot {
+    pub path: String,
+    pub state: RootState,
+    pub entries: u64,
+    pub last_scan: Option<String>,
+    // False when changes are not picked up live, for example because the
+    // system ran out of inotify watches; the index then only updates on rescans
+    pub watching: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IndexQueryOptions {
+    #[serde(default)]
+    pub mode: MatchMode,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    #[serde(default)]
+    pub filters: SearchFilters,
+    pub max_results: Option<usize>,
+    // Only return entries inside these folders
+    pub paths: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct IndexQueryResult {
+    // Best matches first
+    pub hits: Vec<SearchHit>,
+    pub matched: u64,
+    pub truncated: bool,
+    pub elapsed_ms: u64,
+}
+
+struct Root {
+    // Canonical, so watcher paths can be mapped back to it
+    path: PathBuf,
+    table: Table,
+    // Read whenever a `.hidden` file is indexed, so queries stay off the disk
+    hidden_lists: HiddenLists,
+    state: RootState,
+    last_scan: Option<i64>,
+    watching: bool,
+    error: Option<String>,
+    // Set while a scan runs: entries changed meanwhile are checked again once
+    // the scan result replaces the table
+    changed_during_scan: Option<HashSet<String>>,
+    scan: CancellationToken,
+}
+
+impl Root {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            table: Table::default(),
+            hidden_lists: HiddenLists::default(),
+            state: RootState::Scanning,
+            last_scan: None,
+            watching: false,
+            error: None,
+            changed_during_scan: None,
+            scan: CancellationToken::default(),
+        }
+    }
+
+    fn status(&self) -> IndexRoot {
+        IndexRoot {
+            path: self.path.to_string_lossy().to_string(),
+            state: self.state,
+            entries: self.table.len() as u64,
+            last_scan: self.last_scan.map(|millis| millis.to_string()),
+            watching: self.watching,
+            error: self.error.clone(),
+        }
+    }
+
+    // `a/b` for `<root>/a/b`, None for paths outside the root and the root itself
+    fn relative(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.path).ok()?;
+        let relative = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        (!relative.is_empty()).then_some(relative)
+    }
+
+    fn note_change(&mut self, relative: &str) {
+        if let Some(changed) = &mut self.changed_during_scan {
+            changed.insert(relative.to_string());
+        }
+    }
+
+    fn upsert(&mut self, record: Record) {
+        self.note_change(&record.relative);
+        if record.name() == hidden::HIDDEN_FILE {
+            self.read_hidden_list(record.parent());
+        }
+        self.table.upsert(record);
+    }
+
+    fn remove_tree(&mut self, relative: &str) {
+        self.note_change(relative);
+        self.table.remove_tree(relative);
+        let nested = format!("{}/", relative);
+        self.hidden_lists
+            .retain(|folder, _| folder != relative && !folder.starts_with(&nested));
+        if let Some(folder) = hidden_file_folder(relative) {
+            self.hidden_lists.remove(folder);
+        }
+    }
+
+    fn replace_table(&mut self, table: Table) {
+        self.table = table;
+        self.hidden_lists.clear();
+        let folders = self
+            .table
+            .records()
+            .iter()
+            .filter(|record| record.name() == hidden::HIDDEN_FILE)
+            .map(|record| record.parent().to_string())
+            .collect::<Vec<_>>();
+        for folder in folders {
+            self.read_hidden_list(&folder);
+        }
+    }
+
+    fn read_hidden_list(&mut self, folder: &str) {
+        let names = hidden::HiddenNames::for_directory(&self.path.join(folder));
+        self.hidden_lists.insert(folder.to_string(), names);
+    }
+
+    // Brings one entry in line with the disk
+    fn refresh(&mut self, relative: &str) {
+        match fs::symlink_metadata(self.path.join(relative)) {
+            Ok(metadata) => self.upsert(Record::new(relative.to_string(), &metadata)),
+            Err(_) => self.remove_tree(relative),
+        }
+    }
+}
+
+struct Shared {
+    roots: RwLock<Vec<Root>>,
+    file: PathBuf,
+    // None in tests, where no status is reported
+    app: Option<AppHandle>,
+    watcher: Option<Watcher>,
+    unsaved: AtomicBool,
+}
+
+#[derive(Clone)]
+pub struct Index(Arc<Shared>);
+
+impl Index {
+    // Loads the saved index from `data_dir` in the background, then rescans
+    // every root to catch up with changes made while the app was closed
+    pub fn open(app: AppHandle, data_dir: PathBuf) -> Self {
+        let watcher = Watcher::new()
+            .inspect_err(|err| {
+                log_error!("Index changes will not be watched: {}", err);
+            })
+            .ok();
+        let shared = Arc::new(Shared {
+            roots: RwLock::default(),
+            file: data_dir.join(INDEX_FILE),
+            app: Some(app),
+            watcher,
+            unsaved: AtomicBool::new(false),
+        });
+
+        let background = Arc::downgrade(&shared);
+        let spawned = std::thread::Builder::new()
+            .name("file index".to_string())
+            .spawn(move || run_background(background));
+        if let Err(err) = spawned {
+            log_error!("Failed to start the file index: {}", err);
+        }
+        Index(shared)
+    }
+
+    fn shared(&self) -> &Arc<Shared> {
+        &self.0
+    }
+}
+
+impl Shared {
+    fn read(&self) -> RwLockReadGuard<'_, Vec<Root>> {
+        self.roots
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Vec<Root>> {
+        self.roots
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn emit_status(&self, status: &IndexRoot) {
+        let Some(app) = &self.app else {
+            return;
+        };
+        if let Err(err) = app.emit(STATUS_EVENT, status) {
+            log_error!("Failed to emit {}: {}", STATUS_EVENT, err);
+        }
+    }
+
+    fn watch(&self, directory: &Path) -> bool {
+        self.watcher
+            .as_ref()
+            .is_some_and(|watcher| watcher.add(directory))
+    }
+
+    fn unwatch(&self, directory: &Path) {
+        if let Some(watcher) = &self.watcher {
+            watcher.remove_tree(directory);
+        }
+    }
+
+    // Runs `change` on the root containing `path`
+    fn with_root(&self, path: &Path, change: impl FnOnce(&mut Root, &str)) {
+        let mut roots = self.write();
+        let Some(root) = roots.iter_mut().find(|root| path.starts_with(&root.path)) else {
+            return;
+        };
+        if let Some(relative) = root.relative(path) {
+            change(root, &relative);
+            self.unsaved.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn apply(self: &Arc<Self>, changes: Vec<Change>) {
+        let mut refreshed = HashSet::new();
+        let mut overflowed = false;
+        for change in changes {
+            match change {
+                Change::Overflowed => overflowed = true,
+                Change::Removed(path) => {
+                    self.unwatch(&path);
+                    self.with_root(&path, |root, relative| root.remove_tree(relative));
+                }
+                Change::Created(path) if path.is_dir() && !path.is_symlink() => {
+                    self.add_tree(&path);
+                }
+                Change::Created(path) | Change::Modified(path) => {
+                    // A burst of writes to one file only needs one look at it
+                    if refreshed.insert(path.clone()) {
+                        self.with_root(&path, |root, relative| root.refresh(relative));
+                    }
+                }
+            }
+        }
+
+        if overflowed {
+            log_info!("Index watcher overflowed, rescanning every root");
+            let roots = self
+                .read()
+                .iter()
+                .map(|root| root.path.clone())
+                .collect::<Vec<_>>();
+            for root in roots {
+                self.start_scan(&root);
+            }
+        }
+    }
+
+    // Indexes a folder that appeared below a root, moved in or freshly created
+    fn add_tree(&self, directory: &Path) {
+        let mut found = Vec::new();
+        for entry in WalkDir::new(directory)
+            .follow_links(false)
+            .follow_root_links(false)
+            .into_iter()
+            .flatten()
+        {
+            if let Ok(metadata) = entry.metadata() {
+                if metadata.is_dir() {
+                    self.watch(entry.path());
+                }
+                found.push((entry.into_path(), metadata));
+            }
+        }
+
+        let mut roots = self.write();
+        let Some(root) = roots
+            .iter_mut()
+            .find(|root| directory.starts_with(&root.path))
+        else {
+            return;
+        };
+        for (path, metadata) in found {
+            if let Some(relative) = root.relative(&path) {
+                root.upsert(Record::new(relative, &metadata));
+            }
+        }
+        self.unsaved.store(true, Ordering::Relaxed);
+    }
+
+    // Registers a new root without scanning it yet. Roots may not overlap, so
+    // every entry is indexed exactly once.
+    fn add_root(&self, path: &str) -> Result<(PathBuf, IndexRoot), String> {
+        let directory = Path::new(path);
+        if !directory.exists() {
+            return Err(OhMyFSError::DirectoryNotFound {
+                path: path.to_string(),
+            }
+            .to_string());
+        }
+        if !directory.is_dir() {
+            return Err(OhMyFSError::PathNotDirectory {
+                path: path.to_string(),
+            }
+            .to_string());
+        }
+        let canonical = fs::canonicalize(directory).map_err(|err| index_error(path, err))?;
+
+        let status = {
+            let mut roots = self.write();
+            if let Some(existing) = roots
+                .iter()
+                .find(|root| canonical.starts_with(&root.path) || root.path.starts_with(&canonical))
+            {
+                return Err(index_error(
+                    path,
+                    format!("it overlaps the indexed folder {}", existing.path.display()),
+                ));
+            }
+            let root = Root::new(canonical.clone());
+            let status = root.status();
+            roots.push(root);
+            status
+        };
+        log_info!("Added {} to the file index", canonical.display());
+        self.unsaved.store(true, Ordering::Relaxed);
+        Ok((canonical, status))
+    }
+
+    // Replaces any scan already running for the root
+    fn start_scan(self: &Arc<Self>, path: &Path) {
+        let token = CancellationToken::default();
+        {
+            let mut roots = self.write();
+            let Some(root) = roots.iter_mut().find(|root| root.path == path) else {
+                return;
+            };
+            root.scan.cancel();
+            root.scan = token.clone();
+            root.state = RootState::Scanning;
+            root.changed_during_scan = Some(HashSet::new());
+            self.emit_status(&root.status());
+        }
+
+        let shared = self.clone();
+        let root = path.to_path_buf();
+        let spawned = std::thread::Builder::new()
+            .name("index scan".to_string())
+            .spawn(move || shared.scan(&root, &token));
+        if let Err(err) = spawned {
+            log_error!("Failed to start scanning {}: {}", path.display(), err);
+        }
+    }
+
+    fn scan(&self, path: &Path, token: &CancellationToken) {
+        let started = Instant::now();
+        let records = Mutex::new(Vec::new());
+        let watching = AtomicBool::new(self.watch(path));
+
+        let failed = if path.is_dir() {
+            walk_parallel(
+                path,
+                &WalkOptions {
+                    include_hidden: true,
+                    respect_ignore: false,
+                    follow_symlinks: false,
+                },
+                token,
+                |entry, file_type| {
+                    let entry_path = entry.path();
+                    let Ok(metadata) = entry.metadata() else {
+                        return true;
+                    };
+                    if file_type.is_dir() && !self.watch(&entry_path) {
+                        watching.store(false, Ordering::Relaxed);
+                    }
+                    let relative = entry_path
+                        .strip_prefix(path)
+                        .unwrap_or(&entry_path)
+                        .components()
+                        .map(|component| component.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    records
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .push(Record::new(relative, &metadata));
+                    true
+                },
+            )
+        } else {
+            Vec::new()
+        };
+
+        let mut roots = self.write();
+        // Cancelled under this lock when the root was removed or rescanned
+        if token.is_cancelled() {
+            return;
+        }
+        let Some(root) = roots.iter_mut().find(|root| root.path == path) else {
+            return;
+        };
+
+        if path.is_dir() {
+            let records = records
+                .into_inner()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            root.replace_table(Table::from_records(records));
+            for relative in root.changed_during_scan.take().unwrap_or_default() {
+                root.refresh(&relative);
+            }
+            root.state = RootState::Ready;
+            root.error = None;
+            root.last_scan = Some(now_millis());
+            root.watching = watching.into_inner();
+            log_info!(
+                "Indexed {} entries under {} in {:?} ({} folders unreadable)",
+                root.table.len(),
+                path.display(),
+                started.elapsed(),
+                failed.len()
+            );
+        } else {
+            root.changed_during_scan = None;
+            root.state = RootState::Failed;
+            root.error = Some(
+                OhMyFSError::DirectoryNotFound {
+                    path: path.to_string_lossy().to_string(),
+                }
+                .to_string(),
+            );
+            log_error!("Failed to index {}: the folder is gone", path.display());
+        }
+        self.unsaved.store(true, Ordering::Relaxed);
+        self.emit_status(&root.status());
+    }
+
+    fn save(&self) {
+        self.unsaved.store(false, Ordering::Relaxed);
+        let roots = self.read();
+        let saved = store::save(
+            &self.file,
+            roots
+                .iter()
+                .map(|root| (root.path.as_path(), root.last_scan, root.table.records())),
+        );
+        if let Err(err) = saved {
+            log_error!(
+                "Failed to save the file index to {}: {}",
+                self.file.display(),
+                err
+            );
+        }
+    }
+
+    fn load(self: &Arc<Self>) {
+        let stored = match store::load(&self.file) {
+            Ok(stored) => stored,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => {
+                log_error!(
+                    "Discarding unreadable file index {}: {}",
+                    self.file.display(),
+                    err
+                );
+                Vec::new()
+            }
+        };
+
+        let mut paths = Vec::new();
+        {
+            let mut roots = self.write();
+            for stored in stored {
+                if roots.iter().any(|root| root.path == stored.path) {
+                    continue;
+                }
+                let mut root = Root::new(stored.path);
+                root.replace_table(Table::from_records(stored.records));
+                root.last_scan = stored.last_scan;
+                root.state = RootState::Ready;
+                paths.push(root.path.clone());
+                roots.push(root);
+            }
+        }
+        for path in paths {
+            self.start_scan(&path);
+        }
+    }
+
+    fn query(
+        &self,
+        query: &str,
+        options: IndexQueryOptions,
+    ) -> Result<IndexQueryResult, OhMyFSError> {
+        let started = Instant::now();
+        let matcher =
+            NameMatcher::new(query, options.mode, options.case_sensitive).map_err(|details| {
+                OhMyFSError::InvalidSearchPattern {
+                    pattern: query.to_string(),
+                    details,
+                }
+            })?;
+        let include_hidden = options.filters.include_hidden.unwrap_or(false);
+        let filters = Filters::new(options.filters, options.case_sensitive)?;
+        let max_results = options.max_results.unwrap_or(DEFAULT_MAX_RESULTS);
+        let scopes = options.paths.map(|paths| {
+            paths
+                .iter()
+                .map(|path| fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path)))
+                .collect::<Vec<_>>()
+        });
+
+        let roots = self.read();
+        let mut matches = Vec::new();
+        for root in roots.iter() {
+            // None searches the whole root, otherwise only below these folders
+            let prefixes = match &scopes {
+                None => None,
+                Some(scopes) if scopes.iter().any(|scope| root.path.starts_with(scope)) => None,
+                Some(scopes) => {
+                    let prefixes = scopes
+                        .iter()
+                        .filter_map(|scope| root.relative(scope))
+                        .collect::<Vec<_>>();
+                    if prefixes.is_empty() {
+                        continue;
+                    }
+                    Some(prefixes)
+                }
+            };
+
+            let score = |record: &Record| -> Option<i64> {
+                if !include_hidden && record.is_hidden(&root.hidden_lists) {
+                    return None;
+                }
+                if let Some(prefixes) = &prefixes {
+                    let inside = prefixes.iter().any(|prefix| {
+                        record
+                            .relative
+                            .strip_prefix(prefix.as_str())
+                            .is_some_and(|rest| rest.starts_with('/'))
+                    });
+                    if !inside {
+                        return None;
+                    }
+                }
+                let score = matcher.score(record.name())?;
+                let matches = filters
+                    .matches_kind(Path::new(record.name()), record.kind == Kind::Directory)
+                    && filters.matches_path(Path::new(&record.relative))
+                    && filters.matches_attributes(
+                        record.kind == Kind::File,
+                        record.size,
+                        record.modified,
+                    );
+                matches.then_some(score)
+            };
+
+            let records = root.table.records();
+            let found = if records.len() < PARALLEL_QUERY_THRESHOLD {
+                scan_records(records, &score)
+            } else {
+                let workers = std::thread::available_parallelism()
+                    .map(|count| count.get())
+                    .unwrap_or(4);
+                let chunk = records.len().div_ceil(workers);
+                std::thread::scope(|scope| {
+                    let handles = records
+                        .chunks(chunk)
+                        .map(|chunk| scope.spawn(|| scan_records(chunk, &score)))
+                        .collect::<Vec<_>>();
+                    handles
+                        .into_iter()
+                        .flat_map(|handle| handle.join().unwrap_or_default())
+                        .collect()
+                })
+            };
+            matches.extend(
+                found
+                    .into_iter()
+                    .map(|(score, record)| (score, root, record)),
+            );
+        }
+
+        let matched = matches.len();
+        // Best score first, then the shallower and shorter path
+        let order = |a: &(i64, &Root, &Record), b: &(i64, &Root, &Record)| {
+            b.0.cmp(&a.0)
+                .then_with(|| a.2.relative.len().cmp(&b.2.relative.len()))
+                .then_with(|| a.2.relative.cmp(&b.2.relative))
+        };
+        if matched > max_results && max_results > 0 {
+            matches.select_nth_unstable_by(max_results - 1, order);
+        }
+        matches.truncate(max_results);
+        matches.sort_unstable_by(order);
+
+        Ok(IndexQueryResult {
+            hits: matches
+                .into_iter()
+                .map(|(score, root, record)| SearchHit {
+                    entry: record_entry(root, record),
+                    score,
+                })
+                .collect(),
+            matched: matched as u64,
+            truncated: matched > max_results,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+}
+
+// Writes out what the background thread had not saved yet
+impl Drop for Shared {
+    fn drop(&mut self) {
+        if self.unsaved.load(Ordering::Relaxed) {
+            self.save();
+        }
+    }
+}
+
+// The folder whose `.hidden` file `relative` is, if it is one
+fn hidden_file_folder(relative: &str) -> Option<&str> {
+    if relative == hidden::HIDDEN_FILE {
+        return Some("");
+    }
+    relative
+        .strip_suffix(hidden::HIDDEN_FILE)?
+        .strip_suffix('/')
+}
+
+fn scan_records<'a>(
+    records: &'a [Record],
+    score: &(impl Fn(&Record) -> Option<i64> + Sync),
+) -> Vec<(i64, &'a Record)> {
+    records
+        .iter()
+        .filter_map(|record| score(record).map(|score| (score, record)))
+        .collect()
+}
+
+fn record_entry(root: &Root, record: &Record) -> FileEntry {
+    let name = record.name();
+    FileEntry {
+        name: name.to_string(),
+        path: root
+            .path
+            .join(&record.relative)
+            .to_string_lossy()
+            .to_string(),
+        kind: match record.kind {
+            Kind::File => EntryKind::File,
+            Kind::Directory => EntryKind::Directory,
+            Kind::Symlink => EntryKind::Symlink,
+            Kind::Other => EntryKind::Unknown,
+        },
+        is_directory: record.kind == Kind::Directory,
+        is_file: record.kind == Kind::File,
+        is_symlink: record.kind == Kind::Symlink,
+        // The index stores links without following them
+        symlink_target: None,
+        target_kind: None,
+        is_broken: false,
+        size: Some(record.size),
+        compressed_size: None,
+        modified_at: record.modified.map(|millis| millis.to_string()),
+        created_at: None,
+        permissions: None,
+        extension: Path::new(name)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_string()),
+        is_hidden: record.is_hidden_name(&root.hidden_lists),
+        is_ignored: false,
+    }
+}
+
+fn now_millis() -> i64 {
+    to_epoch_millis(SystemTime::now())
+        .parse()
+        .unwrap_or_default()
+}
+
+// Applies watcher events and writes the index out once it has settled. Exits
+// when the index is dropped.
+fn run_background(shared: Weak<Shared>) {
+    match shared.upgrade() {
+        Some(shared) => shared.load(),
+        None => return,
+    }
+
+    let mut last_save = Instant::now();
+    while let Some(shared) = shared.upgrade() {
+        let changes = match &shared.watcher {
+            Some(watcher) => watcher.read(POLL_TIMEOUT_MS).unwrap_or_else(|err| {
+                log_error!("Failed to read index changes: {}", err);
+                std::thread::sleep(Duration::from_millis(POLL_TIMEOUT_MS as u64));
+                Vec::new()
+            }),
+            None => {
+                std::thread::sleep(Duration::from_millis(POLL_TIMEOUT_MS as u64));
+                Vec::new()
+            }
+        };
+        shared.apply(changes);
+
+        if shared.unsaved.load(Ordering::Relaxed) && last_save.elapsed() >= SAVE_DELAY {
+            shared.save();
+            last_save = Instant::now();
+        }
+    }
+}
+
+fn index_error(path: &str, details: impl ToString) -> String {
+    OhMyFSError::IndexFailed {
+        path: path.to_string(),
+        details: details.to_string(),
+    }
+    .to_string()
+}
+
+#[tauri::command]
+pub async fn list_index_roots(index: State<'_, Index>) -> Result<Vec<IndexRoot>, String> {
+    Ok(index.shared().read().iter().map(Root::status).collect())
+}
+
+#[tauri::command]
+pub async fn add_index_root(index: State<'_, Index>, path: String) -> Result<IndexRoot, String> {
+    let (canonical, status) = index.shared().add_root(&path)?;
+    index.shared().start_scan(&canonical);
+    Ok(status)
+}
+
+// Returns false when the folder was not indexed
+#[tauri::command]
+pub async fn remove_index_root(index: State<'_, Index>, path: String) -> Result<bool, String> {
+    let canonical = fs::canonicalize(&path).unwrap_or_else(|_| PathBuf::from(&path));
+    let removed = {
+        let mut roots = index.shared().write();
+        let position = roots
+            .iter()
+            .position(|root| root.path == canonical || root.path == Path::new(&path));
+        position.map(|position| roots.remove(position))
+    };
+    let Some(root) = removed else {
+        return Ok(false);
+    };
+
+    root.scan.cancel();
+    index.shared().unwatch(&root.path);
+    index.shared().unsaved.store(true, Ordering::Relaxed);
+    log_info!("Removed {} from the file index", root.path.display());
+    Ok(true)
+}
+
+// Rescans one root, or all of them when no path is given. The scans run in
+// the background and report on `index://status`.
+#[tauri::command]
+pub async fn rebuild_index(
+    index: State<'_, Index>,
+    path: Option<String>,
+) -> Result<Vec<IndexRoot>, String> {
+    let targets = {
+        let roots = index.shared().read();
+        match &path {
+            Some(path) => {
+                let canonical = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+                let Some(root) = roots.iter().find(|root| root.path == canonical) else {
+                    return Err(index_error(path, "the folder is not indexed"));
+                };
+                vec![root.path.clone()]
+            }
+            None => roots.iter().map(|root| root.path.clone()).collect(),
+        }
+    };
+
+    for target in &targets {
+        index.shared().start_scan(target);
+    }
+    Ok(index.shared().read().iter().map(Root::status).collect())
+}
+
+#[tauri::command]
+pub async fn query_index(
+    index: State<'_, Index>,
+    query: String,
+    options: Option<IndexQueryOptions>,
+) -> Result<IndexQueryResult, String> {
+    let shared = index.shared().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        shared
+            .query(&query, options.unwrap_or_default())
+            .map_err(|err| err.to_string())
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(data_dir: &Path) -> Arc<Shared> {
+        Arc::new(Shared {
+            roots: RwLock::default(),
+            file: data_dir.join(INDEX_FILE),
+            app: None,
+            watcher: None,
+            unsaved: AtomicBool::new(false),
+        })
+    }
+
+    // Adds `path` as a root and scans it on this thread
+    fn indexed(shared: &Shared, path: &Path) -> PathBuf {
+        let (canonical, _) = shared.add_root(&path.to_string_lossy()).unwrap();
+        shared.scan(&canonical, &CancellationToken::default());
+        canonical
+    }
+
+    fn query(shared: &Shared, query: &str, options: IndexQueryOptions) -> IndexQueryResult {
+        shared.query(query, options).unwrap()
+    }
+
+    // The hits' paths relative to `root`, sorted
+    fn found(result: &IndexQueryResult, root: &Path) -> Vec<String> {
+        let mut found = result
+            .hits
+            .iter()
+            .map(|hit| {
+                Path::new(&hit.entry.path)
+                    .strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        found.sort();
+        found
+    }
+
+    fn record(shared: &Shared, relative: &str) -> Option<Record> {
+        shared.read()[0]
+            .table
+            .records()
+            .iter()
+            .find(|record| record.relative == relative)
+            .cloned()
+    }
+
+    #[test]
+    fn queries_stay_inside_the_given_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = dir.path().join("files");
+        for folder in ["a", "ab", "b"] {
+            fs::create_dir_all(files.join(folder)).unwrap();
+            fs::write(files.join(folder).join("notes.txt"), "").unwrap();
+        }
+        let shared = index(dir.path());
+        let root = indexed(&shared, &files);
+
+        let scoped = |paths: &[PathBuf]| {
+            let options = IndexQueryOptions {
+                paths: Some(
+                    paths
+                        .iter()
+                        .map(|path| path.to_string_lossy().to_string())
+                        .collect(),
+                ),
+                ..Default::default()
+            };
+            found(&query(&shared, "notes", options), &root)
+        };
+        assert_eq!(scoped(&[root.join("a")]), ["a/notes.txt"]);
+        assert_eq!(
+            scoped(&[root.join("ab"), root.join("b")]),
+            ["ab/notes.txt", "b/notes.txt"]
+        );
+        // A folder above the root takes in all of it
+        assert_eq!(
+            scoped(&[dir.path().to_path_buf()]),
+            ["a/notes.txt", "ab/notes.txt", "b/notes.txt"]
+        );
+        assert!(scoped(&[root.join("missing")]).is_empty());
+        assert!(scoped(&[]).is_empty());
+        assert_eq!(
+            found(
+                &query(&shared, "notes", IndexQueryOptions::default()),
+                &root
+            )
+            .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn hidden_entries_need_include_hidden() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = dir.path().join("files");
+        for folder in [".cache", "drafts"] {
+            fs::create_dir_all(files.join(folder)).unwrap();
+            fs::write(files.join(folder).join("notes.txt"), "").unwrap();
+        }
+        fs::write(files.join("notes.txt"), "").unwrap();
+        fs::write(files.join(".notes.txt"), "").unwrap();
+        fs::write(files.join(hidden::HIDDEN_FILE), "drafts\n").unwrap();
+        let shared = index(dir.path());
+        let root = indexed(&shared, &files);
+
+        let result = query(&shared, "notes", IndexQueryOptions::default());
+        assert_eq!(found(&result, &root), ["notes.txt"]);
+        assert!(!result.hits[0].entry.is_hidden);
+
+        let mut options = IndexQueryOptions::default();
+        options.filters.include_hidden = Some(true);
+        let result = query(&shared, "notes", options);
+        assert_eq!(
+            found(&result, &root),
+            [
+                ".cache/notes.txt",
+                ".notes.txt",
+                "drafts/notes.txt",
+                "notes.txt"
+            ]
+        );
+        let hidden = |path: &str| {
+            result
+                .hits
+                .iter()
+                .find(|hit| hit.entry.path == root.join(path).to_string_lossy())
+                .unwrap()
+                .entry
+                .is_hidden
+        };
+        // Only the entries hidden by their own name are marked
+        assert!(hidden(".notes.txt"));
+        assert!(!hidden(".cache/notes.txt"));
+        assert!(!hidden("notes.txt"));
+    }
+
+    #[test]
+    fn results_are_cut_at_max_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = dir.path().join("files");
+        fs::create_dir(&files).unwrap();
+        for number in 1..=5 {
+            fs::write(files.join(format!("notes{}.txt", number)), "").unwrap();
+        }
+        let shared = index(dir.path());
+        let root = indexed(&shared, &files);
+
+        let glob = |max_results: Option<usize>| {
+            let options = IndexQueryOptions {
+                mode: MatchMode::Glob,
+                max_results,
+                ..Default::default()
+            };
+            query(&shared, "notes*.txt", options)
+        };
+        let all = glob(None);
+        assert_eq!(all.hits.len(), 5);
+        assert_eq!(all.matched, 5);
+        assert!(!all.truncated);
+
+        // The best two of the same order, equal scores by the shorter path
+        let cut = glob(Some(2));
+        assert_eq!(cut.matched, 5);
+        assert!(cut.truncated);
+        assert_eq!(found(&cut, &root), ["notes1.txt", "notes2.txt"]);
+        assert_eq!(
+            cut.hits
+                .iter()
+                .map(|hit| &hit.entry.path)
+                .collect::<Vec<_>>(),
+            all.hits[..2]
+                .iter()
+                .map(|hit| &hit.entry.path)
+                .collect::<Vec<_>>()
+        );
+
+        assert!(!glob(Some(5)).truncated);
+        let none = glob(Some(0));
+        assert!(none.hits.is_empty());
+        assert!(none.truncated);
+    }
+
+    #[test]
+    fn large_roots_are_queried_in_parallel() {
+        let dir = tempfile::tempdir().unwrap();
+        let shared = index(dir.path());
+        let count = PARALLEL_QUERY_THRESHOLD + 10;
+        let records = (0..count)
+            .map(|number| Record {
+                relative: if number % 1000 == 0 {
+                    format!("folder{}/needle{}.txt", number % 7, number)
+                } else {
+                    format!("folder{}/file{}.txt", number % 7, number)
+                },
+                size: number as u64,
+                modified: None,
+                kind: Kind::File,
+            })
+            .collect::<Vec<_>>();
+        let mut root = Root::new(PathBuf::from("/indexed"));
+        root.replace_table(Table::from_records(records));
+        shared.write().push(root);
+
+        let result = query(&shared, "needle", IndexQueryOptions::default());
+        let mut expected = (0..count)
+            .step_by(1000)
+            .map(|number| format!("folder{}/needle{}.txt", number % 7, number))
+            .collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(result.matched, expected.len() as u64);
+        assert!(!result.truncated);
+        assert_eq!(found(&result, Path::new("/indexed")), expected);
+
+        let options = IndexQueryOptions {
+            max_results: Some(3),
+            ..Default::default()
+        };
+        let result = query(&shared, "needle", options);
+        assert_eq!(result.hits.len(), 3);
+        assert_eq!(result.matched, expected.len() as u64);
+    }
+
+    #[test]
+    fn roots_cannot_overlap() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = dir.path().join("files");
+        let other = dir.path().join("other");
+        fs::create_dir_all(files.join("inner")).unwrap();
+        fs::create_dir(&other).unwrap();
+        fs::write(dir.path().join("file.txt"), "").unwrap();
+        let shared = index(dir.path());
+
+        let add = |path: &Path| shared.add_root(&path.to_string_lossy());
+        let (canonical, status) = add(&files).unwrap();
+        assert_eq!(status.state, RootState::Scanning);
+        assert_eq!(canonical, fs::canonicalize(&files).unwrap());
+
+        for overlapping in [
+            files.clone(),
+            files.join("inner"),
+            files.join("inner").join(".."),
+            dir.path().to_path_buf(),
+        ] {
+            let err = add(&overlapping).unwrap_err();
+            assert!(err.contains("overlaps"), "{}", err);
+        }
+        assert!(add(&dir.path().join("missing")).is_err());
+        assert!(add(&dir.path().join("file.txt")).is_err());
+        assert!(add(&other).is_ok());
+        assert_eq!(shared.read().len(), 2);
+    }
+
+    #[test]
+    fn watcher_changes_update_the_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = dir.path().join("files");
+        fs::create_dir(&files).unwrap();
+        fs::write(files.join("notes.txt"), "").unwrap();
+        let shared = index(dir.path());
+        let root = indexed(&shared, &files);
+        assert_eq!(shared.read()[0].state, RootState::Ready);
+        assert!(record(&shared, "notes.txt").is_some());
+
+        fs::write(root.join("todo.txt"), "").unwrap();
+        fs::write(root.join("notes.txt"), "longer").unwrap();
+        shared.apply(vec![
+            Change::Created(root.join("todo.txt")),
+            Change::Modified(root.join("notes.txt")),
+            Change::Modified(root.join("notes.txt")),
+        ]);
+        assert!(record(&shared, "todo.txt").is_some());
+        assert_eq!(record(&shared, "notes.txt").unwrap().size, 6);
+
+        // A folder that appears is indexed with everything in it
+        fs::create_dir_all(root.join("new").join("deep")).unwrap();
+        fs::write(root.join("new").join("deep").join("file.txt"), "").unwrap();
+        shared.apply(vec![Change::Created(root.join("new"))]);
+        for relative in ["new", "new/deep", "new/deep/file.txt"] {
+            assert!(record(&shared, relative).is_some(), "{}", relative);
+        }
+
+        fs::remove_dir_all(root.join("new")).unwrap();
+        shared.apply(vec![Change::Removed(root.join("new"))]);
+        for relative in ["new", "new/deep", "new/deep/file.txt"] {
+            assert!(record(&shared, relative).is_none(), "{}", relative);
+        }
+
+        // A new `.hidden` file hides what it lists right away
+        fs::write(root.join(hidden::HIDDEN_FILE), "notes.txt\n").unwrap();
+        shared.apply(vec![Change::Created(root.join(hidden::HIDDEN_FILE))]);
+        let result = query(&shared, "txt", IndexQueryOptions::default());
+        assert_eq!(found(&result, &root), ["todo.txt"]);
+
+        // Changes outside every root are ignored
+        fs::write(dir.path().join("outside.txt"), "").unwrap();
+        shared.apply(vec![Change::Created(dir.path().join("outside.txt"))]);
+        assert_eq!(shared.read()[0].table.len(), 3);
+        assert!(shared.unsaved.load(Ordering::Relaxed));
+    }
+}
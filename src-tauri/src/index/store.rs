@@ -0,0 +1,186 @@
+// On-disk format of the file index
+//
+// A zstd stream with, per root, its path and one record per entry holding the
+// path relative to the root. The file is rewritten whole and replaced
+// atomically; when it is missing or unreadable the roots are simply scanned
+// again.
+
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use super::table::{Kind, Record};
+
+const MAGIC: &[u8; 8] = b"OMFSIDX\0";
+const VERSION: u32 = 1;
+// Far above any real path, so a corrupt length cannot ask for gigabytes
+const MAX_PATH_LENGTH: usize = 64 * 1024;
+
+pub(crate) struct StoredRoot {
+    pub path: PathBuf,
+    pub last_scan: Option<i64>,
+    pub records: Vec<Record>,
+}
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+fn write_optional(writer: &mut impl Write, value: Option<i64>) -> io::Result<()> {
+    writer.write_all(&value.unwrap_or(i64::MIN).to_le_bytes())
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    let len = u32::from_le_bytes(read_array(reader)?) as usize;
+    if len > MAX_PATH_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("path of {} bytes", len),
+        ));
+    }
+    let mut bytes = vec![0u8; len];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn read_optional(reader: &mut impl Read) -> io::Result<Option<i64>> {
+    let value = i64::from_le_bytes(read_array(reader)?);
+    Ok((value != i64::MIN).then_some(value))
+}
+
+pub(crate) fn save<'a>(
+    file: &Path,
+    roots: impl ExactSizeIterator<Item = (&'a Path, Option<i64>, &'a [Record])>,
+) -> io::Result<()> {
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temporary = file.with_extension("tmp");
+    let output = BufWriter::new(fs::File::create(&temporary)?);
+    let mut writer = zstd::Encoder::new(output, 3)?;
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(roots.len() as u32).to_le_bytes())?;
+    for (path, last_scan, records) in roots {
+        write_bytes(&mut writer, path.to_string_lossy().as_bytes())?;
+        write_optional(&mut writer, last_scan)?;
+        writer.write_all(&(records.len() as u64).to_le_bytes())?;
+        for record in records {
+            writer.write_all(&[record.kind.to_byte()])?;
+            writer.write_all(&record.size.to_le_bytes())?;
+            write_optional(&mut writer, record.modified)?;
+            write_bytes(&mut writer, record.relative.as_bytes())?;
+        }
+    }
+
+    let mut output = writer.finish()?;
+    output.flush()?;
+    output.get_ref().sync_all()?;
+    drop(output);
+    fs::rename(&temporary, file)
+}
+
+pub(crate) fn load(file: &Path) -> io::Result<Vec<StoredRoot>> {
+    let invalid = |details: &str| io::Error::new(io::ErrorKind::InvalidData, details.to_string());
+    let mut reader = zstd::Decoder::new(BufReader::new(fs::File::open(file)?))?;
+
+    if &read_array::<8>(&mut reader)? != MAGIC {
+        return Err(invalid("not an index file"));
+    }
+    if u32::from_le_bytes(read_array(&mut reader)?) != VERSION {
+        return Err(invalid("unsupported index version"));
+    }
+
+    let root_count = u32::from_le_bytes(read_array(&mut reader)?);
+    // Counts come from the file, so vectors only grow with what was read
+    let mut roots = Vec::new();
+    for _ in 0..root_count {
+        let path = PathBuf::from(read_string(&mut reader)?);
+        let last_scan = read_optional(&mut reader)?;
+        let record_count = u64::from_le_bytes(read_array(&mut reader)?);
+
+        let mut records = Vec::new();
+        for _ in 0..record_count {
+            let [kind] = read_array(&mut reader)?;
+            let kind = Kind::from_byte(kind).ok_or_else(|| invalid("unknown entry kind"))?;
+            let size = u64::from_le_bytes(read_array(&mut reader)?);
+            let modified = read_optional(&mut reader)?;
+            let relative = read_string(&mut reader)?;
+            records.push(Record {
+                relative,
+                size,
+                modified,
+                kind,
+            });
+        }
+        roots.push(StoredRoot {
+            path,
+            last_scan,
+            records,
+        });
+    }
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("index");
+        let records = vec![Record {
+            relative: "src/main.rs".to_string(),
+            size: 42,
+            modified: Some(1_700_000_000_000),
+            kind: Kind::File,
+        }];
+        save(
+            &file,
+            [(Path::new("/home/user/project"), None, records.as_slice())].into_iter(),
+        )
+        .unwrap();
+
+        let roots = load(&file).unwrap();
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0].path, Path::new("/home/user/project"));
+        assert_eq!(roots[0].last_scan, None);
+        assert_eq!(roots[0].records[0].relative, "src/main.rs");
+        assert_eq!(roots[0].records[0].modified, Some(1_700_000_000_000));
+    }
+
+    #[test]
+    fn rejects_corrupt_lengths() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("index");
+        let write = |contents: &[u8]| {
+            fs::write(&file, zstd::encode_all(contents, 3).unwrap()).unwrap();
+        };
+        let header = [MAGIC.as_slice(), &VERSION.to_le_bytes()].concat();
+
+        // Claims billions of roots and a path of almost 4 GiB
+        write(
+            &[
+                header.as_slice(),
+                &u32::MAX.to_le_bytes(),
+                &u32::MAX.to_le_bytes(),
+            ]
+            .concat(),
+        );
+        let err = load(&file).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // Claims more roots than it holds
+        write(&[header.as_slice(), &u32::MAX.to_le_bytes()].concat());
+        assert!(load(&file).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+// In-memory entries of one indexed root
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::time::UNIX_EPOCH;
+
+use crate::hidden::{self, HiddenNames};
+
+// The names each folder's `.hidden` file lists, keyed by the folder's relative
+// path, `""` for the root
+pub(crate) type HiddenLists = HashMap<String, HiddenNames>;
+
+fn is_hidden_name(listed: &HiddenLists, parent: &str, name: &str) -> bool {
+    hidden::is_dotfile(name) || listed.get(parent).is_some_and(|names| names.contains(name))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    File,
+    Directory,
+    Symlink,
+    // Sockets, FIFOs and devices
+    Other,
+}
+
+impl Kind {
+    pub fn from_metadata(metadata: &fs::Metadata) -> Self {
+        let file_type = metadata.file_type();
+        if file_type.is_symlink() {
+            Kind::Symlink
+        } else if file_type.is_dir() {
+            Kind::Directory
+        } else if file_type.is_file() {
+            Kind::File
+        } else {
+            Kind::Other
+        }
+    }
+
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Kind::File => 0,
+            Kind::Directory => 1,
+            Kind::Symlink => 2,
+            Kind::Other => 3,
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        Some(match byte {
+            0 => Kind::File,
+            1 => Kind::Directory,
+            2 => Kind::Symlink,
+            3 => Kind::Other,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Record {
+    // Relative to the root, always with `/` separators
+    pub relative: String,
+    pub size: u64,
+    // Epoch milliseconds
+    pub modified: Option<i64>,
+    pub kind: Kind,
+}
+
+impl Record {
+    pub fn new(relative: String, metadata: &fs::Metadata) -> Self {
+        Self {
+            relative,
+            size: metadata.len(),
+            modified: metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_millis() as i64),
+            kind: Kind::from_metadata(metadata),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.relative
+            .rsplit_once('/')
+            .map_or(self.relative.as_str(), |(_, name)| name)
+    }
+
+    // The folder the entry is in, `""` at the top of the root
+    pub fn parent(&self) -> &str {
+        self.relative
+            .rsplit_once('/')
+            .map_or("", |(parent, _)| parent)
+    }
+
+    // Dotfiles and names listed in their folder's `.hidden` file
+    pub fn is_hidden_name(&self, listed: &HiddenLists) -> bool {
+        is_hidden_name(listed, self.parent(), self.name())
+    }
+
+    // Hidden itself or inside a hidden folder
+    pub fn is_hidden(&self, listed: &HiddenLists) -> bool {
+        let mut start = 0usize;
+        for part in self.relative.split('/') {
+            let parent = &self.relative[..start.saturating_sub(1)];
+            if is_hidden_name(listed, parent, part) {
+                return true;
+            }
+            start += part.len() + 1;
+        }
+        false
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Table {
+    records: Vec<Record>,
+    // Sorted, so everything below a folder is one range
+    positions: BTreeMap<String, usize>,
+}
+
+impl Table {
+    pub fn from_records(records: Vec<Record>) -> Self {
+        let mut table = Table::default();
+        for record in records {
+            table.upsert(record);
+        }
+        table
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    pub fn upsert(&mut self, record: Record) {
+        match self.positions.get(&record.relative) {
+            Some(&position) => self.records[position] = record,
+            None => {
+                self.positions
+                    .insert(record.relative.clone(), self.records.len());
+                self.records.push(record);
+            }
+        }
+    }
+
+    // Removes the entry and, for folders, everything below it
+    pub fn remove_tree(&mut self, relative: &str) {
+        self.remove(relative);
+        // `0` is the character after `/`, so this covers exactly `relative/...`
+        let nested = self
+            .positions
+            .range(format!("{}/", relative)..format!("{}0", relative))
+            .map(|(nested, _)| nested.clone())
+            .collect::<Vec<_>>();
+        for nested in nested {
+            self.remove(&nested);
+        }
+    }
+
+    fn remove(&mut self, relative: &str) {
+        let Some(position) = self.positions.remove(relative) else {
+            return;
+        };
+        self.records.swap_remove(position);
+        if let Some(moved) = self.records.get(position) {
+            if let Some(moved) = self.positions.get_mut(&moved.relative) {
+                *moved = position;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(relative: &str) -> Record {
+        Record {
+            relative: relative.to_string(),
+            size: 0,
+            modified: None,
+            kind: Kind::File,
+        }
+    }
+
+    fn names(table: &Table) -> Vec<&str> {
+        let mut names = table
+            .records()
+            .iter()
+            .map(|record| record.relative.as_str())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn hidden_records() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("docs")).unwrap();
+        fs::write(dir.path().join(hidden::HIDDEN_FILE), "build\n").unwrap();
+        fs::write(
+            dir.path().join("docs").join(hidden::HIDDEN_FILE),
+            "draft.md\n",
+        )
+        .unwrap();
+        let listed = HiddenLists::from([
+            (String::new(), HiddenNames::for_directory(dir.path())),
+            (
+                "docs".to_string(),
+                HiddenNames::for_directory(&dir.path().join("docs")),
+            ),
+        ]);
+
+        let hidden = |relative: &str| record(relative).is_hidden(&listed);
+        assert!(hidden(".git"));
+        assert!(hidden("src/.env"));
+        assert!(hidden("build"));
+        assert!(hidden("build/out/app"));
+        assert!(hidden("docs/draft.md"));
+        assert!(!hidden("docs/build"));
+        assert!(!hidden("docs/intro.md"));
+        assert!(!hidden("src/build"));
+
+        assert!(!record("build/out").is_hidden_name(&listed));
+        assert!(record("docs/draft.md").is_hidden_name(&listed));
+    }
+
+    #[test]
+    fn remove_tree_takes_only_the_folder() {
+        let mut table = Table::from_records(
+            [
+                "a", "a/b", "a/b/c", "a/b/c/d", "a/b.txt", "a/b0", "a/bc", "b",
+            ]
+            .into_iter()
+            .map(record)
+            .collect(),
+        );
+        table.remove_tree("a/b");
+        assert_eq!(names(&table), ["a", "a/b.txt", "a/b0", "a/bc", "b"]);
+
+        // Positions still point at the right records after the swaps
+        table.remove_tree("a/bc");
+        table.upsert(record("a/b0"));
+        assert_eq!(names(&table), ["a", "a/b.txt", "a/b0", "b"]);
+        table.remove_tree("a");
+        assert_eq!(names(&table), ["b"]);
+        assert_eq!(table.len(), 1);
+    }
+}
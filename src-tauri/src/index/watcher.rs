@@ -0,0 +1,158 @@
+// Keeps the index current from inotify
+//
+// Every directory below a root gets its own watch, all on one inotify
+// instance read by the index's background thread.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub(crate) enum Change {
+    Created(PathBuf),
+    Removed(PathBuf),
+    Modified(PathBuf),
+    // The kernel dropped events, so only a rescan gets the index right again
+    Overflowed,
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) use linux::Watcher;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::collections::HashMap;
+    use std::sync::{Mutex, MutexGuard};
+
+    use super::*;
+    use crate::log_error;
+    use crate::watch::inotify::{Inotify, DIRECTORY_MASK};
+
+    #[derive(Default)]
+    struct Watches {
+        paths: HashMap<i32, PathBuf>,
+        descriptors: HashMap<PathBuf, i32>,
+    }
+
+    pub(crate) struct Watcher {
+        inotify: Inotify,
+        watches: Mutex<Watches>,
+    }
+
+    impl Watcher {
+        pub fn new() -> io::Result<Self> {
+            Ok(Self {
+                inotify: Inotify::new()?,
+                watches: Mutex::default(),
+            })
+        }
+
+        // Returns false when the directory could not be watched, usually
+        // because the per-user limit on inotify watches is used up
+        pub fn add(&self, directory: &Path) -> bool {
+            match self.inotify.add_watch(directory, DIRECTORY_MASK) {
+                Ok(wd) => {
+                    let mut watches = self.lock();
+                    // Watching the same inode again hands back the same descriptor
+                    if let Some(previous) = watches.paths.insert(wd, directory.to_path_buf()) {
+                        watches.descriptors.remove(&previous);
+                    }
+                    watches.descriptors.insert(directory.to_path_buf(), wd);
+                    true
+                }
+                // Gone before we got to it; the removal shows up as an event
+                Err(err) if err.kind() == io::ErrorKind::NotFound => true,
+                Err(err) => {
+                    log_error!(
+                        "Failed to watch {} for the index: {}",
+                        directory.display(),
+                        err
+                    );
+                    false
+                }
+            }
+        }
+
+        // Drops the watches of a folder that was removed or moved away
+        pub fn remove_tree(&self, directory: &Path) {
+            let mut watches = self.lock();
+            let removed = watches
+                .descriptors
+                .keys()
+                .filter(|path| path.starts_with(directory))
+                .cloned()
+                .collect::<Vec<_>>();
+            for path in removed {
+                if let Some(wd) = watches.descriptors.remove(&path) {
+                    watches.paths.remove(&wd);
+                    self.inotify.remove_watch(wd);
+                }
+            }
+        }
+
+        pub fn read(&self, timeout: i32) -> io::Result<Vec<Change>> {
+            let events = self.inotify.read(timeout)?;
+            let mut watches = self.lock();
+            let mut changes = Vec::new();
+            for event in events {
+                let mask = event.mask;
+                if mask & libc::IN_Q_OVERFLOW != 0 {
+                    changes.push(Change::Overflowed);
+                    continue;
+                }
+                if mask & libc::IN_IGNORED != 0 {
+                    if let Some(path) = watches.paths.remove(&event.wd) {
+                        watches.descriptors.remove(&path);
+                    }
+                    continue;
+                }
+                // Events about the watched folder itself are reported by its parent
+                let Some(directory) = watches.paths.get(&event.wd) else {
+                    continue;
+                };
+                if event.name.is_empty() {
+                    continue;
+                }
+
+                let path = directory.join(&event.name);
+                changes.push(if mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 {
+                    Change::Created(path)
+                } else if mask & (libc::IN_DELETE | libc::IN_MOVED_FROM) != 0 {
+                    Change::Removed(path)
+                } else {
+                    Change::Modified(path)
+                });
+            }
+            Ok(changes)
+        }
+
+        fn lock(&self) -> MutexGuard<'_, Watches> {
+            self.watches
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+        }
+    }
+}
+
+// Elsewhere the index is only refreshed by scans
+#[cfg(not(target_os = "linux"))]
+pub(crate) struct Watcher;
+
+#[cfg(not(target_os = "linux"))]
+impl Watcher {
+    pub fn new() -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "index watching is only available on Linux",
+        ))
+    }
+
+    pub fn add(&self, _: &Path) -> bool {
+        false
+    }
+
+    pub fn remove_tree(&self, _: &Path) {}
+
+    pub fn read(&self, timeout: i32) -> io::Result<Vec<Change>> {
+        std::thread::sleep(std::time::Duration::from_millis(timeout as u64));
+        Ok(Vec::new())
+    }
+}
@@ -0,0 +1,586 @@
+// Background jobs for long running file operations
+//
+// Every copy, move, delete, compression and extraction runs as a job with an
+// id. Jobs wait in a queue until a running slot frees up, can be paused or
+// cancelled between units of work, and report their state on `job://progress`.
+
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::SystemTime;
+use tauri::{Emitter, State, Window};
+
+use crate::operation::{CancellationToken, ProgressEmitter};
+use crate::{log_error, log_info, to_epoch_millis, OhMyFSError};
+
+pub const PROGRESS_EVENT: &str = "job://progress";
+
+// Further jobs stay queued until one of these slots frees up
+const MAX_RUNNING_JOBS: usize = 2;
+// Finished jobs are kept so the UI can still show how they ended
+const FINISHED_JOBS_KEPT: usize = 50;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    Copy,
+    Move,
+    Delete,
+    Compress,
+    Extract,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Running,
+    Paused,
+    Cancelled,
+    Failed,
+    Done,
+}
+
+impl JobState {
+    pub fn is_finished(self) -> bool {
+        matches!(
+            self,
+            JobState::Cancelled | JobState::Failed | JobState::Done
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Job {
+    pub id: String,
+    pub kind: JobKind,
+    pub state: JobState,
+    pub sources: Vec<String>,
+    pub destination: Option<String>,
+    pub current_file: Option<String>,
+    pub entries_done: u64,
+    pub entries_total: u64,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    // Set when the job failed as a whole; per-entry failures stay in the command result
+    pub error: Option<String>,
+    pub created_at: String,
+    pub finished_at: Option<String>,
+}
+
+// Implemented by the progress reports of each engine so they can update the
+// job they run in
+pub(crate) trait JobProgress {
+    fn apply(&self, job: &mut Job);
+}
+
+struct JobSlot {
+    job: Job,
+    token: CancellationToken,
+    // Holds a running slot, even while paused
+    started: bool,
+}
+
+#[derive(Default)]
+struct JobTable {
+    slots: Mutex<Vec<JobSlot>>,
+    // Signalled whenever a job finishes, resumes or is cancelled
+    changed: Condvar,
+    next_id: AtomicU64,
+}
+
+#[derive(Clone, Default)]
+pub struct Jobs(Arc<JobTable>);
+
+impl Jobs {
+    // Registers a queued job. The frontend may choose the id up front so it
+    // can cancel the job before the command returns.
+    pub(crate) fn create(
+        &self,
+        window: &Window,
+        id: Option<String>,
+        kind: JobKind,
+        sources: Vec<String>,
+        destination: Option<String>,
+    ) -> JobHandle {
+        let mut handle = self.enqueue(id, kind, sources, destination);
+        handle.emitter = Some(ProgressEmitter::new(window, PROGRESS_EVENT));
+        if let Some(job) = self.update(&handle.id, |_| {}) {
+            handle.emit(&job, true);
+        }
+        handle
+    }
+
+    fn enqueue(
+        &self,
+        id: Option<String>,
+        kind: JobKind,
+        sources: Vec<String>,
+        destination: Option<String>,
+    ) -> JobHandle {
+        let mut slots = self.lock();
+        slots.retain(|slot| Some(&slot.job.id) != id.as_ref() || !slot.job.state.is_finished());
+        let id = match id {
+            Some(id) if !slots.iter().any(|slot| slot.job.id == id) => id,
+            _ => format!("job-{}", self.0.next_id.fetch_add(1, Ordering::Relaxed) + 1),
+        };
+
+        let job = Job {
+            id: id.clone(),
+            kind,
+            state: JobState::Queued,
+            sources,
+            destination,
+            current_file: None,
+            entries_done: 0,
+            entries_total: 0,
+            bytes_done: 0,
+            bytes_total: 0,
+            error: None,
+            created_at: to_epoch_millis(SystemTime::now()),
+            finished_at: None,
+        };
+        let token = CancellationToken::default();
+        slots.push(JobSlot {
+            job,
+            token: token.clone(),
+            started: false,
+        });
+        drop(slots);
+
+        JobHandle {
+            jobs: self.clone(),
+            id,
+            token,
+            emitter: None,
+            finished: false,
+        }
+    }
+
+    pub fn list(&self) -> Vec<Job> {
+        self.lock().iter().map(|slot| slot.job.clone()).collect()
+    }
+
+    // Returns false when no unfinished job has that id
+    fn cancel(&self, id: &str) -> bool {
+        let cancelled = self.update(id, |slot| slot.token.cancel()).is_some();
+        if cancelled {
+            log_info!("Cancellation requested for job {}", id);
+            self.notify();
+        }
+        cancelled
+    }
+
+    // Returns the paused job, or None when it was not queued or running
+    fn pause(&self, id: &str) -> Option<Job> {
+        let mut paused = false;
+        let job = self.update(id, |slot| {
+            if matches!(slot.job.state, JobState::Queued | JobState::Running) {
+                slot.token.pause();
+                slot.job.state = JobState::Paused;
+                paused = true;
+            }
+        });
+        let job = job.filter(|_| paused)?;
+        log_info!("Paused job {}", id);
+        Some(job)
+    }
+
+    // Returns the resumed job, or None when it was not paused
+    fn resume(&self, id: &str) -> Option<Job> {
+        let mut resumed = false;
+        let job = self.update(id, |slot| {
+            if slot.job.state == JobState::Paused {
+                slot.token.resume();
+                slot.job.state = if slot.started {
+                    JobState::Running
+                } else {
+                    JobState::Queued
+                };
+                resumed = true;
+            }
+        });
+        let job = job.filter(|_| resumed)?;
+        log_info!("Resumed job {}", id);
+        self.notify();
+        Some(job)
+    }
+
+    // Applies `change` to a job that has not finished yet and returns the result
+    fn update(&self, id: &str, change: impl FnOnce(&mut JobSlot)) -> Option<Job> {
+        let mut slots = self.lock();
+        let slot = slots
+            .iter_mut()
+            .find(|slot| slot.job.id == id && !slot.job.state.is_finished())?;
+        change(slot);
+        Some(slot.job.clone())
+    }
+
+    // Wakes queued workers so they re-check whether they may start
+    fn notify(&self) {
+        self.0.changed.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<JobSlot>> {
+        self.0
+            .slots
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// Owned by the worker running a job
+pub(crate) struct JobHandle {
+    jobs: Jobs,
+    id: String,
+    token: CancellationToken,
+    // Missing for jobs that are not tied to a window
+    emitter: Option<ProgressEmitter>,
+    finished: bool,
+}
+
+impl JobHandle {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    fn emit(&mut self, job: &Job, force: bool) {
+        if let Some(emitter) = &mut self.emitter {
+            emitter.emit(job, force);
+        }
+    }
+
+    // Blocks until this job is first in the queue and a slot is free, or
+    // until it gets cancelled
+    fn wait_for_slot(&mut self) {
+        let mut slots = self.jobs.lock();
+        loop {
+            if self.token.is_cancelled() {
+                return;
+            }
+            let running = slots
+                .iter()
+                .filter(|slot| slot.started && !slot.job.state.is_finished())
+                .count();
+            let next = slots
+                .iter()
+                .position(|slot| slot.job.state == JobState::Queued);
+            if let Some(index) = next.filter(|&index| slots[index].job.id == self.id) {
+                if running < MAX_RUNNING_JOBS {
+                    let slot = &mut slots[index];
+                    slot.started = true;
+                    slot.job.state = JobState::Running;
+                    let job = slot.job.clone();
+                    drop(slots);
+                    self.emit(&job, true);
+                    return;
+                }
+            }
+            slots = self
+                .jobs
+                .0
+                .changed
+                .wait(slots)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    pub fn report<P: JobProgress>(&mut self, progress: &P, force: bool) {
+        if let Some(job) = self
+            .jobs
+            .update(&self.id, |slot| progress.apply(&mut slot.job))
+        {
+            self.emit(&job, force);
+        }
+    }
+
+    fn finish(&mut self, error: Option<String>) {
+        self.finished = true;
+        let cancelled = self.token.is_cancelled();
+        let job = self.jobs.update(&self.id, |slot| {
+            slot.job.state = if error.is_some() {
+                JobState::Failed
+            } else if cancelled {
+                JobState::Cancelled
+            } else {
+                JobState::Done
+            };
+            slot.job.error = error;
+            slot.job.current_file = None;
+            slot.job.finished_at = Some(to_epoch_millis(SystemTime::now()));
+        });
+
+        let mut slots = self.jobs.lock();
+        let finished = slots
+            .iter()
+            .filter(|slot| slot.job.state.is_finished())
+            .count();
+        let mut excess = finished.saturating_sub(FINISHED_JOBS_KEPT);
+        slots.retain(|slot| {
+            let drop_it = excess > 0 && slot.job.state.is_finished();
+            if drop_it {
+                excess -= 1;
+            }
+            !drop_it
+        });
+        drop(slots);
+        self.jobs.notify();
+
+        if let Some(job) = job {
+            log_info!("Job {} finished as {:?}", job.id, job.state);
+            self.emit(&job, true);
+        }
+    }
+}
+
+// A worker that panicked must not keep its running slot forever
+impl Drop for JobHandle {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.finish(Some("the job stopped unexpectedly".to_string()));
+        }
+    }
+}
+
+// Runs `work` on the blocking pool once the job gets a running slot and
+// records how it ended. A job cancelled while queued never runs `work`.
+pub(crate) async fn run<T, E, F>(mut job: JobHandle, work: F) -> Result<T, E>
+where
+    T: Send + 'static,
+    E: std::fmt::Display + From<OhMyFSError> + From<String> + Send + 'static,
+    F: FnOnce(&mut JobHandle) -> Result<T, E> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(move || {
+        job.wait_for_slot();
+        if job.token.is_cancelled() {
+            job.finish(None);
+            return Err(E::from(OhMyFSError::JobCancelled { id: job.id.clone() }));
+        }
+        let result = work(&mut job);
+        job.finish(result.as_ref().err().map(|err| err.to_string()));
+        result
+    })
+    .await
+    .map_err(|err| E::from(err.to_string()))?
+}
+
+fn emit_job(window: &Window, job: &Job) {
+    if let Err(err) = window.emit(PROGRESS_EVENT, job) {
+        log_error!("Failed to emit {}: {}", PROGRESS_EVENT, err);
+    }
+}
+
+#[tauri::command]
+pub async fn list_jobs(jobs: State<'_, Jobs>) -> Result<Vec<Job>, String> {
+    Ok(jobs.list())
+}
+
+// Returns false when no unfinished job has that id
+#[tauri::command]
+pub async fn cancel_job(jobs: State<'_, Jobs>, job_id: String) -> Result<bool, String> {
+    Ok(jobs.cancel(&job_id))
+}
+
+// Running jobs stop at their next checkpoint; queued jobs keep their place
+// but are passed over until resumed
+#[tauri::command]
+pub async fn pause_job(
+    window: Window,
+    jobs: State<'_, Jobs>,
+    job_id: String,
+) -> Result<bool, String> {
+    let job = jobs.pause(&job_id);
+    if let Some(job) = &job {
+        emit_job(&window, job);
+    }
+    Ok(job.is_some())
+}
+
+#[tauri::command]
+pub async fn resume_job(
+    window: Window,
+    jobs: State<'_, Jobs>,
+    job_id: String,
+) -> Result<bool, String> {
+    let job = jobs.resume(&job_id);
+    if let Some(job) = &job {
+        emit_job(&window, job);
+    }
+    Ok(job.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CommandError;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    type Worker<E> = thread::JoinHandle<Result<(), E>>;
+
+    fn spawn<E, F>(jobs: &Jobs, work: F) -> (String, Worker<E>)
+    where
+        E: std::fmt::Display + From<OhMyFSError> + From<String> + Send + 'static,
+        F: FnOnce(&mut JobHandle) -> Result<(), E> + Send + 'static,
+    {
+        let job = jobs.enqueue(None, JobKind::Copy, Vec::new(), None);
+        let id = job.id().to_string();
+        let worker = thread::spawn(move || tauri::async_runtime::block_on(run(job, work)));
+        (id, worker)
+    }
+
+    // A job that keeps its slot until it is released
+    fn held(jobs: &Jobs) -> (String, mpsc::Sender<()>, Worker<String>) {
+        let (release, released) = mpsc::channel();
+        let (id, worker) = spawn(jobs, move |_| {
+            let _ = released.recv();
+            Ok(())
+        });
+        (id, release, worker)
+    }
+
+    fn state(jobs: &Jobs, id: &str) -> JobState {
+        jobs.list()
+            .into_iter()
+            .find(|job| job.id == id)
+            .map(|job| job.state)
+            .unwrap()
+    }
+
+    fn wait_for(jobs: &Jobs, id: &str, expected: JobState) {
+        let started = Instant::now();
+        while state(jobs, id) != expected {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "{} stayed {:?} instead of becoming {:?}",
+                id,
+                state(jobs, id),
+                expected
+            );
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn jobs_wait_for_a_free_slot() {
+        let jobs = Jobs::default();
+        let (first, release_first, first_worker) = held(&jobs);
+        let (second, release_second, second_worker) = held(&jobs);
+        wait_for(&jobs, &first, JobState::Running);
+        wait_for(&jobs, &second, JobState::Running);
+
+        let (third, release_third, third_worker) = held(&jobs);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(state(&jobs, &third), JobState::Queued);
+
+        release_first.send(()).unwrap();
+        assert!(first_worker.join().unwrap().is_ok());
+        assert_eq!(state(&jobs, &first), JobState::Done);
+        wait_for(&jobs, &third, JobState::Running);
+
+        release_second.send(()).unwrap();
+        release_third.send(()).unwrap();
+        assert!(second_worker.join().unwrap().is_ok());
+        assert!(third_worker.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn paused_jobs_are_passed_over_until_resumed() {
+        let jobs = Jobs::default();
+        let (first, release_first, first_worker) = held(&jobs);
+        let (second, release_second, second_worker) = held(&jobs);
+        wait_for(&jobs, &first, JobState::Running);
+        wait_for(&jobs, &second, JobState::Running);
+        let (paused, release_paused, paused_worker) = held(&jobs);
+        let (next, release_next, next_worker) = held(&jobs);
+
+        assert!(jobs.pause(&paused).is_some());
+        release_first.send(()).unwrap();
+        wait_for(&jobs, &next, JobState::Running);
+        assert_eq!(state(&jobs, &paused), JobState::Paused);
+
+        assert_eq!(jobs.resume(&paused).unwrap().state, JobState::Queued);
+        assert!(jobs.resume(&paused).is_none());
+        release_second.send(()).unwrap();
+        wait_for(&jobs, &paused, JobState::Running);
+
+        for release in [release_paused, release_next] {
+            release.send(()).unwrap();
+        }
+        for worker in [first_worker, second_worker, paused_worker, next_worker] {
+            assert!(worker.join().unwrap().is_ok());
+        }
+    }
+
+    #[test]
+    fn paused_jobs_stop_at_their_next_checkpoint() {
+        let jobs = Jobs::default();
+        let (release, released) = mpsc::channel();
+        let (done, finished) = mpsc::channel();
+        let (id, worker) = spawn(&jobs, move |job| {
+            let _ = released.recv();
+            assert!(!job.token().checkpoint());
+            done.send(()).unwrap();
+            Ok::<_, String>(())
+        });
+        wait_for(&jobs, &id, JobState::Running);
+
+        assert!(jobs.pause(&id).is_some());
+        release.send(()).unwrap();
+        assert!(finished.recv_timeout(Duration::from_millis(50)).is_err());
+
+        assert_eq!(jobs.resume(&id).unwrap().state, JobState::Running);
+        finished.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(worker.join().unwrap().is_ok());
+        assert_eq!(state(&jobs, &id), JobState::Done);
+    }
+
+    #[test]
+    fn jobs_cancelled_while_queued_never_run() {
+        let jobs = Jobs::default();
+        let (first, release_first, first_worker) = held(&jobs);
+        let (second, release_second, second_worker) = held(&jobs);
+        wait_for(&jobs, &first, JobState::Running);
+        wait_for(&jobs, &second, JobState::Running);
+
+        let ran = Arc::new(AtomicBool::new(false));
+        let flag = ran.clone();
+        let (queued, queued_worker) = spawn(&jobs, move |_| {
+            flag.store(true, Ordering::Relaxed);
+            Ok::<_, CommandError>(())
+        });
+        assert!(jobs.cancel(&queued));
+        let err = queued_worker.join().unwrap().unwrap_err();
+        assert_eq!(err.code, "JobCancelled");
+        assert!(!ran.load(Ordering::Relaxed));
+        assert_eq!(state(&jobs, &queued), JobState::Cancelled);
+        assert!(!jobs.cancel(&queued));
+
+        release_first.send(()).unwrap();
+        release_second.send(()).unwrap();
+        assert!(first_worker.join().unwrap().is_ok());
+        assert!(second_worker.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn running_jobs_stop_once_cancelled() {
+        let jobs = Jobs::default();
+        let (id, worker) = spawn(&jobs, |job| {
+            while !job.token().checkpoint() {
+                thread::sleep(Duration::from_millis(5));
+            }
+            Ok::<_, String>(())
+        });
+        wait_for(&jobs, &id, JobState::Running);
+
+        assert!(jobs.cancel(&id));
+        assert!(worker.join().unwrap().is_ok());
+        assert_eq!(state(&jobs, &id), JobState::Cancelled);
+    }
+}
@@ -3,7 +3,21 @@
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use std::fs;
-use std::fs::read_dir;
+
+mod archive;
+mod engine;
+mod hidden;
+mod ignore;
+mod index;
+mod job;
+mod listing;
+mod operation;
+mod search;
+mod stat;
+mod transfer;
+mod trash;
+mod watch;
+
 // Cross-platform permission handling
 fn get_permission_number(permissions: &fs::Permissions) -> u32 {
     #[cfg(windows)]
@@ -24,7 +38,44 @@ fn get_permission_number(permissions: &fs::Permissions) -> u32 {
     }
 }
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tauri::Manager;
+
+// Timestamps cross IPC as milliseconds since epoch for JavaScript compatibility
+pub(crate) fn to_epoch_millis(time: SystemTime) -> String {
+    let duration = time.duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+    format!("{}", duration.as_millis())
+}
+
+// Tries the numbers from `start` on until `take` accepts one; some number is
+// always free
+pub(crate) fn first_free<T>(start: u32, take: impl FnMut(u32) -> Option<T>) -> T {
+    (start..)
+        .find_map(take)
+        .expect("unbounded range always yields a free name")
+}
+
+// Finds a free "name (n).ext" sibling for a conflicting path
+pub(crate) fn unique_destination(path: &Path) -> PathBuf {
+    let parent = path.parent().unwrap_or_else(|| Path::new(""));
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path.extension().map(|e| e.to_string_lossy().to_string());
+
+    first_free(1, |n| {
+        let name = match &extension {
+            Some(ext) => format!("{} ({}).{}", stem, n, ext),
+            None => format!("{} ({})", stem, n),
+        };
+        let candidate = parent.join(name);
+        fs::symlink_metadata(&candidate)
+            .is_err()
+            .then_some(candidate)
+    })
+}
 
 // Structured error types for better error handling
 #[derive(Debug, Error, Serialize, Deserialize)]
@@ -46,27 +97,237 @@ pub enum OhMyFSError {
 
     #[error("Clipboard operation failed")]
     ClipboardFailed,
+
+    #[error("Failed to write archive {path}: {details}")]
+    ArchiveWriteFailed { path: String, details: String },
+
+    #[error("Archive entry {path} failed: {details}")]
+    ArchiveEntryFailed { path: String, details: String },
+
+    #[error("Failed to read archive {path}: {details}")]
+    ArchiveReadFailed { path: String, details: String },
+
+    #[error("Refusing to extract unsafe archive entry {path}: {details}")]
+    UnsafeArchiveEntry { path: String, details: String },
+
+    #[error("Unsupported archive format: {path}")]
+    UnsupportedArchiveFormat { path: String },
+
+    #[error("Archive {path} is encrypted and needs a password")]
+    ArchivePasswordRequired { path: String },
+
+    #[error("Incorrect password for archive {path}")]
+    InvalidArchivePassword { path: String },
+
+    #[error("Verification of {path} failed: {details}")]
+    VerificationFailed { path: String, details: String },
+
+    #[error("Failed to move {path} to the trash: {details}")]
+    TrashFailed { path: String, details: String },
+
+    #[error("Trash item not found: {id}")]
+    TrashItemNotFound { id: String },
+
+    #[error("Job {id} was cancelled before it started")]
+    JobCancelled { id: String },
+
+    #[error("Failed to watch {path}: {details}")]
+    WatchFailed { path: String, details: String },
+
+    #[error("Invalid search pattern {pattern}: {details}")]
+    InvalidSearchPattern { pattern: String, details: String },
+
+    #[error("Failed to index {path}: {details}")]
+    IndexFailed { path: String, details: String },
+
+    #[error("Path does not exist: {path}")]
+    PathNotFound { path: String },
+
+    #[error("Invalid definition at {location}: {details}")]
+    InvalidDefinition { location: String, details: String },
+
+    #[error("Required variable {name} was not provided and has no default")]
+    VariableNotProvided { name: String },
+
+    #[error("Undefined variable {name} at {location}")]
+    UndefinedVariable { name: String, location: String },
+
+    #[error("Invalid template at {location}: {details}")]
+    InvalidTemplate { location: String, details: String },
+
+    #[error("Cannot execute plan: {details}")]
+    InvalidPlan { details: String },
+
+    #[error("Cannot capture {path}: {details}")]
+    SnapshotFailed { path: String, details: String },
+}
+
+impl OhMyFSError {
+    // The variant's name, which stays the same when the wording changes
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::FileReadFailed { .. } => "FileReadFailed",
+            Self::FileWriteFailed { .. } => "FileWriteFailed",
+            Self::DirectoryNotFound { .. } => "DirectoryNotFound",
+            Self::PathNotDirectory { .. } => "PathNotDirectory",
+            Self::DirectoryReadFailed { .. } => "DirectoryReadFailed",
+            Self::ClipboardFailed => "ClipboardFailed",
+            Self::ArchiveWriteFailed { .. } => "ArchiveWriteFailed",
+            Self::ArchiveEntryFailed { .. } => "ArchiveEntryFailed",
+            Self::ArchiveReadFailed { .. } => "ArchiveReadFailed",
+            Self::UnsafeArchiveEntry { .. } => "UnsafeArchiveEntry",
+            Self::UnsupportedArchiveFormat { .. } => "UnsupportedArchiveFormat",
+            Self::ArchivePasswordRequired { .. } => "ArchivePasswordRequired",
+            Self::InvalidArchivePassword { .. } => "InvalidArchivePassword",
+            Self::VerificationFailed { .. } => "VerificationFailed",
+            Self::TrashFailed { .. } => "TrashFailed",
+            Self::TrashItemNotFound { .. } => "TrashItemNotFound",
+            Self::JobCancelled { .. } => "JobCancelled",
+            Self::WatchFailed { .. } => "WatchFailed",
+            Self::InvalidSearchPattern { .. } => "InvalidSearchPattern",
+            Self::IndexFailed { .. } => "IndexFailed",
+            Self::PathNotFound { .. } => "PathNotFound",
+            Self::InvalidDefinition { .. } => "InvalidDefinition",
+            Self::VariableNotProvided { .. } => "VariableNotProvided",
+            Self::UndefinedVariable { .. } => "UndefinedVariable",
+            Self::InvalidTemplate { .. } => "InvalidTemplate",
+            Self::InvalidPlan { .. } => "InvalidPlan",
+            Self::SnapshotFailed { .. } => "SnapshotFailed",
+        }
+    }
+}
+
+// Returned by commands whose failures the UI has to tell apart, like
+// extraction asking for a password. `code` is an `OhMyFSError` variant name,
+// or "Failed" for errors that have none; `message` is the text to show.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CommandError {
+    pub code: String,
+    pub message: String,
+}
+
+impl From<OhMyFSError> for CommandError {
+    fn from(err: OhMyFSError) -> Self {
+        Self {
+            code: err.code().to_string(),
+            message: err.to_string(),
+        }
+    }
+}
+
+// Commands that still return plain strings only show the message
+impl From<OhMyFSError> for String {
+    fn from(err: OhMyFSError) -> Self {
+        err.to_string()
+    }
+}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        Self {
+            code: "Failed".to_string(),
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
 }
 
 // File system entry models
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    Socket,
+    Fifo,
+    BlockDevice,
+    CharDevice,
+    Unknown,
+}
+
+impl EntryKind {
+    pub(crate) fn from_file_type(file_type: &fs::FileType) -> Self {
+        if file_type.is_symlink() {
+            return EntryKind::Symlink;
+        }
+        if file_type.is_dir() {
+            return EntryKind::Directory;
+        }
+        if file_type.is_file() {
+            return EntryKind::File;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+            if file_type.is_socket() {
+                return EntryKind::Socket;
+            }
+            if file_type.is_fifo() {
+                return EntryKind::Fifo;
+            }
+            if file_type.is_block_device() {
+                return EntryKind::BlockDevice;
+            }
+            if file_type.is_char_device() {
+                return EntryKind::CharDevice;
+            }
+        }
+        EntryKind::Unknown
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FileEntry {
     pub name: String,
     pub path: String,
+    pub kind: EntryKind,
     pub is_directory: bool,
     pub is_file: bool,
     pub is_symlink: bool,
+    // Only set for symlinks: the target as stored in the link and the kind of
+    // what it resolves to
+    pub symlink_target: Option<String>,
+    pub target_kind: Option<EntryKind>,
+    // A symlink whose target is missing, unreachable or part of a loop
+    #[serde(default)]
+    pub is_broken: bool,
     pub size: Option<u64>,
+    // Only set for entries inside an archive
+    pub compressed_size: Option<u64>,
     pub modified_at: Option<String>,
     pub created_at: Option<String>,
     pub permissions: Option<String>,
     pub extension: Option<String>,
+    // Dotfiles, entries named in the folder's `.hidden` file and, on Windows,
+    // entries with the hidden attribute
+    #[serde(default)]
+    pub is_hidden: bool,
+    // Matched by `.gitignore`, `.ignore` or the global git excludes; only
+    // evaluated when a listing is asked to respect them
+    #[serde(default)]
+    pub is_ignored: bool,
+}
+
+impl FileEntry {
+    // Folders, and symlinks to folders, which open like one
+    pub(crate) fn lists_as_directory(&self) -> bool {
+        self.is_directory || self.target_kind == Some(EntryKind::Directory)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DirectoryContents {
     pub directories: Vec<FileEntry>,
     pub files: Vec<FileEntry>,
+    // Set when grouping was asked for; each group covers the next entries of
+    // both lists
+    pub groups: Option<Vec<listing::EntryGroup>>,
 }
 
 impl From<std::io::Error> for OhMyFSError {
@@ -78,7 +339,7 @@ impl From<std::io::Error> for OhMyFSError {
             },
             std::io::ErrorKind::PermissionDenied => OhMyFSError::FileReadFailed {
                 path: "unknown".to_string(),
-                details: format!("Permission denied: {}", err.to_string()),
+                details: format!("Permission denied: {}", err),
             },
             _ => OhMyFSError::FileReadFailed {
                 path: "unknown".to_string(),
@@ -119,6 +380,52 @@ macro_rules! log_warn {
     };
 }
 
+// Builds the entry the frontend shows for a path on disk. `metadata` is
+// expected not to follow symlinks, like `DirEntry::metadata`.
+pub(crate) fn file_entry(path: &Path, metadata: &fs::Metadata) -> FileEntry {
+    let file_type = metadata.file_type();
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let is_hidden = hidden::is_hidden_entry(&name, metadata);
+    // Following the link fails for missing targets and for loops alike
+    let (symlink_target, target_kind) = if file_type.is_symlink() {
+        (
+            fs::read_link(path)
+                .ok()
+                .map(|target| target.to_string_lossy().to_string()),
+            fs::metadata(path)
+                .ok()
+                .map(|target| EntryKind::from_file_type(&target.file_type())),
+        )
+    } else {
+        (None, None)
+    };
+    let is_broken = file_type.is_symlink() && target_kind.is_none();
+    FileEntry {
+        name,
+        path: path.to_string_lossy().to_string(),
+        kind: EntryKind::from_file_type(&file_type),
+        is_directory: file_type.is_dir(),
+        is_file: file_type.is_file(),
+        is_symlink: file_type.is_symlink(),
+        symlink_target,
+        target_kind,
+        is_broken,
+        size: Some(metadata.len()),
+        compressed_size: None,
+        modified_at: metadata.modified().ok().map(to_epoch_millis),
+        created_at: metadata.created().ok().map(to_epoch_millis),
+        permissions: Some(format!("{:o}", get_permission_number(&metadata.permissions()))),
+        extension: path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_string()),
+        is_hidden,
+        is_ignored: false,
+    }
+}
+
 #[tauri::command]
 fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
@@ -136,104 +443,26 @@ async fn copy_to_clipboard(text: String) -> Result<(), String> {
 }
 
 #[tauri::command]
-async fn read_directory(path: String, show_hidden: bool) -> Result<DirectoryContents, String> {
-    let path_obj = Path::new(&path);
-
-    // Check if path exists
-    if !path_obj.exists() {
-        log_error!("Directory does not exist: {}", path);
-        return Err(OhMyFSError::DirectoryNotFound { path }.to_string());
-    }
-
-    // Check if path is a directory
-    if !path_obj.is_dir() {
-        log_error!("Path is not a directory: {}", path);
-        return Err(OhMyFSError::PathNotDirectory { path }.to_string());
-    }
-
-    let mut directories = Vec::new();
-    let mut files = Vec::new();
-
-    let entries = read_dir(path_obj).map_err(|err| {
-        log_error!("Failed to read directory: {}", err);
-        OhMyFSError::DirectoryReadFailed {
-            path: path.clone(),
-            details: err.to_string(),
-        }
-        .to_string()
-    })?;
-
-    for entry in entries {
-        let entry = entry.map_err(|err| {
-            log_error!("Failed to read entry: {}", err);
-            OhMyFSError::DirectoryReadFailed {
-                path: path.clone(),
-                details: err.to_string(),
-            }
-            .to_string()
-        })?;
-
-        let file_name = entry.file_name().to_string_lossy().to_string();
-        let entry_path = entry.path();
-        let file_type = entry.file_type().map_err(|err| {
-            log_error!("Failed to get file type: {}", err);
-            OhMyFSError::DirectoryReadFailed {
-                path: path.clone(),
-                details: err.to_string(),
-            }
-            .to_string()
-        })?;
-
-        // Skip hidden files if not showing them
-        if !show_hidden && file_name.starts_with('.') {
-            continue;
-        }
-
-        let metadata = entry.metadata().map_err(|err| {
-            log_error!("Failed to get metadata: {}", err);
-            OhMyFSError::DirectoryReadFailed {
-                path: path.clone(),
-                details: err.to_string(),
-            }
-            .to_string()
-        })?;
-
-        let extension = entry_path
-            .extension()
-            .map(|ext| ext.to_string_lossy().to_string());
-
-        let file_entry = FileEntry {
-            name: file_name,
-            path: entry_path.to_string_lossy().to_string(),
-            is_directory: file_type.is_dir(),
-            is_file: file_type.is_file(),
-            is_symlink: file_type.is_symlink(),
-            size: Some(metadata.len()),
-            modified_at: metadata.modified().ok().map(|t| {
-                let duration = t.duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
-                // Return as milliseconds since epoch for JavaScript compatibility
-                format!("{}", duration.as_millis())
-            }),
-            created_at: metadata.created().ok().map(|t| {
-                let duration = t.duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
-                // Return as milliseconds since epoch for JavaScript compatibility
-                format!("{}", duration.as_millis())
-            }),
-            permissions: Some(format!("{:o}", get_permission_number(&metadata.permissions()))),
-            extension,
-        };
-
-        if file_type.is_dir() {
-            directories.push(file_entry);
-        } else if file_type.is_file() {
-            files.push(file_entry);
-        }
-    }
-
-    Ok(DirectoryContents {
-        directories,
-        files,
+async fn read_directory(
+    path: String,
+    show_hidden: bool,
+    respect_ignore: Option<bool>,
+    sort: Option<listing::SortOptions>,
+) -> Result<DirectoryContents, String> {
+    let options = listing::ListOptions {
+        show_hidden,
+        respect_ignore,
+        sort: sort.unwrap_or_default(),
+        ..Default::default()
+    };
+    tauri::async_runtime::spawn_blocking(move || {
+        listing::read_contents(&path, &options).map_err(|err| {
+            log_error!("Failed to read directory {}: {}", path, err);
+            err.to_string()
+        })
     })
+    .await
+    .map_err(|err| err.to_string())?
 }
 
 #[tauri::command]
@@ -281,30 +510,36 @@ async fn create_file(path: String) -> Result<(), String> {
 }
 
 #[tauri::command]
-async fn delete_file(path: String) -> Result<(), String> {
-    let path_obj = Path::new(&path);
-
-    if path_obj.is_dir() {
-        fs::remove_dir_all(path_obj).map_err(|err| {
-            log_error!("Failed to delete directory {}: {}", path, err);
-            OhMyFSError::FileWriteFailed {
-                path,
-                details: err.to_string(),
-            }
-            .to_string()
-        })?;
-    } else {
-        fs::remove_file(path_obj).map_err(|err| {
-            log_error!("Failed to delete file {}: {}", path, err);
-            OhMyFSError::FileWriteFailed {
-                path,
-                details: err.to_string(),
-            }
-            .to_string()
-        })?;
-    }
-
-    Ok(())
+async fn delete_file(
+    window: tauri::Window,
+    jobs: tauri::State<'_, job::Jobs>,
+    path: String,
+    job_id: Option<String>,
+) -> Result<(), String> {
+    let job = jobs.create(
+        &window,
+        job_id,
+        job::JobKind::Delete,
+        vec![path.clone()],
+        None,
+    );
+    let job_id = job.id().to_string();
+
+    job::run(job, move |job| {
+        let token = job.token().clone();
+        let mut emitter = operation::ProgressEmitter::new(&window, transfer::PROGRESS_EVENT);
+        transfer::delete(
+            Path::new(&path),
+            Some(job_id),
+            &token,
+            &mut |progress, force| {
+                emitter.emit(progress, force);
+                job.report(progress, force);
+            },
+        )
+        .map_err(|err| err.to_string())
+    })
+    .await
 }
 
 #[tauri::command]
@@ -392,8 +627,16 @@ async fn write_log_file(content: String, path: String) -> Result<(), String> {
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
+        .manage(job::Jobs::default())
+        .manage(watch::Watchers::default())
+        .manage(search::Searches::default())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
+        .setup(|app| {
+            let data_dir = app.path().app_local_data_dir()?;
+            app.manage(index::Index::open(app.handle().clone(), data_dir));
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             greet,
             copy_to_clipboard,
@@ -405,7 +648,36 @@ pub fn run() {
             copy_file,
             write_file,
             read_file,
-            write_log_file
+            write_log_file,
+            archive::compress_paths,
+            archive::extract_archive,
+            archive::list_archive,
+            job::list_jobs,
+            job::cancel_job,
+            job::pause_job,
+            job::resume_job,
+            transfer::copy_paths,
+            transfer::move_paths,
+            trash::trash_paths,
+            trash::list_trash,
+            trash::restore_from_trash,
+            trash::empty_trash,
+            watch::watch_directory,
+            watch::unwatch_directory,
+            search::search_files,
+            search::cancel_search,
+            search::content::search_content,
+            index::list_index_roots,
+            index::add_index_root,
+            index::remove_index_root,
+            index::rebuild_index,
+            index::query_index,
+            stat::stat_path,
+            stat::path_exists,
+            listing::list_directory,
+            engine::plan_definition,
+            engine::apply::apply_plan,
+            engine::snapshot::snapshot_to_definition
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,541 @@
+// Streamed directory listing for folders too large for a single IPC message
+//
+// `list_directory` sends entries through a channel in chunks. Unsorted
+// listings go out while the folder is still being read; sorted ones are read
+// whole, sorted and paged first. An entry that cannot be read is reported in
+// its chunk instead of failing the listing.
+
+mod sort;
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use tauri::ipc::Channel;
+
+use crate::{
+    archive, file_entry, hidden, ignore, log_error, log_info, log_warn, DirectoryContents,
+    FileEntry, OhMyFSError,
+};
+use sort::Sorter;
+pub use sort::{EntryGroup, SortOptions};
+
+const DEFAULT_CHUNK_SIZE: usize = 500;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ListOptions {
+    #[serde(default)]
+    pub show_hidden: bool,
+    pub respect_ignore: Option<bool>,
+    // Without `sort_by` and `group_by` entries are streamed in the order the
+    // file system returns them
+    #[serde(flatten)]
+    pub sort: SortOptions,
+    // Applied after hidden entries are dropped and after sorting
+    #[serde(default)]
+    pub offset: usize,
+    pub limit: Option<usize>,
+    pub chunk_size: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ListingChunk {
+    pub entries: Vec<FileEntry>,
+    // Entries that could not be read
+    pub failed: Vec<OhMyFSError>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ListingSummary {
+    // Entries in the folder that passed the hidden filter, before paging
+    pub total: u64,
+    pub sent: u64,
+    pub failed: u64,
+    // Counted over the whole listing, not just the page that was sent
+    pub groups: Option<Vec<EntryGroup>>,
+}
+
+// Collects entries into chunks and sends each as soon as it is full
+struct ChunkSender<'a> {
+    path: &'a str,
+    channel: &'a Channel<ListingChunk>,
+    chunk_size: usize,
+    pending: ListingChunk,
+    sent: u64,
+    failed: u64,
+}
+
+impl<'a> ChunkSender<'a> {
+    fn new(path: &'a str, channel: &'a Channel<ListingChunk>, chunk_size: usize) -> Self {
+        Self {
+            path,
+            channel,
+            chunk_size: chunk_size.max(1),
+            pending: ListingChunk {
+                entries: Vec::new(),
+                failed: Vec::new(),
+            },
+            sent: 0,
+            failed: 0,
+        }
+    }
+
+    fn entry(&mut self, entry: FileEntry) -> Result<(), OhMyFSError> {
+        self.sent += 1;
+        self.pending.entries.push(entry);
+        if self.pending.entries.len() >= self.chunk_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn error(&mut self, error: OhMyFSError) -> Result<(), OhMyFSError> {
+        self.failed += 1;
+        self.pending.failed.push(error);
+        // A folder of unreadable entries would otherwise pile them all up
+        if self.pending.failed.len() >= self.chunk_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), OhMyFSError> {
+        if self.pending.entries.is_empty() && self.pending.failed.is_empty() {
+            return Ok(());
+        }
+        let chunk = ListingChunk {
+            entries: std::mem::take(&mut self.pending.entries),
+            failed: std::mem::take(&mut self.pending.failed),
+        };
+        // Fails when the frontend is gone, so there is no point going on
+        self.channel
+            .send(chunk)
+            .map_err(|err| OhMyFSError::DirectoryReadFailed {
+                path: self.path.to_string(),
+                details: format!("the listing could not be sent: {}", err),
+            })
+    }
+}
+
+// Applies `offset` and `limit` to a stream of entries
+struct Page {
+    skip: usize,
+    remaining: usize,
+}
+
+impl Page {
+    fn new(options: &ListOptions) -> Self {
+        Self {
+            skip: options.offset,
+            remaining: options.limit.unwrap_or(usize::MAX),
+        }
+    }
+
+    fn take(&mut self) -> bool {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return false;
+        }
+        if self.remaining == 0 {
+            return false;
+        }
+        self.remaining -= 1;
+        true
+    }
+}
+
+// Reads entries of a folder on disk, filtering hidden ones and tagging ignored
+// ones. Shared by both listing commands so they agree on what is shown.
+struct DirectoryReader {
+    show_hidden: bool,
+    hidden_names: hidden::HiddenNames,
+    ignores: Option<ignore::GitIgnores>,
+}
+
+impl DirectoryReader {
+    fn new(directory: &Path, options: &ListOptions) -> Self {
+        Self {
+            show_hidden: options.show_hidden,
+            hidden_names: hidden::HiddenNames::for_directory(directory),
+            ignores: options
+                .respect_ignore
+                .unwrap_or(false)
+                .then(|| ignore::GitIgnores::for_directory(directory)),
+        }
+    }
+
+    // None when the entry is hidden and hidden entries are not wanted
+    fn read(&self, entry: &fs::DirEntry) -> Option<Result<FileEntry, OhMyFSError>> {
+        let path = entry.path();
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                let hidden = hidden::is_dotfile(&entry.file_name().to_string_lossy());
+                return (self.show_hidden || !hidden).then(|| {
+                    Err(OhMyFSError::FileReadFailed {
+                        path: path.to_string_lossy().to_string(),
+                        details: err.to_string(),
+                    })
+                });
+            }
+        };
+
+        let mut file_entry = file_entry(&path, &metadata);
+        file_entry.is_hidden |= self.hidden_names.contains(&file_entry.name);
+        if !self.show_hidden && file_entry.is_hidden {
+            return None;
+        }
+        if let Some(ignores) = &self.ignores {
+            file_entry.is_ignored = ignores.is_ignored(&path, metadata.is_dir());
+        }
+        Some(Ok(file_entry))
+    }
+}
+
+fn open_directory(path: &str) -> Result<&Path, OhMyFSError> {
+    let directory = Path::new(path);
+    if !directory.exists() {
+        return Err(OhMyFSError::DirectoryNotFound {
+            path: path.to_string(),
+        });
+    }
+    if !directory.is_dir() {
+        return Err(OhMyFSError::PathNotDirectory {
+            path: path.to_string(),
+        });
+    }
+    Ok(directory)
+}
+
+fn read_failed(path: &str, err: std::io::Error) -> OhMyFSError {
+    OhMyFSError::DirectoryReadFailed {
+        path: path.to_string(),
+        details: err.to_string(),
+    }
+}
+
+// Orders a `read_directory` result. Folders and files come back in separate
+// lists there, so folders are always first.
+fn sort_contents(contents: DirectoryContents, options: &SortOptions) -> DirectoryContents {
+    let Some(sorter) = Sorter::new(options) else {
+        return contents;
+    };
+    let mut entries = contents.directories;
+    entries.extend(contents.files);
+    let (entries, groups) = sorter.with_directories_first().sort(entries);
+    let (directories, files) = entries.into_iter().partition(FileEntry::lists_as_directory);
+    DirectoryContents {
+        directories,
+        files,
+        groups,
+    }
+}
+
+fn list(
+    path: &str,
+    options: &ListOptions,
+    channel: &Channel<ListingChunk>,
+) -> Result<ListingSummary, OhMyFSError> {
+    let mut sender = ChunkSender::new(
+        path,
+        channel,
+        options.chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE),
+    );
+    let mut page = Page::new(options);
+    let mut total = 0u64;
+    let mut groups = None;
+    let sorter = Sorter::new(&options.sort);
+
+    // Archives are listed in one go anyway, so they always take the sorted path
+    let entries = match archive::split_archive_path(path) {
+        Some((archive_path, inner)) => {
+            let contents =
+                archive::read_archive_directory(&archive_path, &inner, options.show_hidden)?;
+            let mut entries = contents.directories;
+            entries.extend(contents.files);
+            Some(entries)
+        }
+        None => {
+            let directory = open_directory(path)?;
+            let reader = DirectoryReader::new(directory, options);
+            let streaming = sorter.is_none();
+            let mut collected = Vec::new();
+            for entry in fs::read_dir(directory).map_err(|err| read_failed(path, err))? {
+                let read = match entry {
+                    Ok(entry) => reader.read(&entry),
+                    Err(err) => Some(Err(read_failed(path, err))),
+                };
+                match read {
+                    None => {}
+                    Some(Err(err)) => sender.error(err)?,
+                    Some(Ok(entry)) if streaming => {
+                        total += 1;
+                        if page.take() {
+                            sender.entry(entry)?;
+                        }
+                    }
+                    Some(Ok(entry)) => collected.push(entry),
+                }
+            }
+            (!streaming).then_some(collected)
+        }
+    };
+
+    if let Some(mut entries) = entries {
+        if let Some(sorter) = &sorter {
+            (entries, groups) = sorter.sort(entries);
+        }
+        total = entries.len() as u64;
+        for entry in entries {
+            if page.take() {
+                sender.entry(entry)?;
+            }
+        }
+    }
+    sender.flush()?;
+
+    Ok(ListingSummary {
+        total,
+        sent: sender.sent,
+        failed: sender.failed,
+        groups,
+    })
+}
+
+// The whole listing of a folder, or of a folder inside an archive, for
+// `read_directory`. Entries that cannot be read are left out.
+pub(crate) fn read_contents(
+    path: &str,
+    options: &ListOptions,
+) -> Result<DirectoryContents, OhMyFSError> {
+    // Paths like `/x/build.zip!/src/` browse inside an archive
+    if let Some((archive_path, inner)) = archive::split_archive_path(path) {
+        let contents = archive::read_archive_directory(&archive_path, &inner, options.show_hidden)?;
+        return Ok(sort_contents(contents, &options.sort));
+    }
+
+    let directory = open_directory(path)?;
+    let reader = DirectoryReader::new(directory, options);
+    let mut contents = DirectoryContents {
+        directories: Vec::new(),
+        files: Vec::new(),
+        groups: None,
+    };
+    for entry in fs::read_dir(directory).map_err(|err| read_failed(path, err))? {
+        let read = match entry {
+            Ok(entry) => reader.read(&entry),
+            Err(err) => Some(Err(read_failed(path, err))),
+        };
+        match read {
+            None => {}
+            // Often an entry deleted while the folder is being read
+            Some(Err(err)) => log_warn!("Skipping unreadable entry in {}: {}", path, err),
+            // Everything that is not a folder, including sockets, FIFOs and
+            // devices, is listed with the files
+            Some(Ok(entry)) if entry.lists_as_directory() => contents.directories.push(entry),
+            Some(Ok(entry)) => contents.files.push(entry),
+        }
+    }
+    Ok(sort_contents(contents, &options.sort))
+}
+
+// Streams the entries of a folder (or of a folder inside an archive) to
+// `on_chunk`, returning once everything was sent
+#[tauri::command]
+pub async fn list_directory(
+    path: String,
+    options: Option<ListOptions>,
+    on_chunk: Channel<ListingChunk>,
+) -> Result<ListingSummary, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let options = options.unwrap_or_default();
+        let summary = list(&path, &options, &on_chunk).map_err(|err| {
+            log_error!("Failed to list directory {}: {}", path, err);
+            err.to_string()
+        })?;
+        if summary.failed > 0 {
+            log_info!("Listed {} with {} unreadable entries", path, summary.failed);
+        }
+        Ok(summary)
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sort::SortBy;
+    use std::sync::{Arc, Mutex};
+    use tauri::ipc::InvokeResponseBody;
+
+    type Chunks = Arc<Mutex<Vec<ListingChunk>>>;
+
+    // A channel that keeps every chunk it is sent
+    fn collector() -> (Channel<ListingChunk>, Chunks) {
+        let chunks = Chunks::default();
+        let collected = chunks.clone();
+        let channel = Channel::new(move |body| {
+            let InvokeResponseBody::Json(json) = body else {
+                panic!("listing chunks are sent as JSON");
+            };
+            collected
+                .lock()
+                .unwrap()
+                .push(serde_json::from_str(&json).unwrap());
+            Ok(())
+        });
+        (channel, chunks)
+    }
+
+    fn folder(names: &[&str]) -> tempfile::TempDir {
+        let temp = tempfile::tempdir().unwrap();
+        for name in names {
+            fs::write(temp.path().join(name), name).unwrap();
+        }
+        temp
+    }
+
+    fn names(chunks: &Chunks) -> Vec<Vec<String>> {
+        chunks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|chunk| {
+                chunk
+                    .entries
+                    .iter()
+                    .map(|entry| entry.name.clone())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn sorted(offset: usize, limit: Option<usize>, chunk_size: usize) -> ListOptions {
+        ListOptions {
+            sort: SortOptions {
+                sort_by: Some(SortBy::Name),
+                ..Default::default()
+            },
+            offset,
+            limit,
+            chunk_size: Some(chunk_size),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pages_are_taken_from_the_sorted_listing() {
+        let temp = folder(&["e.txt", "a.txt", "c.txt", "b.txt", "d.txt"]);
+        let path = temp.path().to_string_lossy().to_string();
+        let (channel, chunks) = collector();
+
+        let summary = list(&path, &sorted(1, Some(3), 2), &channel).unwrap();
+
+        assert_eq!(names(&chunks), [vec!["b.txt", "c.txt"], vec!["d.txt"]]);
+        assert_eq!((summary.total, summary.sent, summary.failed), (5, 3, 0));
+    }
+
+    #[test]
+    fn streamed_listings_are_paged_and_chunked() {
+        let temp = folder(&["1", "2", "3", "4", "5", "6", "7"]);
+        let path = temp.path().to_string_lossy().to_string();
+
+        let (channel, chunks) = collector();
+        let options = ListOptions {
+            chunk_size: Some(3),
+            ..Default::default()
+        };
+        let summary = list(&path, &options, &channel).unwrap();
+        let sizes = names(&chunks).iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(sizes, [3, 3, 1]);
+        assert_eq!((summary.total, summary.sent), (7, 7));
+
+        let (channel, chunks) = collector();
+        let options = ListOptions {
+            offset: 5,
+            limit: Some(10),
+            chunk_size: Some(3),
+            ..Default::default()
+        };
+        let summary = list(&path, &options, &channel).unwrap();
+        assert_eq!(names(&chunks).concat().len(), 2);
+        assert_eq!((summary.total, summary.sent), (7, 2));
+    }
+
+    #[test]
+    fn whole_listings_split_folders_and_hide_dotfiles() {
+        let temp = folder(&["b.txt", ".env", "a.txt"]);
+        fs::create_dir(temp.path().join("src")).unwrap();
+        let path = temp.path().to_string_lossy().to_string();
+
+        let contents = read_contents(&path, &sorted(0, None, 1)).unwrap();
+        let names = |entries: &[FileEntry]| {
+            entries
+                .iter()
+                .map(|entry| entry.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&contents.directories), ["src"]);
+        assert_eq!(names(&contents.files), ["a.txt", "b.txt"]);
+
+        let options = ListOptions {
+            show_hidden: true,
+            ..Default::default()
+        };
+        assert_eq!(read_contents(&path, &options).unwrap().files.len(), 3);
+    }
+
+    #[test]
+    fn errors_alone_fill_a_chunk() {
+        let (channel, chunks) = collector();
+        let mut sender = ChunkSender::new("/listing", &channel, 2);
+        let unreadable = |name: &str| OhMyFSError::FileReadFailed {
+            path: format!("/listing/{}", name),
+            details: "denied".to_string(),
+        };
+
+        for name in ["a", "b", "c"] {
+            sender.error(unreadable(name)).unwrap();
+        }
+        assert_eq!(chunks.lock().unwrap().len(), 1);
+        sender.flush().unwrap();
+
+        let chunks = chunks.lock().unwrap();
+        let failed = chunks
+            .iter()
+            .map(|chunk| (chunk.entries.len(), chunk.failed.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(failed, [(0, 2), (0, 1)]);
+        assert_eq!(sender.failed, 3);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_entries_do_not_stop_the_listing() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = folder(&["a.txt", "b.txt", "c.txt"]);
+        // Names can still be read without search permission, their metadata not
+        fs::set_permissions(temp.path(), fs::Permissions::from_mode(0o600)).unwrap();
+        let unreadable = fs::symlink_metadata(temp.path().join("a.txt")).is_err();
+        let path = temp.path().to_string_lossy().to_string();
+        let (channel, chunks) = collector();
+
+        let summary = list(&path, &sorted(0, None, 2), &channel);
+        fs::set_permissions(temp.path(), fs::Permissions::from_mode(0o700)).unwrap();
+        // Permissions do not apply to root
+        if !unreadable {
+            return;
+        }
+
+        let summary = summary.unwrap();
+        assert_eq!((summary.sent, summary.failed), (0, 3));
+        let failed = chunks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|chunk| chunk.failed.len())
+            .sum::<usize>();
+        assert_eq!(failed, 3);
+    }
+}
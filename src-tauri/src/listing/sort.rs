@@ -0,0 +1,453 @@
+// Server-side ordering and grouping of listings
+//
+// Entries are put into their group first, so every group is one contiguous
+// run, then ordered within it. Names compare naturally: `file2` sorts before
+// `file10`.
+
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+use crate::FileEntry;
+
+const MB: u64 = 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortBy {
+    #[default]
+    Name,
+    Size,
+    Modified,
+    Created,
+    Extension,
+    // Folders, then files by extension
+    Type,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+// Mirrors `FileViewMode["groupBy"]` on the frontend
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    Type,
+    // Today, yesterday, earlier this week (from Monday), month and year, older
+    Date,
+    Size,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SortOptions {
+    // None keeps the order the file system returns entries in
+    pub sort_by: Option<SortBy>,
+    #[serde(default)]
+    pub sort_order: SortOrder,
+    // Defaults to true
+    pub directories_first: Option<bool>,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    pub group_by: Option<GroupBy>,
+}
+
+// One group of a grouped listing. Groups come in display order and hold the
+// next `directories + files` entries of the listing.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EntryGroup {
+    pub key: String,
+    pub label: String,
+    pub directories: u64,
+    pub files: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Bucket {
+    // Position among the groups
+    rank: u8,
+    key: String,
+}
+
+pub(crate) struct Sorter {
+    sort_by: SortBy,
+    order: SortOrder,
+    directories_first: bool,
+    case_sensitive: bool,
+    group_by: Option<GroupBy>,
+    today: NaiveDate,
+}
+
+impl Sorter {
+    // None when the options ask for neither sorting nor grouping
+    pub fn new(options: &SortOptions) -> Option<Self> {
+        if options.sort_by.is_none() && options.group_by.is_none() {
+            return None;
+        }
+        Some(Self {
+            sort_by: options.sort_by.unwrap_or_default(),
+            order: options.sort_order,
+            directories_first: options.directories_first.unwrap_or(true),
+            case_sensitive: options.case_sensitive,
+            group_by: options.group_by,
+            today: Local::now().date_naive(),
+        })
+    }
+
+    // Listings split into folders and files already have folders first
+    pub fn with_directories_first(mut self) -> Self {
+        self.directories_first = true;
+        self
+    }
+
+    // Sorts the entries and, when grouping, returns the groups in order
+    pub fn sort(&self, entries: Vec<FileEntry>) -> (Vec<FileEntry>, Option<Vec<EntryGroup>>) {
+        // Buckets are computed once per entry, not once per comparison
+        let mut keyed = entries
+            .into_iter()
+            .map(|entry| {
+                (
+                    self.group_by.map(|group_by| self.bucket(&entry, group_by)),
+                    entry,
+                )
+            })
+            .collect::<Vec<_>>();
+        keyed.sort_by(|(a_bucket, a), (b_bucket, b)| {
+            a_bucket
+                .cmp(b_bucket)
+                .then_with(|| {
+                    if self.directories_first {
+                        b.lists_as_directory().cmp(&a.lists_as_directory())
+                    } else {
+                        Ordering::Equal
+                    }
+                })
+                .then_with(|| match self.order {
+                    SortOrder::Asc => self.compare(a, b),
+                    SortOrder::Desc => self.compare(a, b).reverse(),
+                })
+        });
+
+        let mut groups = self.group_by.map(|_| Vec::<EntryGroup>::new());
+        let mut sorted = Vec::with_capacity(keyed.len());
+        let mut current: Option<Bucket> = None;
+        for (bucket, entry) in keyed {
+            if let (Some(groups), Some(bucket), Some(group_by)) =
+                (&mut groups, bucket, self.group_by)
+            {
+                if current.as_ref() != Some(&bucket) {
+                    groups.push(EntryGroup {
+                        label: label(&bucket, group_by),
+                        key: bucket.key.clone(),
+                        directories: 0,
+                        files: 0,
+                    });
+                    current = Some(bucket);
+                }
+                if let Some(group) = groups.last_mut() {
+                    if entry.lists_as_directory() {
+                        group.directories += 1;
+                    } else {
+                        group.files += 1;
+                    }
+                }
+            }
+            sorted.push(entry);
+        }
+        (sorted, groups)
+    }
+
+    fn compare(&self, a: &FileEntry, b: &FileEntry) -> Ordering {
+        let ordering = match self.sort_by {
+            SortBy::Name => Ordering::Equal,
+            SortBy::Size => a.size.cmp(&b.size),
+            SortBy::Modified => millis(&a.modified_at).cmp(&millis(&b.modified_at)),
+            SortBy::Created => millis(&a.created_at).cmp(&millis(&b.created_at)),
+            SortBy::Extension => self.compare_extensions(a, b),
+            SortBy::Type => b
+                .lists_as_directory()
+                .cmp(&a.lists_as_directory())
+                .then_with(|| self.compare_extensions(a, b)),
+        };
+        ordering
+            .then_with(|| natural_cmp(&a.name, &b.name, self.case_sensitive))
+            .then_with(|| a.name.cmp(&b.name))
+    }
+
+    fn compare_extensions(&self, a: &FileEntry, b: &FileEntry) -> Ordering {
+        match (&a.extension, &b.extension) {
+            (Some(a), Some(b)) => natural_cmp(a, b, self.case_sensitive),
+            (a, b) => a.is_some().cmp(&b.is_some()),
+        }
+    }
+
+    fn bucket(&self, entry: &FileEntry, group_by: GroupBy) -> Bucket {
+        let bucket = |rank: u8, key: &str| Bucket {
+            rank,
+            key: key.to_string(),
+        };
+        match group_by {
+            GroupBy::Type => match &entry.extension {
+                _ if entry.lists_as_directory() => bucket(0, "folders"),
+                Some(extension) => bucket(1, &format!("ext:{}", extension.to_lowercase())),
+                None => bucket(2, "files"),
+            },
+            GroupBy::Date => {
+                let date = millis(&entry.modified_at)
+                    .and_then(DateTime::from_timestamp_millis)
+                    .map(|time| time.with_timezone(&Local).date_naive());
+                let Some(date) = date else {
+                    return bucket(6, "unknown");
+                };
+                let days = (self.today - date).num_days();
+                if days <= 0 {
+                    bucket(0, "today")
+                } else if days == 1 {
+                    bucket(1, "yesterday")
+                } else if date.iso_week() == self.today.iso_week() {
+                    bucket(2, "this_week")
+                } else if date.year() == self.today.year() && date.month() == self.today.month() {
+                    bucket(3, "this_month")
+                } else if date.year() == self.today.year() {
+                    bucket(4, "this_year")
+                } else {
+                    bucket(5, "older")
+                }
+            }
+            GroupBy::Size => match entry.size {
+                // Folder sizes say nothing about their contents
+                _ if entry.lists_as_directory() => bucket(0, "folders"),
+                None => bucket(4, "unknown"),
+                Some(size) if size < MB => bucket(1, "small"),
+                Some(size) if size < 100 * MB => bucket(2, "medium"),
+                Some(_) => bucket(3, "large"),
+            },
+        }
+    }
+}
+
+fn label(bucket: &Bucket, group_by: GroupBy) -> String {
+    let label = match (group_by, bucket.key.as_str()) {
+        (_, "folders") => "Folders",
+        (GroupBy::Type, "files") => "Files",
+        (GroupBy::Type, key) => {
+            let extension = key.trim_start_matches("ext:");
+            return format!("{} Files", extension.to_uppercase());
+        }
+        (GroupBy::Date, "today") => "Today",
+        (GroupBy::Date, "yesterday") => "Yesterday",
+        (GroupBy::Date, "this_week") => "Earlier this week",
+        (GroupBy::Date, "this_month") => "Earlier this month",
+        (GroupBy::Date, "this_year") => "Earlier this year",
+        (GroupBy::Date, "older") => "Older",
+        (GroupBy::Size, "small") => "Small (< 1 MB)",
+        (GroupBy::Size, "medium") => "Medium (1 - 100 MB)",
+        (GroupBy::Size, "large") => "Large (> 100 MB)",
+        _ => "Unknown",
+    };
+    label.to_string()
+}
+
+fn millis(value: &Option<String>) -> Option<i64> {
+    value.as_deref().and_then(|value| value.parse().ok())
+}
+
+// Compares runs of digits by their value and everything else character by
+// character, so `img9` < `img10` < `IMG11`
+fn natural_cmp(a: &str, b: &str, case_sensitive: bool) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        let (x, y) = match (a.peek(), b.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(&x), Some(&y)) => (x, y),
+        };
+
+        let ordering = if x.is_ascii_digit() && y.is_ascii_digit() {
+            let x = take_digits(&mut a);
+            let y = take_digits(&mut b);
+            let x = x.trim_start_matches('0');
+            let y = y.trim_start_matches('0');
+            x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+        } else {
+            a.next();
+            b.next();
+            if case_sensitive {
+                x.cmp(&y)
+            } else {
+                x.to_lowercase().cmp(y.to_lowercase())
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        digits.push(digit);
+    }
+    digits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EntryKind;
+
+    fn entry(name: &str, is_directory: bool, size: u64, modified: NaiveDate) -> FileEntry {
+        let modified = modified
+            .and_hms_opt(12, 0, 0)
+            .and_then(|time| time.and_local_timezone(Local).single())
+            .map(|time| time.timestamp_millis().to_string());
+        FileEntry {
+            name: name.to_string(),
+            path: format!("/listing/{}", name),
+            kind: if is_directory {
+                EntryKind::Directory
+            } else {
+                EntryKind::File
+            },
+            is_directory,
+            is_file: !is_directory,
+            is_symlink: false,
+            symlink_target: None,
+            target_kind: None,
+            is_broken: false,
+            size: Some(size),
+            compressed_size: None,
+            modified_at: modified,
+            created_at: None,
+            permissions: None,
+            extension: name
+                .rsplit_once('.')
+                .filter(|_| !is_directory)
+                .map(|(_, extension)| extension.to_string()),
+            is_hidden: false,
+            is_ignored: false,
+        }
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn names(entries: &[FileEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[test]
+    fn natural_cmp_orders_numbers_by_value() {
+        assert_eq!(natural_cmp("img9", "img10", false), Ordering::Less);
+        assert_eq!(natural_cmp("img10", "IMG11", false), Ordering::Less);
+        assert_eq!(natural_cmp("a007", "a7", false), Ordering::Equal);
+        assert_eq!(natural_cmp("file", "file1", false), Ordering::Less);
+        assert_eq!(natural_cmp("B", "a", true), Ordering::Less);
+        assert_eq!(natural_cmp("B", "a", false), Ordering::Greater);
+    }
+
+    #[test]
+    fn sorts_with_directories_first_in_either_order() {
+        let day = date(2024, 5, 10);
+        let entries = vec![
+            entry("file10.txt", false, 1, day),
+            entry("docs", true, 0, day),
+            entry("file2.txt", false, 1, day),
+            entry("Assets", true, 0, day),
+        ];
+        let mut options = SortOptions {
+            sort_by: Some(SortBy::Name),
+            ..Default::default()
+        };
+        let (sorted, groups) = Sorter::new(&options).unwrap().sort(entries.clone());
+        assert_eq!(
+            names(&sorted),
+            ["Assets", "docs", "file2.txt", "file10.txt"]
+        );
+        assert!(groups.is_none());
+
+        options.sort_order = SortOrder::Desc;
+        let (sorted, _) = Sorter::new(&options).unwrap().sort(entries);
+        assert_eq!(
+            names(&sorted),
+            ["docs", "Assets", "file10.txt", "file2.txt"]
+        );
+    }
+
+    #[test]
+    fn groups_by_date_are_contiguous_and_counted() {
+        // A Thursday, so Monday the 10th is earlier this week and Sunday the
+        // 9th is not
+        let today = date(2024, 6, 13);
+        let entries = vec![
+            entry("old.txt", false, 1, date(2023, 12, 31)),
+            entry("monday.txt", false, 1, date(2024, 6, 10)),
+            entry("now", true, 0, today),
+            entry("sunday.txt", false, 1, date(2024, 6, 9)),
+            entry("now.txt", false, 1, today),
+            entry("yesterday.txt", false, 1, date(2024, 6, 12)),
+        ];
+        let mut sorter = Sorter::new(&SortOptions {
+            group_by: Some(GroupBy::Date),
+            ..Default::default()
+        })
+        .unwrap();
+        sorter.today = today;
+
+        let (sorted, groups) = sorter.sort(entries);
+        assert_eq!(
+            names(&sorted),
+            [
+                "now",
+                "now.txt",
+                "yesterday.txt",
+                "monday.txt",
+                "sunday.txt",
+                "old.txt"
+            ]
+        );
+        let groups = groups.unwrap();
+        let keys = groups
+            .iter()
+            .map(|group| group.key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            ["today", "yesterday", "this_week", "this_month", "older"]
+        );
+        assert_eq!((groups[0].directories, groups[0].files), (1, 1));
+        assert_eq!(groups[2].label, "Earlier this week");
+    }
+
+    #[test]
+    fn groups_by_type_put_folders_first_and_label_extensions() {
+        let day = date(2024, 5, 10);
+        let entries = vec![
+            entry("Makefile", false, 1, day),
+            entry("b.RS", false, 1, day),
+            entry("src", true, 0, day),
+            entry("a.rs", false, 1, day),
+        ];
+        let (sorted, groups) = Sorter::new(&SortOptions {
+            group_by: Some(GroupBy::Type),
+            ..Default::default()
+        })
+        .unwrap()
+        .sort(entries);
+        assert_eq!(names(&sorted), ["src", "a.rs", "b.RS", "Makefile"]);
+        let labels = groups
+            .unwrap()
+            .into_iter()
+            .map(|group| group.label)
+            .collect::<Vec<_>>();
+        assert_eq!(labels, ["Folders", "RS Files", "Files"]);
+    }
+}
@@ -0,0 +1,98 @@
+// Plumbing shared by long running commands: throttled progress events and
+// tokens that let a job be cancelled or paused between units of work
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Window};
+
+use crate::log_error;
+
+// Minimum delay between two progress events for the same operation
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+// Rate-limits progress events so a folder of tiny files does not flood the IPC bridge
+pub(crate) struct ProgressEmitter {
+    window: Window,
+    event: &'static str,
+    last_emit: Option<Instant>,
+}
+
+impl ProgressEmitter {
+    pub fn new(window: &Window, event: &'static str) -> Self {
+        Self {
+            window: window.clone(),
+            event,
+            last_emit: None,
+        }
+    }
+
+    pub fn emit<T: Serialize + Clone>(&mut self, progress: &T, force: bool) {
+        let due = self
+            .last_emit
+            .is_none_or(|last| last.elapsed() >= PROGRESS_INTERVAL);
+        if !force && !due {
+            return;
+        }
+
+        if let Err(err) = self.window.emit(self.event, progress) {
+            log_error!("Failed to emit {}: {}", self.event, err);
+        }
+        self.last_emit = Some(Instant::now());
+    }
+}
+
+#[derive(Debug, Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    paused: Mutex<bool>,
+    resumed: Condvar,
+}
+
+// Shared between a running operation and whoever may cancel or pause it
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<TokenState>);
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::Relaxed);
+        // Taking the lock makes sure a paused worker is not between its check and its wait
+        let _paused = self.lock_paused();
+        self.0.resumed.notify_all();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn pause(&self) {
+        *self.lock_paused() = true;
+    }
+
+    pub fn resume(&self) {
+        *self.lock_paused() = false;
+        self.0.resumed.notify_all();
+    }
+
+    // Called by workers between units of work: blocks while the operation is
+    // paused and returns true once it has been cancelled
+    pub fn checkpoint(&self) -> bool {
+        let mut paused = self.lock_paused();
+        while *paused && !self.is_cancelled() {
+            paused = self
+                .0
+                .resumed
+                .wait(paused)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        self.is_cancelled()
+    }
+
+    fn lock_paused(&self) -> std::sync::MutexGuard<'_, bool> {
+        self.0
+            .paused
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
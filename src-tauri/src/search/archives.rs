@@ -0,0 +1,97 @@
+// Looking inside archives found while searching, without extracting them
+
+use std::path::Path;
+
+use crate::archive::{self, ArchiveFormat};
+use crate::hidden;
+use crate::{FileEntry, OhMyFSError};
+
+// Only the name is checked; sniffing the contents of every file on the way
+// would slow the walk down far more than it is worth
+pub(crate) fn is_archive(path: &Path) -> bool {
+    ArchiveFormat::from_extension(path).is_some()
+}
+
+// Entries are hidden when any folder on their way inside the archive is
+pub(crate) fn is_hidden(inner: &str) -> bool {
+    inner
+        .split('/')
+        .any(|part| part != "." && part != ".." && hidden::is_dotfile(part))
+}
+
+pub(crate) fn entries(archive: &Path, include_hidden: bool) -> Result<Vec<FileEntry>, OhMyFSError> {
+    let listing = archive::list(archive)?;
+    let mut entries = archive::virtual_entries(archive, &listing.entries);
+    if !include_hidden {
+        // Every path starts with this, so there is no need to split it again
+        let prefix = archive::virtual_path(archive, "");
+        entries.retain(|entry| {
+            entry
+                .path
+                .strip_prefix(&prefix)
+                .is_none_or(|inner| !is_hidden(inner))
+        });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    #[test]
+    fn hidden_entries_are_left_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("project.zip");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+        for name in [
+            "README.md",
+            ".env",
+            "src/main.rs",
+            "src/.cache/build.log",
+            ".git/config",
+        ] {
+            writer
+                .start_file(name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(name.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let inner = |include_hidden: bool| {
+            let prefix = archive::virtual_path(&archive, "");
+            let mut inner = entries(&archive, include_hidden)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.path.strip_prefix(&prefix).unwrap().to_string())
+                .collect::<Vec<_>>();
+            inner.sort();
+            inner
+        };
+        assert_eq!(inner(false), ["README.md", "src", "src/main.rs"]);
+        assert_eq!(
+            inner(true),
+            [
+                ".env",
+                ".git",
+                ".git/config",
+                "README.md",
+                "src",
+                "src/.cache",
+                "src/.cache/build.log",
+                "src/main.rs",
+            ]
+        );
+    }
+
+    #[test]
+    fn hidden_inner_paths() {
+        assert!(is_hidden(".env"));
+        assert!(is_hidden("src/.cache/build.log"));
+        assert!(!is_hidden("src/main.rs"));
+        assert!(!is_hidden("../main.rs"));
+    }
+}
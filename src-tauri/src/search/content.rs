@@ -0,0 +1,484 @@
+// Content search behind `search_content`, a small grep over a tree
+//
+// Files are read whole, so anything above `max_file_size` is skipped along
+// with files that look binary.
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use tauri::{State, Window};
+
+use super::archives;
+use super::walk::{walk_parallel, WalkOptions};
+use super::{search_root, BatchSink, Searches};
+use crate::archive;
+use crate::ignore::IgnoreRules;
+use crate::operation::CancellationToken;
+use crate::{log_info, OhMyFSError};
+
+pub const CONTENT_RESULTS_EVENT: &str = "search://content";
+
+const DEFAULT_CONTEXT_LINES: usize = 2;
+const DEFAULT_MAX_MATCHES: usize = 10_000;
+const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+// Same heuristic as git and grep: a NUL byte early on means binary
+pub(crate) const BINARY_SNIFF_LENGTH: usize = 8 * 1024;
+// Minified bundles have single lines of megabytes; the UI only needs a preview
+const MAX_LINE_LENGTH: usize = 500;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ContentSearchOptions {
+    // The pattern is a literal unless this is set
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    #[serde(default)]
+    pub whole_word: bool,
+    pub context_lines: Option<usize>,
+    // `.gitignore`-style, relative to the search root
+    pub ignore_patterns: Option<Vec<String>>,
+    pub include_hidden: Option<bool>,
+    // Also skip what `.gitignore`, `.ignore` and the global git excludes ignore
+    pub respect_ignore: Option<bool>,
+    // Descend into symlinked folders, each folder searched once
+    pub follow_symlinks: Option<bool>,
+    // Also grep text files inside zip, tar and 7z files, reported as `archive.zip!/inner/path`
+    #[serde(default)]
+    pub search_archives: bool,
+    // Matching lines across all files
+    pub max_matches: Option<usize>,
+    pub max_file_size: Option<u64>,
+    pub search_id: Option<String>,
+}
+
+// Columns are character offsets into the `line` sent with the match, which
+// is only a preview of long lines
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MatchRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LineMatch {
+    pub line_number: u64,
+    pub line: String,
+    pub ranges: Vec<MatchRange>,
+    pub context_before: Vec<String>,
+    pub context_after: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileMatches {
+    pub path: String,
+    pub matches: Vec<LineMatch>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ContentSearchSummary {
+    pub search_id: String,
+    pub files_matched: u64,
+    pub matches: u64,
+    pub files_searched: u64,
+    pub binary_skipped: u64,
+    pub large_skipped: u64,
+    pub truncated: bool,
+    pub cancelled: bool,
+    pub failed: Vec<OhMyFSError>,
+}
+
+struct ContentSearch<'a> {
+    root: &'a Path,
+    pattern: Regex,
+    ignore: IgnoreRules,
+    context_lines: usize,
+    max_matches: usize,
+    max_file_size: u64,
+    include_hidden: bool,
+    respect_ignore: bool,
+    follow_symlinks: bool,
+    search_archives: bool,
+    token: &'a CancellationToken,
+    sink: &'a BatchSink<FileMatches>,
+    files_matched: AtomicU64,
+    matches: AtomicU64,
+    files_searched: AtomicU64,
+    binary_skipped: AtomicU64,
+    large_skipped: AtomicU64,
+    truncated: AtomicBool,
+    failed: Mutex<Vec<OhMyFSError>>,
+}
+
+impl ContentSearch<'_> {
+    fn run(self) -> ContentSearchSummary {
+        let mut failed = walk_parallel(
+            self.root,
+            &WalkOptions {
+                include_hidden: self.include_hidden,
+                respect_ignore: self.respect_ignore,
+                follow_symlinks: self.follow_symlinks,
+            },
+            self.token,
+            |entry, file_type| {
+                let path = entry.path();
+                let relative = path.strip_prefix(self.root).unwrap_or(&path);
+                if self.ignore.is_ignored(relative, file_type.is_dir()) {
+                    return false;
+                }
+                if file_type.is_file() {
+                    self.search_file(&path);
+                    if self.search_archives && archives::is_archive(&path) {
+                        self.search_archive(&path);
+                    }
+                }
+                true
+            },
+        );
+        self.sink.flush();
+
+        failed.append(
+            &mut self
+                .failed
+                .into_inner()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        );
+        let truncated = self.truncated.into_inner();
+        ContentSearchSummary {
+            search_id: self.sink.search_id().to_string(),
+            files_matched: self.files_matched.into_inner(),
+            matches: self.matches.into_inner().min(self.max_matches as u64),
+            files_searched: self.files_searched.into_inner(),
+            binary_skipped: self.binary_skipped.into_inner(),
+            large_skipped: self.large_skipped.into_inner(),
+            truncated,
+            cancelled: self.token.is_cancelled() && !truncated,
+            failed,
+        }
+    }
+
+    fn search_file(&self, path: &Path) {
+        let contents = fs::File::open(path).and_then(|file| {
+            if file.metadata()?.len() > self.max_file_size {
+                return Ok(Contents::TooLarge);
+            }
+            read_text(file, self.max_file_size)
+        });
+        match contents {
+            Ok(contents) => self.search_contents(&path.to_string_lossy(), contents),
+            Err(err) => self.fail(OhMyFSError::FileReadFailed {
+                path: path.to_string_lossy().to_string(),
+                details: err.to_string(),
+            }),
+        }
+    }
+
+    fn search_archive(&self, archive: &Path) {
+        let searched = archive::read_files(archive, self.token, &mut |info, reader| {
+            let inner = archive::normalize_entry_name(&info.name);
+            if !self.include_hidden && archives::is_hidden(&inner) {
+                return;
+            }
+            let path = archive::virtual_path(archive, &inner);
+            if info.size > self.max_file_size {
+                self.search_contents(&path, Contents::TooLarge);
+                return;
+            }
+            match read_text(reader, self.max_file_size) {
+                Ok(contents) => self.search_contents(&path, contents),
+                Err(err) => self.fail(OhMyFSError::ArchiveEntryFailed {
+                    path,
+                    details: err.to_string(),
+                }),
+            }
+        });
+        if let Err(err) = searched {
+            self.fail(err);
+        }
+    }
+
+    fn fail(&self, err: OhMyFSError) {
+        self.failed
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(err);
+    }
+
+    fn search_contents(&self, path: &str, contents: Contents) {
+        let contents = match contents {
+            Contents::Text(contents) => contents,
+            Contents::Binary => {
+                self.binary_skipped.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            Contents::TooLarge => {
+                self.large_skipped.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+        self.files_searched.fetch_add(1, Ordering::Relaxed);
+
+        let lines = contents.lines().collect::<Vec<_>>();
+        let mut matches = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            let found = self
+                .pattern
+                .find_iter(line)
+                .map(|found| found.range())
+                .collect::<Vec<_>>();
+            if found.is_empty() {
+                continue;
+            }
+
+            // Workers can race past the limit, so only the first `max_matches` get through
+            if self.matches.fetch_add(1, Ordering::Relaxed) >= self.max_matches as u64 {
+                self.truncated.store(true, Ordering::Relaxed);
+                self.token.cancel();
+                break;
+            }
+            let before = index.saturating_sub(self.context_lines);
+            let after = (index + 1 + self.context_lines).min(lines.len());
+            let (line, ranges) = match_preview(line, &found);
+            matches.push(LineMatch {
+                line_number: index as u64 + 1,
+                line,
+                ranges,
+                context_before: lines[before..index]
+                    .iter()
+                    .map(|line| preview(line))
+                    .collect(),
+                context_after: lines[index + 1..after]
+                    .iter()
+                    .map(|line| preview(line))
+                    .collect(),
+            });
+        }
+
+        if !matches.is_empty() {
+            self.files_matched.fetch_add(1, Ordering::Relaxed);
+            self.sink.push(FileMatches {
+                path: path.to_string(),
+                matches,
+            });
+        }
+    }
+}
+
+enum Contents {
+    Text(String),
+    Binary,
+    TooLarge,
+}
+
+fn read_text(reader: impl Read, max_file_size: u64) -> std::io::Result<Contents> {
+    let mut bytes = Vec::new();
+    reader.take(max_file_size + 1).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > max_file_size {
+        return Ok(Contents::TooLarge);
+    }
+    if bytes[..bytes.len().min(BINARY_SNIFF_LENGTH)].contains(&0) {
+        return Ok(Contents::Binary);
+    }
+    // Invalid UTF-8 shows up as replacement characters instead of hiding matches
+    Ok(Contents::Text(match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(err) => String::from_utf8_lossy(err.as_bytes()).into_owned(),
+    }))
+}
+
+fn preview(line: &str) -> String {
+    if line.len() <= MAX_LINE_LENGTH {
+        return line.to_string();
+    }
+    let end = (0..=MAX_LINE_LENGTH)
+        .rev()
+        .find(|&index| line.is_char_boundary(index))
+        .unwrap_or(0);
+    format!("{}…", &line[..end])
+}
+
+// Cuts a long matching line around its first match and moves the ranges
+// along. Matches outside the preview are dropped, ones running over its edge
+// are clamped.
+fn match_preview(line: &str, found: &[std::ops::Range<usize>]) -> (String, Vec<MatchRange>) {
+    let (mut start, mut end) = (0, line.len());
+    if line.len() > MAX_LINE_LENGTH {
+        let first = &found[0];
+        let room = MAX_LINE_LENGTH.saturating_sub(first.len());
+        start = first.start - (room / 2).min(first.start);
+        end = (start + MAX_LINE_LENGTH).min(line.len());
+        start = end - MAX_LINE_LENGTH;
+        while !line.is_char_boundary(start) {
+            start += 1;
+        }
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+    }
+
+    let leading = if start > 0 { "…" } else { "" };
+    let shown = &line[start..end];
+    let offset = |index: usize| leading.chars().count() + shown[..index - start].chars().count();
+    let ranges = found
+        .iter()
+        .filter(|found| {
+            if found.is_empty() {
+                (start..=end).contains(&found.start)
+            } else {
+                found.start < end && found.end > start
+            }
+        })
+        .map(|found| MatchRange {
+            start: offset(found.start.max(start)),
+            end: offset(found.end.min(end)),
+        })
+        .collect();
+
+    let trailing = if end < line.len() { "…" } else { "" };
+    (format!("{}{}{}", leading, shown, trailing), ranges)
+}
+
+#[tauri::command]
+pub async fn search_content(
+    window: Window,
+    searches: State<'_, Searches>,
+    root: String,
+    pattern: String,
+    options: Option<ContentSearchOptions>,
+) -> Result<ContentSearchSummary, String> {
+    let options = options.unwrap_or_default();
+    let root_path = search_root(&root)?;
+
+    let mut source = if options.regex {
+        pattern.clone()
+    } else {
+        regex::escape(&pattern)
+    };
+    if options.whole_word {
+        source = format!(r"\b(?:{})\b", source);
+    }
+    let invalid = |details: String| {
+        OhMyFSError::InvalidSearchPattern {
+            pattern: pattern.clone(),
+            details,
+        }
+        .to_string()
+    };
+    if pattern.is_empty() {
+        return Err(invalid("the pattern is empty".to_string()));
+    }
+    let regex = RegexBuilder::new(&source)
+        .case_insensitive(!options.case_sensitive)
+        .build()
+        .map_err(|err| invalid(err.to_string()))?;
+
+    let (search_id, generation, token) = searches.start(options.search_id);
+    log_info!("Content search {} for {:?} in {}", search_id, pattern, root);
+    let sink = BatchSink::new(window, CONTENT_RESULTS_EVENT, search_id.clone());
+    let ignore = IgnoreRules::new(&options.ignore_patterns.unwrap_or_default());
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        ContentSearch {
+            root: &root_path,
+            pattern: regex,
+            ignore,
+            context_lines: options.context_lines.unwrap_or(DEFAULT_CONTEXT_LINES),
+            max_matches: options.max_matches.unwrap_or(DEFAULT_MAX_MATCHES),
+            max_file_size: options.max_file_size.unwrap_or(DEFAULT_MAX_FILE_SIZE),
+            include_hidden: options.include_hidden.unwrap_or(false),
+            respect_ignore: options.respect_ignore.unwrap_or(false),
+            follow_symlinks: options.follow_symlinks.unwrap_or(false),
+            search_archives: options.search_archives,
+            token: &token,
+            sink: &sink,
+            files_matched: AtomicU64::new(0),
+            matches: AtomicU64::new(0),
+            files_searched: AtomicU64::new(0),
+            binary_skipped: AtomicU64::new(0),
+            large_skipped: AtomicU64::new(0),
+            truncated: AtomicBool::new(false),
+            failed: Mutex::new(Vec::new()),
+        }
+        .run()
+    })
+    .await
+    .map_err(|err| err.to_string());
+    searches.finish(&search_id, generation);
+
+    let summary = result?;
+    log_info!(
+        "Content search {} found {} matches in {} of {} files",
+        summary.search_id,
+        summary.matches,
+        summary.files_matched,
+        summary.files_searched
+    );
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(line: &str, pattern: &str) -> (String, Vec<(usize, usize)>) {
+        let found = Regex::new(pattern)
+            .unwrap()
+            .find_iter(line)
+            .map(|found| found.range())
+            .collect::<Vec<_>>();
+        let (preview, ranges) = match_preview(line, &found);
+        let ranges = ranges
+            .into_iter()
+            .map(|range| (range.start, range.end))
+            .collect();
+        (preview, ranges)
+    }
+
+    fn highlighted(preview: &str, (start, end): (usize, usize)) -> String {
+        preview.chars().skip(start).take(end - start).collect()
+    }
+
+    #[test]
+    fn short_lines_are_kept_whole() {
+        let (preview, found) = ranges("let café = needle;", "needle");
+        assert_eq!(preview, "let café = needle;");
+        assert_eq!(found, vec![(11, 17)]);
+    }
+
+    #[test]
+    fn long_lines_are_cut_around_the_first_match() {
+        let line = format!("{}needle{}needle", "é".repeat(800), "x".repeat(1000));
+        let (preview, found) = ranges(&line, "needle");
+        assert!(preview.starts_with('…') && preview.ends_with('…'));
+        assert!(preview.len() <= MAX_LINE_LENGTH + 2 * '…'.len_utf8());
+        // The second match is far outside the preview
+        assert_eq!(found.len(), 1);
+        assert_eq!(highlighted(&preview, found[0]), "needle");
+    }
+
+    #[test]
+    fn matches_at_the_end_keep_a_full_preview() {
+        let line = format!("{}needle", "x".repeat(2000));
+        let (preview, found) = ranges(&line, "needle");
+        assert!(preview.starts_with('…') && preview.ends_with("needle"));
+        assert_eq!(preview.len(), MAX_LINE_LENGTH + '…'.len_utf8());
+        assert_eq!(highlighted(&preview, found[0]), "needle");
+    }
+
+    #[test]
+    fn matches_over_the_edge_are_clamped() {
+        let line = format!("{}{}", "a".repeat(300), "b".repeat(900));
+        let (preview, found) = ranges(&line, "a+|b+");
+        assert_eq!(found.len(), 2);
+        assert_eq!(highlighted(&preview, found[0]), "a".repeat(300));
+        let (start, end) = found[1];
+        assert_eq!(end, preview.chars().count() - 1);
+        assert!(highlighted(&preview, (start, end))
+            .chars()
+            .all(|c| c == 'b'));
+    }
+}
@@ -0,0 +1,207 @@
+// Name matching for `search_files`
+
+use glob::{MatchOptions, Pattern};
+use regex::{Regex, RegexBuilder};
+
+use super::MatchMode;
+
+pub(crate) enum NameMatcher {
+    // Lowercased unless the search is case sensitive
+    Fuzzy {
+        query: Vec<char>,
+        case_sensitive: bool,
+    },
+    Glob {
+        pattern: Pattern,
+        options: MatchOptions,
+    },
+    Regex(Regex),
+}
+
+impl NameMatcher {
+    pub fn new(query: &str, mode: MatchMode, case_sensitive: bool) -> Result<Self, String> {
+        Ok(match mode {
+            MatchMode::Fuzzy => NameMatcher::Fuzzy {
+                query: fold_case(query.trim(), case_sensitive),
+                case_sensitive,
+            },
+            MatchMode::Glob => NameMatcher::Glob {
+                pattern: Pattern::new(query).map_err(|err| err.to_string())?,
+                options: MatchOptions {
+                    case_sensitive,
+                    require_literal_separator: false,
+                    require_literal_leading_dot: false,
+                },
+            },
+            MatchMode::Regex => NameMatcher::Regex(
+                RegexBuilder::new(query)
+                    .case_insensitive(!case_sensitive)
+                    .build()
+                    .map_err(|err| err.to_string())?,
+            ),
+        })
+    }
+
+    // Higher scores are better matches; glob and regex matches all score the same
+    pub fn score(&self, name: &str) -> Option<i64> {
+        match self {
+            NameMatcher::Fuzzy {
+                query,
+                case_sensitive,
+            } => fuzzy_score(query, name, *case_sensitive),
+            NameMatcher::Glob { pattern, options } => {
+                pattern.matches_with(name, *options).then_some(0)
+            }
+            NameMatcher::Regex(regex) => regex.is_match(name).then_some(0),
+        }
+    }
+}
+
+fn fold_case(text: &str, case_sensitive: bool) -> Vec<char> {
+    if case_sensitive {
+        text.chars().collect()
+    } else {
+        text.chars().flat_map(char::to_lowercase).collect()
+    }
+}
+
+// Word starts are where people begin typing: after a separator or at a camelCase hump
+fn is_word_start(previous: Option<char>, current: char) -> bool {
+    match previous {
+        None => true,
+        Some(previous) => {
+            matches!(previous, '/' | '\\' | '_' | '-' | '.' | ' ')
+                || (previous.is_lowercase() && current.is_uppercase())
+        }
+    }
+}
+
+// Subsequence match in the spirit of fzf: every query character has to appear
+// in order. Consecutive runs and word starts score higher, gaps cost a little.
+fn fuzzy_score(query: &[char], name: &str, case_sensitive: bool) -> Option<i64> {
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    // Most names do not match at all; find out before allocating anything
+    let mut remaining = query.iter().peekable();
+    let mut check = |candidate: char| {
+        if remaining.next_if_eq(&&candidate).is_some() {
+            return remaining.peek().is_some();
+        }
+        true
+    };
+    let all_found = if case_sensitive {
+        !name.chars().all(&mut check)
+    } else {
+        !name.chars().flat_map(char::to_lowercase).all(&mut check)
+    };
+    if !all_found {
+        return None;
+    }
+
+    let original: Vec<char> = name.chars().collect();
+    let folded = fold_case(name, case_sensitive);
+    // Lowercasing can change the length of exotic characters; fall back to plain matching
+    let aligned = folded.len() == original.len();
+
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous_match: Option<usize> = None;
+    for (index, &candidate) in folded.iter().enumerate() {
+        if next == query.len() {
+            break;
+        }
+        if candidate != query[next] {
+            continue;
+        }
+
+        score += 16;
+        if previous_match.is_some_and(|previous| previous + 1 == index) {
+            score += 16;
+        } else if let Some(previous) = previous_match {
+            score -= (index - previous - 1).min(8) as i64;
+        }
+        if aligned && is_word_start(index.checked_sub(1).map(|i| original[i]), original[index]) {
+            score += 24;
+        }
+        previous_match = Some(index);
+        next += 1;
+    }
+
+    if next < query.len() {
+        return None;
+    }
+    let query: String = query.iter().collect();
+    let folded: String = folded.into_iter().collect();
+    if folded == query {
+        score += 100;
+    } else if folded.starts_with(&query) {
+        score += 50;
+    }
+    // Shorter names win among otherwise equal matches
+    Some(score - original.len() as i64 / 4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(query: &str, name: &str) -> Option<i64> {
+        NameMatcher::new(query, MatchMode::Fuzzy, false)
+            .unwrap()
+            .score(name)
+    }
+
+    #[test]
+    fn needs_every_character_in_order() {
+        assert!(score("mrs", "main.rs").is_some());
+        assert!(score("srm", "main.rs").is_none());
+        assert!(score("mainn", "main.rs").is_none());
+        assert!(score("x", "").is_none());
+        assert_eq!(score("", "anything"), Some(0));
+        assert_eq!(score("  ", "anything"), Some(0));
+    }
+
+    #[test]
+    fn exact_beats_prefix_beats_inside() {
+        let exact = score("main", "main").unwrap();
+        let prefix = score("main", "main.rs").unwrap();
+        let inside = score("main", "domain.rs").unwrap();
+        assert!(exact > prefix, "{} {}", exact, prefix);
+        assert!(prefix > inside, "{} {}", prefix, inside);
+    }
+
+    #[test]
+    fn word_starts_and_runs_score_higher() {
+        assert!(score("fb", "foo_bar").unwrap() > score("fb", "fabric").unwrap());
+        assert!(score("fb", "FooBar").unwrap() > score("fb", "Foobar").unwrap());
+        assert!(score("abc", "abcxyz").unwrap() > score("abc", "axbxcx").unwrap());
+        // Wider gaps cost more, up to a limit
+        assert!(score("ac", "abc").unwrap() > score("ac", "abbbc").unwrap());
+        assert_eq!(score("ac", "a________c"), score("ac", "a_________c"));
+    }
+
+    #[test]
+    fn shorter_names_win_ties() {
+        assert!(score("read", "readme").unwrap() > score("read", "readme.markdown").unwrap());
+    }
+
+    #[test]
+    fn case_sensitivity() {
+        assert!(score("READ", "readme").is_some());
+        assert!(score("read", "README").is_some());
+
+        let sensitive = NameMatcher::new("Read", MatchMode::Fuzzy, true).unwrap();
+        assert!(sensitive.score("readme").is_none());
+        assert!(sensitive.score("ReadMe").is_some());
+    }
+
+    #[test]
+    fn handles_case_folding_that_changes_length() {
+        // `İ` lowercases to two characters, so word starts are not scored
+        assert!(score("stan", "İstanbul").is_some());
+        assert!(score("i", "İ").is_some());
+        assert!(score("ss", "Straße").is_none());
+    }
+}
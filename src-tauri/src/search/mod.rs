@@ -0,0 +1,547 @@
+// Native search commands
+//
+// Walks trees on several threads and streams matches to the webview in
+// batches, instead of listing every folder and running Fuse.js over the
+// result in the UI thread.
+
+mod archives;
+pub mod content;
+mod matcher;
+mod walk;
+
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tauri::{Emitter, State, Window};
+
+use crate::operation::CancellationToken;
+use crate::{file_entry, log_error, log_info, FileEntry, OhMyFSError};
+pub(crate) use matcher::NameMatcher;
+pub(crate) use walk::{walk_parallel, WalkOptions};
+
+pub const RESULTS_EVENT: &str = "search://results";
+
+const DEFAULT_MAX_RESULTS: usize = 1000;
+// A batch goes out when it is this full or this old, whichever comes first
+const BATCH_SIZE: usize = 200;
+const BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMode {
+    #[default]
+    Fuzzy,
+    Glob,
+    Regex,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SizeRange {
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+}
+
+// Bounds are epoch milliseconds, like the timestamps on `FileEntry`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DateRange {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+// Mirrors `SearchFilters` on the frontend
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SearchFilters {
+    // Extensions without the dot; "folder" matches directories
+    pub extensions: Option<Vec<String>>,
+    pub size: Option<SizeRange>,
+    pub modified: Option<DateRange>,
+    // Glob matched against the path relative to the search root
+    pub path_pattern: Option<String>,
+    pub include_hidden: Option<bool>,
+    // Skip what `.gitignore`, `.ignore` and the global git excludes ignore
+    pub respect_ignore: Option<bool>,
+    // Descend into symlinked folders, each folder searched once
+    pub follow_symlinks: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SearchOptions {
+    #[serde(default)]
+    pub mode: MatchMode,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    #[serde(default)]
+    pub filters: SearchFilters,
+    pub max_results: Option<usize>,
+    // Also match entries inside zip, tar and 7z files, reported as `archive.zip!/inner/path`
+    #[serde(default)]
+    pub search_archives: bool,
+    // Chosen by the frontend so it can cancel the search and tell batches apart
+    pub search_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SearchHit {
+    pub entry: FileEntry,
+    // Only meaningful for fuzzy searches, where higher is better
+    pub score: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SearchBatch<T> {
+    pub search_id: String,
+    pub hits: Vec<T>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchSummary {
+    pub search_id: String,
+    pub matched: u64,
+    pub scanned: u64,
+    // Set when the search stopped at `max_results`
+    pub truncated: bool,
+    pub cancelled: bool,
+    pub failed: Vec<OhMyFSError>,
+}
+
+struct RunningSearch {
+    // Tells a finishing search apart from a newer one that reused its id
+    generation: u64,
+    token: CancellationToken,
+}
+
+#[derive(Default)]
+pub struct Searches {
+    running: Mutex<HashMap<String, RunningSearch>>,
+    next_id: AtomicU64,
+}
+
+impl Searches {
+    // Starting a search under an id that is still running replaces it, which
+    // is what a search box does on every keystroke
+    fn start(&self, id: Option<String>) -> (String, u64, CancellationToken) {
+        let generation = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let id = id.unwrap_or_else(|| format!("search-{}", generation));
+        let token = CancellationToken::default();
+        let previous = self.lock().insert(
+            id.clone(),
+            RunningSearch {
+                generation,
+                token: token.clone(),
+            },
+        );
+        if let Some(previous) = previous {
+            previous.token.cancel();
+        }
+        (id, generation, token)
+    }
+
+    fn finish(&self, id: &str, generation: u64) {
+        let mut running = self.lock();
+        if running
+            .get(id)
+            .is_some_and(|search| search.generation == generation)
+        {
+            running.remove(id);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, RunningSearch>> {
+        self.running
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// Collects hits from all walker threads and sends them out in batches
+pub(crate) struct BatchSink<T> {
+    window: Window,
+    event: &'static str,
+    search_id: String,
+    pending: Mutex<(Vec<T>, Instant)>,
+}
+
+impl<T: Serialize + Clone> BatchSink<T> {
+    pub fn new(window: Window, event: &'static str, search_id: String) -> Self {
+        Self {
+            window,
+            event,
+            search_id,
+            pending: Mutex::new((Vec::new(), Instant::now())),
+        }
+    }
+
+    pub fn search_id(&self) -> &str {
+        &self.search_id
+    }
+
+    pub fn push(&self, hit: T) {
+        let mut pending = self
+            .pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        pending.0.push(hit);
+        if pending.0.len() >= BATCH_SIZE || pending.1.elapsed() >= BATCH_INTERVAL {
+            let hits = std::mem::take(&mut pending.0);
+            pending.1 = Instant::now();
+            self.send(hits);
+        }
+    }
+
+    pub fn flush(&self) {
+        let hits = std::mem::take(
+            &mut self
+                .pending
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .0,
+        );
+        if !hits.is_empty() {
+            self.send(hits);
+        }
+    }
+
+    fn send(&self, hits: Vec<T>) {
+        let batch = SearchBatch {
+            search_id: self.search_id.clone(),
+            hits,
+        };
+        if let Err(err) = self.window.emit(self.event, &batch) {
+            log_error!("Failed to emit {}: {}", self.event, err);
+        }
+    }
+}
+
+// `SearchFilters` with its patterns compiled and extensions normalised
+pub(crate) struct Filters {
+    extensions: Option<Vec<String>>,
+    path_pattern: Option<Pattern>,
+    case_sensitive: bool,
+    size: Option<SizeRange>,
+    modified: Option<DateRange>,
+}
+
+impl Filters {
+    pub fn new(filters: SearchFilters, case_sensitive: bool) -> Result<Self, OhMyFSError> {
+        let path_pattern = filters
+            .path_pattern
+            .filter(|pattern| !pattern.trim().is_empty())
+            .map(|pattern| {
+                Pattern::new(&pattern).map_err(|err| OhMyFSError::InvalidSearchPattern {
+                    pattern: pattern.clone(),
+                    details: err.to_string(),
+                })
+            })
+            .transpose()?;
+        let extensions = filters
+            .extensions
+            .filter(|extensions| !extensions.is_empty())
+            .map(|extensions| {
+                extensions
+                    .iter()
+                    .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                    .collect()
+            });
+
+        Ok(Self {
+            extensions,
+            path_pattern,
+            case_sensitive,
+            size: filters.size,
+            modified: filters.modified,
+        })
+    }
+
+    pub fn matches_kind(&self, path: &Path, is_dir: bool) -> bool {
+        let Some(extensions) = &self.extensions else {
+            return true;
+        };
+        if is_dir {
+            return extensions.iter().any(|ext| ext == "folder");
+        }
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        extension.is_some_and(|extension| extensions.contains(&extension))
+    }
+
+    pub fn matches_path(&self, relative: &Path) -> bool {
+        let Some(pattern) = &self.path_pattern else {
+            return true;
+        };
+        let options = MatchOptions {
+            case_sensitive: self.case_sensitive,
+            require_literal_separator: false,
+            require_literal_leading_dot: false,
+        };
+        pattern.matches_path_with(relative, options)
+    }
+
+    fn needs_metadata(&self) -> bool {
+        self.size.is_some() || self.modified.is_some()
+    }
+
+    fn matches_metadata(&self, metadata: &fs::Metadata) -> bool {
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_millis() as i64);
+        self.matches_attributes(metadata.is_file(), metadata.len(), modified)
+    }
+
+    // Entries inside archives carry their attributes on the `FileEntry`
+    fn matches_file_entry(&self, entry: &FileEntry) -> bool {
+        let modified = entry
+            .modified_at
+            .as_deref()
+            .and_then(|millis| millis.parse().ok());
+        self.matches_attributes(entry.is_file, entry.size.unwrap_or(0), modified)
+    }
+
+    pub fn matches_attributes(&self, is_file: bool, size: u64, modified: Option<i64>) -> bool {
+        if let Some(range) = &self.size {
+            // Like the old frontend filter, size ranges only ever match files
+            if !is_file
+                || range.min.is_some_and(|min| size < min)
+                || range.max.is_some_and(|max| size > max)
+            {
+                return false;
+            }
+        }
+        if let Some(range) = &self.modified {
+            let Some(modified) = modified else {
+                return false;
+            };
+            if range.from.is_some_and(|from| modified < from)
+                || range.to.is_some_and(|to| modified > to)
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+struct NameSearch<'a> {
+    root: &'a Path,
+    matcher: NameMatcher,
+    filters: Filters,
+    include_hidden: bool,
+    respect_ignore: bool,
+    follow_symlinks: bool,
+    search_archives: bool,
+    max_results: usize,
+    token: &'a CancellationToken,
+    sink: &'a BatchSink<SearchHit>,
+    matched: AtomicU64,
+    scanned: AtomicU64,
+    truncated: AtomicBool,
+    failed: Mutex<Vec<OhMyFSError>>,
+}
+
+impl NameSearch<'_> {
+    fn run(self) -> SearchSummary {
+        let mut failed = walk_parallel(
+            self.root,
+            &WalkOptions {
+                include_hidden: self.include_hidden,
+                respect_ignore: self.respect_ignore,
+                follow_symlinks: self.follow_symlinks,
+            },
+            self.token,
+            |entry, file_type| self.visit(entry, file_type),
+        );
+        self.sink.flush();
+
+        failed.append(
+            &mut self
+                .failed
+                .into_inner()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        );
+        let truncated = self.truncated.into_inner();
+        SearchSummary {
+            search_id: self.sink.search_id().to_string(),
+            matched: self.matched.into_inner().min(self.max_results as u64),
+            scanned: self.scanned.into_inner(),
+            truncated,
+            cancelled: self.token.is_cancelled() && !truncated,
+            failed,
+        }
+    }
+
+    fn visit(&self, entry: &fs::DirEntry, file_type: &fs::FileType) -> bool {
+        self.scanned.fetch_add(1, Ordering::Relaxed);
+        let path = entry.path();
+        if self.search_archives && file_type.is_file() && archives::is_archive(&path) {
+            self.search_archive(&path);
+        }
+
+        let Some(score) = self.matcher.score(&entry.file_name().to_string_lossy()) else {
+            return true;
+        };
+        let relative = path.strip_prefix(self.root).unwrap_or(&path);
+        if !self.filters.matches_kind(&path, file_type.is_dir())
+            || !self.filters.matches_path(relative)
+        {
+            return true;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            return true;
+        };
+        if self.filters.needs_metadata() && !self.filters.matches_metadata(&metadata) {
+            return true;
+        }
+        self.record(file_entry(&path, &metadata), score)
+    }
+
+    fn search_archive(&self, archive: &Path) {
+        let entries = match archives::entries(archive, self.include_hidden) {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.failed
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .push(err);
+                return;
+            }
+        };
+
+        for entry in entries {
+            self.scanned.fetch_add(1, Ordering::Relaxed);
+            let Some(score) = self.matcher.score(&entry.name) else {
+                continue;
+            };
+            let path = Path::new(&entry.path);
+            let relative = path.strip_prefix(self.root).unwrap_or(path);
+            if !self.filters.matches_kind(path, entry.is_directory)
+                || !self.filters.matches_path(relative)
+                || !self.filters.matches_file_entry(&entry)
+            {
+                continue;
+            }
+            if !self.record(entry, score) {
+                break;
+            }
+        }
+    }
+
+    // Returns false once `max_results` is reached
+    fn record(&self, entry: FileEntry, score: i64) -> bool {
+        // Workers can race past the limit, so only the first `max_results` get through
+        if self.matched.fetch_add(1, Ordering::Relaxed) >= self.max_results as u64 {
+            self.truncated.store(true, Ordering::Relaxed);
+            self.token.cancel();
+            return false;
+        }
+        self.sink.push(SearchHit { entry, score });
+        true
+    }
+}
+
+pub(crate) fn search_root(root: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(root);
+    if !path.exists() {
+        return Err(OhMyFSError::DirectoryNotFound {
+            path: root.to_string(),
+        }
+        .to_string());
+    }
+    if !path.is_dir() {
+        return Err(OhMyFSError::PathNotDirectory {
+            path: root.to_string(),
+        }
+        .to_string());
+    }
+    Ok(path)
+}
+
+#[tauri::command]
+pub async fn search_files(
+    window: Window,
+    searches: State<'_, Searches>,
+    root: String,
+    query: String,
+    options: Option<SearchOptions>,
+) -> Result<SearchSummary, String> {
+    let options = options.unwrap_or_default();
+    let root_path = search_root(&root)?;
+
+    let matcher =
+        NameMatcher::new(&query, options.mode, options.case_sensitive).map_err(|details| {
+            OhMyFSError::InvalidSearchPattern {
+                pattern: query.clone(),
+                details,
+            }
+            .to_string()
+        })?;
+    let include_hidden = options.filters.include_hidden.unwrap_or(false);
+    let respect_ignore = options.filters.respect_ignore.unwrap_or(false);
+    let follow_symlinks = options.filters.follow_symlinks.unwrap_or(false);
+    let filters =
+        Filters::new(options.filters, options.case_sensitive).map_err(|err| err.to_string())?;
+    let max_results = options.max_results.unwrap_or(DEFAULT_MAX_RESULTS);
+
+    let (search_id, generation, token) = searches.start(options.search_id);
+    log_info!(
+        "Search {} for {:?} in {} ({:?})",
+        search_id,
+        query,
+        root,
+        options.mode
+    );
+    let sink = BatchSink::new(window, RESULTS_EVENT, search_id.clone());
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        NameSearch {
+            root: &root_path,
+            matcher,
+            filters,
+            include_hidden,
+            respect_ignore,
+            follow_symlinks,
+            search_archives: options.search_archives,
+            max_results,
+            token: &token,
+            sink: &sink,
+            matched: AtomicU64::new(0),
+            scanned: AtomicU64::new(0),
+            truncated: AtomicBool::new(false),
+            failed: Mutex::new(Vec::new()),
+        }
+        .run()
+    })
+    .await
+    .map_err(|err| err.to_string());
+    searches.finish(&search_id, generation);
+
+    let summary = result?;
+    log_info!(
+        "Search {} matched {} of {} entries",
+        summary.search_id,
+        summary.matched,
+        summary.scanned
+    );
+    Ok(summary)
+}
+
+// Returns false when no search with that id is running
+#[tauri::command]
+pub async fn cancel_search(
+    searches: State<'_, Searches>,
+    search_id: String,
+) -> Result<bool, String> {
+    let running = searches.lock();
+    let Some(search) = running.get(&search_id) else {
+        return Ok(false);
+    };
+    search.token.cancel();
+    log_info!("Cancellation requested for search {}", search_id);
+    Ok(true)
+}
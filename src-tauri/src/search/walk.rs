@@ -0,0 +1,232 @@
+// Parallel directory walk shared by the search commands
+//
+// Worker threads take directories from a shared stack, read them and push the
+// subdirectories they are told to descend into back onto it. The walk ends
+// when the stack is empty and no worker is still reading. Each directory
+// carries the ignore rules that apply inside it when those are respected.
+//
+// When symlinks are followed, every folder is walked at most once, keyed by
+// its canonical path, so a link back to an ancestor cannot loop forever.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+
+use crate::hidden::HiddenNames;
+use crate::ignore::GitIgnores;
+use crate::operation::CancellationToken;
+use crate::OhMyFSError;
+
+const MAX_WORKERS: usize = 8;
+
+#[derive(Default)]
+struct Queue {
+    directories: Vec<(PathBuf, Option<GitIgnores>)>,
+    // Workers currently reading a directory, which may still add more
+    busy: usize,
+}
+
+pub(crate) struct WalkOptions {
+    pub include_hidden: bool,
+    // Skip entries matched by `.gitignore`, `.ignore` and the global excludes
+    pub respect_ignore: bool,
+    // Descend into symlinks to folders
+    pub follow_symlinks: bool,
+}
+
+// Calls `visit` for every entry below `root`, from several threads at once.
+// Directories are descended into when `visit` returns true. Symlinks are
+// reported, and unless `follow_symlinks` is set never followed. A followed link
+// is passed the file type of its target.
+pub(crate) fn walk_parallel<F>(
+    root: &Path,
+    options: &WalkOptions,
+    token: &CancellationToken,
+    visit: F,
+) -> Vec<OhMyFSError>
+where
+    F: Fn(&fs::DirEntry, &fs::FileType) -> bool + Sync,
+{
+    let queue = Mutex::new(Queue {
+        directories: vec![(
+            root.to_path_buf(),
+            options
+                .respect_ignore
+                .then(|| GitIgnores::for_directory(root)),
+        )],
+        busy: 0,
+    });
+    let changed = Condvar::new();
+    let failed = Mutex::new(Vec::new());
+    let visited = Mutex::new(HashSet::new());
+    // Only needed when following links; real folders alone cannot form a cycle
+    let first_visit = |directory: &Path| {
+        if !options.follow_symlinks {
+            return true;
+        }
+        // A folder that cannot be resolved is still walked once, by its own path
+        let key = fs::canonicalize(directory).unwrap_or_else(|_| directory.to_path_buf());
+        visited
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(key)
+    };
+    first_visit(root);
+    let workers = std::thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(4)
+        .min(MAX_WORKERS);
+
+    let lock = || {
+        queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    };
+    let next_directory = || {
+        let mut state = lock();
+        loop {
+            if token.is_cancelled() {
+                changed.notify_all();
+                return None;
+            }
+            if let Some(directory) = state.directories.pop() {
+                state.busy += 1;
+                return Some(directory);
+            }
+            if state.busy == 0 {
+                changed.notify_all();
+                return None;
+            }
+            state = changed
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    };
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while let Some((directory, ignores)) = next_directory() {
+                    let mut found = Vec::new();
+                    match fs::read_dir(&directory) {
+                        Ok(entries) => {
+                            let hidden_names = (!options.include_hidden)
+                                .then(|| HiddenNames::for_directory(&directory));
+                            for entry in entries.flatten() {
+                                if token.is_cancelled() {
+                                    break;
+                                }
+                                if hidden_names
+                                    .as_ref()
+                                    .is_some_and(|names| names.hides(&entry))
+                                {
+                                    continue;
+                                }
+                                let Ok(mut file_type) = entry.file_type() else {
+                                    continue;
+                                };
+                                let path = entry.path();
+                                if options.follow_symlinks && file_type.is_symlink() {
+                                    // Broken links are reported as links
+                                    if let Ok(target) = fs::metadata(&path) {
+                                        file_type = target.file_type();
+                                    }
+                                }
+                                if ignores.as_ref().is_some_and(|ignores| {
+                                    ignores.is_ignored(&path, file_type.is_dir())
+                                }) {
+                                    continue;
+                                }
+                                if visit(&entry, &file_type)
+                                    && file_type.is_dir()
+                                    && first_visit(&path)
+                                {
+                                    let ignores =
+                                        ignores.as_ref().map(|ignores| ignores.descend(&path));
+                                    found.push((path, ignores));
+                                }
+                            }
+                        }
+                        Err(err) => failed
+                            .lock()
+                            .unwrap_or_else(|poisoned| poisoned.into_inner())
+                            .push(OhMyFSError::DirectoryReadFailed {
+                                path: directory.to_string_lossy().to_string(),
+                                details: err.to_string(),
+                            }),
+                    }
+
+                    let mut state = lock();
+                    state.directories.append(&mut found);
+                    state.busy -= 1;
+                    changed.notify_all();
+                }
+            });
+        }
+    });
+
+    failed
+        .into_inner()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walk(root: &Path, follow_symlinks: bool) -> Vec<String> {
+        let options = WalkOptions {
+            include_hidden: true,
+            respect_ignore: false,
+            follow_symlinks,
+        };
+        let seen = Mutex::new(Vec::new());
+        let failed = walk_parallel(root, &options, &CancellationToken::default(), |entry, _| {
+            let relative = entry.path().strip_prefix(root).unwrap().to_path_buf();
+            seen.lock()
+                .unwrap()
+                .push(relative.to_string_lossy().to_string());
+            true
+        });
+        assert!(failed.is_empty());
+        let mut seen = seen.into_inner().unwrap();
+        seen.sort();
+        seen
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_back_to_an_ancestor_are_walked_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/file.txt"), "x").unwrap();
+        std::os::unix::fs::symlink(root.join("a"), root.join("a/b/up")).unwrap();
+        std::os::unix::fs::symlink("missing", root.join("broken")).unwrap();
+
+        assert_eq!(
+            walk(root, false),
+            ["a", "a/b", "a/b/file.txt", "a/b/up", "broken"]
+        );
+        // `a/b/up` leads back to `a`, which was already walked
+        assert_eq!(
+            walk(root, true),
+            ["a", "a/b", "a/b/file.txt", "a/b/up", "broken"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_to_other_folders_are_followed() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(dir.path().join("outside")).unwrap();
+        fs::write(dir.path().join("outside/file.txt"), "x").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("outside"), root.join("link")).unwrap();
+
+        assert_eq!(walk(&root, false), ["link"]);
+        assert_eq!(walk(&root, true), ["link", "link/file.txt"]);
+    }
+}
@@ -0,0 +1,207 @@
+// Metadata for a single path
+//
+// `stat_path` looks at the path itself instead of listing its parent, so it
+// works in folders that cannot be read and costs the same in any folder size.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::{file_entry, hidden, log_error, to_epoch_millis, FileEntry, OhMyFSError};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PathStat {
+    #[serde(flatten)]
+    pub entry: FileEntry,
+    // The unix fields are None on other platforms
+    pub inode: Option<u64>,
+    pub device: Option<u64>,
+    pub links: Option<u64>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    // None when the id has no user or group, as on files from another machine
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    pub accessed_at: Option<String>,
+}
+
+fn stat(path: &Path) -> Result<PathStat, OhMyFSError> {
+    let display = path.to_string_lossy().to_string();
+    let metadata = fs::symlink_metadata(path).map_err(|err| match err.kind() {
+        std::io::ErrorKind::NotFound => OhMyFSError::PathNotFound {
+            path: display.clone(),
+        },
+        _ => OhMyFSError::FileReadFailed {
+            path: display.clone(),
+            details: err.to_string(),
+        },
+    })?;
+
+    let mut entry = file_entry(path, &metadata);
+    if let Some(parent) = path.parent() {
+        entry.is_hidden |= hidden::HiddenNames::for_directory(parent).contains(&entry.name);
+    }
+
+    let ids = unix_ids(&metadata);
+    Ok(PathStat {
+        entry,
+        inode: ids.map(|ids| ids.inode),
+        device: ids.map(|ids| ids.device),
+        links: ids.map(|ids| ids.links),
+        uid: ids.map(|ids| ids.uid),
+        gid: ids.map(|ids| ids.gid),
+        owner: ids.and_then(|ids| user_name(ids.uid)),
+        group: ids.and_then(|ids| group_name(ids.gid)),
+        accessed_at: metadata.accessed().ok().map(to_epoch_millis),
+    })
+}
+
+#[derive(Clone, Copy)]
+struct UnixIds {
+    inode: u64,
+    device: u64,
+    links: u64,
+    uid: u32,
+    gid: u32,
+}
+
+#[cfg(unix)]
+fn unix_ids(metadata: &fs::Metadata) -> Option<UnixIds> {
+    use std::os::unix::fs::MetadataExt;
+    Some(UnixIds {
+        inode: metadata.ino(),
+        device: metadata.dev(),
+        links: metadata.nlink(),
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+    })
+}
+
+#[cfg(not(unix))]
+fn unix_ids(_: &fs::Metadata) -> Option<UnixIds> {
+    None
+}
+
+// Calls a reentrant passwd/group lookup, growing the string buffer until the
+// entry fits. Returns None when there is no entry for the id.
+#[cfg(unix)]
+fn lookup_name<T>(
+    lookup: impl Fn(*mut T, *mut libc::c_char, usize, *mut *mut T) -> libc::c_int,
+    name: impl Fn(&T) -> *const libc::c_char,
+) -> Option<String> {
+    let mut size = 1024;
+    loop {
+        let mut buffer = vec![0 as libc::c_char; size];
+        // SAFETY: passwd and group are plain C structs for which all zeroes is valid
+        let mut record: T = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        let status = lookup(&mut record, buffer.as_mut_ptr(), buffer.len(), &mut result);
+        if status == libc::ERANGE && size < 1 << 20 {
+            size *= 2;
+            continue;
+        }
+        if status != 0 || result.is_null() {
+            return None;
+        }
+        // SAFETY: on success the name points into `buffer`, which is still alive
+        let name = unsafe { std::ffi::CStr::from_ptr(name(&record)) };
+        return Some(name.to_string_lossy().to_string());
+    }
+}
+
+#[cfg(unix)]
+fn user_name(uid: u32) -> Option<String> {
+    lookup_name(
+        // SAFETY: every pointer comes from lookup_name and is valid for the call
+        |record, buffer, size, result| unsafe {
+            libc::getpwuid_r(uid, record, buffer, size, result)
+        },
+        |record: &libc::passwd| record.pw_name,
+    )
+}
+
+#[cfg(unix)]
+fn group_name(gid: u32) -> Option<String> {
+    lookup_name(
+        // SAFETY: every pointer comes from lookup_name and is valid for the call
+        |record, buffer, size, result| unsafe {
+            libc::getgrgid_r(gid, record, buffer, size, result)
+        },
+        |record: &libc::group| record.gr_name,
+    )
+}
+
+#[cfg(not(unix))]
+fn user_name(_: u32) -> Option<String> {
+    None
+}
+
+#[cfg(not(unix))]
+fn group_name(_: u32) -> Option<String> {
+    None
+}
+
+#[tauri::command]
+pub async fn stat_path(path: String) -> Result<PathStat, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        stat(Path::new(&path)).map_err(|err| {
+            log_error!("Failed to stat {}: {}", path, err);
+            err.to_string()
+        })
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+// True for anything at the path, including symlinks whose target is gone
+#[tauri::command]
+pub async fn path_exists(path: String) -> Result<bool, String> {
+    // A stat on a hung network mount can block for a long time
+    tauri::async_runtime::spawn_blocking(move || fs::symlink_metadata(&path).is_ok())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_paths_are_not_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = stat(&dir.path().join("missing")).unwrap_err();
+        assert!(matches!(err, OhMyFSError::PathNotFound { .. }));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn files_report_their_ids_and_owner() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "hello").unwrap();
+        fs::hard_link(&path, dir.path().join("again.txt")).unwrap();
+
+        let stat = stat(&path).unwrap();
+        assert_eq!(stat.entry.name, "notes.txt");
+        assert_eq!(stat.entry.size, Some(5));
+        assert_eq!(stat.links, Some(2));
+        // SAFETY: getuid has no preconditions
+        let uid = unsafe { libc::getuid() };
+        assert_eq!(stat.uid, Some(uid));
+        assert_eq!(stat.owner, user_name(uid));
+        assert!(stat.inode.is_some() && stat.accessed_at.is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dangling_symlinks_are_broken_not_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink("gone.txt", &link).unwrap();
+
+        let stat = stat(&link).unwrap();
+        assert!(stat.entry.is_symlink && stat.entry.is_broken);
+        assert_eq!(stat.entry.symlink_target.as_deref(), Some("gone.txt"));
+        assert_eq!(stat.entry.target_kind, None);
+    }
+}
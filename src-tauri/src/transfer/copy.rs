@@ -0,0 +1,729 @@
+// Recursive copy engine behind `copy_paths` and the fallback path of `move_paths`
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+use super::{
+    ConflictStrategy, TransferConflict, TransferOperation, TransferOptions, TransferProgress,
+    TransferSummary, COPY_BUFFER_SIZE,
+};
+use crate::operation::CancellationToken;
+use crate::{log_error, unique_destination, OhMyFSError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum EntryKind {
+    Directory,
+    File,
+    Symlink,
+    Other,
+}
+
+struct PlannedEntry {
+    source: PathBuf,
+    // Relative to the source root's parent, so it starts with the root name
+    relative: PathBuf,
+    kind: EntryKind,
+    metadata: fs::Metadata,
+}
+
+// An entry that made it to the destination, with what is needed to verify it
+pub(super) struct CopiedEntry {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub kind: EntryKind,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+    // CRC32 of the bytes read from the source, only computed when verifying
+    pub checksum: Option<u32>,
+}
+
+pub(super) struct CopyOutcome {
+    pub summary: TransferSummary,
+    pub copied: Vec<CopiedEntry>,
+}
+
+enum CopyError {
+    Read(String),
+    Write(String),
+    Cancelled,
+}
+
+// Directory permissions and times are applied once their contents are in
+// place, otherwise read-only folders could not be filled
+struct PendingDirectory {
+    target: PathBuf,
+    permissions: fs::Permissions,
+    modified: Option<SystemTime>,
+}
+
+fn entry_kind(file_type: fs::FileType) -> EntryKind {
+    if file_type.is_dir() {
+        EntryKind::Directory
+    } else if file_type.is_symlink() {
+        EntryKind::Symlink
+    } else if file_type.is_file() {
+        EntryKind::File
+    } else {
+        EntryKind::Other
+    }
+}
+
+fn create_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(target, link)
+    }
+    #[cfg(windows)]
+    {
+        match fs::metadata(link.parent().unwrap_or(Path::new("")).join(target)) {
+            Ok(metadata) if metadata.is_dir() => std::os::windows::fs::symlink_dir(target, link),
+            _ => std::os::windows::fs::symlink_file(target, link),
+        }
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = (target, link);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "symbolic links are not supported on this platform",
+        ))
+    }
+}
+
+// Sibling used while a file is being written, renamed over the target at the end
+fn partial_path(target: &Path) -> PathBuf {
+    let name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut partial = target.with_file_name(format!(".{}.ohmyfs-partial", name));
+    let mut attempt = 1;
+    while fs::symlink_metadata(&partial).is_ok() {
+        partial = target.with_file_name(format!(".{}.ohmyfs-partial-{}", name, attempt));
+        attempt += 1;
+    }
+    partial
+}
+
+pub(super) fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+pub(super) fn copies_into_itself(root: &Path, destination: &Path) -> bool {
+    if !root.is_dir() || root.is_symlink() {
+        return false;
+    }
+    match (fs::canonicalize(root), fs::canonicalize(destination)) {
+        (Ok(root), Ok(destination)) => destination.starts_with(root),
+        _ => false,
+    }
+}
+
+// Walks every source up front so that totals are known before copying
+fn plan(
+    sources: &[String],
+    destination: &Path,
+    failed: &mut Vec<OhMyFSError>,
+) -> Vec<Vec<PlannedEntry>> {
+    let mut roots = Vec::new();
+
+    for source in sources {
+        let root = Path::new(source);
+        let base = root.parent().unwrap_or_else(|| Path::new(""));
+
+        if copies_into_itself(root, destination) {
+            log_error!("Refusing to copy {} into itself", source);
+            failed.push(OhMyFSError::FileWriteFailed {
+                path: source.clone(),
+                details: "a folder cannot be copied into itself".to_string(),
+            });
+            continue;
+        }
+
+        let mut entries = Vec::new();
+        for entry in WalkDir::new(root)
+            .follow_links(false)
+            .follow_root_links(false)
+        {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    let path = err
+                        .path()
+                        .map(|p| p.to_string_lossy().to_string())
+                        .unwrap_or_else(|| source.clone());
+                    log_error!("Failed to walk {}: {}", path, err);
+                    failed.push(OhMyFSError::FileReadFailed {
+                        path,
+                        details: err.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    failed.push(OhMyFSError::FileReadFailed {
+                        path: entry.path().to_string_lossy().to_string(),
+                        details: err.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            entries.push(PlannedEntry {
+                relative: entry
+                    .path()
+                    .strip_prefix(base)
+                    .unwrap_or(entry.path())
+                    .to_path_buf(),
+                kind: entry_kind(entry.file_type()),
+                source: entry.into_path(),
+                metadata,
+            });
+        }
+
+        if !entries.is_empty() {
+            roots.push(entries);
+        }
+    }
+
+    roots
+}
+
+struct Copier<'a> {
+    strategy: ConflictStrategy,
+    token: &'a CancellationToken,
+    buffer: Vec<u8>,
+    progress: TransferProgress,
+    summary: TransferSummary,
+    pending_directories: Vec<PendingDirectory>,
+    verify: bool,
+    copied: Vec<CopiedEntry>,
+    on_progress: &'a mut dyn FnMut(&TransferProgress, bool),
+}
+
+impl<'a> Copier<'a> {
+    fn copy_root(&mut self, destination: &Path, entries: &[PlannedEntry]) {
+        // Target directory of every copied folder; `None` marks skipped subtrees
+        let mut targets: HashMap<&Path, Option<PathBuf>> = HashMap::new();
+        let mut root_target = None;
+
+        for entry in entries {
+            if self.token.checkpoint() {
+                self.summary.cancelled = true;
+                break;
+            }
+
+            let parent_target = match entry
+                .relative
+                .parent()
+                .filter(|p| !p.as_os_str().is_empty())
+            {
+                None => Some(destination.to_path_buf()),
+                Some(parent) => targets.get(parent).cloned().flatten(),
+            };
+            let Some(parent_target) = parent_target else {
+                // Part of a subtree that was skipped or failed
+                self.account(entry);
+                continue;
+            };
+
+            self.progress.current_file = Some(entry.source.to_string_lossy().to_string());
+            (self.on_progress)(&self.progress, false);
+
+            let name = entry.relative.file_name().unwrap_or_default();
+            let target = match self.resolve(entry, parent_target.join(name)) {
+                Ok(Some(target)) => target,
+                Ok(None) => {
+                    self.account(entry);
+                    targets.insert(&entry.relative, None);
+                    continue;
+                }
+                Err(details) => {
+                    self.fail_write(entry, details);
+                    targets.insert(&entry.relative, None);
+                    self.progress.entries_done += 1;
+                    continue;
+                }
+            };
+
+            match self.copy_entry(entry, &target) {
+                Ok(checksum) => {
+                    self.summary.entries_copied += 1;
+                    self.copied.push(CopiedEntry {
+                        source: entry.source.clone(),
+                        target: target.clone(),
+                        kind: entry.kind,
+                        len: entry.metadata.len(),
+                        modified: entry.metadata.modified().ok(),
+                        checksum,
+                    });
+                    targets.insert(&entry.relative, Some(target.clone()));
+                    if root_target.is_none() {
+                        root_target = Some(target);
+                    }
+                }
+                Err(CopyError::Cancelled) => {
+                    self.summary.cancelled = true;
+                    break;
+                }
+                Err(CopyError::Read(details)) => {
+                    log_error!("Failed to read {}: {}", entry.source.display(), details);
+                    self.summary.failed.push(OhMyFSError::FileReadFailed {
+                        path: entry.source.to_string_lossy().to_string(),
+                        details,
+                    });
+                    targets.insert(&entry.relative, None);
+                }
+                Err(CopyError::Write(details)) => {
+                    self.fail_write(entry, details);
+                    targets.insert(&entry.relative, None);
+                }
+            }
+            self.progress.entries_done += 1;
+        }
+
+        if let Some(target) = root_target {
+            self.summary
+                .created
+                .push(target.to_string_lossy().to_string());
+        }
+    }
+
+    // Counts an entry that will not be copied so progress still reaches the total
+    fn account(&mut self, entry: &PlannedEntry) {
+        self.progress.entries_done += 1;
+        if entry.kind == EntryKind::File {
+            self.progress.bytes_done += entry.metadata.len();
+        }
+        (self.on_progress)(&self.progress, false);
+    }
+
+    fn fail_write(&mut self, entry: &PlannedEntry, details: String) {
+        log_error!("Failed to copy {}: {}", entry.source.display(), details);
+        self.summary.failed.push(OhMyFSError::FileWriteFailed {
+            path: entry.source.to_string_lossy().to_string(),
+            details,
+        });
+    }
+
+    // Applies the conflict strategy. `None` means the entry is left out.
+    fn resolve(
+        &mut self,
+        entry: &PlannedEntry,
+        target: PathBuf,
+    ) -> Result<Option<PathBuf>, String> {
+        let existing = match fs::symlink_metadata(&target) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Some(target)),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        // Copying something onto itself always duplicates it
+        if is_same_file(&entry.source, &target) && !existing.is_symlink() {
+            return Ok(Some(unique_destination(&target)));
+        }
+
+        let merge = entry.kind == EntryKind::Directory && existing.is_dir();
+        match self.strategy {
+            ConflictStrategy::KeepBoth => Ok(Some(unique_destination(&target))),
+            ConflictStrategy::Ask => {
+                self.summary.conflicts.push(TransferConflict {
+                    source: entry.source.to_string_lossy().to_string(),
+                    destination: target.to_string_lossy().to_string(),
+                    is_directory: entry.kind == EntryKind::Directory,
+                });
+                Ok(None)
+            }
+            _ if merge => Ok(Some(target)),
+            ConflictStrategy::Skip => {
+                self.summary
+                    .skipped
+                    .push(entry.source.to_string_lossy().to_string());
+                Ok(None)
+            }
+            ConflictStrategy::Overwrite => {
+                if existing.is_dir() {
+                    return Err(format!(
+                        "{} is an existing folder and will not be replaced",
+                        target.display()
+                    ));
+                }
+                Ok(Some(target))
+            }
+        }
+    }
+
+    fn copy_entry(
+        &mut self,
+        entry: &PlannedEntry,
+        target: &Path,
+    ) -> Result<Option<u32>, CopyError> {
+        let write_error = |err: io::Error| CopyError::Write(err.to_string());
+
+        match entry.kind {
+            EntryKind::Directory => {
+                match fs::symlink_metadata(target) {
+                    Ok(existing) if existing.is_dir() => {}
+                    Ok(_) => {
+                        // Only reachable with `overwrite`, replacing a file by a folder
+                        fs::remove_file(target).map_err(write_error)?;
+                        fs::create_dir(target).map_err(write_error)?;
+                    }
+                    Err(_) => fs::create_dir(target).map_err(write_error)?,
+                }
+                self.pending_directories.push(PendingDirectory {
+                    target: target.to_path_buf(),
+                    permissions: entry.metadata.permissions(),
+                    modified: entry.metadata.modified().ok(),
+                });
+                Ok(None)
+            }
+            EntryKind::Symlink => {
+                let link =
+                    fs::read_link(&entry.source).map_err(|err| CopyError::Read(err.to_string()))?;
+                if fs::symlink_metadata(target).is_ok() {
+                    fs::remove_file(target).map_err(write_error)?;
+                }
+                create_symlink(&link, target).map_err(write_error)?;
+                Ok(None)
+            }
+            EntryKind::File => self.copy_file(entry, target),
+            EntryKind::Other => Err(CopyError::Read(
+                "sockets, FIFOs and device files cannot be copied".to_string(),
+            )),
+        }
+    }
+
+    // Writes into a partial sibling first, so an interrupted copy never
+    // leaves a truncated file under the real name or destroys the file it replaces
+    fn copy_file(&mut self, entry: &PlannedEntry, target: &Path) -> Result<Option<u32>, CopyError> {
+        let mut source =
+            fs::File::open(&entry.source).map_err(|err| CopyError::Read(err.to_string()))?;
+        let partial = partial_path(target);
+        let mut output = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&partial)
+            .map_err(|err| CopyError::Write(err.to_string()))?;
+
+        let result = self
+            .copy_contents(&mut source, &mut output)
+            .and_then(|checksum| {
+                output
+                    .set_permissions(entry.metadata.permissions())
+                    .map_err(|err| CopyError::Write(err.to_string()))?;
+                if let Ok(modified) = entry.metadata.modified() {
+                    output
+                        .set_modified(modified)
+                        .map_err(|err| CopyError::Write(err.to_string()))?;
+                }
+                drop(output);
+                fs::rename(&partial, target).map_err(|err| CopyError::Write(err.to_string()))?;
+                Ok(checksum)
+            });
+
+        if result.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        result
+    }
+
+    fn copy_contents(
+        &mut self,
+        source: &mut fs::File,
+        output: &mut fs::File,
+    ) -> Result<Option<u32>, CopyError> {
+        let mut hasher = self.verify.then(crc32fast::Hasher::new);
+        loop {
+            if self.token.checkpoint() {
+                return Err(CopyError::Cancelled);
+            }
+
+            let read = match source.read(&mut self.buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(CopyError::Read(err.to_string())),
+            };
+            output
+                .write_all(&self.buffer[..read])
+                .map_err(|err| CopyError::Write(err.to_string()))?;
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(&self.buffer[..read]);
+            }
+            self.summary.bytes_copied += read as u64;
+            self.progress.bytes_done += read as u64;
+            (self.on_progress)(&self.progress, false);
+        }
+        output
+            .flush()
+            .map_err(|err| CopyError::Write(err.to_string()))?;
+        Ok(hasher.map(crc32fast::Hasher::finalize))
+    }
+
+    fn finish_directories(&mut self) {
+        // Deepest folders first so that a read-only parent is locked last
+        for directory in self.pending_directories.drain(..).rev() {
+            let applied =
+                fs::set_permissions(&directory.target, directory.permissions).and_then(|()| {
+                    match directory.modified {
+                        Some(modified) => fs::File::open(&directory.target)?.set_modified(modified),
+                        None => Ok(()),
+                    }
+                });
+            if let Err(err) = applied {
+                log_error!(
+                    "Failed to restore metadata of {}: {}",
+                    directory.target.display(),
+                    err
+                );
+            }
+        }
+    }
+}
+
+pub(super) fn copy_tree(
+    sources: &[String],
+    destination: &Path,
+    options: &TransferOptions,
+    operation: TransferOperation,
+    verify: bool,
+    token: &CancellationToken,
+    on_progress: &mut dyn FnMut(&TransferProgress, bool),
+) -> Result<CopyOutcome, OhMyFSError> {
+    if !destination.is_dir() {
+        return Err(OhMyFSError::PathNotDirectory {
+            path: destination.to_string_lossy().to_string(),
+        });
+    }
+
+    let mut failed = Vec::new();
+    let roots = plan(sources, destination, &mut failed);
+    let entries = roots.iter().flatten();
+
+    let progress = TransferProgress {
+        job_id: options.job_id.clone(),
+        operation,
+        current_file: None,
+        entries_done: 0,
+        entries_total: entries.clone().count() as u64,
+        bytes_done: 0,
+        bytes_total: entries
+            .filter(|entry| entry.kind == EntryKind::File)
+            .map(|entry| entry.metadata.len())
+            .sum(),
+    };
+    on_progress(&progress, true);
+
+    let mut copier = Copier {
+        strategy: options.conflict.unwrap_or_default(),
+        token,
+        buffer: vec![0u8; COPY_BUFFER_SIZE],
+        progress,
+        summary: TransferSummary {
+            operation,
+            created: Vec::new(),
+            skipped: Vec::new(),
+            conflicts: Vec::new(),
+            entries_copied: 0,
+            bytes_copied: 0,
+            cancelled: false,
+            failed,
+        },
+        pending_directories: Vec::new(),
+        verify,
+        copied: Vec::new(),
+        on_progress,
+    };
+
+    for entries in &roots {
+        copier.copy_root(destination, entries);
+        if copier.summary.cancelled {
+            break;
+        }
+    }
+    copier.finish_directories();
+
+    copier.progress.current_file = None;
+    (copier.on_progress)(&copier.progress, true);
+
+    Ok(CopyOutcome {
+        summary: copier.summary,
+        copied: copier.copied,
+    })
+}
+
+pub(crate) fn copy(
+    sources: &[String],
+    destination: &Path,
+    options: &TransferOptions,
+    token: &CancellationToken,
+    on_progress: &mut dyn FnMut(&TransferProgress, bool),
+) -> Result<TransferSummary, OhMyFSError> {
+    copy_tree(
+        sources,
+        destination,
+        options,
+        TransferOperation::Copy,
+        false,
+        token,
+        on_progress,
+    )
+    .map(|outcome| outcome.summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn copy_with(
+        sources: &[&Path],
+        destination: &Path,
+        strategy: ConflictStrategy,
+    ) -> TransferSummary {
+        let sources = sources
+            .iter()
+            .map(|source| source.to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        let options = TransferOptions {
+            conflict: Some(strategy),
+            job_id: None,
+        };
+        copy(
+            &sources,
+            destination,
+            &options,
+            &CancellationToken::default(),
+            &mut |_: &TransferProgress, _: bool| {},
+        )
+        .unwrap()
+    }
+
+    fn conflicting(temp: &Path) -> (PathBuf, PathBuf) {
+        let source = temp.join("src/a.txt");
+        let destination = temp.join("dest");
+        fs::create_dir_all(source.parent().unwrap()).unwrap();
+        fs::create_dir_all(&destination).unwrap();
+        fs::write(&source, "new").unwrap();
+        fs::write(destination.join("a.txt"), "old").unwrap();
+        (source, destination)
+    }
+
+    #[test]
+    fn conflict_strategies() {
+        let read = |path: PathBuf| fs::read_to_string(path).unwrap();
+
+        let temp = tempfile::tempdir().unwrap();
+        let (source, destination) = conflicting(temp.path());
+        let summary = copy_with(&[&source], &destination, ConflictStrategy::Overwrite);
+        assert_eq!(summary.entries_copied, 1);
+        assert_eq!(read(destination.join("a.txt")), "new");
+
+        let temp = tempfile::tempdir().unwrap();
+        let (source, destination) = conflicting(temp.path());
+        let summary = copy_with(&[&source], &destination, ConflictStrategy::Skip);
+        assert_eq!(summary.skipped, [source.to_string_lossy().to_string()]);
+        assert_eq!(read(destination.join("a.txt")), "old");
+
+        let temp = tempfile::tempdir().unwrap();
+        let (source, destination) = conflicting(temp.path());
+        let summary = copy_with(&[&source], &destination, ConflictStrategy::KeepBoth);
+        assert_eq!(read(destination.join("a.txt")), "old");
+        assert_eq!(read(destination.join("a (1).txt")), "new");
+        assert_eq!(
+            summary.created,
+            [destination.join("a (1).txt").to_string_lossy().to_string()]
+        );
+
+        let temp = tempfile::tempdir().unwrap();
+        let (source, destination) = conflicting(temp.path());
+        let summary = copy_with(&[&source], &destination, ConflictStrategy::Ask);
+        assert_eq!(summary.entries_copied, 0);
+        assert_eq!(summary.conflicts.len(), 1);
+        assert_eq!(
+            summary.conflicts[0].destination,
+            destination.join("a.txt").to_string_lossy()
+        );
+        assert_eq!(read(destination.join("a.txt")), "old");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn trees_keep_times_permissions_and_links() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let tree = temp.path().join("tree");
+        let destination = temp.path().join("dest");
+        fs::create_dir_all(tree.join("sub")).unwrap();
+        fs::create_dir_all(&destination).unwrap();
+        let file = tree.join("sub/f.txt");
+        fs::write(&file, "data").unwrap();
+        std::os::unix::fs::symlink("sub/f.txt", tree.join("link")).unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        fs::File::open(tree.join("sub"))
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        let summary = copy_with(&[&tree], &destination, ConflictStrategy::Ask);
+        assert!(summary.failed.is_empty());
+        assert_eq!(summary.entries_copied, 4);
+
+        let copied = fs::metadata(destination.join("tree/sub/f.txt")).unwrap();
+        assert_eq!(copied.permissions().mode() & 0o777, 0o640);
+        assert_eq!(copied.modified().unwrap(), modified);
+        let folder = fs::metadata(destination.join("tree/sub")).unwrap();
+        assert_eq!(folder.modified().unwrap(), modified);
+
+        let link = destination.join("tree/link");
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(fs::read_link(&link).unwrap(), Path::new("sub/f.txt"));
+        assert_eq!(fs::read_to_string(&link).unwrap(), "data");
+    }
+
+    #[test]
+    fn cancelling_leaves_no_partial_target() {
+        let temp = tempfile::tempdir().unwrap();
+        let source = temp.path().join("big.bin");
+        let destination = temp.path().join("dest");
+        fs::create_dir_all(&destination).unwrap();
+        fs::write(&source, vec![7u8; COPY_BUFFER_SIZE * 3]).unwrap();
+
+        let token = CancellationToken::default();
+        let summary = copy(
+            &[source.to_string_lossy().to_string()],
+            &destination,
+            &TransferOptions::default(),
+            &token,
+            &mut |progress: &TransferProgress, _: bool| {
+                if progress.bytes_done > 0 {
+                    token.cancel();
+                }
+            },
+        )
+        .unwrap();
+
+        assert!(summary.cancelled);
+        assert_eq!(summary.entries_copied, 0);
+        assert_eq!(fs::read_dir(&destination).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,66 @@
+// Permanent delete behind `delete_file`
+//
+// Trees are removed entry by entry, contents before their folder, so the job
+// can be paused or cancelled half way without leaving a half-removed entry.
+
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+use super::{TransferOperation, TransferProgress};
+use crate::operation::CancellationToken;
+use crate::{log_error, OhMyFSError};
+
+pub(crate) fn delete(
+    path: &Path,
+    job_id: Option<String>,
+    token: &CancellationToken,
+    on_progress: &mut dyn FnMut(&TransferProgress, bool),
+) -> Result<(), OhMyFSError> {
+    let write_error = |path: &Path, details: String| OhMyFSError::FileWriteFailed {
+        path: path.to_string_lossy().to_string(),
+        details,
+    };
+
+    let entries = WalkDir::new(path)
+        .follow_links(false)
+        .follow_root_links(false)
+        .contents_first(true)
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| write_error(path, err.to_string()))?;
+
+    let mut progress = TransferProgress {
+        job_id,
+        operation: TransferOperation::Delete,
+        current_file: None,
+        entries_done: 0,
+        entries_total: entries.len() as u64,
+        bytes_done: 0,
+        bytes_total: 0,
+    };
+    on_progress(&progress, true);
+
+    for entry in entries {
+        if token.checkpoint() {
+            break;
+        }
+        progress.current_file = Some(entry.path().to_string_lossy().to_string());
+        on_progress(&progress, false);
+
+        let removed = if entry.file_type().is_dir() {
+            fs::remove_dir(entry.path())
+        } else {
+            fs::remove_file(entry.path())
+        };
+        if let Err(err) = removed {
+            log_error!("Failed to delete {}: {}", entry.path().display(), err);
+            return Err(write_error(entry.path(), err.to_string()));
+        }
+        progress.entries_done += 1;
+    }
+
+    progress.current_file = None;
+    on_progress(&progress, true);
+    Ok(())
+}
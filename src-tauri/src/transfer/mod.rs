@@ -0,0 +1,174 @@
+// Native copy, move and delete commands
+//
+// Trees are copied entry by entry with chunked I/O so progress can be
+// reported in bytes and the job can be paused or cancelled between chunks.
+
+mod copy;
+mod delete;
+mod moving;
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tauri::{State, Window};
+
+use crate::job::{self, Job, JobKind, JobProgress, Jobs};
+use crate::operation::ProgressEmitter;
+use crate::{log_error, log_info, OhMyFSError};
+
+pub(crate) use delete::delete;
+
+pub const PROGRESS_EVENT: &str = "transfer://progress";
+
+pub(crate) const COPY_BUFFER_SIZE: usize = 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferOperation {
+    Copy,
+    Move,
+    Delete,
+}
+
+// What to do when a copied entry already exists at the destination
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStrategy {
+    // Files are replaced, folders are merged
+    Overwrite,
+    Skip,
+    // Writes the new entry as "name (1).ext"
+    KeepBoth,
+    // Leaves the entry alone and reports it in `conflicts`, so the UI can ask
+    // the user and run the command again for those paths
+    #[default]
+    Ask,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TransferOptions {
+    pub conflict: Option<ConflictStrategy>,
+    // Id for the job running this transfer, generated when missing
+    pub job_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransferProgress {
+    pub job_id: Option<String>,
+    pub operation: TransferOperation,
+    pub current_file: Option<String>,
+    pub entries_done: u64,
+    pub entries_total: u64,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+}
+
+impl JobProgress for TransferProgress {
+    fn apply(&self, job: &mut Job) {
+        job.current_file = self.current_file.clone();
+        job.entries_done = self.entries_done;
+        job.entries_total = self.entries_total;
+        job.bytes_done = self.bytes_done;
+        job.bytes_total = self.bytes_total;
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransferConflict {
+    pub source: String,
+    pub destination: String,
+    pub is_directory: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TransferSummary {
+    pub operation: TransferOperation,
+    // Top level paths created or merged at the destination
+    pub created: Vec<String>,
+    // Source paths left alone by the `skip` strategy
+    pub skipped: Vec<String>,
+    pub conflicts: Vec<TransferConflict>,
+    pub entries_copied: u64,
+    pub bytes_copied: u64,
+    pub cancelled: bool,
+    pub failed: Vec<OhMyFSError>,
+}
+
+#[tauri::command]
+pub async fn copy_paths(
+    window: Window,
+    jobs: State<'_, Jobs>,
+    sources: Vec<String>,
+    destination: String,
+    options: Option<TransferOptions>,
+) -> Result<TransferSummary, String> {
+    let mut options = options.unwrap_or_default();
+    let job = jobs.create(
+        &window,
+        options.job_id.take(),
+        JobKind::Copy,
+        sources.clone(),
+        Some(destination.clone()),
+    );
+    options.job_id = Some(job.id().to_string());
+    log_info!("Copying {} path(s) into {}", sources.len(), destination);
+
+    job::run(job, move |job| {
+        let token = job.token().clone();
+        let mut emitter = ProgressEmitter::new(&window, PROGRESS_EVENT);
+        copy::copy(
+            &sources,
+            Path::new(&destination),
+            &options,
+            &token,
+            &mut |progress, force| {
+                emitter.emit(progress, force);
+                job.report(progress, force);
+            },
+        )
+        .map_err(|err| {
+            log_error!("Copy into {} failed: {}", destination, err);
+            err.to_string()
+        })
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn move_paths(
+    window: Window,
+    jobs: State<'_, Jobs>,
+    sources: Vec<String>,
+    destination: String,
+    options: Option<TransferOptions>,
+) -> Result<TransferSummary, String> {
+    let mut options = options.unwrap_or_default();
+    let job = jobs.create(
+        &window,
+        options.job_id.take(),
+        JobKind::Move,
+        sources.clone(),
+        Some(destination.clone()),
+    );
+    options.job_id = Some(job.id().to_string());
+    log_info!("Moving {} path(s) into {}", sources.len(), destination);
+
+    job::run(job, move |job| {
+        let token = job.token().clone();
+        let mut emitter = ProgressEmitter::new(&window, PROGRESS_EVENT);
+        moving::move_paths(
+            &sources,
+            Path::new(&destination),
+            &options,
+            &token,
+            &mut |progress, force| {
+                emitter.emit(progress, force);
+                job.report(progress, force);
+            },
+        )
+        .map_err(|err| {
+            log_error!("Move into {} failed: {}", destination, err);
+            err.to_string()
+        })
+    })
+    .await
+}
@@ -0,0 +1,418 @@
+// Move engine behind `move_paths`
+//
+// Sources are renamed in place whenever possible. Only what cannot be renamed
+// (another filesystem, or a folder that has to be merged) goes through the
+// copy engine, and a source file is deleted only once its copy is verified.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use super::copy::{copies_into_itself, copy_tree, CopiedEntry, EntryKind};
+use super::{
+    ConflictStrategy, TransferOperation, TransferOptions, TransferProgress, TransferSummary,
+    COPY_BUFFER_SIZE,
+};
+use crate::operation::CancellationToken;
+use crate::{log_error, unique_destination, OhMyFSError};
+
+enum RenamePlan {
+    // The source already sits in the destination folder
+    InPlace,
+    Rename(PathBuf),
+    // Left to the copy engine, which handles merges and reports conflicts
+    Copy,
+}
+
+fn plan_rename(
+    root: &Path,
+    destination: &Path,
+    target: PathBuf,
+    strategy: ConflictStrategy,
+) -> RenamePlan {
+    let same_folder = root
+        .parent()
+        .and_then(|parent| fs::canonicalize(parent).ok())
+        .is_some_and(|parent| fs::canonicalize(destination).is_ok_and(|d| d == parent));
+    if same_folder {
+        return RenamePlan::InPlace;
+    }
+
+    let existing = match fs::symlink_metadata(&target) {
+        Ok(existing) => existing,
+        Err(_) => return RenamePlan::Rename(target),
+    };
+    match strategy {
+        ConflictStrategy::KeepBoth => RenamePlan::Rename(unique_destination(&target)),
+        // Renaming replaces files atomically, folders need a merge
+        ConflictStrategy::Overwrite if !existing.is_dir() && !root.is_dir() => {
+            RenamePlan::Rename(target)
+        }
+        _ => RenamePlan::Copy,
+    }
+}
+
+fn checksum(path: &Path, buffer: &mut [u8]) -> io::Result<u32> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = crc32fast::Hasher::new();
+    loop {
+        match file.read(buffer) {
+            Ok(0) => break,
+            Ok(read) => hasher.update(&buffer[..read]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(hasher.finalize())
+}
+
+// Makes sure the copy matches what was read and that the source did not
+// change underneath us, since that change would be lost with the source
+fn verify(entry: &CopiedEntry, buffer: &mut [u8]) -> Result<(), String> {
+    match entry.kind {
+        EntryKind::File => {
+            let source = fs::symlink_metadata(&entry.source).map_err(|err| err.to_string())?;
+            if source.len() != entry.len || source.modified().ok() != entry.modified {
+                return Err("the source changed while it was being moved".to_string());
+            }
+
+            let target = fs::symlink_metadata(&entry.target).map_err(|err| err.to_string())?;
+            if target.len() != entry.len {
+                return Err(format!(
+                    "copy has {} bytes instead of {}",
+                    target.len(),
+                    entry.len
+                ));
+            }
+            let copied = checksum(&entry.target, buffer).map_err(|err| err.to_string())?;
+            if Some(copied) != entry.checksum {
+                return Err("copy does not match the source contents".to_string());
+            }
+            Ok(())
+        }
+        EntryKind::Symlink => {
+            let source = fs::read_link(&entry.source).map_err(|err| err.to_string())?;
+            let target = fs::read_link(&entry.target).map_err(|err| err.to_string())?;
+            if source != target {
+                return Err("copied link points somewhere else".to_string());
+            }
+            Ok(())
+        }
+        EntryKind::Directory | EntryKind::Other => Ok(()),
+    }
+}
+
+// Deletes the sources of verified entries. Folders are removed only once
+// they are empty, so anything skipped, failed or unverified keeps its parents.
+fn remove_sources(copied: &[CopiedEntry], summary: &mut TransferSummary) {
+    let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+    let mut directories = Vec::new();
+
+    for entry in copied {
+        if entry.kind == EntryKind::Directory {
+            directories.push(&entry.source);
+            continue;
+        }
+
+        if let Err(details) = verify(entry, &mut buffer) {
+            log_error!(
+                "Keeping {} because its copy could not be verified: {}",
+                entry.source.display(),
+                details
+            );
+            summary.failed.push(OhMyFSError::VerificationFailed {
+                path: entry.target.to_string_lossy().to_string(),
+                details,
+            });
+            continue;
+        }
+
+        if let Err(err) = fs::remove_file(&entry.source) {
+            log_error!("Failed to remove {}: {}", entry.source.display(), err);
+            summary.failed.push(OhMyFSError::FileWriteFailed {
+                path: entry.source.to_string_lossy().to_string(),
+                details: format!("copied, but the source could not be removed: {}", err),
+            });
+        }
+    }
+
+    for directory in directories.into_iter().rev() {
+        let _ = fs::remove_dir(directory);
+    }
+}
+
+pub(crate) fn move_paths(
+    sources: &[String],
+    destination: &Path,
+    options: &TransferOptions,
+    token: &CancellationToken,
+    on_progress: &mut dyn FnMut(&TransferProgress, bool),
+) -> Result<TransferSummary, OhMyFSError> {
+    if !destination.is_dir() {
+        return Err(OhMyFSError::PathNotDirectory {
+            path: destination.to_string_lossy().to_string(),
+        });
+    }
+
+    let strategy = options.conflict.unwrap_or_default();
+    let mut renamed = Vec::new();
+    let mut failed = Vec::new();
+    let mut leftovers = Vec::new();
+
+    // Each rename counts as one entry, plus its size for files. Whatever is
+    // left for the copy engine is added on top once it has been planned.
+    let mut progress = TransferProgress {
+        job_id: options.job_id.clone(),
+        operation: TransferOperation::Move,
+        current_file: None,
+        entries_done: 0,
+        entries_total: 0,
+        bytes_done: 0,
+        bytes_total: 0,
+    };
+    on_progress(&progress, true);
+
+    for source in sources {
+        if token.checkpoint() {
+            break;
+        }
+        let root = Path::new(source);
+        let Some(name) = root.file_name() else {
+            failed.push(OhMyFSError::FileWriteFailed {
+                path: source.clone(),
+                details: "a filesystem root cannot be moved".to_string(),
+            });
+            continue;
+        };
+        let metadata = match fs::symlink_metadata(root) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                failed.push(OhMyFSError::FileReadFailed {
+                    path: source.clone(),
+                    details: err.to_string(),
+                });
+                continue;
+            }
+        };
+        if copies_into_itself(root, destination) {
+            log_error!("Refusing to move {} into itself", source);
+            failed.push(OhMyFSError::FileWriteFailed {
+                path: source.clone(),
+                details: "a folder cannot be moved into itself".to_string(),
+            });
+            continue;
+        }
+
+        let moved = match plan_rename(root, destination, destination.join(name), strategy) {
+            // Already where it was asked to go
+            RenamePlan::InPlace => Some(source.clone()),
+            RenamePlan::Copy => None,
+            RenamePlan::Rename(target) => match fs::rename(root, &target) {
+                Ok(()) => Some(target.to_string_lossy().to_string()),
+                Err(err) if err.kind() == io::ErrorKind::CrossesDevices => None,
+                Err(err) => {
+                    log_error!("Failed to move {}: {}", source, err);
+                    failed.push(OhMyFSError::FileWriteFailed {
+                        path: source.clone(),
+                        details: err.to_string(),
+                    });
+                    continue;
+                }
+            },
+        };
+        match moved {
+            Some(target) => {
+                let len = if metadata.is_file() {
+                    metadata.len()
+                } else {
+                    0
+                };
+                progress.current_file = Some(source.clone());
+                progress.entries_done += 1;
+                progress.entries_total += 1;
+                progress.bytes_done += len;
+                progress.bytes_total += len;
+                on_progress(&progress, false);
+                renamed.push(target);
+            }
+            None => leftovers.push(source.clone()),
+        }
+    }
+
+    let (entries_before, bytes_before) = (progress.entries_done, progress.bytes_done);
+    let outcome = copy_tree(
+        &leftovers,
+        destination,
+        options,
+        TransferOperation::Move,
+        true,
+        token,
+        &mut |copied: &TransferProgress, force: bool| {
+            progress.current_file = copied.current_file.clone();
+            progress.entries_done = entries_before + copied.entries_done;
+            progress.entries_total = entries_before + copied.entries_total;
+            progress.bytes_done = bytes_before + copied.bytes_done;
+            progress.bytes_total = bytes_before + copied.bytes_total;
+            on_progress(&progress, force);
+        },
+    )?;
+    let mut summary = outcome.summary;
+    remove_sources(&outcome.copied, &mut summary);
+    summary.cancelled = token.is_cancelled();
+
+    renamed.append(&mut summary.created);
+    summary.created = renamed;
+    failed.append(&mut summary.failed);
+    summary.failed = failed;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn copied(sources: &[&Path], destination: &Path) -> (TransferSummary, Vec<CopiedEntry>) {
+        let sources = sources
+            .iter()
+            .map(|source| source.to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        let outcome = copy_tree(
+            &sources,
+            destination,
+            &TransferOptions::default(),
+            TransferOperation::Move,
+            true,
+            &CancellationToken::default(),
+            &mut |_: &TransferProgress, _: bool| {},
+        )
+        .unwrap();
+        (outcome.summary, outcome.copied)
+    }
+
+    fn move_with(source: &Path, destination: &Path, strategy: ConflictStrategy) -> TransferSummary {
+        let options = TransferOptions {
+            conflict: Some(strategy),
+            job_id: None,
+        };
+        move_paths(
+            &[source.to_string_lossy().to_string()],
+            destination,
+            &options,
+            &CancellationToken::default(),
+            &mut |_: &TransferProgress, _: bool| {},
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn sources_changed_after_the_copy_are_kept() {
+        let temp = tempfile::tempdir().unwrap();
+        let source = temp.path().join("notes.txt");
+        let destination = temp.path().join("dest");
+        fs::create_dir(&destination).unwrap();
+        fs::write(&source, "first").unwrap();
+
+        let (mut summary, copied) = copied(&[&source], &destination);
+        fs::write(&source, "edited meanwhile").unwrap();
+        remove_sources(&copied, &mut summary);
+
+        assert_eq!(fs::read_to_string(&source).unwrap(), "edited meanwhile");
+        assert!(matches!(
+            summary.failed.as_slice(),
+            [OhMyFSError::VerificationFailed { .. }]
+        ));
+    }
+
+    #[test]
+    fn folders_are_removed_only_once_empty() {
+        let temp = tempfile::tempdir().unwrap();
+        let tree = temp.path().join("tree");
+        let destination = temp.path().join("dest");
+        fs::create_dir_all(tree.join("done")).unwrap();
+        fs::create_dir_all(tree.join("kept")).unwrap();
+        fs::create_dir(&destination).unwrap();
+        fs::write(tree.join("done/a.txt"), "a").unwrap();
+        fs::write(tree.join("kept/b.txt"), "b").unwrap();
+
+        let (mut summary, copied) = copied(&[&tree], &destination);
+        fs::write(tree.join("kept/b.txt"), "changed").unwrap();
+        remove_sources(&copied, &mut summary);
+
+        assert!(!tree.join("done").exists());
+        assert!(tree.join("kept").is_dir());
+        assert_eq!(
+            fs::read_to_string(tree.join("kept/b.txt")).unwrap(),
+            "changed"
+        );
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(
+            fs::read_to_string(destination.join("tree/done/a.txt")).unwrap(),
+            "a"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn free_targets_are_renamed_and_merges_copied() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let destination = temp.path().join("dest");
+        fs::create_dir_all(destination.join("tree")).unwrap();
+        fs::write(destination.join("tree/old.txt"), "old").unwrap();
+
+        // Nothing in the way: the file itself moves, keeping its inode
+        let file = temp.path().join("file.txt");
+        fs::write(&file, "file").unwrap();
+        let inode = fs::metadata(&file).unwrap().ino();
+        assert!(matches!(
+            plan_rename(
+                &file,
+                &destination,
+                destination.join("file.txt"),
+                ConflictStrategy::Ask
+            ),
+            RenamePlan::Rename(_)
+        ));
+        let summary = move_with(&file, &destination, ConflictStrategy::Ask);
+        assert!(summary.failed.is_empty());
+        assert!(!file.exists());
+        assert_eq!(
+            fs::metadata(destination.join("file.txt")).unwrap().ino(),
+            inode
+        );
+
+        // An existing folder has to be merged through the copy engine
+        let tree = temp.path().join("tree");
+        fs::create_dir(&tree).unwrap();
+        fs::write(tree.join("new.txt"), "new").unwrap();
+        let inode = fs::metadata(tree.join("new.txt")).unwrap().ino();
+        assert!(matches!(
+            plan_rename(
+                &tree,
+                &destination,
+                destination.join("tree"),
+                ConflictStrategy::Overwrite
+            ),
+            RenamePlan::Copy
+        ));
+        let summary = move_with(&tree, &destination, ConflictStrategy::Overwrite);
+        assert!(summary.failed.is_empty());
+        assert!(!tree.exists());
+        let merged = destination.join("tree/new.txt");
+        assert_eq!(fs::read_to_string(&merged).unwrap(), "new");
+        assert_ne!(fs::metadata(&merged).unwrap().ino(), inode);
+        assert!(destination.join("tree/old.txt").exists());
+
+        // Moving into the folder it is already in changes nothing
+        let summary = move_with(
+            &destination.join("file.txt"),
+            &destination,
+            ConflictStrategy::Ask,
+        );
+        assert_eq!(
+            summary.created,
+            [destination.join("file.txt").to_string_lossy().to_string()]
+        );
+    }
+}
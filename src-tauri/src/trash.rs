@@ -0,0 +1,712 @@
+// Trash support following the freedesktop.org Trash specification
+//
+// Every trash directory holds `files/` with the trashed entries and `info/`
+// with one `.trashinfo` per entry recording where it came from. Files are
+// only ever renamed into a trash on their own filesystem: the home trash when
+// they live on the same device, otherwise `$topdir/.Trash/$uid` or
+// `$topdir/.Trash-$uid` at the root of their mount.
+
+use chrono::{Local, NaiveDateTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::{first_free, log_error, log_info, to_epoch_millis, OhMyFSError};
+
+const INFO_EXTENSION: &str = ".trashinfo";
+const DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrashItem {
+    // Location inside the trash, used to restore or purge the item
+    pub id: String,
+    pub name: String,
+    pub original_path: String,
+    pub deleted_at: Option<String>,
+    pub is_directory: bool,
+    // Only known for files
+    pub size: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TrashSummary {
+    pub items: Vec<TrashItem>,
+    pub failed: Vec<OhMyFSError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TrashDir {
+    path: PathBuf,
+    // Mount point for per-volume trashes, whose info files use relative paths
+    topdir: Option<PathBuf>,
+}
+
+impl TrashDir {
+    fn files(&self) -> PathBuf {
+        self.path.join("files")
+    }
+
+    fn info(&self) -> PathBuf {
+        self.path.join("info")
+    }
+
+    fn info_file(&self, name: &str) -> PathBuf {
+        self.info().join(format!("{}{}", name, INFO_EXTENSION))
+    }
+}
+
+fn current_uid() -> u32 {
+    #[cfg(unix)]
+    {
+        // SAFETY: getuid has no preconditions and cannot fail
+        unsafe { libc::getuid() }
+    }
+    #[cfg(not(unix))]
+    {
+        0
+    }
+}
+
+fn device_of(metadata: &fs::Metadata) -> u64 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        metadata.dev()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        0
+    }
+}
+
+fn unsupported(path: &str) -> Option<OhMyFSError> {
+    (cfg!(not(unix)) || cfg!(target_os = "macos")).then(|| OhMyFSError::TrashFailed {
+        path: path.to_string(),
+        details: "the freedesktop trash is only available on Linux and BSD".to_string(),
+    })
+}
+
+fn home_trash() -> Option<TrashDir> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))?;
+    Some(TrashDir {
+        path: data_home.join("Trash"),
+        topdir: None,
+    })
+}
+
+// Creates a trash directory owned by the user with the permissions the spec asks for
+fn create_private_dir(path: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(path)
+}
+
+fn ensure_layout(trash: &TrashDir) -> io::Result<()> {
+    create_private_dir(&trash.files())?;
+    create_private_dir(&trash.info())
+}
+
+// The administrator-provided `$topdir/.Trash` only counts when it is a real
+// directory with the sticky bit set, anything else could be planted by another user
+fn shared_trash_is_valid(path: &Path) -> bool {
+    match fs::symlink_metadata(path) {
+        #[cfg(unix)]
+        Ok(metadata) => {
+            use std::os::unix::fs::PermissionsExt;
+            metadata.is_dir() && metadata.permissions().mode() & 0o1000 != 0
+        }
+        #[cfg(not(unix))]
+        Ok(metadata) => metadata.is_dir(),
+        Err(_) => false,
+    }
+}
+
+fn owned_directory(path: &Path) -> bool {
+    match fs::symlink_metadata(path) {
+        #[cfg(unix)]
+        Ok(metadata) => {
+            use std::os::unix::fs::MetadataExt;
+            metadata.is_dir() && metadata.uid() == current_uid()
+        }
+        #[cfg(not(unix))]
+        Ok(metadata) => metadata.is_dir(),
+        Err(_) => false,
+    }
+}
+
+fn topdir_trashes(topdir: &Path) -> [TrashDir; 2] {
+    let uid = current_uid().to_string();
+    [
+        TrashDir {
+            path: topdir.join(".Trash").join(&uid),
+            topdir: Some(topdir.to_path_buf()),
+        },
+        TrashDir {
+            path: topdir.join(format!(".Trash-{}", uid)),
+            topdir: Some(topdir.to_path_buf()),
+        },
+    ]
+}
+
+// Highest ancestor of `path` that is still on the same device
+fn mount_topdir(path: &Path, device: u64) -> PathBuf {
+    let mut topdir = path.to_path_buf();
+    for ancestor in path.ancestors().skip(1) {
+        match fs::metadata(ancestor) {
+            Ok(metadata) if device_of(&metadata) == device => topdir = ancestor.to_path_buf(),
+            _ => break,
+        }
+    }
+    topdir
+}
+
+// Picks the trash a path has to go to so that trashing is a plain rename
+fn trash_for(path: &Path) -> Result<TrashDir, String> {
+    let parent = path.parent().ok_or("a filesystem root cannot be trashed")?;
+    let device = fs::metadata(parent)
+        .map(|metadata| device_of(&metadata))
+        .map_err(|err| err.to_string())?;
+
+    if let Some(home) = home_trash() {
+        ensure_layout(&home).map_err(|err| err.to_string())?;
+        if fs::metadata(&home.path).is_ok_and(|metadata| device_of(&metadata) == device) {
+            return Ok(home);
+        }
+    }
+
+    let topdir = mount_topdir(parent, device);
+    let [shared, private] = topdir_trashes(&topdir);
+    if shared_trash_is_valid(&topdir.join(".Trash")) && ensure_layout(&shared).is_ok() {
+        return Ok(shared);
+    }
+    if fs::symlink_metadata(&private.path).is_ok() && !owned_directory(&private.path) {
+        return Err(format!(
+            "{} exists but is not a directory owned by you",
+            private.path.display()
+        ));
+    }
+    ensure_layout(&private)
+        .map_err(|err| format!("no trash available on {}: {}", topdir.display(), err))?;
+    Ok(private)
+}
+
+// Every trash that currently exists: the home trash plus the per-volume
+// trashes of mounted filesystems
+fn known_trashes() -> Vec<TrashDir> {
+    let mut trashes: Vec<TrashDir> = home_trash().into_iter().collect();
+
+    let mounts = fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+    for line in mounts.lines() {
+        let Some(mount_point) = line.split_whitespace().nth(1) else {
+            continue;
+        };
+        let topdir = PathBuf::from(decode_mount_field(mount_point));
+        for trash in topdir_trashes(&topdir) {
+            if trash.files().is_dir() && !trashes.contains(&trash) {
+                trashes.push(trash);
+            }
+        }
+    }
+
+    trashes.retain(|trash| trash.files().is_dir());
+    trashes
+}
+
+// Escapes are read from bytes, since whatever follows `\` or `%` may be the
+// middle of a character
+fn octal_byte(digits: &[u8]) -> Option<u8> {
+    digits.iter().try_fold(0u8, |value, &c| {
+        let digit = (c as char).to_digit(8)? as u8;
+        value.checked_mul(8)?.checked_add(digit)
+    })
+}
+
+fn hex_byte(digits: &[u8]) -> Option<u8> {
+    digits.iter().try_fold(0u8, |value, &c| {
+        let digit = (c as char).to_digit(16)? as u8;
+        Some(value * 16 + digit)
+    })
+}
+
+// /proc/mounts escapes whitespace as octal sequences such as `\040`
+fn decode_mount_field(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'\\' {
+            if let Some(value) = bytes.get(index + 1..index + 4).and_then(octal_byte) {
+                decoded.push(value);
+                index += 4;
+                continue;
+            }
+        }
+        decoded.push(bytes[index]);
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn percent_encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            if let Some(byte) = bytes.get(index + 1..index + 3).and_then(hex_byte) {
+                decoded.push(byte);
+                index += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[index]);
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+// Absolute path without resolving the final component, so trashing a
+// symlink trashes the link and not what it points to
+fn absolute(path: &Path) -> io::Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    Ok(fs::canonicalize(parent)?.join(name))
+}
+
+fn numbered_name(name: &str, attempt: u32) -> String {
+    if attempt == 0 {
+        return name.to_string();
+    }
+    let path = Path::new(name);
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => format!(
+            "{} ({}).{}",
+            stem.to_string_lossy(),
+            attempt,
+            ext.to_string_lossy()
+        ),
+        _ => format!("{} ({})", name, attempt),
+    }
+}
+
+fn trash_path(path: &Path) -> Result<TrashItem, String> {
+    let source = absolute(path).map_err(|err| err.to_string())?;
+    let metadata = fs::symlink_metadata(&source).map_err(|err| err.to_string())?;
+    let trash = trash_for(&source)?;
+
+    let recorded_path = match &trash.topdir {
+        Some(topdir) => source.strip_prefix(topdir).unwrap_or(&source),
+        None => &source,
+    };
+    let deleted_at = Local::now();
+    let info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(&recorded_path.to_string_lossy()),
+        deleted_at.format(DATE_FORMAT)
+    );
+
+    // Claiming the info file with `create_new` is what reserves a name
+    let base = source
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let (name, info_path) = first_free(0, |attempt| {
+        let name = numbered_name(&base, attempt);
+        let info_path = trash.info_file(&name);
+        if fs::symlink_metadata(trash.files().join(&name)).is_ok() {
+            return None;
+        }
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(mut file) => Some(file.write_all(info.as_bytes()).map(|()| (name, info_path))),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => None,
+            Err(err) => Some(Err(err)),
+        }
+    })
+    .map_err(|err| err.to_string())?;
+
+    let trashed = trash.files().join(&name);
+    if let Err(err) = fs::rename(&source, &trashed) {
+        let _ = fs::remove_file(&info_path);
+        return Err(err.to_string());
+    }
+
+    Ok(TrashItem {
+        id: trashed.to_string_lossy().to_string(),
+        name: base,
+        original_path: source.to_string_lossy().to_string(),
+        deleted_at: Some(to_epoch_millis(deleted_at.into())),
+        is_directory: metadata.is_dir(),
+        size: metadata.is_file().then_some(metadata.len()),
+    })
+}
+
+fn read_info(trash: &TrashDir, name: &str) -> Option<TrashItem> {
+    let contents = fs::read_to_string(trash.info_file(name)).ok()?;
+    let mut lines = contents.lines().map(str::trim);
+    if lines.next()? != "[Trash Info]" {
+        return None;
+    }
+
+    let mut original = None;
+    let mut deleted_at = None;
+    for line in lines {
+        if line.starts_with('[') {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Path=") {
+            original = Some(percent_decode(value));
+        } else if let Some(value) = line.strip_prefix("DeletionDate=") {
+            deleted_at = NaiveDateTime::parse_from_str(value, DATE_FORMAT)
+                .ok()
+                .and_then(|date| Local.from_local_datetime(&date).earliest())
+                .map(|date| to_epoch_millis(date.into()));
+        }
+    }
+
+    let original = PathBuf::from(original?);
+    let original = match &trash.topdir {
+        Some(topdir) if original.is_relative() => topdir.join(original),
+        _ => original,
+    };
+
+    let trashed = trash.files().join(name);
+    let metadata = fs::symlink_metadata(&trashed).ok()?;
+    Some(TrashItem {
+        id: trashed.to_string_lossy().to_string(),
+        name: original
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| name.to_string()),
+        original_path: original.to_string_lossy().to_string(),
+        deleted_at,
+        is_directory: metadata.is_dir(),
+        size: metadata.is_file().then_some(metadata.len()),
+    })
+}
+
+fn list_items() -> Vec<TrashItem> {
+    let mut items = Vec::new();
+    for trash in known_trashes() {
+        let Ok(entries) = fs::read_dir(trash.info()) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            // Info files without a matching entry are leftovers and ignored
+            if let Some(name) = file_name.strip_suffix(INFO_EXTENSION) {
+                items.extend(read_info(&trash, name));
+            }
+        }
+    }
+    items
+}
+
+// Maps an id back to its trash, refusing anything that is not directly
+// inside the `files/` directory of a known trash
+fn locate(id: &str) -> Result<(TrashDir, String), OhMyFSError> {
+    let not_found = || OhMyFSError::TrashItemNotFound { id: id.to_string() };
+    let path = Path::new(id);
+    let name = match path.components().next_back() {
+        Some(Component::Normal(name)) => name.to_string_lossy().to_string(),
+        _ => return Err(not_found()),
+    };
+    let files = path.parent().ok_or_else(not_found)?;
+    let trash = known_trashes()
+        .into_iter()
+        .find(|trash| trash.files() == files)
+        .ok_or_else(not_found)?;
+    if fs::symlink_metadata(path).is_err() {
+        return Err(not_found());
+    }
+    Ok((trash, name))
+}
+
+fn restore(id: &str) -> Result<TrashItem, OhMyFSError> {
+    let (trash, name) = locate(id)?;
+    let item = read_info(&trash, &name)
+        .ok_or_else(|| OhMyFSError::TrashItemNotFound { id: id.to_string() })?;
+    let write_error = |details: String| OhMyFSError::FileWriteFailed {
+        path: item.original_path.clone(),
+        details,
+    };
+
+    let original = Path::new(&item.original_path);
+    if fs::symlink_metadata(original).is_ok() {
+        return Err(write_error(
+            "something already exists at the original location".to_string(),
+        ));
+    }
+    if let Some(parent) = original.parent() {
+        fs::create_dir_all(parent).map_err(|err| write_error(err.to_string()))?;
+    }
+    fs::rename(id, original).map_err(|err| write_error(err.to_string()))?;
+    let _ = fs::remove_file(trash.info_file(&name));
+    Ok(item)
+}
+
+fn purge(id: &str) -> Result<TrashItem, OhMyFSError> {
+    let (trash, name) = locate(id)?;
+    let item = read_info(&trash, &name).unwrap_or_else(|| TrashItem {
+        id: id.to_string(),
+        name: name.clone(),
+        original_path: String::new(),
+        deleted_at: None,
+        is_directory: Path::new(id).is_dir(),
+        size: None,
+    });
+
+    let path = Path::new(id);
+    let removed = if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir()) {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+    removed.map_err(|err| OhMyFSError::FileWriteFailed {
+        path: id.to_string(),
+        details: err.to_string(),
+    })?;
+    // The info file goes last so an interrupted purge still shows up in the trash
+    let _ = fs::remove_file(trash.info_file(&name));
+    Ok(item)
+}
+
+#[tauri::command]
+pub async fn trash_paths(paths: Vec<String>) -> Result<TrashSummary, String> {
+    if let Some(err) = unsupported(&paths.join(", ")) {
+        return Err(err.to_string());
+    }
+    log_info!("Moving {} path(s) to the trash", paths.len());
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut summary = TrashSummary::default();
+        for path in paths {
+            match trash_path(Path::new(&path)) {
+                Ok(item) => summary.items.push(item),
+                Err(details) => {
+                    log_error!("Failed to trash {}: {}", path, details);
+                    summary
+                        .failed
+                        .push(OhMyFSError::TrashFailed { path, details });
+                }
+            }
+        }
+        summary
+    })
+    .await
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn list_trash() -> Result<Vec<TrashItem>, String> {
+    if let Some(err) = unsupported("trash") {
+        return Err(err.to_string());
+    }
+    tauri::async_runtime::spawn_blocking(list_items)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn restore_from_trash(ids: Vec<String>) -> Result<TrashSummary, String> {
+    if let Some(err) = unsupported("trash") {
+        return Err(err.to_string());
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut summary = TrashSummary::default();
+        for id in ids {
+            match restore(&id) {
+                Ok(item) => summary.items.push(item),
+                Err(err) => {
+                    log_error!("Failed to restore {}: {}", id, err);
+                    summary.failed.push(err);
+                }
+            }
+        }
+        summary
+    })
+    .await
+    .map_err(|err| err.to_string())
+}
+
+// Empties the whole trash, or only the given items
+#[tauri::command]
+pub async fn empty_trash(ids: Option<Vec<String>>) -> Result<TrashSummary, String> {
+    if let Some(err) = unsupported("trash") {
+        return Err(err.to_string());
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        let ids = ids.unwrap_or_else(|| list_items().into_iter().map(|item| item.id).collect());
+        let mut summary = TrashSummary::default();
+        for id in ids {
+            match purge(&id) {
+                Ok(item) => summary.items.push(item),
+                Err(err) => {
+                    log_error!("Failed to purge {}: {}", id, err);
+                    summary.failed.push(err);
+                }
+            }
+        }
+        summary
+    })
+    .await
+    .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_encoding_round_trips() {
+        for path in [
+            "/home/user/notes.txt",
+            "/home/user/My Documents/100% done.txt",
+            "/tmp/ünïcödé/日本語",
+            "/tmp/a%2Fb?#&=+",
+        ] {
+            let encoded = percent_encode(path);
+            assert!(encoded.bytes().all(|c| c.is_ascii_graphic() && c != b' '));
+            assert_eq!(percent_decode(&encoded), path);
+        }
+        assert_eq!(
+            percent_encode("/a b/ü~.-_"),
+            "/a%20b/%C3%BC~.-_".to_string()
+        );
+    }
+
+    #[test]
+    fn malformed_escapes_stay_literal() {
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%4"), "%4");
+        assert_eq!(percent_decode("%zz%20"), "%zz ");
+        assert_eq!(percent_decode("%+1"), "%+1");
+        assert_eq!(percent_decode("%é"), "%é");
+        assert_eq!(percent_decode("%e9"), "\u{FFFD}");
+    }
+
+    #[test]
+    fn mount_fields_decode_octal_escapes() {
+        assert_eq!(decode_mount_field("/mnt/my\\040disk"), "/mnt/my disk");
+        assert_eq!(decode_mount_field("/mnt/tab\\011"), "/mnt/tab\t");
+        assert_eq!(
+            decode_mount_field("/mnt/back\\134slash"),
+            "/mnt/back\\slash"
+        );
+        assert_eq!(decode_mount_field("/mnt/odd\\08"), "/mnt/odd\\08");
+        assert_eq!(decode_mount_field("/mnt/end\\04"), "/mnt/end\\04");
+        assert_eq!(decode_mount_field("/mnt/\\é12"), "/mnt/\\é12");
+    }
+
+    #[test]
+    fn numbered_names_keep_the_extension() {
+        assert_eq!(numbered_name("notes.txt", 0), "notes.txt");
+        assert_eq!(numbered_name("notes.txt", 2), "notes (2).txt");
+        assert_eq!(numbered_name("Makefile", 1), "Makefile (1)");
+        assert_eq!(numbered_name(".bashrc", 1), ".bashrc (1)");
+    }
+
+    fn trash_with(info: &str, topdir: Option<PathBuf>) -> (tempfile::TempDir, TrashDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let trash = TrashDir {
+            path: dir.path().to_path_buf(),
+            topdir,
+        };
+        ensure_layout(&trash).unwrap();
+        fs::write(trash.files().join("report.pdf"), b"pdf").unwrap();
+        fs::write(trash.info_file("report.pdf"), info).unwrap();
+        (dir, trash)
+    }
+
+    #[test]
+    fn reads_trash_info() {
+        let (_dir, trash) = trash_with(
+            "[Trash Info]\nPath=/home/user/My%20Files/report.pdf\nDeletionDate=2024-03-01T10:20:30\n",
+            None,
+        );
+        let item = read_info(&trash, "report.pdf").unwrap();
+        assert_eq!(item.name, "report.pdf");
+        assert_eq!(item.original_path, "/home/user/My Files/report.pdf");
+        assert_eq!(item.size, Some(3));
+        assert!(!item.is_directory);
+        assert_eq!(item.id, trash.files().join("report.pdf").to_string_lossy());
+
+        let expected = Local
+            .from_local_datetime(
+                &NaiveDateTime::parse_from_str("2024-03-01T10:20:30", DATE_FORMAT).unwrap(),
+            )
+            .earliest()
+            .unwrap();
+        assert_eq!(item.deleted_at, Some(to_epoch_millis(expected.into())));
+    }
+
+    #[test]
+    fn relative_paths_are_joined_to_the_topdir() {
+        let (_dir, trash) = trash_with(
+            "[Trash Info]\nPath=docs/report.pdf\nDeletionDate=2024-03-01T10:20:30\n",
+            Some(PathBuf::from("/media/usb")),
+        );
+        let item = read_info(&trash, "report.pdf").unwrap();
+        assert_eq!(
+            PathBuf::from(item.original_path),
+            Path::new("/media/usb/docs/report.pdf")
+        );
+    }
+
+    #[test]
+    fn tolerates_odd_trash_info() {
+        // Keys of later groups are ignored, a bad date only loses the date
+        let (_dir, trash) = trash_with(
+            "[Trash Info]\r\nDeletionDate=yesterday\r\nPath=/a/report.pdf\r\n[Other]\r\nPath=/b\r\n",
+            None,
+        );
+        let item = read_info(&trash, "report.pdf").unwrap();
+        assert_eq!(item.original_path, "/a/report.pdf");
+        assert_eq!(item.deleted_at, None);
+    }
+
+    #[test]
+    fn rejects_invalid_trash_info() {
+        let (_dir, trash) = trash_with("Path=/a/report.pdf\n", None);
+        assert!(read_info(&trash, "report.pdf").is_none());
+
+        let (_dir, trash) = trash_with("[Trash Info]\nDeletionDate=2024-03-01T10:20:30\n", None);
+        assert!(read_info(&trash, "report.pdf").is_none());
+
+        // The info file outlived the trashed entry
+        let (_dir, trash) = trash_with("[Trash Info]\nPath=/a/report.pdf\n", None);
+        fs::remove_file(trash.files().join("report.pdf")).unwrap();
+        assert!(read_info(&trash, "report.pdf").is_none());
+    }
+}
@@ -0,0 +1,687 @@
+// Directory watching for the file list
+//
+// Every watched directory gets its own inotify instance polled by a thread.
+// Raw events are coalesced per name and flushed once the directory has been
+// quiet for a moment, so a burst like an unpacked archive arrives as one batch.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tauri::{Emitter, State, Window};
+
+use crate::{file_entry, log_error, log_info, FileEntry, OhMyFSError};
+
+pub const CHANGE_EVENT: &str = "fs://change";
+
+// A batch is sent once no event arrived for this long...
+const DEBOUNCE: Duration = Duration::from_millis(150);
+// ...or once its oldest change has waited this long, so long bursts still show up
+const MAX_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Removed,
+    Renamed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DirectoryChange {
+    pub kind: ChangeKind,
+    pub path: String,
+    // Previous path of renamed entries
+    pub old_path: Option<String>,
+    // Missing for removed entries
+    pub entry: Option<FileEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DirectoryChanges {
+    // The watched directory, as passed to `watch_directory`
+    pub path: String,
+    pub changes: Vec<DirectoryChange>,
+    // The kernel dropped events, so the listing has to be read again
+    pub overflowed: bool,
+}
+
+// What happened to a name since the last batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Pending {
+    Created,
+    Modified,
+    Removed,
+    // Holds the name the entry had before
+    RenamedFrom(OsString),
+}
+
+// Raw events waiting for the directory to settle
+#[derive(Default)]
+struct Batch {
+    pending: HashMap<OsString, Pending>,
+    // Names in the order they first changed
+    order: Vec<OsString>,
+    // Names moved away, by inotify cookie, until the matching arrival shows up
+    moves: HashMap<u32, OsString>,
+    overflowed: bool,
+    first_change: Option<Instant>,
+    last_change: Option<Instant>,
+}
+
+impl Batch {
+    fn is_empty(&self) -> bool {
+        self.pending.is_empty() && self.moves.is_empty() && !self.overflowed
+    }
+
+    fn is_due(&self) -> bool {
+        !self.is_empty()
+            && (self
+                .last_change
+                .is_some_and(|last| last.elapsed() >= DEBOUNCE)
+                || self
+                    .first_change
+                    .is_some_and(|first| first.elapsed() >= MAX_DELAY))
+    }
+
+    fn touch(&mut self) {
+        let now = Instant::now();
+        self.first_change.get_or_insert(now);
+        self.last_change = Some(now);
+    }
+
+    fn set(&mut self, name: OsString, pending: Option<Pending>) {
+        match pending {
+            Some(pending) => {
+                if self.pending.insert(name.clone(), pending).is_none() {
+                    self.order.push(name);
+                }
+            }
+            None => {
+                self.pending.remove(&name);
+            }
+        }
+    }
+
+    fn created(&mut self, name: OsString) {
+        let next = match self.pending.get(&name) {
+            // Replaced by a new entry under the same name
+            Some(Pending::Removed) => Pending::Modified,
+            Some(existing) => existing.clone(),
+            None => Pending::Created,
+        };
+        self.set(name, Some(next));
+    }
+
+    fn modified(&mut self, name: OsString) {
+        let next = match self.pending.get(&name) {
+            Some(Pending::Removed) | None => Pending::Modified,
+            Some(existing) => existing.clone(),
+        };
+        self.set(name, Some(next));
+    }
+
+    fn removed(&mut self, name: OsString) {
+        match self.pending.get(&name).cloned() {
+            // Never seen by the frontend, nothing to report
+            Some(Pending::Created) => self.set(name, None),
+            Some(Pending::RenamedFrom(original)) => {
+                self.set(name, None);
+                self.set(original, Some(Pending::Removed));
+            }
+            _ => self.set(name, Some(Pending::Removed)),
+        }
+    }
+
+    fn renamed(&mut self, from: OsString, to: OsString) {
+        let next = match self.pending.get(&from).cloned() {
+            Some(Pending::Created) => Pending::Created,
+            Some(Pending::RenamedFrom(original)) => Pending::RenamedFrom(original),
+            _ => Pending::RenamedFrom(from.clone()),
+        };
+        self.set(from, None);
+        match next {
+            // Renamed back to where it started
+            Pending::RenamedFrom(original) if original == to => {
+                self.modified(to);
+            }
+            next => self.set(to, Some(next)),
+        }
+    }
+
+    // The first half of a move, held until its arrival shows up
+    fn moved_from(&mut self, cookie: u32, name: OsString) {
+        self.moves.insert(cookie, name);
+    }
+
+    // An arrival without a matching departure came from another directory
+    fn moved_to(&mut self, cookie: u32, name: OsString) {
+        match self.moves.remove(&cookie) {
+            Some(from) => self.renamed(from, name),
+            None => self.created(name),
+        }
+    }
+
+    // Settles the pending changes, reading the current state of every entry
+    // that still exists
+    fn drain(&mut self, directory: &Path) -> (Vec<Settled>, bool) {
+        // Moves whose arrival never came left the directory
+        for (_, name) in std::mem::take(&mut self.moves) {
+            self.removed(name);
+        }
+
+        let mut settled = Vec::new();
+        for name in std::mem::take(&mut self.order) {
+            let Some(pending) = self.pending.remove(&name) else {
+                continue;
+            };
+            let metadata = fs::symlink_metadata(directory.join(&name)).ok();
+
+            let (kind, old_name) = match (pending, &metadata) {
+                (Pending::Removed, _) => (ChangeKind::Removed, None),
+                (Pending::RenamedFrom(original), Some(_)) => (ChangeKind::Renamed, Some(original)),
+                // Gone again before the batch was sent
+                (Pending::RenamedFrom(original), None) => {
+                    settled.push(Settled {
+                        kind: ChangeKind::Removed,
+                        name: original,
+                        old_name: None,
+                        metadata: None,
+                    });
+                    continue;
+                }
+                (_, None) => (ChangeKind::Removed, None),
+                (Pending::Created, Some(_)) => (ChangeKind::Created, None),
+                (Pending::Modified, Some(_)) => (ChangeKind::Modified, None),
+            };
+            settled.push(Settled {
+                kind,
+                name,
+                old_name,
+                metadata: metadata.filter(|_| kind != ChangeKind::Removed),
+            });
+        }
+
+        let overflowed = std::mem::take(&mut self.overflowed);
+        self.first_change = None;
+        self.last_change = None;
+        (settled, overflowed)
+    }
+}
+
+// A change ready to be sent, relative to the watched directory
+struct Settled {
+    kind: ChangeKind,
+    name: OsString,
+    old_name: Option<OsString>,
+    // Missing for removed entries
+    metadata: Option<fs::Metadata>,
+}
+
+// Events for the directory as one subscriber named it
+fn describe(directory: &Path, settled: &[Settled]) -> Vec<DirectoryChange> {
+    settled
+        .iter()
+        .map(|change| {
+            let path = directory.join(&change.name);
+            DirectoryChange {
+                kind: change.kind,
+                path: path.to_string_lossy().to_string(),
+                old_path: change
+                    .old_name
+                    .as_ref()
+                    .map(|old| directory.join(old).to_string_lossy().to_string()),
+                entry: change
+                    .metadata
+                    .as_ref()
+                    .map(|metadata| file_entry(&path, metadata)),
+            }
+        })
+        .collect()
+}
+
+fn removed_change(path: &Path) -> DirectoryChange {
+    DirectoryChange {
+        kind: ChangeKind::Removed,
+        path: path.to_string_lossy().to_string(),
+        old_path: None,
+        entry: None,
+    }
+}
+
+// Thin wrapper over the inotify syscalls, shared with the file index
+#[cfg(target_os = "linux")]
+pub(crate) mod inotify {
+    use std::ffi::{CString, OsString};
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::path::Path;
+
+    const HEADER_SIZE: usize = std::mem::size_of::<libc::inotify_event>();
+    // Room for plenty of events with maximum length names
+    const BUFFER_SIZE: usize = 64 * 1024;
+
+    // Everything that changes the listing of a directory, or the directory itself
+    pub const DIRECTORY_MASK: u32 = libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_MODIFY
+        | libc::IN_ATTRIB
+        | libc::IN_CLOSE_WRITE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO
+        | libc::IN_DELETE_SELF
+        | libc::IN_MOVE_SELF
+        | libc::IN_ONLYDIR;
+
+    pub struct RawEvent {
+        pub wd: i32,
+        pub mask: u32,
+        pub cookie: u32,
+        pub name: OsString,
+    }
+
+    pub struct Inotify {
+        fd: OwnedFd,
+    }
+
+    impl Inotify {
+        pub fn new() -> io::Result<Self> {
+            // SAFETY: plain syscall, the returned descriptor is checked below
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: `fd` is a freshly created descriptor nobody else owns
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            Ok(Self { fd })
+        }
+
+        // An instance watching a single directory
+        pub fn watch(directory: &Path) -> io::Result<Self> {
+            let inotify = Self::new()?;
+            inotify.add_watch(directory, DIRECTORY_MASK)?;
+            Ok(inotify)
+        }
+
+        // Returns the watch descriptor that events for `path` will carry
+        pub fn add_watch(&self, path: &Path, mask: u32) -> io::Result<i32> {
+            let path = CString::new(path.as_os_str().as_bytes())?;
+            // SAFETY: `path` is a valid NUL-terminated string for the duration of the call
+            let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), mask) };
+            if wd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(wd)
+        }
+
+        pub fn remove_watch(&self, wd: i32) {
+            // SAFETY: plain syscall; an unknown descriptor only makes it fail
+            unsafe { libc::inotify_rm_watch(self.fd.as_raw_fd(), wd) };
+        }
+
+        // Waits up to `timeout` milliseconds and returns whatever events arrived
+        pub fn read(&self, timeout: i32) -> io::Result<Vec<RawEvent>> {
+            let mut poll = libc::pollfd {
+                fd: self.fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: `poll` points to exactly one valid pollfd
+            let ready = unsafe { libc::poll(&mut poll, 1, timeout) };
+            if ready < 0 {
+                let err = io::Error::last_os_error();
+                return match err.kind() {
+                    io::ErrorKind::Interrupted => Ok(Vec::new()),
+                    _ => Err(err),
+                };
+            }
+            if ready == 0 {
+                return Ok(Vec::new());
+            }
+
+            let mut buffer = vec![0u8; BUFFER_SIZE];
+            // SAFETY: the buffer is valid for writes of its whole length
+            let read = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buffer.as_mut_ptr().cast(),
+                    buffer.len(),
+                )
+            };
+            if read < 0 {
+                let err = io::Error::last_os_error();
+                return match err.kind() {
+                    io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => Ok(Vec::new()),
+                    _ => Err(err),
+                };
+            }
+
+            let bytes = &buffer[..read as usize];
+            let field = |offset: usize| {
+                u32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap_or_default())
+            };
+            let mut events = Vec::new();
+            let mut offset = 0;
+            // Each record is `wd, mask, cookie, len` followed by a NUL padded name
+            while offset + HEADER_SIZE <= bytes.len() {
+                let wd = field(offset) as i32;
+                let mask = field(offset + 4);
+                let cookie = field(offset + 8);
+                let len = field(offset + 12) as usize;
+                let name = bytes
+                    .get(offset + HEADER_SIZE..offset + HEADER_SIZE + len)
+                    .unwrap_or_default();
+                let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+                events.push(RawEvent {
+                    wd,
+                    mask,
+                    cookie,
+                    name: OsString::from_vec(name[..end].to_vec()),
+                });
+                offset += HEADER_SIZE + len;
+            }
+            Ok(events)
+        }
+    }
+}
+
+// The path strings subscribers used, one per `watch_directory` call. Each
+// distinct string gets its own events, with entry paths under it.
+type Subscribers = Arc<Mutex<Vec<String>>>;
+
+struct Watch {
+    subscribers: Subscribers,
+    // Set by `unwatch_directory`, or by the thread itself once the directory is gone
+    stop: Arc<AtomicBool>,
+}
+
+// Directories currently watched, keyed by their canonical path
+#[derive(Default)]
+pub struct Watchers {
+    active: Mutex<HashMap<PathBuf, Watch>>,
+}
+
+impl Watchers {
+    fn lock(&self) -> MutexGuard<'_, HashMap<PathBuf, Watch>> {
+        self.active
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn emit_changes(window: &Window, changes: &DirectoryChanges) {
+    if let Err(err) = window.emit(CHANGE_EVENT, changes) {
+        log_error!("Failed to emit {}: {}", CHANGE_EVENT, err);
+    }
+}
+
+fn lock_subscribers(subscribers: &Subscribers) -> MutexGuard<'_, Vec<String>> {
+    subscribers
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(target_os = "linux")]
+fn start(
+    window: Window,
+    directory: PathBuf,
+    path: String,
+    subscribers: Subscribers,
+    stop: Arc<AtomicBool>,
+) -> Result<(), String> {
+    let inotify = inotify::Inotify::watch(&directory).map_err(|err| err.to_string())?;
+
+    std::thread::Builder::new()
+        .name(format!("watch {}", path))
+        .spawn(move || {
+            let mut batch = Batch::default();
+            let mut gone = false;
+
+            while !stop.load(Ordering::Relaxed) {
+                let events = match inotify.read(50) {
+                    Ok(events) => events,
+                    Err(err) => {
+                        log_error!("Stopped watching {}: {}", path, err);
+                        break;
+                    }
+                };
+
+                for event in events {
+                    let mask = event.mask;
+                    if mask & libc::IN_Q_OVERFLOW != 0 {
+                        batch.overflowed = true;
+                    } else if mask & (libc::IN_DELETE_SELF | libc::IN_MOVE_SELF | libc::IN_IGNORED)
+                        != 0
+                    {
+                        gone = true;
+                    } else if mask & libc::IN_MOVED_FROM != 0 {
+                        batch.moved_from(event.cookie, event.name);
+                    } else if mask & libc::IN_MOVED_TO != 0 {
+                        batch.moved_to(event.cookie, event.name);
+                    } else if mask & libc::IN_CREATE != 0 {
+                        batch.created(event.name);
+                    } else if mask & libc::IN_DELETE != 0 {
+                        batch.removed(event.name);
+                    } else {
+                        batch.modified(event.name);
+                    }
+                    batch.touch();
+                }
+
+                if gone || batch.is_due() {
+                    let (settled, overflowed) = batch.drain(&directory);
+                    let mut paths = lock_subscribers(&subscribers).clone();
+                    paths.sort();
+                    paths.dedup();
+                    for path in paths {
+                        let mut changes = describe(Path::new(&path), &settled);
+                        if gone {
+                            changes.push(removed_change(Path::new(&path)));
+                        }
+                        emit_changes(
+                            &window,
+                            &DirectoryChanges {
+                                path,
+                                changes,
+                                overflowed,
+                            },
+                        );
+                    }
+                }
+                if gone {
+                    log_info!("Watched directory {} disappeared", path);
+                    stop.store(true, Ordering::Relaxed);
+                }
+            }
+        })
+        .map_err(|err| err.to_string())?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn start(
+    _: Window,
+    _: PathBuf,
+    _: String,
+    _: Subscribers,
+    _: Arc<AtomicBool>,
+) -> Result<(), String> {
+    Err("directory watching is only available on Linux".to_string())
+}
+
+// Watching the same directory twice shares one watcher; each call needs its
+// own `unwatch_directory`
+#[tauri::command]
+pub async fn watch_directory(
+    window: Window,
+    watchers: State<'_, Watchers>,
+    path: String,
+) -> Result<(), String> {
+    // Archive contents only change with the archive file, which the watch on
+    // its parent directory already reports
+    if crate::archive::split_archive_path(&path).is_some() {
+        return Ok(());
+    }
+
+    let directory = Path::new(&path);
+    if !directory.exists() {
+        return Err(OhMyFSError::DirectoryNotFound { path }.to_string());
+    }
+    if !directory.is_dir() {
+        return Err(OhMyFSError::PathNotDirectory { path }.to_string());
+    }
+    let canonical = fs::canonicalize(directory).map_err(|err| {
+        OhMyFSError::WatchFailed {
+            path: path.clone(),
+            details: err.to_string(),
+        }
+        .to_string()
+    })?;
+
+    let mut active = watchers.lock();
+    if let Some(watch) = active.get_mut(&canonical) {
+        if !watch.stop.load(Ordering::Relaxed) {
+            lock_subscribers(&watch.subscribers).push(path);
+            return Ok(());
+        }
+    }
+
+    let subscribers = Arc::new(Mutex::new(vec![path.clone()]));
+    let stop = Arc::new(AtomicBool::new(false));
+    start(
+        window,
+        canonical.clone(),
+        path.clone(),
+        subscribers.clone(),
+        stop.clone(),
+    )
+    .map_err(|details| {
+        log_error!("Failed to watch {}: {}", path, details);
+        OhMyFSError::WatchFailed {
+            path: path.clone(),
+            details,
+        }
+        .to_string()
+    })?;
+    log_info!("Watching {}", path);
+    active.insert(canonical, Watch { subscribers, stop });
+    Ok(())
+}
+
+// Returns false when the directory was not being watched
+#[tauri::command]
+pub async fn unwatch_directory(
+    watchers: State<'_, Watchers>,
+    path: String,
+) -> Result<bool, String> {
+    let mut active = watchers.lock();
+    let key = fs::canonicalize(&path).ok().or_else(|| {
+        // The directory may be gone already; fall back to the path it was watched under
+        active
+            .iter()
+            .find(|(_, watch)| lock_subscribers(&watch.subscribers).contains(&path))
+            .map(|(key, _)| key.clone())
+    });
+    let Some(key) = key else {
+        return Ok(false);
+    };
+    let Some(watch) = active.get_mut(&key) else {
+        return Ok(false);
+    };
+
+    let mut subscribers = lock_subscribers(&watch.subscribers);
+    // Another spelling of the same directory still ends one subscription
+    let index = subscribers
+        .iter()
+        .position(|subscriber| *subscriber == path)
+        .unwrap_or(0);
+    if index < subscribers.len() {
+        subscribers.remove(index);
+    }
+    let unused = subscribers.is_empty();
+    drop(subscribers);
+    if unused {
+        watch.stop.store(true, Ordering::Relaxed);
+        active.remove(&key);
+        log_info!("Stopped watching {}", path);
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settled(batch: &mut Batch, directory: &Path) -> Vec<(ChangeKind, String, Option<String>)> {
+        let (settled, overflowed) = batch.drain(directory);
+        assert!(!overflowed);
+        settled
+            .into_iter()
+            .map(|change| {
+                (
+                    change.kind,
+                    change.name.to_string_lossy().to_string(),
+                    change.old_name.map(|old| old.to_string_lossy().to_string()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn paired_moves_become_renames() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(temp.path().join("new.txt"), "").unwrap();
+        let mut batch = Batch::default();
+
+        batch.moved_from(7, "old.txt".into());
+        batch.moved_from(8, "away.txt".into());
+        batch.moved_to(7, "new.txt".into());
+
+        assert_eq!(
+            settled(&mut batch, temp.path()),
+            [
+                (
+                    ChangeKind::Renamed,
+                    "new.txt".to_string(),
+                    Some("old.txt".to_string())
+                ),
+                // Its arrival never came, so it left the directory
+                (ChangeKind::Removed, "away.txt".to_string(), None),
+            ]
+        );
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    fn unpaired_arrivals_become_creates() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(temp.path().join("in.txt"), "").unwrap();
+        let mut batch = Batch::default();
+
+        batch.moved_to(9, "in.txt".into());
+
+        assert_eq!(
+            settled(&mut batch, temp.path()),
+            [(ChangeKind::Created, "in.txt".to_string(), None)]
+        );
+    }
+
+    #[test]
+    fn created_then_removed_cancels_out() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut batch = Batch::default();
+
+        batch.created("scratch.tmp".into());
+        batch.modified("scratch.tmp".into());
+        batch.removed("scratch.tmp".into());
+
+        assert!(batch.is_empty());
+        assert!(settled(&mut batch, temp.path()).is_empty());
+    }
+}
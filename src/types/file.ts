@@ -1,14 +1,36 @@
+export type EntryKind =
+  | "file"
+  | "directory"
+  | "symlink"
+  | "socket"
+  | "fifo"
+  | "block_device"
+  | "char_device"
+  | "unknown";
+
 export interface FileEntry {
   name: string;
   path: string;
   isDirectory: boolean;
   isFile: boolean;
   isSymlink: boolean;
+  kind?: EntryKind;
+  // Only set for symlinks: the stored target and what it resolves to
+  symlinkTarget?: string;
+  targetKind?: EntryKind;
+  // A symlink whose target is missing or part of a loop
+  isBroken?: boolean;
   size?: number;
+  // Only set for entries browsed inside an archive
+  compressedSize?: number;
   modifiedAt?: Date;
   createdAt?: Date;
   permissions?: string;
   extension?: string;
+  // Dotfiles, names listed in the folder's .hidden file and Windows hidden files
+  isHidden?: boolean;
+  // Matched by .gitignore, .ignore or the global git excludes
+  isIgnored?: boolean;
   mimeType?: string;
   thumbnail?: string;
   metadata?: Record<string, unknown>;
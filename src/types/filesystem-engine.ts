@@ -17,11 +17,23 @@ export interface Variable {
   validation?: unknown; // Zod schema or unknown for imported definitions
 }
 
+/**
+ * Any value JSON can hold
+ */
+export type JsonValue =
+  | string
+  | number
+  | boolean
+  | null
+  | JsonValue[]
+  | { [key: string]: JsonValue };
+
 /**
  * Template for file content with variable substitution
  */
 export interface FileTemplate {
-  content: string; // Template string with {{variable}} placeholders
+  content: JsonValue; // Template string with {{variable}} placeholders, or JSON of them
+  template?: string; // Kind of file, e.g. 'json', 'markdown', 'javascript'
   encoding?: "utf8" | "base64" | "binary";
   executable?: boolean; // For Unix systems
   permissions?: string; // Octal permissions like '755'
@@ -78,10 +90,14 @@ export interface Condition {
     | "equals"
     | "notEquals"
     | "contains"
-    | "notContains";
-  path?: string; // Path to check
+    | "notContains"
+    | "all"
+    | "any"
+    | "not";
+  path?: string; // Path to check, or whose content to compare
   value?: unknown; // Value to compare against
   variable?: string; // Variable name to check
+  conditions?: Condition[]; // Nested conditions for all/any/not
 }
 
 /**
@@ -199,6 +215,7 @@ export interface FileSystemDiff {
   metadata?: {
     safetyScore: number;
     blockedChangesCount: number;
+    skippedNodes?: ResolvedFileSystemNode[]; // Left out by their conditions
   };
 }
 
@@ -266,8 +283,6 @@ export interface EngineConfig {
   verbose: boolean;
   backup: boolean; // Create backups before destructive operations
   ignoreErrors: boolean; // Continue execution despite errors
-  concurrency: number; // Max concurrent operations
-  timeout: number; // Timeout per operation in seconds
 }
 
 // ============================================================================
@@ -312,26 +327,44 @@ export const VariableSchema = z.object({
   validation: z.unknown().optional(), // Zod schema
 });
 
+export const JsonValueSchema: z.ZodType<JsonValue> = z.lazy(() =>
+  z.union([
+    z.string(),
+    z.number(),
+    z.boolean(),
+    z.null(),
+    z.array(JsonValueSchema),
+    z.record(z.string(), JsonValueSchema),
+  ])
+);
+
 export const FileTemplateSchema = z.object({
-  content: z.string(),
+  content: JsonValueSchema,
+  template: z.string().optional(),
   encoding: z.enum(["utf8", "base64", "binary"]).default("utf8"),
   executable: z.boolean().default(false),
   permissions: z.string().optional(),
 });
 
-export const ConditionSchema = z.object({
-  type: z.enum([
-    "exists",
-    "notExists",
-    "equals",
-    "notEquals",
-    "contains",
-    "notContains",
-  ]),
-  path: z.string().optional(),
-  value: z.unknown().optional(),
-  variable: z.string().optional(),
-});
+export const ConditionSchema: z.ZodType<Condition> = z.lazy(() =>
+  z.object({
+    type: z.enum([
+      "exists",
+      "notExists",
+      "equals",
+      "notEquals",
+      "contains",
+      "notContains",
+      "all",
+      "any",
+      "not",
+    ]),
+    path: z.string().optional(),
+    value: z.unknown().optional(),
+    variable: z.string().optional(),
+    conditions: z.array(ConditionSchema).optional(),
+  })
+);
 
 export const FileDefinitionSchema = z.object({
   type: z.literal("file"),
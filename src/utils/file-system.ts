@@ -1,18 +1,37 @@
 import { invoke } from "@tauri-apps/api/core";
-import { basename, dirname, resolve, sep } from "@tauri-apps/api/path";
-import type { FileEntry, FileViewMode } from "~/types/file";
+import { dirname, resolve, sep } from "@tauri-apps/api/path";
+import type { EntryKind, FileEntry, FileViewMode } from "~/types/file";
 
 interface RustFileEntry {
   name: string;
   path: string;
+  kind: EntryKind;
   is_directory: boolean;
   is_file: boolean;
   is_symlink: boolean;
+  symlink_target?: string;
+  target_kind?: EntryKind;
+  is_broken?: boolean;
   size?: number;
+  compressed_size?: number;
   modified_at?: string;
   created_at?: string;
   permissions?: string;
   extension?: string;
+  is_hidden?: boolean;
+  is_ignored?: boolean;
+}
+
+// Returned by stat_path: a file entry plus the details of a single stat call
+interface RustPathStat extends RustFileEntry {
+  inode?: number;
+  device?: number;
+  links?: number;
+  uid?: number;
+  gid?: number;
+  owner?: string;
+  group?: string;
+  accessed_at?: string;
 }
 
 interface RustDirectoryContents {
@@ -137,10 +156,16 @@ function transformRustEntry(entry: RustFileEntry): FileEntry {
   return {
     name: entry.name,
     path: entry.path,
-    isDirectory: entry.is_directory,
+    // Symlinks to folders open like folders
+    isDirectory: entry.is_directory || entry.target_kind === "directory",
     isFile: entry.is_file,
     isSymlink: entry.is_symlink,
+    kind: entry.kind,
+    symlinkTarget: entry.symlink_target ?? undefined,
+    targetKind: entry.target_kind ?? undefined,
+    isBroken: entry.is_broken ?? false,
     size: entry.size || 0,
+    compressedSize: entry.compressed_size ?? undefined,
     modifiedAt: entry.modified_at
       ? new Date(Number.parseInt(entry.modified_at, 10))
       : undefined,
@@ -150,6 +175,8 @@ function transformRustEntry(entry: RustFileEntry): FileEntry {
     permissions: entry.permissions,
     extension,
     mimeType: getMimeType(extension),
+    isHidden: entry.is_hidden ?? entry.name.startsWith("."),
+    isIgnored: entry.is_ignored ?? false,
   };
 }
 
@@ -198,7 +225,8 @@ export function formatDate(date: Date): string {
 
 export async function readDirectory(
   path: string,
-  showHidden = false
+  showHidden = false,
+  respectIgnore = false
 ): Promise<FileEntry[]> {
   try {
     // Validate path for security
@@ -213,6 +241,7 @@ export async function readDirectory(
     const result = (await invoke("read_directory", {
       path: resolvedPath,
       showHidden,
+      respectIgnore,
     })) as RustDirectoryContents;
 
     const fileEntries: FileEntry[] = [];
@@ -269,32 +298,32 @@ export async function getFileStats(path: string) {
     }
 
     const resolvedPath = await resolve(path);
-    // For now, we'll get stats by reading the directory containing the file
-    // This is a simplified approach - we could add a dedicated command if needed
-    const dirPath = await dirname(resolvedPath);
-    const fileName = await basename(resolvedPath);
-
-    const dirContents = (await invoke("read_directory", {
-      path: dirPath,
-      showHidden: true,
-    })) as RustDirectoryContents;
-
-    const allEntries = [...dirContents.directories, ...dirContents.files];
-    const entry = allEntries.find((e) => e.name === fileName);
-
-    if (!entry) {
-      throw new Error(`File not found: ${path}`);
-    }
+    const entry = (await invoke("stat_path", {
+      path: resolvedPath,
+    })) as RustPathStat;
 
     return {
       size: entry.size || 0,
       mtime: entry.modified_at
         ? new Date(Number.parseInt(entry.modified_at, 10))
         : null,
-      atime: entry.created_at
+      atime: entry.accessed_at
+        ? new Date(Number.parseInt(entry.accessed_at, 10))
+        : null,
+      birthtime: entry.created_at
         ? new Date(Number.parseInt(entry.created_at, 10))
         : null,
       mode: entry.permissions ? Number.parseInt(entry.permissions, 8) : null,
+      ino: entry.inode ?? null,
+      dev: entry.device ?? null,
+      nlink: entry.links ?? null,
+      uid: entry.uid ?? null,
+      gid: entry.gid ?? null,
+      owner: entry.owner ?? null,
+      group: entry.group ?? null,
+      symlinkTarget: entry.symlink_target ?? null,
+      isBroken: entry.is_broken ?? false,
+      entry: transformRustEntry(entry),
     };
   } catch (error) {
     console.error("Error getting file stats:", error);
@@ -312,22 +341,7 @@ export async function fileExists(path: string): Promise<boolean> {
     }
 
     const resolvedPath = await resolve(path);
-    // For now, we'll check by trying to read the directory containing the file
-    // This is a simplified approach - we could add a dedicated command if needed
-    const dirPath = await dirname(resolvedPath);
-    const fileName = await basename(resolvedPath);
-
-    try {
-      const dirContents = (await invoke("read_directory", {
-        path: dirPath,
-        showHidden: true,
-      })) as RustDirectoryContents;
-
-      const allEntries = [...dirContents.directories, ...dirContents.files];
-      return allEntries.some((e) => e.name === fileName);
-    } catch {
-      return false;
-    }
+    return (await invoke("path_exists", { path: resolvedPath })) as boolean;
   } catch {
     return false;
   }
@@ -31,8 +31,6 @@ export class FileSystemEngine {
       verbose: false,
       backup: true,
       ignoreErrors: false,
-      concurrency: 1,
-      timeout: 30,
       ...config,
     };
   }
@@ -6,6 +6,7 @@ import type {
   FileEntry,
   FileStructureDefinition,
   FileSystemDefinition,
+  JsonValue,
   ResolvedDefinition,
   ResolvedFileSystemNode,
   SymlinkDefinition,
@@ -147,7 +148,7 @@ async function resolveNodeRecursive(
   if (node.type === "file") {
     const fileNode = node as FileDefinition;
     if (fileNode.content) {
-      resolvedNode.resolvedContent = interpolateString(
+      resolvedNode.resolvedContent = interpolateContent(
         fileNode.content.content,
         variables
       );
@@ -262,6 +263,43 @@ function interpolateString(
   });
 }
 
+/**
+ * Interpolate file content; structured content is written out as JSON
+ */
+function interpolateContent(
+  content: JsonValue,
+  variables: VariableValues
+): string {
+  if (typeof content === "string") {
+    return interpolateString(content, variables);
+  }
+  return JSON.stringify(interpolateValue(content, variables), null, 2);
+}
+
+/**
+ * Interpolate variables in every key and string of a JSON value
+ */
+function interpolateValue(
+  value: JsonValue,
+  variables: VariableValues
+): JsonValue {
+  if (typeof value === "string") {
+    return interpolateString(value, variables);
+  }
+  if (Array.isArray(value)) {
+    return value.map((item) => interpolateValue(item, variables));
+  }
+  if (value !== null && typeof value === "object") {
+    return Object.fromEntries(
+      Object.entries(value).map(([key, field]) => [
+        interpolateString(key, variables),
+        interpolateValue(field, variables),
+      ])
+    );
+  }
+  return value;
+}
+
 /**
  * Get a human-readable reason for why this node should exist
  */
@@ -415,7 +415,11 @@ function estimateDefinitionSize(definition: FileStructureDefinition): string {
   function calculateSize(structure: FileSystemDefinition[]): void {
     for (const item of structure) {
       if (item.type === "file" && item.content?.content) {
-        totalSize += item.content.content.length;
+        const content = item.content.content;
+        totalSize +=
+          typeof content === "string"
+            ? content.length
+            : JSON.stringify(content).length;
       } else if (item.type === "directory" && item.children) {
         calculateSize(item.children);
       }
@@ -29,8 +29,6 @@ export function createExecutionPlan(
     verbose: false,
     backup: true,
     ignoreErrors: false,
-    concurrency: 1,
-    timeout: 30,
     ...config,
   };
 